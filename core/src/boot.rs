@@ -0,0 +1,189 @@
+// Boot ROMs. At power on the boot ROM is mapped over the cartridge at 0000-00FF, and on the
+// CGB also at 0200-08FF (the cartridge header at 0100-01FF stays visible). It scrolls the
+// logo, checks the header, then unmaps itself by writing to FF50 as PC reaches 0100.
+// FF50   BOOT - Write bit 0 = 1 to unmap the boot ROM, it cannot be mapped again
+//
+// Without a boot ROM the machine starts in the state the boot ROM leaves: the CPU registers
+// of `GameBoy::with_model`, the I/O registers below, and on the DMG the logo in VRAM. The
+// PPU starts at the top of a frame.
+// https://gbdev.io/pandocs/Power_Up_Sequence.html
+
+use std::fmt;
+
+use crate::{cartridge::Cartridge, joypad, model::Model};
+
+pub const BOOT: u16 = 0xFF50;
+
+/// Size of the DMG, MGB and SGB boot ROMs.
+pub const DMG_SIZE: usize = 0x100;
+/// Size of the CGB and AGB boot ROMs, the 0x100 bytes under the cartridge header included.
+pub const CGB_SIZE: usize = 0x900;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BootRomError {
+    /// The boot ROM does not have the size of the model's, holds its size.
+    Size(usize),
+}
+
+impl fmt::Display for BootRomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootRomError::Size(size) => write!(
+                f,
+                "boot ROM has {} bytes, expected {} (DMG) or {} (CGB)",
+                size, DMG_SIZE, CGB_SIZE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BootRomError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    /// A boot ROM for `model`, which must have its size.
+    pub fn new(data: Vec<u8>, model: Model) -> Result<Self, BootRomError> {
+        let size = if model.is_cgb() { CGB_SIZE } else { DMG_SIZE };
+        if data.len() != size {
+            return Err(BootRomError::Size(data.len()));
+        }
+        Ok(BootRom { data })
+    }
+
+    /// A boot ROM of either size, as found in save states.
+    pub(crate) fn from_state(data: &[u8]) -> Option<Self> {
+        [DMG_SIZE, CGB_SIZE].contains(&data.len()).then(|| BootRom {
+            data: data.to_vec(),
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The byte mapped at `address`, `None` where the cartridge shows through.
+    pub(crate) fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x00FF | 0x0200..=0x08FF => self.data.get(address as usize).copied(),
+            _ => None,
+        }
+    }
+}
+
+/// I/O registers the boot ROM leaves set, written in order. The sound registers are only
+/// stored, and DMA (0xFF on the DMG) is left alone as writing it starts a transfer.
+pub(crate) fn io_registers(model: Model) -> [(u16, u8); 25] {
+    let nr52 = if model == Model::Sgb { 0xF0 } else { 0xF1 };
+    [
+        (joypad::P1, 0x00),
+        (0xFF10, 0x80), // NR10
+        (0xFF11, 0xBF), // NR11
+        (0xFF12, 0xF3), // NR12
+        (0xFF13, 0xFF), // NR13
+        (0xFF14, 0xBF), // NR14
+        (0xFF16, 0x3F), // NR21
+        (0xFF17, 0x00), // NR22
+        (0xFF18, 0xFF), // NR23
+        (0xFF19, 0xBF), // NR24
+        (0xFF1A, 0x7F), // NR30
+        (0xFF1B, 0xFF), // NR31
+        (0xFF1C, 0x9F), // NR32
+        (0xFF1D, 0xFF), // NR33
+        (0xFF1E, 0xBF), // NR34
+        (0xFF20, 0xFF), // NR41
+        (0xFF21, 0x00), // NR42
+        (0xFF22, 0x00), // NR43
+        (0xFF23, 0xBF), // NR44
+        (0xFF24, 0x77), // NR50
+        (0xFF25, 0xF3), // NR51
+        (0xFF26, nr52), // NR52
+        (0xFF47, 0xFC), // BGP
+        (0xFF0F, 0x01), // IF, V-Blank requested during the boot
+        (0xFF40, 0x91), // LCDC
+    ]
+}
+
+/// Internal counter of the timer when the boot ROM ends, whose upper byte is DIV. Only known
+/// for the DMG boot ROM (and the MGB one, which runs the same code).
+pub(crate) fn div_counter(model: Model) -> u16 {
+    match model {
+        Model::Dmg | Model::Mgb => 0xABCC,
+        _ => 0x0000,
+    }
+}
+
+/// The ® next to the logo.
+const REGISTERED: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+/// VRAM writes of the DMG boot ROM: the logo of the header (0x0104-0x0133) scaled up twice
+/// in tiles 1-24, the ® in tile 25 and a tile map showing them.
+pub(crate) fn logo_vram(cartridge: &Cartridge) -> Vec<(u16, u8)> {
+    // Each bit is doubled horizontally and each row is written twice, bit plane 1 stays 0.
+    let double = |nibble: u8| {
+        (0..4).fold(0u8, |byte, bit| {
+            byte | ((nibble >> bit & 1) * 3) << (bit * 2)
+        })
+    };
+    let mut rows = Vec::new();
+    for address in 0x0104..=0x0133 {
+        let byte = cartridge.read_rom(address);
+        for nibble in [byte >> 4, byte & 0x0F] {
+            rows.extend([double(nibble); 2]);
+        }
+    }
+    rows.extend(REGISTERED);
+
+    let mut writes: Vec<(u16, u8)> = (0..)
+        .zip(rows)
+        .map(|(row, value)| (0x8010 + row * 2, value))
+        .collect();
+    for i in 0..12 {
+        writes.push((0x9904 + i, i as u8 + 0x01));
+        writes.push((0x9924 + i, i as u8 + 0x0D));
+    }
+    writes.push((0x9910, 0x19));
+    writes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_rom() {
+        assert_eq!(
+            BootRom::new(vec![0; 0x100], Model::Cgb),
+            Err(BootRomError::Size(0x100))
+        );
+        let boot = BootRom::new((0..0x900).map(|i| i as u8).collect(), Model::Cgb).unwrap();
+        assert_eq!(boot.read(0x00FF), Some(0xFF));
+        assert_eq!(boot.read(0x0100), None);
+        assert_eq!(boot.read(0x0201), Some(0x01));
+        assert_eq!(boot.read(0x0900), None);
+        let boot = BootRom::new(vec![0; 0x100], Model::Dmg).unwrap();
+        assert_eq!(boot.read(0x0200), None);
+    }
+
+    #[test]
+    fn test_logo_vram() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0104] = 0xCE; // First byte of the Nintendo logo
+        let writes = logo_vram(&Cartridge::new(rom).unwrap());
+        // 0xC doubles to 0xF0 and 0xE to 0xFC, two rows each.
+        assert_eq!(
+            &writes[..4],
+            &[
+                (0x8010, 0xF0),
+                (0x8012, 0xF0),
+                (0x8014, 0xFC),
+                (0x8016, 0xFC)
+            ]
+        );
+        assert!(writes.contains(&(0x8190, 0x3C)));
+        assert!(writes.contains(&(0x992F, 0x18)));
+    }
+}
@@ -0,0 +1,473 @@
+// Cartridge header and memory bank controllers (MBC).
+// 0000-3FFF   ROM bank 00 (or the bank selected by the MBC)
+// 4000-7FFF   Switchable ROM bank
+// A000-BFFF   External RAM, RTC registers or other mapper registers
+//
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+
+use std::fmt;
+
+use crate::{
+    infrared::IrPeer,
+    state::{self, Snapshot, StateError, StateReader, StateWriter},
+};
+
+mod camera;
+mod huc1;
+mod huc3;
+mod m161;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc7;
+mod rom_only;
+mod sachen;
+mod unlicensed;
+mod wisdom_tree;
+
+pub use camera::{SENSOR_HEIGHT, SENSOR_WIDTH};
+pub use unlicensed::{Unlicensed, NINTENDO_LOGO};
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+const HEADER_END: usize = 0x150;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CartridgeError {
+    /// The ROM is too small to hold a header.
+    TooSmall(usize),
+    /// Cartridge type byte (0x0147) of a mapper that is not supported.
+    UnsupportedType(u8),
+    /// A save file whose size is neither the RAM size nor the RAM and clock size, holds its size.
+    SaveFileSize(usize),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::TooSmall(size) => {
+                write!(
+                    f,
+                    "ROM has {} bytes, too small for a cartridge header",
+                    size
+                )
+            }
+            CartridgeError::UnsupportedType(kind) => {
+                write!(f, "unsupported cartridge type 0x{:02X}", kind)
+            }
+            CartridgeError::SaveFileSize(size) => {
+                write!(
+                    f,
+                    "save file has {} bytes, not the size of the cartridge RAM",
+                    size
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// Memory bank controller, maps the ROM and RAM of a cartridge into the address space.
+/// Its snapshot holds the registers and RAM but not the ROM.
+pub trait Mbc: Snapshot {
+    /// Read from 0x0000-0x7FFF.
+    fn read_rom(&self, address: u16) -> u8;
+    /// Write to 0x0000-0x7FFF, which sets the MBC registers.
+    fn write_rom(&mut self, address: u16, value: u8);
+    /// Read from 0xA000-0xBFFF.
+    fn read_ram(&self, address: u16) -> u8;
+    /// Write to 0xA000-0xBFFF.
+    fn write_ram(&mut self, address: u16, value: u8);
+    /// External RAM, all banks.
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+    /// Bank selected for 0x0000-0x7FFF (ROM) or 0xA000-0xBFFF (RAM).
+    fn bank(&self, address: u16) -> usize;
+    /// Advance clocks kept by the cartridge (e.g. MBC3 RTC) by M-cycles.
+    fn tick(&mut self, _cycles: u32) {}
+    /// Set the image seen by the camera sensor of a Pocket Camera, see `Cartridge::set_camera_image`.
+    fn set_sensor_image(&mut self, _image: &[u8]) {}
+    /// Set the acceleration measured by the accelerometer of an MBC7, see
+    /// `Cartridge::set_accelerometer`.
+    fn set_accelerometer(&mut self, _x: f32, _y: f32) {}
+    /// State of the clock kept by the battery, stored after the RAM in save files. Empty
+    /// without a clock.
+    fn clock(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    /// Set the clock from the bytes returned by `clock`.
+    fn set_clock(&mut self, _clock: &[u8]) {}
+    /// Point the infrared LED and receiver at `peer`, see `Cartridge::set_ir_peer`.
+    fn set_ir_peer(&mut self, _peer: Option<Box<dyn IrPeer>>) {}
+    /// Called when the boot ROM unmaps itself, or at power on without a boot ROM.
+    fn boot_done(&mut self) {}
+}
+
+pub struct Header {
+    pub title: String,
+    /// 0x0143: 0x80 CGB enhanced, 0xC0 CGB only.
+    pub cgb_flag: u8,
+    /// 0x0146: 0x03 when the game supports SGB functions.
+    pub sgb_flag: u8,
+    pub cartridge_type: u8,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+pub struct Cartridge {
+    header: Header,
+    rom_banks: usize,
+    checksum: u32,
+    unlicensed: Option<Unlicensed>,
+    mbc: Box<dyn Mbc>,
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        // Newer cartridges use the end of the title for the manufacturer code and CGB flag.
+        let title = rom[0x0134..0x0144]
+            .iter()
+            .take_while(|&&c| c != 0 && c < 0x80)
+            .map(|&c| c as char)
+            .collect();
+        let ram_size = match rom[0x0149] {
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            _ => 0,
+        };
+
+        Ok(Header {
+            title,
+            cgb_flag: rom[0x0143],
+            sgb_flag: rom[0x0146],
+            cartridge_type: rom[0x0147],
+            rom_size: (ROM_BANK_SIZE * 2) << rom[0x0148].min(8),
+            ram_size,
+            header_checksum: rom[0x014D],
+            global_checksum: u16::from(rom[0x014E]) << 8 | u16::from(rom[0x014F]),
+        })
+    }
+
+    /// Whether the header checksum at 0x014D matches bytes 0x0134-0x014C, as checked by the boot ROM.
+    pub fn is_checksum_valid(&self, rom: &[u8]) -> bool {
+        let checksum = rom[0x0134..=0x014C]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+        checksum == self.header_checksum
+    }
+
+    pub fn has_battery(&self) -> bool {
+        matches!(
+            self.cartridge_type,
+            0x03 | 0x06
+                | 0x09
+                | 0x0D
+                | 0x0F
+                | 0x10
+                | 0x13
+                | 0x1B
+                | 0x1E
+                | 0x22
+                | 0xFC
+                | 0xFE
+                | 0xFF
+        )
+    }
+}
+
+impl Cartridge {
+    /// The cartridge of `rom`, with the mapper its type byte tells or, for unlicensed and
+    /// bootleg cartridges, the one detected.
+    pub fn new(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        let header = Header::parse(&rom)?;
+        let checksum = state::crc32(&rom);
+        let rom_banks = rom.len().div_ceil(ROM_BANK_SIZE);
+        let unlicensed = Unlicensed::detect(&header, &rom);
+        let ram = vec![0; header.ram_size];
+
+        let mbc: Box<dyn Mbc> = match (unlicensed, header.cartridge_type) {
+            (Some(kind), _) => {
+                let ram = vec![0; kind.ram_size(header.ram_size)];
+                match kind {
+                    Unlicensed::WisdomTree => Box::new(wisdom_tree::WisdomTree::new(rom)),
+                    Unlicensed::SachenMmc1 | Unlicensed::SachenMmc2 => {
+                        Box::new(sachen::Sachen::new(rom))
+                    }
+                    Unlicensed::M161 => Box::new(m161::M161::new(rom)),
+                    Unlicensed::Mbc1Multicart => Box::new(mbc1::Mbc1::multicart(rom, ram)),
+                    Unlicensed::BootlegMbc1 => Box::new(mbc1::Mbc1::new(rom, ram)),
+                    Unlicensed::BootlegMbc5 => Box::new(mbc5::Mbc5::new(rom, ram)),
+                }
+            }
+            (None, cartridge_type) => match cartridge_type {
+                0x00 | 0x08 | 0x09 => Box::new(rom_only::RomOnly::new(rom, ram)),
+                0x01..=0x03 => Box::new(mbc1::Mbc1::new(rom, ram)),
+                0x05 | 0x06 => Box::new(mbc2::Mbc2::new(rom)),
+                0x0F..=0x13 => {
+                    let rtc = matches!(header.cartridge_type, 0x0F | 0x10);
+                    Box::new(mbc3::Mbc3::new(rom, ram, rtc))
+                }
+                0x19..=0x1E => Box::new(mbc5::Mbc5::new(rom, ram)),
+                0x22 => Box::new(mbc7::Mbc7::new(rom)),
+                0xFC => Box::new(camera::PocketCamera::new(rom, ram)),
+                0xFE => Box::new(huc3::Huc3::new(rom, ram)),
+                0xFF => Box::new(huc1::Huc1::new(rom, ram)),
+                kind => return Err(CartridgeError::UnsupportedType(kind)),
+            },
+        };
+
+        Ok(Cartridge {
+            header,
+            rom_banks,
+            checksum,
+            unlicensed,
+            mbc,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The mapper detected for an unlicensed or bootleg cartridge, None when the type byte
+    /// was trusted.
+    pub fn unlicensed(&self) -> Option<Unlicensed> {
+        self.unlicensed
+    }
+
+    /// CRC-32 of the whole ROM, identifies the game in save states.
+    pub fn rom_checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    pub fn read_rom(&self, address: u16) -> u8 {
+        self.mbc.read_rom(address)
+    }
+
+    pub fn write_rom(&mut self, address: u16, value: u8) {
+        self.mbc.write_rom(address, value)
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.mbc.read_ram(address)
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        self.mbc.write_ram(address, value)
+    }
+
+    /// Bank mapped at a ROM or external RAM address. ROM bank numbers past the end of the ROM
+    /// wrap around, as they do when reading.
+    pub fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x7FFF => self.mbc.bank(address) % self.rom_banks,
+            _ => self.mbc.bank(address),
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        self.mbc.tick(cycles)
+    }
+
+    /// Called when the boot ROM is done, Sachen mappers stop showing it the alternate header.
+    pub(crate) fn boot_done(&mut self) {
+        self.mbc.boot_done()
+    }
+
+    /// Whether this is a Pocket Camera, whose sensor sees the image of `set_camera_image`.
+    pub fn has_camera(&self) -> bool {
+        self.header.cartridge_type == 0xFC
+    }
+
+    /// Set the image the camera sensor sees from now on: the brightness of each pixel, 0
+    /// (black) to 255 (white), row by row. Other cartridges ignore it.
+    ///
+    /// # Panics
+    ///
+    /// If `image` does not hold `SENSOR_WIDTH * SENSOR_HEIGHT` pixels.
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        assert_eq!(
+            image.len(),
+            SENSOR_WIDTH * SENSOR_HEIGHT,
+            "camera image size"
+        );
+        self.mbc.set_sensor_image(image)
+    }
+
+    /// Set the acceleration the accelerometer of an MBC7 measures, in g: `x` positive when the
+    /// Game Boy tilts to the right, `y` positive when it tilts towards its bottom. Level is
+    /// (0, 0). Other cartridges ignore it.
+    pub fn set_accelerometer(&mut self, x: f32, y: f32) {
+        self.mbc.set_accelerometer(x, y)
+    }
+
+    /// Contents of the save file of a cartridge with a battery: the external RAM, then the
+    /// clock of mappers that have one.
+    pub fn save_file(&self) -> Vec<u8> {
+        let mut data = self.mbc.ram().to_vec();
+        data.extend(self.mbc.clock());
+        data
+    }
+
+    /// Restore the RAM and clock from a save file. A file with the RAM only leaves the
+    /// clock alone.
+    pub fn load_save_file(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        let ram_size = self.mbc.ram().len();
+        let clock_size = self.mbc.clock().len();
+        if data.len() != ram_size && data.len() != ram_size + clock_size {
+            return Err(CartridgeError::SaveFileSize(data.len()));
+        }
+        let (ram, clock) = data.split_at(ram_size);
+        self.mbc.ram_mut().copy_from_slice(ram);
+        if !clock.is_empty() {
+            self.mbc.set_clock(clock);
+        }
+        Ok(())
+    }
+
+    /// Connect the infrared port of a HuC1 or HuC3 to `peer`, or disconnect it with `None`.
+    /// Other cartridges have no infrared port and ignore it.
+    pub fn set_ir_peer(&mut self, peer: Option<Box<dyn IrPeer>>) {
+        self.mbc.set_ir_peer(peer)
+    }
+}
+
+impl Snapshot for Cartridge {
+    fn save(&self, writer: &mut StateWriter) {
+        self.mbc.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.mbc.load(reader)
+    }
+}
+
+/// Byte of `bank` at `address`, wrapping banks past the end of the ROM as the hardware does.
+fn rom_byte(rom: &[u8], bank: usize, address: u16) -> u8 {
+    let index = bank * ROM_BANK_SIZE + (address as usize & (ROM_BANK_SIZE - 1));
+    rom.get(index % rom.len().max(1)).copied().unwrap_or(0xFF)
+}
+
+/// Index into the external RAM of `bank` at `address`, or None when there is no RAM.
+fn ram_index(ram: &[u8], bank: usize, address: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+    let index = bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1));
+    Some(index % ram.len())
+}
+
+#[cfg(test)]
+pub(crate) fn test_rom(cartridge_type: u8, banks: usize, ram_size: u8) -> Vec<u8> {
+    // Every ROM bank is filled with its bank number.
+    let mut rom: Vec<u8> = (0..banks)
+        .flat_map(|bank| std::iter::repeat_n(bank as u8, ROM_BANK_SIZE))
+        .collect();
+    rom[0x0134..0x0139].copy_from_slice(b"TESTS");
+    rom[0x0143] = 0x00;
+    rom[0x0146] = 0x00;
+    rom[0x0147] = cartridge_type;
+    rom[0x0148] = (banks / 2).trailing_zeros() as u8;
+    rom[0x0149] = ram_size;
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let rom = test_rom(0x03, 8, 0x03);
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.title, "TESTS");
+        assert_eq!(header.cartridge_type, 0x03);
+        assert_eq!(header.rom_size, 8 * ROM_BANK_SIZE);
+        assert_eq!(header.ram_size, 0x8000);
+        assert!(header.has_battery());
+    }
+
+    #[test]
+    fn test_header_checksum() {
+        let mut rom = test_rom(0x00, 2, 0x00);
+        let header = Header::parse(&rom).unwrap();
+        assert!(!header.is_checksum_valid(&rom));
+
+        rom[0x014D] = rom[0x0134..=0x014C]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1));
+        let header = Header::parse(&rom).unwrap();
+        assert!(header.is_checksum_valid(&rom));
+    }
+
+    #[test]
+    fn test_mapped_bank() {
+        let mut cartridge = Cartridge::new(test_rom(0x01, 8, 0)).unwrap();
+        assert_eq!(cartridge.bank(0x0150), 0);
+        assert_eq!(cartridge.bank(0x4000), 1);
+        cartridge.write_rom(0x2000, 0x03);
+        assert_eq!(cartridge.bank(0x7FFF), 3);
+        // Bank 9 of a 8 bank ROM reads bank 1.
+        cartridge.write_rom(0x2000, 0x09);
+        assert_eq!(cartridge.bank(0x4000), 1);
+    }
+
+    #[test]
+    fn test_save_file() {
+        let mut cartridge = Cartridge::new(test_rom(0x10, 4, 0x03)).unwrap();
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        cartridge.tick(1 << 20);
+        let data = cartridge.save_file();
+        assert_eq!(data.len(), 0x8000 + 10);
+
+        let mut other = Cartridge::new(test_rom(0x10, 4, 0x03)).unwrap();
+        other.load_save_file(&data).unwrap();
+        assert_eq!(other.save_file(), data);
+        // The RAM alone is accepted, other sizes are not.
+        other.load_save_file(&data[..0x8000]).unwrap();
+        assert_eq!(
+            other.load_save_file(&data[..0x2000]),
+            Err(CartridgeError::SaveFileSize(0x2000))
+        );
+    }
+
+    #[test]
+    fn test_unlicensed() {
+        // A bootleg claiming to be ROM only with 128KB of ROM and RAM banks like an MBC1.
+        let mut cartridge = Cartridge::new(test_rom(0x00, 8, 0)).unwrap();
+        assert_eq!(cartridge.unlicensed(), Some(Unlicensed::BootlegMbc1));
+        cartridge.write_rom(0x2000, 0x05);
+        assert_eq!(cartridge.read_rom(0x4000), 5);
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+
+        let mut rom = test_rom(0x01, 8, 0);
+        rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+        assert_eq!(Cartridge::new(rom).unwrap().unlicensed(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Cartridge::new(vec![0; 0x100]).err(),
+            Some(CartridgeError::TooSmall(0x100))
+        );
+        assert_eq!(
+            Cartridge::new(test_rom(0xFD, 2, 0)).err(),
+            Some(CartridgeError::UnsupportedType(0xFD))
+        );
+    }
+}
@@ -0,0 +1,325 @@
+// Pocket Camera (MAC-GBD): 1MB ROM, 128KB RAM and the M64282FP image sensor.
+// 0000-1FFF   RAM write enable (0x0A enables), RAM is always readable
+// 2000-3FFF   ROM bank number, 6 bits (bank 0 can be mapped)
+// 4000-5FFF   RAM bank number (0x00-0x0F), or 0x10 to map the CAM registers
+//
+// CAM registers, mirrored every 0x80 bytes of A000-BFFF. Only A000 can be read, the others
+// read 0x00.
+// A000        Bit 0: write 1 to start a capture, reads 1 until it ends. Bits 1-2 are stored
+// A001        Bits 5-7: edge mode (7 = 2D enhancement), bits 0-4: gain
+// A002-A003   Exposure time, big endian, in units of 16 M-cycles
+// A004        Bits 4-6: edge enhancement ratio, bit 3: invert, bits 0-2: reference voltage
+// A005        Bit 7: zero point calibration, bits 0-5: output reference voltage
+// A006-A035   Dithering matrix: for each of the 4x4 pixel positions, 3 thresholds dividing
+//             the brightness into the 4 shades. Games compute them from the contrast setting
+//
+// A capture turns the sensor image into 128x112 pixels of 2bpp tiles, 16 tiles a row, at
+// A100-AEFF of RAM bank 0. The gain, invert and reference voltages tune the analog sensor and
+// are stored only: the brightness of the input image is scaled by the exposure alone.
+// https://gbdev.io/pandocs/Gameboy_Camera.html
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+
+const REGISTERS: usize = 0x36;
+const DITHER_MATRIX: usize = 0x06;
+const IMAGE_ADDRESS: usize = 0x0100;
+
+/// Exposure at which the brightness of the input image is kept.
+const UNIT_EXPOSURE: u32 = 0x1000;
+
+/// Edge enhancement ratios (A004 bits 4-6) in quarters: 0.5, 0.75, 1, 1.25, 2, 3, 4 and 5.
+const EDGE_RATIOS: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers: [u8; REGISTERS],
+    /// M-cycles until the capture in progress ends, 0 when none is.
+    capture: u32,
+    /// Brightness of each pixel of the sensor, 0 (black) to 255 (white), row by row.
+    image: Vec<u8>,
+}
+
+impl PocketCamera {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        PocketCamera {
+            rom,
+            ram,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers: [0; REGISTERS],
+            capture: 0,
+            image: vec![0x80; SENSOR_WIDTH * SENSOR_HEIGHT],
+        }
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.ram_bank & 0x10 != 0
+    }
+
+    fn exposure(&self) -> u32 {
+        u32::from(self.registers[2]) << 8 | u32::from(self.registers[3])
+    }
+
+    /// M-cycles a capture takes with the current registers.
+    fn capture_cycles(&self) -> u32 {
+        let n = self.registers[1] & 0x80 != 0;
+        32446 + if n { 0 } else { 512 } + 16 * self.exposure()
+    }
+
+    /// Brightness of the sensor pixel at (x, y) after the exposure, the edges repeat past
+    /// the borders.
+    fn exposed(&self, x: isize, y: isize) -> i32 {
+        let x = x.clamp(0, SENSOR_WIDTH as isize - 1) as usize;
+        let y = y.clamp(0, SENSOR_HEIGHT as isize - 1) as usize;
+        let pixel = u32::from(self.image[y * SENSOR_WIDTH + x]);
+        (pixel * self.exposure() / UNIT_EXPOSURE) as i32
+    }
+
+    /// Shade (0 white to 3 black) of the pixel at (x, y) of the captured image.
+    fn shade(&self, x: usize, y: usize) -> u8 {
+        let (sx, sy) = (x as isize, y as isize);
+        let mut value = self.exposed(sx, sy);
+        if self.registers[1] & 0xE0 == 0xE0 {
+            let ratio = EDGE_RATIOS[usize::from(self.registers[4] >> 4 & 0x07)];
+            let neighbours = self.exposed(sx - 1, sy)
+                + self.exposed(sx + 1, sy)
+                + self.exposed(sx, sy - 1)
+                + self.exposed(sx, sy + 1);
+            value += (value * 4 - neighbours) * ratio / 4;
+        }
+        let value = value.clamp(0, 255) as u8;
+
+        let index = DITHER_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+        let thresholds = &self.registers[index..index + 3];
+        thresholds
+            .iter()
+            .position(|&threshold| value < threshold)
+            .map_or(0, |level| 3 - level as u8)
+    }
+
+    /// Write the captured image into RAM bank 0.
+    fn finish_capture(&mut self) {
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let shade = self.shade(x, y);
+                let tile = (y / 8) * (SENSOR_WIDTH / 8) + x / 8;
+                let address = IMAGE_ADDRESS + tile * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+                for (plane, mask) in [(0, 0x01), (1, 0x02)] {
+                    if let Some(byte) = self.ram.get_mut(address + plane) {
+                        if shade & mask != 0 {
+                            *byte |= bit;
+                        } else {
+                            *byte &= !bit;
+                        }
+                    }
+                }
+            }
+        }
+        self.registers[0] &= !0x01;
+    }
+}
+
+impl Snapshot for PocketCamera {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ram_enabled);
+        writer.u8(self.rom_bank);
+        writer.u8(self.ram_bank);
+        writer.bytes(&self.registers);
+        writer.u32(self.capture);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = reader.bool()?;
+        self.rom_bank = reader.u8()? & 0x3F;
+        self.ram_bank = reader.u8()? & 0x1F;
+        reader.bytes_into(&mut self.registers, "camera registers")?;
+        self.capture = reader.u32()?;
+        Ok(())
+    }
+}
+
+impl Mbc for PocketCamera {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => self.ram_bank as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x1F,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.registers_mapped() {
+            return match address & 0x7F {
+                0x00 => self.registers[0] & 0x07,
+                _ => 0x00,
+            };
+        }
+        // The RAM cannot be read while the sensor writes to it.
+        if self.capture > 0 {
+            return 0x00;
+        }
+        match ram_index(&self.ram, self.ram_bank as usize, address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.registers_mapped() {
+            let register = usize::from(address & 0x7F);
+            match register {
+                0x00 => {
+                    self.registers[0] = value & 0x07;
+                    // Writing 0 cancels the capture in progress.
+                    self.capture = match value & 0x01 {
+                        0 => 0,
+                        _ if self.capture > 0 => self.capture,
+                        _ => self.capture_cycles(),
+                    };
+                }
+                0x01..REGISTERS => self.registers[register] = value,
+                _ => {}
+            }
+            return;
+        }
+        if let Some(index) = ram_index(&self.ram, self.ram_bank as usize, address) {
+            if self.ram_enabled {
+                self.ram[index] = value;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if self.capture == 0 {
+            return;
+        }
+        self.capture = self.capture.saturating_sub(cycles);
+        if self.capture == 0 {
+            self.finish_capture();
+        }
+    }
+
+    fn set_sensor_image(&mut self, image: &[u8]) {
+        self.image.copy_from_slice(image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    /// A camera with its registers mapped and every pixel of the sensor at `brightness`.
+    fn camera(brightness: u8) -> PocketCamera {
+        let mut camera = PocketCamera::new(test_rom(0xFC, 64, 0x04), vec![0; 0x20000]);
+        camera.set_sensor_image(&[brightness; SENSOR_WIDTH * SENSOR_HEIGHT]);
+        camera.write_rom(0x0000, 0x0A);
+        camera.write_rom(0x4000, 0x10);
+        camera
+    }
+
+    fn capture(camera: &mut PocketCamera) {
+        camera.write_ram(0xA000, 0x01);
+        assert_eq!(camera.read_ram(0xA000), 0x01);
+        let cycles = camera.capture_cycles();
+        camera.tick(cycles - 1);
+        assert_eq!(camera.read_ram(0xA080), 0x01);
+        camera.tick(1);
+        assert_eq!(camera.read_ram(0xA000), 0x00);
+        camera.write_rom(0x4000, 0x00);
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut camera = camera(0x80);
+        camera.write_ram(0xA002, 0x12);
+        camera.write_ram(0xA003, 0x34);
+        assert_eq!(camera.exposure(), 0x1234);
+        assert_eq!(camera.read_ram(0xA002), 0x00);
+        assert_eq!(camera.capture_cycles(), 32446 + 512 + 16 * 0x1234);
+        assert_eq!(camera.bank(0xA000), 0x10);
+
+        // RAM reads 0 during a capture, and writing 0 to A000 cancels it.
+        camera.write_ram(0xA000, 0x01);
+        camera.write_rom(0x4000, 0x00);
+        assert_eq!(camera.read_ram(0xA100), 0x00);
+        camera.write_rom(0x4000, 0x10);
+        camera.write_ram(0xA000, 0x00);
+        camera.tick(u32::MAX);
+        assert_eq!(camera.capture, 0);
+    }
+
+    #[test]
+    fn test_capture_applies_exposure_and_dithering() {
+        let mut camera = camera(0x80);
+        camera.write_ram(0xA002, 0x10); // Exposure 0x1000 keeps the brightness
+        for position in 0..16 {
+            // Thresholds 0x40, 0x80 and 0xC0: 0x80 is light grey.
+            for (level, threshold) in [0x40, 0x80, 0xC0].into_iter().enumerate() {
+                camera.write_ram(0xA006 + position * 3 + level as u16, threshold);
+            }
+        }
+        capture(&mut camera);
+        // Shade 1: low plane set, high plane clear.
+        assert_eq!(camera.read_ram(0xA100), 0xFF);
+        assert_eq!(camera.read_ram(0xA101), 0x00);
+        assert_eq!(camera.read_ram(0xAEFE), 0xFF);
+        assert_eq!(camera.read_ram(0xAEFF), 0x00);
+        assert_eq!(camera.read_ram(0xAF00), 0x00);
+
+        // Half the exposure darkens 0x80 to 0x40, dark grey.
+        camera.write_rom(0x4000, 0x10);
+        camera.write_ram(0xA002, 0x08);
+        capture(&mut camera);
+        assert_eq!(camera.read_ram(0xA100), 0x00);
+        assert_eq!(camera.read_ram(0xA101), 0xFF);
+    }
+
+    #[test]
+    fn test_dithering_matrix_positions() {
+        let mut camera = camera(0x80);
+        camera.write_ram(0xA002, 0x10);
+        // Only the first pixel of each 4x4 block has thresholds above the brightness.
+        for level in 0..3 {
+            camera.write_ram(0xA006 + level, 0xFF);
+        }
+        capture(&mut camera);
+        // Pixels 0 and 4 of the first row are black, the others white.
+        assert_eq!(camera.read_ram(0xA100), 0x88);
+        assert_eq!(camera.read_ram(0xA101), 0x88);
+        assert_eq!(camera.read_ram(0xA102), 0x00);
+    }
+}
@@ -0,0 +1,163 @@
+// HuC1 (Hudson): up to 1MB ROM, 32KB RAM and an infrared LED and receiver.
+// 0000-1FFF   0x0E maps the IR register at A000-BFFF, other values map the RAM
+// 2000-3FFF   ROM bank number, 6 bits (0 is mapped as 1)
+// 4000-5FFF   RAM bank number (0x00-0x03)
+//
+// IR register, through the whole of A000-BFFF:
+// Read        0xC1 while the receiver sees light, 0xC0 otherwise
+// Write       Bit 0: turn the LED on
+//
+// There is no RAM enable: the RAM is always mapped unless the IR register is.
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::{
+    infrared::IrPeer,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub struct Huc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ir_mapped: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    led: bool,
+    peer: Option<Box<dyn IrPeer>>,
+}
+
+impl Huc1 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        Huc1 {
+            rom,
+            ram,
+            ir_mapped: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            led: false,
+            peer: None,
+        }
+    }
+}
+
+impl Snapshot for Huc1 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ir_mapped);
+        writer.u8(self.rom_bank);
+        writer.u8(self.ram_bank);
+        writer.bool(self.led);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ir_mapped = reader.bool()?;
+        self.rom_bank = (reader.u8()? & 0x3F).max(1);
+        self.ram_bank = reader.u8()? & 0x03;
+        self.led = reader.bool()?;
+        if let Some(peer) = &mut self.peer {
+            peer.set_led(self.led);
+        }
+        Ok(())
+    }
+}
+
+impl Mbc for Huc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => self.ram_bank as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mapped = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x3F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ir_mapped {
+            let light = self.peer.as_ref().is_some_and(|peer| peer.receiving());
+            return 0xC0 | u8::from(light);
+        }
+        match ram_index(&self.ram, self.ram_bank as usize, address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ir_mapped {
+            self.led = value & 0x01 != 0;
+            if let Some(peer) = &mut self.peer {
+                peer.set_led(self.led);
+            }
+            return;
+        }
+        if let Some(index) = ram_index(&self.ram, self.ram_bank as usize, address) {
+            self.ram[index] = value;
+        }
+    }
+
+    fn set_ir_peer(&mut self, peer: Option<Box<dyn IrPeer>>) {
+        self.peer = peer;
+        if let Some(peer) = &mut self.peer {
+            peer.set_led(self.led);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cartridge::test_rom, infrared::IrLink};
+
+    #[test]
+    fn test_ram_and_ir_register() {
+        let mut huc1 = Huc1::new(test_rom(0xFF, 64, 0x03), vec![0; 0x8000]);
+        huc1.write_rom(0x4000, 0x02);
+        huc1.write_ram(0xA000, 0x42);
+        assert_eq!(huc1.read_ram(0xA000), 0x42);
+
+        huc1.write_rom(0x0000, 0x0E);
+        assert_eq!(huc1.read_ram(0xA000), 0xC0);
+        huc1.write_ram(0xB000, 0x01);
+        assert!(huc1.led);
+        huc1.write_rom(0x0000, 0x0A);
+        assert_eq!(huc1.read_ram(0xA000), 0x42);
+        assert_eq!(huc1.ram[0x4000], 0x42);
+    }
+
+    #[test]
+    fn test_infrared_between_two_cartridges() {
+        let mut a = Huc1::new(test_rom(0xFF, 64, 0x03), vec![0; 0x8000]);
+        let mut b = Huc1::new(test_rom(0xFF, 64, 0x03), vec![0; 0x8000]);
+        let (link_a, link_b) = IrLink::pair();
+        a.set_ir_peer(Some(Box::new(link_a)));
+        b.set_ir_peer(Some(Box::new(link_b)));
+        a.write_rom(0x0000, 0x0E);
+        b.write_rom(0x0000, 0x0E);
+
+        a.write_ram(0xA000, 0x01);
+        assert_eq!(b.read_ram(0xA000), 0xC1);
+        assert_eq!(a.read_ram(0xA000), 0xC0);
+        a.write_ram(0xA000, 0x00);
+        assert_eq!(b.read_ram(0xA000), 0xC0);
+    }
+}
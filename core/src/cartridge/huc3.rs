@@ -0,0 +1,344 @@
+// HuC3 (Hudson): up to 2MB ROM, 32KB RAM, a clock chip with a tone generator and an infrared
+// LED and receiver.
+// 0000-1FFF   Maps A000-BFFF: 0x00 RAM read only, 0x0A RAM, 0x0B command, 0x0C result,
+//             0x0D semaphore, 0x0E IR register; nothing for other values
+// 2000-3FFF   ROM bank number, 7 bits (0 is mapped as 1)
+// 4000-5FFF   RAM bank number (0x00-0x03)
+//
+// The clock chip is driven by commands: write bits 4-6 command and bits 0-3 argument in
+// command mode, then write the semaphore with bit 0 clear to run it. The semaphore reads
+// bit 0 set when done (always, commands take no time), the result reads bits 4-6 command
+// and bits 0-3 result.
+// 1           Read the nibble at the access address into the result, then increment it
+// 3           Write the argument at the access address, then increment it
+// 4, 5        Set bits 0-3 or 4-7 of the access address
+// 6           0: copy the clock to nibbles 0x00-0x05 (minutes of the day, then days, 12
+//             bits each, low nibble first), 1: set the clock from them, 2: result 1,
+//             E: play the tone selected at nibble 0x26 (only recorded, there is no sound)
+//
+// IR register: reads 0xC1 while the receiver sees light and 0xC0 otherwise, writing bit 0
+// turns the LED on.
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::{
+    infrared::IrPeer,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+/// M-cycles in one minute, the resolution of the clock.
+const CYCLES_PER_MINUTE: u32 = 60 << 20;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+const TONE: usize = 0x26;
+
+pub struct Huc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mode: u8,
+    rom_bank: u8,
+    ram_bank: u8,
+    /// Command and argument written last, in the format of the command register.
+    command: u8,
+    result: u8,
+    address: u8,
+    /// Nibbles of the clock chip memory.
+    memory: [u8; 0x100],
+    /// Minutes into the day, and days counted (12 bits).
+    minutes: u16,
+    days: u16,
+    /// M-cycles into the current minute.
+    cycles: u32,
+    tone: Option<u8>,
+    led: bool,
+    peer: Option<Box<dyn IrPeer>>,
+}
+
+impl Huc3 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        Huc3 {
+            rom,
+            ram,
+            mode: 0,
+            rom_bank: 1,
+            ram_bank: 0,
+            command: 0,
+            result: 0,
+            address: 0,
+            memory: [0; 0x100],
+            minutes: 0,
+            days: 0,
+            cycles: 0,
+            tone: None,
+            led: false,
+            peer: None,
+        }
+    }
+
+    fn execute(&mut self) {
+        let argument = self.command & 0x0F;
+        match self.command >> 4 & 0x07 {
+            0x1 => {
+                self.result = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | argument << 4,
+            0x6 => match argument {
+                0x0 => {
+                    for (i, value) in [self.minutes, self.days].into_iter().enumerate() {
+                        for nibble in 0..3 {
+                            self.memory[i * 3 + nibble] = (value >> (nibble * 4)) as u8 & 0x0F;
+                        }
+                    }
+                }
+                0x1 => {
+                    let value = |start: usize| {
+                        (0..3).fold(0, |value, nibble| {
+                            value | u16::from(self.memory[start + nibble]) << (nibble * 4)
+                        })
+                    };
+                    self.minutes = value(0) % MINUTES_PER_DAY;
+                    self.days = value(3);
+                    self.cycles = 0;
+                }
+                0x2 => self.result = 0x1,
+                0xE => self.tone = Some(self.memory[TONE]),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn set_led(&mut self, on: bool) {
+        self.led = on;
+        if let Some(peer) = &mut self.peer {
+            peer.set_led(on);
+        }
+    }
+}
+
+impl Snapshot for Huc3 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.u8(self.mode);
+        writer.u8(self.rom_bank);
+        writer.u8(self.ram_bank);
+        writer.u8(self.command);
+        writer.u8(self.result);
+        writer.u8(self.address);
+        writer.bytes(&self.memory);
+        writer.u16(self.minutes);
+        writer.u16(self.days);
+        writer.u32(self.cycles);
+        writer.bool(self.tone.is_some());
+        writer.u8(self.tone.unwrap_or(0));
+        writer.bool(self.led);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.mode = reader.u8()? & 0x0F;
+        self.rom_bank = (reader.u8()? & 0x7F).max(1);
+        self.ram_bank = reader.u8()? & 0x03;
+        self.command = reader.u8()? & 0x7F;
+        self.result = reader.u8()? & 0x0F;
+        self.address = reader.u8()?;
+        reader.bytes_into(&mut self.memory, "HuC3 memory size")?;
+        self.minutes = reader.u16()? % MINUTES_PER_DAY;
+        self.days = reader.u16()? & 0x0FFF;
+        self.cycles = reader.u32()? % CYCLES_PER_MINUTE;
+        let playing = reader.bool()?;
+        self.tone = Some(reader.u8()?).filter(|_| playing);
+        let led = reader.bool()?;
+        self.set_led(led);
+        Ok(())
+    }
+}
+
+impl Mbc for Huc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => self.ram_bank as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.mode {
+            0x00 | 0x0A => match ram_index(&self.ram, self.ram_bank as usize, address) {
+                Some(index) => self.ram[index],
+                None => 0xFF,
+            },
+            0x0C => 0x80 | (self.command & 0x70) | self.result,
+            0x0D => 0xFF,
+            0x0E => {
+                let light = self.peer.as_ref().is_some_and(|peer| peer.receiving());
+                0xC0 | u8::from(light)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        match self.mode {
+            0x0A => {
+                if let Some(index) = ram_index(&self.ram, self.ram_bank as usize, address) {
+                    self.ram[index] = value;
+                }
+            }
+            0x0B => self.command = value & 0x7F,
+            0x0D if value & 0x01 == 0 => self.execute(),
+            0x0E => self.set_led(value & 0x01 != 0),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_MINUTE {
+            self.cycles -= CYCLES_PER_MINUTE;
+            self.minutes += 1;
+            if self.minutes == MINUTES_PER_DAY {
+                self.minutes = 0;
+                self.days = (self.days + 1) & 0x0FFF;
+            }
+        }
+    }
+
+    fn clock(&self) -> Vec<u8> {
+        // Minutes and days, little endian, then the memory of the clock chip.
+        let mut clock = Vec::with_capacity(4 + self.memory.len());
+        clock.extend(self.minutes.to_le_bytes());
+        clock.extend(self.days.to_le_bytes());
+        clock.extend(self.memory);
+        clock
+    }
+
+    fn set_clock(&mut self, clock: &[u8]) {
+        self.minutes = u16::from_le_bytes([clock[0], clock[1]]) % MINUTES_PER_DAY;
+        self.days = u16::from_le_bytes([clock[2], clock[3]]) & 0x0FFF;
+        self.cycles = 0;
+        for (nibble, &value) in self.memory.iter_mut().zip(&clock[4..]) {
+            *nibble = value & 0x0F;
+        }
+    }
+
+    fn set_ir_peer(&mut self, peer: Option<Box<dyn IrPeer>>) {
+        self.peer = peer;
+        self.set_led(self.led);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cartridge::test_rom, infrared::IrLink};
+
+    fn huc3() -> Huc3 {
+        Huc3::new(test_rom(0xFE, 128, 0x03), vec![0; 0x8000])
+    }
+
+    /// Run `command` with `argument` and return the result register.
+    fn command(huc3: &mut Huc3, command: u8, argument: u8) -> u8 {
+        huc3.write_rom(0x0000, 0x0B);
+        huc3.write_ram(0xA000, command << 4 | argument);
+        huc3.write_rom(0x0000, 0x0D);
+        huc3.write_ram(0xA000, 0xFE);
+        assert_eq!(huc3.read_ram(0xA000) & 0x01, 0x01);
+        huc3.write_rom(0x0000, 0x0C);
+        huc3.read_ram(0xA000)
+    }
+
+    #[test]
+    fn test_ram_modes() {
+        let mut huc3 = huc3();
+        huc3.write_rom(0x0000, 0x0A);
+        huc3.write_ram(0xA000, 0x42);
+        huc3.write_rom(0x0000, 0x00);
+        huc3.write_ram(0xA000, 0x43);
+        assert_eq!(huc3.read_ram(0xA000), 0x42);
+        huc3.write_rom(0x0000, 0x05);
+        assert_eq!(huc3.read_ram(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_memory_commands() {
+        let mut huc3 = huc3();
+        command(&mut huc3, 0x4, 0x0);
+        command(&mut huc3, 0x5, 0x2);
+        command(&mut huc3, 0x3, 0x7);
+        command(&mut huc3, 0x3, 0x3);
+        assert_eq!(huc3.address, 0x22);
+        assert_eq!(huc3.memory[0x20..0x22], [0x7, 0x3]);
+
+        command(&mut huc3, 0x4, 0x1);
+        assert_eq!(command(&mut huc3, 0x1, 0x0), 0x80 | 0x10 | 0x3);
+        assert_eq!(command(&mut huc3, 0x6, 0x2), 0x80 | 0x60 | 0x1);
+
+        // Tone 5 selected at 0x26, then played.
+        command(&mut huc3, 0x4, 0x6);
+        command(&mut huc3, 0x3, 0x5);
+        command(&mut huc3, 0x6, 0xE);
+        assert_eq!(huc3.tone, Some(5));
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut huc3 = huc3();
+        for _ in 0..MINUTES_PER_DAY + 0x123 {
+            huc3.tick(CYCLES_PER_MINUTE);
+        }
+        command(&mut huc3, 0x6, 0x0);
+        assert_eq!(huc3.memory[..6], [0x3, 0x2, 0x1, 0x1, 0x0, 0x0]);
+
+        // Set 2 days and 1 minute.
+        huc3.memory[..6].copy_from_slice(&[0x1, 0x0, 0x0, 0x2, 0x0, 0x0]);
+        command(&mut huc3, 0x6, 0x1);
+        assert_eq!((huc3.minutes, huc3.days), (1, 2));
+
+        let mut other = Huc3::new(test_rom(0xFE, 128, 0x03), vec![0; 0x8000]);
+        other.set_clock(&huc3.clock());
+        assert_eq!((other.minutes, other.days), (1, 2));
+        assert_eq!(other.memory, huc3.memory);
+    }
+
+    #[test]
+    fn test_infrared_between_two_cartridges() {
+        let (mut a, mut b) = (huc3(), huc3());
+        let (link_a, link_b) = IrLink::pair();
+        a.set_ir_peer(Some(Box::new(link_a)));
+        b.set_ir_peer(Some(Box::new(link_b)));
+        a.write_rom(0x0000, 0x0E);
+        b.write_rom(0x0000, 0x0E);
+
+        b.write_ram(0xA000, 0x01);
+        assert_eq!(a.read_ram(0xA000), 0xC1);
+        assert_eq!(b.read_ram(0xA000), 0xC0);
+    }
+}
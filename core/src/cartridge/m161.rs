@@ -0,0 +1,88 @@
+// M161: unlicensed multicart mapper switching the whole of 0000-7FFF in 32KB banks, no RAM.
+// 4000-5FFF   Bits 0-2 select the 32KB bank. Only the first write counts: the menu picks a
+//             game and the mapper stays locked on it until power off
+
+use super::{rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct M161 {
+    rom: Vec<u8>,
+    bank: u8,
+    locked: bool,
+}
+
+impl M161 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        M161 {
+            rom,
+            bank: 0,
+            locked: false,
+        }
+    }
+}
+
+impl Snapshot for M161 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.bank);
+        writer.bool(self.locked);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.bank = reader.u8()? & 0x07;
+        self.locked = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Mbc for M161 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => self.bank as usize * 2,
+            0x4000..=0x7FFF => self.bank as usize * 2 + 1,
+            _ => 0,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if (0x4000..=0x5FFF).contains(&address) && !self.locked {
+            self.bank = value & 0x07;
+            self.locked = true;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_first_write_locks() {
+        let mut mbc = M161::new(test_rom(0x10, 16, 0));
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(mbc.read_rom(0x0000), 6);
+        assert_eq!(mbc.read_rom(0x4000), 7);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 7);
+    }
+}
@@ -0,0 +1,183 @@
+// MBC1: up to 2MB ROM and 32KB RAM.
+// 0000-1FFF   RAM enable (0x0A enables)
+// 2000-3FFF   ROM bank number, lower 5 bits (0 is mapped as 1)
+// 4000-5FFF   RAM bank number or upper 2 bits of the ROM bank number
+// 6000-7FFF   Banking mode: 0 = simple, 1 = upper bits also apply to 0000-3FFF and RAM
+//
+// MBC1M multicarts wire the upper bits one line lower, as bits 4-5 of the ROM bank: each of
+// their 4 games has 256KB, and only 4 bits of the lower register are used.
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    bank1: u8,
+    bank2: u8,
+    mode: u8,
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        Mbc1 {
+            rom,
+            ram,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: 0,
+            multicart: false,
+        }
+    }
+
+    /// An MBC1M multicart.
+    pub fn multicart(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        Mbc1 {
+            multicart: true,
+            ..Mbc1::new(rom, ram)
+        }
+    }
+
+    /// Upper ROM bank bits, from the second register.
+    fn upper_bank(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        (self.bank2 as usize) << shift
+    }
+
+    fn lower_bank(&self) -> usize {
+        let mask = if self.multicart { 0x0F } else { 0x1F };
+        (self.bank1 & mask) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode == 1 {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Snapshot for Mbc1 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ram_enabled);
+        writer.u8(self.bank1);
+        writer.u8(self.bank2);
+        writer.u8(self.mode);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = reader.bool()?;
+        self.bank1 = (reader.u8()? & 0x1F).max(1);
+        self.bank2 = reader.u8()? & 0x03;
+        self.mode = reader.u8()? & 0x01;
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01,
+        }
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF if self.mode == 1 => self.upper_bank(),
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.upper_bank() | self.lower_bank(),
+            _ => self.ram_bank(),
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match ram_index(&self.ram, self.ram_bank(), address) {
+            Some(index) if self.ram_enabled => self.ram[index],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = ram_index(&self.ram, self.ram_bank(), address) {
+            if self.ram_enabled {
+                self.ram[index] = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc1::new(test_rom(0x01, 64, 0), vec![]);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+
+        mbc.write_rom(0x2000, 0x00); // bank 0 is mapped as 1
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x7FFF), 5);
+
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x25);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_multicart_banking() {
+        let mut mbc = Mbc1::multicart(test_rom(0x01, 64, 0), vec![]);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x2000, 0x13);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x20);
+        // Bank 0x10 reads as bank 0 of the game, it is not mapped as 1.
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(0x4000), 0x20);
+    }
+
+    #[test]
+    fn test_ram_enable_and_banking() {
+        let mut mbc = Mbc1::new(test_rom(0x03, 4, 0x03), vec![0; 0x8000]);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_ram(0xA000, 0x34);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+    }
+}
@@ -0,0 +1,107 @@
+// MBC2: up to 256KB ROM and 512x4 bits of built-in RAM.
+// 0000-3FFF   Address bit 8 clear: RAM enable (0x0A enables)
+//             Address bit 8 set: ROM bank number, 4 bits (0 is mapped as 1)
+// A000-BFFF   512 half-bytes of RAM, repeated through the area
+
+use super::{rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    ram: [u8; 0x200],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc2 {
+            rom,
+            ram: [0; 0x200],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Snapshot for Mbc2 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ram_enabled);
+        writer.u8(self.rom_bank);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = reader.bool()?;
+        self.rom_bank = (reader.u8()? & 0x0F).max(1);
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x3FFF if address & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => self.rom_bank = (value & 0x0F).max(1),
+            _ => {}
+        }
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => 0,
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ram_enabled {
+            0xF0 | self.ram[(address & 0x01FF) as usize]
+        } else {
+            0xFF
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            self.ram[(address & 0x01FF) as usize] = value & 0x0F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_half_byte_ram() {
+        let mut mbc = Mbc2::new(test_rom(0x06, 16, 0));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA001, 0xAB);
+        assert_eq!(mbc.read_ram(0xA001), 0xFB);
+        assert_eq!(mbc.read_ram(0xA201), 0xFB);
+    }
+
+    #[test]
+    fn test_rom_bank_uses_address_bit_8() {
+        let mut mbc = Mbc2::new(test_rom(0x05, 16, 0));
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2100, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 3);
+    }
+}
@@ -0,0 +1,346 @@
+// MBC3: up to 2MB ROM, 32KB RAM and an optional real time clock (RTC).
+// 0000-1FFF   RAM and RTC enable (0x0A enables)
+// 2000-3FFF   ROM bank number, 7 bits (0 is mapped as 1)
+// 4000-5FFF   RAM bank number (0x00-0x03) or RTC register (0x08-0x0C)
+// 6000-7FFF   Writing 0x00 then 0x01 latches the clock into the RTC registers
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// M-cycles in one second.
+const CYCLES_PER_SECOND: u32 = 1 << 20;
+
+/// Real time clock counting emulated time.
+#[derive(Clone, Copy, Default)]
+pub struct Rtc {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days: u16, // 9 bits
+    pub halted: bool,
+    pub day_carry: bool,
+    /// M-cycles into the current second.
+    pub cycles: u32,
+}
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    has_rtc: bool,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    rtc: Rtc,
+    latched: Rtc,
+    latch: u8,
+}
+
+impl Rtc {
+    fn tick(&mut self, cycles: u32) {
+        if self.halted {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    fn advance_second(&mut self) {
+        // Registers count up to their width, values out of range wrap on overflow.
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            _ => {
+                ((self.days >> 8) as u8 & 0x01)
+                    | if self.halted { 0x40 } else { 0x00 }
+                    | if self.day_carry { 0x80 } else { 0x00 }
+            }
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+        }
+    }
+}
+
+impl Snapshot for Rtc {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.seconds);
+        writer.u8(self.minutes);
+        writer.u8(self.hours);
+        writer.u16(self.days);
+        writer.bool(self.halted);
+        writer.bool(self.day_carry);
+        writer.u32(self.cycles);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.seconds = reader.u8()? & 0x3F;
+        self.minutes = reader.u8()? & 0x3F;
+        self.hours = reader.u8()? & 0x1F;
+        self.days = reader.u16()? & 0x1FF;
+        self.halted = reader.bool()?;
+        self.day_carry = reader.bool()?;
+        self.cycles = reader.u32()? % CYCLES_PER_SECOND;
+        Ok(())
+    }
+}
+
+impl Snapshot for Mbc3 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ram_enabled);
+        writer.u8(self.rom_bank);
+        writer.u8(self.ram_bank);
+        writer.u8(self.latch);
+        self.rtc.save(writer);
+        self.latched.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = reader.bool()?;
+        self.rom_bank = (reader.u8()? & 0x7F).max(1);
+        self.ram_bank = reader.u8()? & 0x0F;
+        self.latch = reader.u8()?;
+        self.rtc.load(reader)?;
+        self.latched.load(reader)
+    }
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>, has_rtc: bool) -> Self {
+        Mbc3 {
+            rom,
+            ram,
+            has_rtc,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc: Rtc::default(),
+            latched: Rtc::default(),
+            latch: 0xFF,
+        }
+    }
+
+    fn rtc_selected(&self) -> bool {
+        self.has_rtc && (0x08..=0x0C).contains(&self.ram_bank)
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => self.ram_bank as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if self.latch == 0x00 && value == 0x01 {
+                    self.latched = self.rtc;
+                }
+                self.latch = value;
+            }
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        if self.rtc_selected() {
+            return self.latched.read(self.ram_bank);
+        }
+        match ram_index(&self.ram, (self.ram_bank & 0x03) as usize, address) {
+            Some(index) if self.ram_bank <= 0x03 => self.ram[index],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if self.rtc_selected() {
+            self.rtc.write(self.ram_bank, value);
+            self.latched.write(self.ram_bank, value);
+            return;
+        }
+        if let Some(index) = ram_index(&self.ram, (self.ram_bank & 0x03) as usize, address) {
+            if self.ram_bank <= 0x03 {
+                self.ram[index] = value;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if self.has_rtc {
+            self.rtc.tick(cycles);
+        }
+    }
+
+    fn clock(&self) -> Vec<u8> {
+        if !self.has_rtc {
+            return Vec::new();
+        }
+        // Registers 0x08-0x0C of the clock, then of the latched clock.
+        [&self.rtc, &self.latched]
+            .into_iter()
+            .flat_map(|rtc| (0x08..=0x0C).map(|register| rtc.read(register)))
+            .collect()
+    }
+
+    fn set_clock(&mut self, clock: &[u8]) {
+        for (rtc, values) in [&mut self.rtc, &mut self.latched]
+            .into_iter()
+            .zip(clock.chunks(5))
+        {
+            for (register, &value) in (0x08..).zip(values) {
+                rtc.write(register, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_rom_banking() {
+        let mut mbc = Mbc3::new(test_rom(0x11, 128, 0), vec![], false);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(0x4000), 0x7F);
+    }
+
+    #[test]
+    fn test_rtc_latch() {
+        let mut mbc = Mbc3::new(test_rom(0x10, 4, 0x03), vec![0; 0x8000], true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+
+        mbc.tick(CYCLES_PER_SECOND * 61);
+        assert_eq!(mbc.read_ram(0xA000), 0);
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 1);
+        mbc.write_rom(0x4000, 0x09);
+        assert_eq!(mbc.read_ram(0xA000), 1);
+    }
+
+    #[test]
+    fn test_clock_in_save_file() {
+        let mut mbc = Mbc3::new(test_rom(0x10, 4, 0x03), vec![0; 0x8000], true);
+        mbc.tick(CYCLES_PER_SECOND * 3661);
+        let clock = mbc.clock();
+        assert_eq!(clock, [1, 1, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut other = Mbc3::new(test_rom(0x10, 4, 0x03), vec![0; 0x8000], true);
+        other.set_clock(&clock);
+        assert_eq!(other.rtc.hours, 1);
+        assert!(Mbc3::new(test_rom(0x13, 4, 0x03), vec![], false)
+            .clock()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rtc_halt_and_day_carry() {
+        let mut rtc = Rtc {
+            hours: 23,
+            minutes: 59,
+            seconds: 59,
+            days: 0x1FF,
+            ..Rtc::default()
+        };
+        rtc.tick(CYCLES_PER_SECOND);
+        assert_eq!(rtc.days, 0);
+        assert!(rtc.day_carry);
+        assert_eq!(rtc.read(0x0C), 0x80);
+
+        rtc.write(0x0C, 0x40);
+        rtc.tick(CYCLES_PER_SECOND * 10);
+        assert_eq!(rtc.seconds, 0);
+    }
+
+    #[test]
+    fn test_snapshot_keeps_rtc() {
+        let mut mbc = Mbc3::new(test_rom(0x10, 4, 0x03), vec![0; 0x8000], true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x42);
+        mbc.tick(CYCLES_PER_SECOND * 3 + 5);
+
+        let mut writer = StateWriter::new(0);
+        mbc.save(&mut writer);
+        let data = writer.finish();
+        let (_, mut reader) = StateReader::new(&data).unwrap();
+        let mut other = Mbc3::new(test_rom(0x10, 4, 0x03), vec![0; 0x8000], true);
+        other.load(&mut reader).unwrap();
+
+        assert_eq!(other.read_ram(0xA000), 0x42);
+        assert_eq!(other.rtc.seconds, 3);
+        assert_eq!(other.rtc.cycles, 5);
+    }
+}
@@ -0,0 +1,112 @@
+// MBC5: up to 8MB ROM and 128KB RAM.
+// 0000-1FFF   RAM enable (0x0A enables)
+// 2000-2FFF   ROM bank number, lower 8 bits (bank 0 can be mapped)
+// 3000-3FFF   ROM bank number, bit 8
+// 4000-5FFF   RAM bank number (0x00-0x0F)
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        Mbc5 {
+            rom,
+            ram,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Snapshot for Mbc5 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+        writer.bool(self.ram_enabled);
+        writer.u16(self.rom_bank);
+        writer.u8(self.ram_bank);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")?;
+        self.ram_enabled = reader.bool()?;
+        self.rom_bank = reader.u16()? & 0x1FF;
+        self.ram_bank = reader.u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => self.ram_bank as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match ram_index(&self.ram, self.ram_bank as usize, address) {
+            Some(index) if self.ram_enabled => self.ram[index],
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = ram_index(&self.ram, self.ram_bank as usize, address) {
+            if self.ram_enabled {
+                self.ram[index] = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_nine_bit_rom_bank() {
+        let mut rom = test_rom(0x19, 512, 0);
+        rom[0x100 * 0x4000 + 0x0010] = 0xAB;
+        let mut mbc = Mbc5::new(rom, vec![]);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(0x4010), 0xAB);
+    }
+}
@@ -0,0 +1,418 @@
+// MBC7: up to 2MB ROM, a 2-axis accelerometer and a 93LC56 serial EEPROM of 128 16-bit words.
+// 0000-1FFF   RAM enable 1 (0x0A enables)
+// 2000-3FFF   ROM bank number, 7 bits (bank 0 can be mapped)
+// 4000-5FFF   RAM enable 2 (0x40 enables), both are needed to map the registers
+//
+// Registers, selected by bits 4-7 of the address in A000-AFFF (B000-BFFF reads 0xFF):
+// Ax0x   Write 0x55 to erase the latched accelerometer values (they read 0x8000)
+// Ax1x   Write 0xAA to latch the accelerometer, once after each erase
+// Ax2x   Latched X, low byte; Ax3x: high byte. 0x81D0 level, 0x70 more or less per g
+// Ax4x   Latched Y, low byte; Ax5x: high byte
+// Ax6x   Reads 0x00
+// Ax7x   Reads 0xFF
+// Ax8x   EEPROM pins: bit 7 CS, bit 6 CLK, bit 1 DI (written), bit 0 DO (read)
+//
+// The EEPROM is bit-banged: with CS high, DI is clocked in on each rising edge of CLK. A
+// command is a 1 start bit, 2 opcode bits and 8 address bits (the top one ignored), followed
+// by 16 data bits, most significant first, for the writes:
+// 10 READ    DO shifts out a dummy 0 then the word, one bit per rising edge
+// 01 WRITE   11 ERASE (word to 0xFFFF)
+// 00 with address bits 7-6: 11 EWEN (enable writes), 00 EWDS (disable writes),
+//    10 ERAL (erase all), 01 WRAL (write the word to all)
+// Writes and erases only work after EWEN, and finish at once: DO reads 1 (ready).
+// https://gbdev.io/pandocs/MBC7.html
+
+use super::{rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// Bytes of the EEPROM, the words are stored little endian in save files.
+const EEPROM_SIZE: usize = 0x100;
+
+/// Latched accelerometer values when level, and their change for 1 g.
+const LEVEL: u16 = 0x81D0;
+const PER_G: f32 = 112.0;
+
+const CS: u8 = 0x80;
+const CLK: u8 = 0x40;
+const DI: u8 = 0x02;
+
+/// Bits of a command before its data: opcode and address.
+const COMMAND_BITS: u8 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EepromState {
+    /// Waiting for the start bit.
+    Idle,
+    /// Shifting in the command (and its data), counts the bits received.
+    Command(u8),
+    /// Shifting out the word read, counts the bits left including the dummy 0.
+    Read(u8),
+}
+
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    ram_enabled: [bool; 2],
+    rom_bank: u8,
+    /// Accelerometer in g, x to the right and y towards the bottom of the screen.
+    acceleration: (f32, f32),
+    latched: (u16, u16),
+    erased: bool,
+    eeprom: [u8; EEPROM_SIZE],
+    /// CS, CLK and DI as last written.
+    pins: u8,
+    data_out: bool,
+    state: EepromState,
+    /// Bits shifted in, or the word shifted out.
+    shift: u32,
+    write_enabled: bool,
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc7 {
+            rom,
+            ram_enabled: [false; 2],
+            rom_bank: 1,
+            acceleration: (0.0, 0.0),
+            latched: (0x8000, 0x8000),
+            erased: false,
+            eeprom: [0xFF; EEPROM_SIZE],
+            pins: 0,
+            data_out: true,
+            state: EepromState::Idle,
+            shift: 0,
+            write_enabled: false,
+        }
+    }
+
+    fn registers_mapped(&self) -> bool {
+        self.ram_enabled == [true; 2]
+    }
+
+    fn word(&self, address: u32) -> u16 {
+        let index = (address as usize & 0x7F) * 2;
+        u16::from_le_bytes([self.eeprom[index], self.eeprom[index + 1]])
+    }
+
+    fn set_word(&mut self, address: u32, value: u16) {
+        let index = (address as usize & 0x7F) * 2;
+        self.eeprom[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_pins(&mut self, value: u8) {
+        let previous = self.pins;
+        self.pins = value & (CS | CLK | DI);
+        if self.pins & CS == 0 {
+            self.state = EepromState::Idle;
+            self.data_out = true;
+            return;
+        }
+        if previous & CLK == 0 && self.pins & CLK != 0 {
+            self.clock(self.pins & DI != 0);
+        }
+    }
+
+    /// Rising edge of CLK with CS high.
+    fn clock(&mut self, bit: bool) {
+        match self.state {
+            EepromState::Idle => {
+                if bit {
+                    self.state = EepromState::Command(0);
+                    self.shift = 0;
+                }
+            }
+            EepromState::Command(count) => {
+                self.shift = self.shift << 1 | u32::from(bit);
+                self.state = EepromState::Command(count + 1);
+                self.command(count + 1);
+            }
+            EepromState::Read(left) => {
+                self.data_out = self.shift >> (left - 1) & 1 != 0;
+                self.state = match left {
+                    1 => EepromState::Idle,
+                    _ => EepromState::Read(left - 1),
+                };
+            }
+        }
+    }
+
+    /// Run the command once its `count` bits are in.
+    fn command(&mut self, count: u8) {
+        let data_bits = count.saturating_sub(COMMAND_BITS);
+        if count < COMMAND_BITS || (1..16).contains(&data_bits) {
+            return;
+        }
+        let (opcode, address) = if count == COMMAND_BITS {
+            (self.shift >> 8, self.shift & 0xFF)
+        } else {
+            (self.shift >> 24, self.shift >> 16 & 0xFF)
+        };
+        let data = self.shift as u16;
+        match (opcode, address >> 6) {
+            (0b10, _) => {
+                // The dummy 0 goes out now, then the 16 bits of the word.
+                self.data_out = false;
+                self.shift = u32::from(self.word(address));
+                self.state = EepromState::Read(16);
+                return;
+            }
+            (0b11, _) => {
+                if self.write_enabled {
+                    self.set_word(address, 0xFFFF);
+                }
+            }
+            (0b00, 0b11) => self.write_enabled = true,
+            (0b00, 0b00) => self.write_enabled = false,
+            (0b00, 0b10) => {
+                if self.write_enabled {
+                    self.eeprom = [0xFF; EEPROM_SIZE];
+                }
+            }
+            // WRITE and WRAL wait for their data.
+            (0b01, _) | (0b00, 0b01) if count == COMMAND_BITS => return,
+            (0b01, _) if count == COMMAND_BITS + 16 => {
+                if self.write_enabled {
+                    self.set_word(address, data);
+                }
+            }
+            (0b00, 0b01) if count == COMMAND_BITS + 16 => {
+                if self.write_enabled {
+                    for address in 0..0x80 {
+                        self.set_word(address, data);
+                    }
+                }
+            }
+            _ => return,
+        }
+        self.data_out = true;
+        self.state = EepromState::Idle;
+    }
+
+    fn latch(&mut self) {
+        let axis = |g: f32| (f32::from(LEVEL) + g * PER_G).clamp(0.0, f32::from(u16::MAX)) as u16;
+        self.latched = (axis(self.acceleration.0), axis(self.acceleration.1));
+        self.erased = false;
+    }
+}
+
+impl Snapshot for Mbc7 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.eeprom);
+        writer.bool(self.ram_enabled[0]);
+        writer.bool(self.ram_enabled[1]);
+        writer.u8(self.rom_bank);
+        writer.u16(self.latched.0);
+        writer.u16(self.latched.1);
+        writer.bool(self.erased);
+        writer.u8(self.pins);
+        writer.bool(self.data_out);
+        let (state, count) = match self.state {
+            EepromState::Idle => (0, 0),
+            EepromState::Command(count) => (1, count),
+            EepromState::Read(left) => (2, left),
+        };
+        writer.u8(state);
+        writer.u8(count);
+        writer.u32(self.shift);
+        writer.bool(self.write_enabled);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.eeprom, "EEPROM size")?;
+        self.ram_enabled = [reader.bool()?, reader.bool()?];
+        self.rom_bank = reader.u8()? & 0x7F;
+        self.latched = (reader.u16()?, reader.u16()?);
+        self.erased = reader.bool()?;
+        self.pins = reader.u8()? & (CS | CLK | DI);
+        self.data_out = reader.bool()?;
+        let (state, count) = (reader.u8()?, reader.u8()?);
+        self.state = match (state, count) {
+            (0, _) => EepromState::Idle,
+            (1, 0..=26) => EepromState::Command(count),
+            (2, 1..=16) => EepromState::Read(count),
+            _ => return Err(StateError::Invalid("EEPROM state")),
+        };
+        self.shift = reader.u32()?;
+        self.write_enabled = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Mbc for Mbc7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            0x4000..=0x7FFF => self.rom_bank as usize,
+            _ => 0,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled[0] = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled[1] = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.eeprom
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.eeprom
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_mapped() || address >= 0xB000 {
+            return 0xFF;
+        }
+        match address >> 4 & 0x0F {
+            0x2 => self.latched.0 as u8,
+            0x3 => (self.latched.0 >> 8) as u8,
+            0x4 => self.latched.1 as u8,
+            0x5 => (self.latched.1 >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.pins | u8::from(self.data_out),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_mapped() || address >= 0xB000 {
+            return;
+        }
+        match (address >> 4 & 0x0F, value) {
+            (0x0, 0x55) => {
+                self.latched = (0x8000, 0x8000);
+                self.erased = true;
+            }
+            (0x1, 0xAA) if self.erased => self.latch(),
+            (0x8, _) => self.write_pins(value),
+            _ => {}
+        }
+    }
+
+    fn set_accelerometer(&mut self, x: f32, y: f32) {
+        self.acceleration = (x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    fn mbc7() -> Mbc7 {
+        let mut mbc = Mbc7::new(test_rom(0x22, 64, 0));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x40);
+        mbc
+    }
+
+    /// Send the start bit, `opcode`, `address` and `data` if any, return DO after the last bit.
+    fn command(mbc: &mut Mbc7, opcode: u32, address: u32, data: Option<u16>) -> u32 {
+        let value = (0b100 | opcode) << 8 | address;
+        let output = match data {
+            Some(data) => send(mbc, value << 16 | u32::from(data), 27),
+            None => send(mbc, value, 11),
+        };
+        output & 1
+    }
+
+    /// Clock `bits` of `value` into the EEPROM, most significant first, and return DO after
+    /// each rising edge.
+    fn send(mbc: &mut Mbc7, value: u32, bits: u8) -> u32 {
+        let mut output = 0;
+        for bit in (0..bits).rev() {
+            let di = if value >> bit & 1 != 0 { DI } else { 0 };
+            mbc.write_ram(0xA080, CS | di);
+            mbc.write_ram(0xA080, CS | CLK | di);
+            output = output << 1 | u32::from(mbc.read_ram(0xA080) & 0x01);
+        }
+        output
+    }
+
+    fn deselect(mbc: &mut Mbc7) {
+        mbc.write_ram(0xA080, 0x00);
+    }
+
+    #[test]
+    fn test_accelerometer_latch() {
+        let mut mbc = mbc7();
+        mbc.set_accelerometer(1.0, -0.5);
+        // Latching without erasing first does nothing.
+        mbc.write_ram(0xA010, 0xAA);
+        assert_eq!(mbc.read_ram(0xA020), 0x00);
+        assert_eq!(mbc.read_ram(0xA030), 0x80);
+
+        mbc.write_ram(0xA000, 0x55);
+        mbc.write_ram(0xA010, 0xAA);
+        let read = |mbc: &Mbc7, low: u16| {
+            u16::from(mbc.read_ram(low)) | u16::from(mbc.read_ram(low + 0x10)) << 8
+        };
+        assert_eq!(read(&mbc, 0xA020), 0x81D0 + 0x70);
+        assert_eq!(read(&mbc, 0xA040), 0x81D0 - 0x38);
+
+        // The values stay latched until the next erase and latch.
+        mbc.set_accelerometer(0.0, 0.0);
+        assert_eq!(read(&mbc, 0xA020), 0x81D0 + 0x70);
+        assert_eq!(mbc.read_ram(0xA060), 0x00);
+        assert_eq!(mbc.read_ram(0xB020), 0xFF);
+
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA020), 0xFF);
+    }
+
+    #[test]
+    fn test_eeprom_write_and_read() {
+        let mut mbc = mbc7();
+        // A write before EWEN is ignored.
+        command(&mut mbc, 0b01, 5, Some(0x1234));
+        deselect(&mut mbc);
+        assert_eq!(mbc.word(5), 0xFFFF);
+
+        command(&mut mbc, 0b00, 0xC0, None); // EWEN
+        deselect(&mut mbc);
+        assert_eq!(command(&mut mbc, 0b01, 5, Some(0x1234)), 1);
+        deselect(&mut mbc);
+        assert_eq!(mbc.word(5), 0x1234);
+        assert_eq!(mbc.eeprom[10..12], [0x34, 0x12]);
+
+        // READ: after the address comes the dummy 0, then the word.
+        assert_eq!(command(&mut mbc, 0b10, 5, None), 0);
+        assert_eq!(send(&mut mbc, 0, 16), 0x1234);
+        deselect(&mut mbc);
+    }
+
+    #[test]
+    fn test_eeprom_erase_and_write_all() {
+        let mut mbc = mbc7();
+        command(&mut mbc, 0b00, 0xC0, None); // EWEN
+        deselect(&mut mbc);
+        command(&mut mbc, 0b00, 0x40, Some(0xABCD)); // WRAL
+        deselect(&mut mbc);
+        assert!((0..0x80).all(|address| mbc.word(address) == 0xABCD));
+
+        command(&mut mbc, 0b11, 3, None);
+        deselect(&mut mbc);
+        assert_eq!(mbc.word(3), 0xFFFF);
+        assert_eq!(mbc.word(4), 0xABCD);
+
+        command(&mut mbc, 0b00, 0x80, None); // ERAL
+        deselect(&mut mbc);
+        assert!(mbc.eeprom.iter().all(|&byte| byte == 0xFF));
+
+        // EWDS protects the EEPROM again.
+        command(&mut mbc, 0b00, 0x00, None);
+        deselect(&mut mbc);
+        command(&mut mbc, 0b01, 0, Some(0x0000));
+        deselect(&mut mbc);
+        assert_eq!(mbc.word(0), 0xFFFF);
+    }
+}
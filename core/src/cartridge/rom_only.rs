@@ -0,0 +1,61 @@
+// 32KB ROM without banking, optionally with up to 8KB of RAM.
+
+use super::{ram_index, rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram: Vec<u8>) -> Self {
+        RomOnly { rom, ram }
+    }
+}
+
+impl Snapshot for RomOnly {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.ram);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.ram, "cartridge RAM size")
+    }
+}
+
+impl Mbc for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x7FFF => (address >> 14) as usize,
+            _ => 0,
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match ram_index(&self.ram, 0, address) {
+            Some(index) => self.ram[index],
+            None => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if let Some(index) = ram_index(&self.ram, 0, address) {
+            self.ram[index] = value;
+        }
+    }
+}
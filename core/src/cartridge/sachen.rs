@@ -0,0 +1,154 @@
+// Sachen MMC1 and MMC2: unlicensed mappers of up to 2MB ROM, used by single games and
+// multicarts.
+// 0000-1FFF   Base ROM bank, written only while the ROM bank has bits 4-5 set
+// 2000-3FFF   ROM bank number, 8 bits (0 is mapped as 1)
+// 4000-5FFF   ROM bank mask, written only while the ROM bank has bits 4-5 set
+// 0000-3FFF maps bank (base & mask), 4000-7FFF maps bank (base & mask) | (bank & !mask).
+//
+// Logo scrambling: the ROM holds the Sachen logo at 0104, and a header with the Nintendo logo
+// for the boot ROM elsewhere. Until the boot ROM is done, reads from 0100-01FF have A7 set
+// and the A0/A6 and A1/A4 lines swapped, which shows the boot ROM that header. The hardware
+// counts A15 edges to know when the boot ROM is done, here it is told. The MMC2 also locks
+// for the second header read of the CGB boot ROM, released at the same time, so both
+// versions work alike.
+
+use super::{rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct Sachen {
+    rom: Vec<u8>,
+    base: u8,
+    mask: u8,
+    rom_bank: u8,
+    locked: bool,
+}
+
+/// ROM address read at `address` of 0100-01FF while the mapper is locked.
+pub fn locked_address(address: u16) -> u16 {
+    let swapped = (address & 0xFFAC)
+        | (address & 0x40) >> 6
+        | (address & 0x10) >> 3
+        | (address & 0x02) << 3
+        | (address & 0x01) << 6;
+    swapped | 0x80
+}
+
+impl Sachen {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Sachen {
+            rom,
+            base: 0,
+            mask: 0,
+            rom_bank: 1,
+            locked: true,
+        }
+    }
+
+    fn registers_writable(&self) -> bool {
+        self.rom_bank & 0x30 == 0x30
+    }
+}
+
+impl Snapshot for Sachen {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.base);
+        writer.u8(self.mask);
+        writer.u8(self.rom_bank);
+        writer.bool(self.locked);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.base = reader.u8()?;
+        self.mask = reader.u8()?;
+        self.rom_bank = reader.u8()?.max(1);
+        self.locked = reader.bool()?;
+        Ok(())
+    }
+}
+
+impl Mbc for Sachen {
+    fn read_rom(&self, address: u16) -> u8 {
+        let address = match address {
+            0x0100..=0x01FF if self.locked => locked_address(address),
+            _ => address,
+        };
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        let outer = self.base & self.mask;
+        match address {
+            0x0000..=0x3FFF => outer as usize,
+            _ => (outer | (self.rom_bank & !self.mask)) as usize,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF if self.registers_writable() => self.base = value,
+            0x2000..=0x3FFF => self.rom_bank = value.max(1),
+            0x4000..=0x5FFF if self.registers_writable() => self.mask = value,
+            _ => {}
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+
+    fn boot_done(&mut self) {
+        self.locked = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_locked_header() {
+        assert_eq!(locked_address(0x0104), 0x0184);
+        assert_eq!(locked_address(0x0101), 0x01C0);
+        assert_eq!(locked_address(0x0110), 0x0182);
+        assert_eq!(locked_address(0x0112), 0x0192);
+
+        let mut rom = test_rom(0x00, 8, 0);
+        rom[0x0104] = 0x11;
+        rom[0x0184] = 0xCE;
+        let mut sachen = Sachen::new(rom);
+        assert_eq!(sachen.read_rom(0x0104), 0xCE);
+        sachen.boot_done();
+        assert_eq!(sachen.read_rom(0x0104), 0x11);
+    }
+
+    #[test]
+    fn test_outer_bank() {
+        let mut sachen = Sachen::new(test_rom(0x00, 64, 0));
+        sachen.write_rom(0x2000, 0x05);
+        // The base and mask cannot be written yet.
+        sachen.write_rom(0x0000, 0x10);
+        assert_eq!(sachen.read_rom(0x4000), 5);
+
+        sachen.write_rom(0x2000, 0x30);
+        sachen.write_rom(0x0000, 0x10);
+        sachen.write_rom(0x4000, 0x30);
+        sachen.write_rom(0x2000, 0x03);
+        assert_eq!(sachen.read_rom(0x0000), 0x10);
+        assert_eq!(sachen.read_rom(0x4000), 0x13);
+        // Locked now that the ROM bank does not have bits 4-5 set.
+        sachen.write_rom(0x4000, 0x00);
+        sachen.write_rom(0x2000, 0x00);
+        assert_eq!(sachen.read_rom(0x4000), 0x11);
+    }
+}
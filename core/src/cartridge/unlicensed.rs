@@ -0,0 +1,186 @@
+// Unlicensed and bootleg cartridges, whose cartridge type byte (0x0147) does not tell their
+// mapper. They are recognized from the header and ROM size instead:
+// - Sachen MMC1/MMC2: no Nintendo logo at 0104, but one in the header the locked mapper shows
+//   the boot ROM. The MMC2 is for CGB games.
+// - M161: 256KB of 8 32KB games, each with its header.
+// - MBC1M: an MBC1 type with 1MB of 4 256KB games, the second header at 0x40100.
+// - Wisdom Tree: more than 32KB and the company name in bank 0.
+// - Bootleg MBC1/MBC5: an unassigned type, or ROM only with more than 32KB, is guessed to be
+//   an MBC1 up to 2MB and an MBC5 past that. An MBC1 or MBC5 type with RAM but no RAM size
+//   gets the most RAM the mapper can bank.
+
+use super::{sachen, Header, ROM_BANK_SIZE};
+
+/// The logo the boot ROM checks at 0104-0133.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const LOGO_ADDRESS: usize = 0x0104;
+const GAME_SIZE: usize = 2 * ROM_BANK_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unlicensed {
+    WisdomTree,
+    SachenMmc1,
+    SachenMmc2,
+    M161,
+    Mbc1Multicart,
+    BootlegMbc1,
+    BootlegMbc5,
+}
+
+/// Cartridge types of the licensed mappers, supported or not.
+fn is_assigned(cartridge_type: u8) -> bool {
+    matches!(
+        cartridge_type,
+        0x00..=0x03
+            | 0x05
+            | 0x06
+            | 0x08
+            | 0x09
+            | 0x0B..=0x0D
+            | 0x0F..=0x13
+            | 0x19..=0x1E
+            | 0x20
+            | 0x22
+            | 0xFC..=0xFF
+    )
+}
+
+fn has_logo(rom: &[u8], header: usize) -> bool {
+    rom.get(header + LOGO_ADDRESS..header + LOGO_ADDRESS + NINTENDO_LOGO.len())
+        == Some(&NINTENDO_LOGO[..])
+}
+
+impl Unlicensed {
+    /// The mapper of `rom` when its type byte cannot be trusted, None for licensed cartridges.
+    pub fn detect(header: &Header, rom: &[u8]) -> Option<Self> {
+        let locked = |address: u16| {
+            let address = sachen::locked_address(address) as usize;
+            rom.get(address).copied().unwrap_or(0xFF)
+        };
+        if !has_logo(rom, 0) {
+            let logo = (0..NINTENDO_LOGO.len() as u16).map(|i| locked(0x0104 + i));
+            if logo.eq(NINTENDO_LOGO) {
+                return Some(match locked(0x0143) & 0x80 {
+                    0 => Unlicensed::SachenMmc1,
+                    _ => Unlicensed::SachenMmc2,
+                });
+            }
+        }
+        if rom.len() == 8 * GAME_SIZE && (0..8).all(|game| has_logo(rom, game * GAME_SIZE)) {
+            return Some(Unlicensed::M161);
+        }
+        if matches!(header.cartridge_type, 0x01..=0x03)
+            && rom.len() == 0x100000
+            && has_logo(rom, 0x40000)
+        {
+            return Some(Unlicensed::Mbc1Multicart);
+        }
+        let bank0 = &rom[..rom.len().min(ROM_BANK_SIZE)];
+        let wisdom_tree = [&b"WISDOM TREE"[..], &b"WISDOM\0TREE"[..]]
+            .iter()
+            .any(|name| bank0.windows(name.len()).any(|window| window == *name));
+        if rom.len() > GAME_SIZE && wisdom_tree {
+            return Some(Unlicensed::WisdomTree);
+        }
+
+        let lying = !is_assigned(header.cartridge_type)
+            || (header.cartridge_type == 0x00 && rom.len() > GAME_SIZE);
+        if lying {
+            return Some(if rom.len() > 0x200000 {
+                Unlicensed::BootlegMbc5
+            } else {
+                Unlicensed::BootlegMbc1
+            });
+        }
+        if header.ram_size == 0 {
+            match header.cartridge_type {
+                0x02 | 0x03 => return Some(Unlicensed::BootlegMbc1),
+                0x1A | 0x1B | 0x1D | 0x1E => return Some(Unlicensed::BootlegMbc5),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Bytes of RAM of the mapper, `header_size` when the header tells.
+    pub fn ram_size(self, header_size: usize) -> usize {
+        match self {
+            Unlicensed::Mbc1Multicart => header_size,
+            Unlicensed::BootlegMbc1 if header_size == 0 => 0x8000,
+            Unlicensed::BootlegMbc5 if header_size == 0 => 0x20000,
+            Unlicensed::BootlegMbc1 | Unlicensed::BootlegMbc5 => header_size,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    fn detect(rom: &[u8]) -> Option<Unlicensed> {
+        Unlicensed::detect(&Header::parse(rom).unwrap(), rom)
+    }
+
+    fn with_logo(mut rom: Vec<u8>, header: usize) -> Vec<u8> {
+        rom[header + LOGO_ADDRESS..header + LOGO_ADDRESS + 48].copy_from_slice(&NINTENDO_LOGO);
+        rom
+    }
+
+    #[test]
+    fn test_licensed() {
+        assert_eq!(detect(&with_logo(test_rom(0x01, 64, 0), 0)), None);
+        assert_eq!(detect(&with_logo(test_rom(0x00, 2, 0), 0)), None);
+        assert_eq!(detect(&with_logo(test_rom(0x1B, 8, 0x04), 0)), None);
+        // A licensed mapper this emulator lacks is left alone.
+        assert_eq!(detect(&with_logo(test_rom(0xFD, 8, 0), 0)), None);
+    }
+
+    #[test]
+    fn test_sachen() {
+        let mut rom = test_rom(0x00, 16, 0);
+        for (i, &byte) in NINTENDO_LOGO.iter().enumerate() {
+            rom[sachen::locked_address(0x0104 + i as u16) as usize] = byte;
+        }
+        assert_eq!(detect(&rom), Some(Unlicensed::SachenMmc1));
+        rom[sachen::locked_address(0x0143) as usize] = 0x80;
+        assert_eq!(detect(&rom), Some(Unlicensed::SachenMmc2));
+    }
+
+    #[test]
+    fn test_multicarts() {
+        let mut rom = test_rom(0x10, 16, 0);
+        for game in 0..8 {
+            rom = with_logo(rom, game * GAME_SIZE);
+        }
+        assert_eq!(detect(&rom), Some(Unlicensed::M161));
+
+        let rom = with_logo(with_logo(test_rom(0x01, 64, 0), 0), 0x40000);
+        assert_eq!(detect(&rom), Some(Unlicensed::Mbc1Multicart));
+    }
+
+    #[test]
+    fn test_wisdom_tree() {
+        let mut rom = with_logo(test_rom(0x00, 8, 0), 0);
+        rom[0x0150..0x015B].copy_from_slice(b"WISDOM TREE");
+        assert_eq!(detect(&rom), Some(Unlicensed::WisdomTree));
+    }
+
+    #[test]
+    fn test_bootlegs() {
+        let rom = with_logo(test_rom(0x00, 8, 0), 0);
+        assert_eq!(detect(&rom), Some(Unlicensed::BootlegMbc1));
+        let rom = with_logo(test_rom(0x44, 256, 0), 0);
+        assert_eq!(detect(&rom), Some(Unlicensed::BootlegMbc5));
+        let rom = with_logo(test_rom(0x1B, 8, 0), 0);
+        assert_eq!(detect(&rom), Some(Unlicensed::BootlegMbc5));
+        assert_eq!(Unlicensed::BootlegMbc5.ram_size(0), 0x20000);
+        assert_eq!(Unlicensed::BootlegMbc1.ram_size(0x2000), 0x2000);
+    }
+}
@@ -0,0 +1,79 @@
+// Wisdom Tree: unlicensed mapper switching the whole of 0000-7FFF in 32KB banks, no RAM.
+// 0000-3FFF   A write selects the 32KB bank numbered by the low byte of its address, the
+//             value written is ignored
+
+use super::{rom_byte, Mbc};
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub struct WisdomTree {
+    rom: Vec<u8>,
+    bank: u8,
+}
+
+impl WisdomTree {
+    pub fn new(rom: Vec<u8>) -> Self {
+        WisdomTree { rom, bank: 0 }
+    }
+}
+
+impl Snapshot for WisdomTree {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.bank);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.bank = reader.u8()?;
+        Ok(())
+    }
+}
+
+impl Mbc for WisdomTree {
+    fn read_rom(&self, address: u16) -> u8 {
+        rom_byte(&self.rom, self.bank(address), address)
+    }
+
+    fn bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => self.bank as usize * 2,
+            0x4000..=0x7FFF => self.bank as usize * 2 + 1,
+            _ => 0,
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, _value: u8) {
+        if address < 0x4000 {
+            self.bank = address as u8;
+        }
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_rom;
+
+    #[test]
+    fn test_32kb_banks() {
+        let mut mbc = WisdomTree::new(test_rom(0x00, 16, 0));
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x0003, 0xFF);
+        assert_eq!(mbc.read_rom(0x0000), 6);
+        assert_eq!(mbc.read_rom(0x7FFF), 7);
+        mbc.write_rom(0x4001, 0x00);
+        assert_eq!(mbc.read_rom(0x0000), 6);
+    }
+}
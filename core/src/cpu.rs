@@ -1,59 +1,240 @@
-use crate::{memory::Memory, mmu::MMU};
+use crate::{
+    interrupt::{self, INTERRUPT_ENABLE, INTERRUPT_FLAG},
+    memory::Memory,
+    mmu::MMU,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
 
 mod decode;
 mod instructions;
-mod registers;
+pub mod registers;
+pub mod trace;
 
+/// EI only enables interrupts after the instruction that follows it.
+#[derive(Default)]
 pub struct ImeFlagTimer {
     pub ei: u8,
-    pub di: u8,
 }
 
 impl ImeFlagTimer {
     pub fn new() -> Self {
-        ImeFlagTimer { ei: 0, di: 0 }
+        ImeFlagTimer { ei: 0 }
     }
 }
 
-pub struct CPU {
+/// What the CPU did on the bus during one M-cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusCycle {
+    Read(u16, u8),
+    Write(u16, u8),
+    Idle,
+}
+
+/// SM83 core. It only talks to the rest of the machine through the `Memory`
+/// bus, so the same instructions can run on the Game Boy `MMU` or any other
+/// `Memory` (e.g. `FlatMemory` for tests and sandboxes).
+pub struct CPU<M: Memory = MMU> {
+    cycles: u64, // M-cycles run since power on
     halt: bool,
+    halt_bug: bool, // HALT with IME off and an interrupt pending: the next fetch does not move PC
     ime: bool,
     ime_timer: ImeFlagTimer,
-    mmu: MMU,
+    mmu: M,
     registers: registers::Registers,
+    tracer: Option<trace::Tracer>,
+    bus_log: Option<Vec<BusCycle>>, // Bus activity of the last step, when recording
 }
 
-impl CPU {
-    pub fn new(mmu: MMU) -> Self {
+impl<M: Memory> CPU<M> {
+    pub fn new(mmu: M) -> Self {
         CPU {
             registers: registers::Registers::new(),
-            mmu: mmu,
+            mmu,
             ime: true,
             ime_timer: ImeFlagTimer::new(),
             halt: false,
+            halt_bug: false,
+            cycles: 0,
+            tracer: None,
+            bus_log: None,
+        }
+    }
+
+    /// Attach a tracer that logs every instruction before it runs, or detach it with `None`.
+    pub fn set_tracer(&mut self, tracer: Option<trace::Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer(&self) -> Option<&trace::Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Record the bus activity of each M-cycle of the following steps, or stop with `false`.
+    pub fn set_bus_log(&mut self, enabled: bool) {
+        self.bus_log = enabled.then(Vec::new);
+    }
+
+    /// Bus activity of each M-cycle of the last step, empty when not recording. Internal
+    /// M-cycles come last, except the one before a push and the one RET cc spends on its
+    /// condition.
+    pub fn bus_log(&self) -> &[BusCycle] {
+        self.bus_log.as_deref().unwrap_or_default()
+    }
+
+    pub fn registers(&self) -> &registers::Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut registers::Registers {
+        &mut self.registers
+    }
+
+    pub fn mmu(&self) -> &M {
+        &self.mmu
+    }
+
+    pub fn mmu_mut(&mut self) -> &mut M {
+        &mut self.mmu
+    }
+
+    /// Interrupt master enable flag.
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = ime;
+        self.ime_timer.ei = 0;
+    }
+
+    /// Whether an EI is waiting for the next instruction to enable interrupts.
+    pub fn ime_scheduled(&self) -> bool {
+        self.ime_timer.ei != 0
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halt
+    }
+
+    /// M-cycles run since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Read from the bus in an M-cycle of its own.
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.mmu.read_byte(address);
+        if let Some(log) = &mut self.bus_log {
+            log.push(BusCycle::Read(address, value));
+        }
+        value
+    }
+
+    /// Write to the bus in an M-cycle of its own.
+    fn write(&mut self, address: u16, value: u8) {
+        self.mmu.write_byte(address, value);
+        if let Some(log) = &mut self.bus_log {
+            log.push(BusCycle::Write(address, value));
+        }
+    }
+
+    /// An internal M-cycle that does not use the bus.
+    fn idle(&mut self) {
+        if let Some(log) = &mut self.bus_log {
+            log.push(BusCycle::Idle);
         }
     }
 
     fn fetch_byte(&mut self) -> u8 {
-        let byte = self.mmu.read_byte(self.registers.pc);
-        self.registers.pc = self.registers.pc.wrapping_add(1);
+        let byte = self.read(self.registers.pc);
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
         byte
     }
 
     fn fetch_word(&mut self) -> u16 {
-        let word = self.mmu.read_word(self.registers.pc);
+        let low = self.read(self.registers.pc);
+        let high = self.read(self.registers.pc.wrapping_add(1));
         self.registers.pc = self.registers.pc.wrapping_add(2);
-        word
+        u16::from_le_bytes([low, high])
     }
 
-    fn step(&mut self) -> u32 {
+    pub fn step(&mut self) -> u32 {
+        if let Some(log) = &mut self.bus_log {
+            log.clear();
+        }
         self.update_ime();
+        if let Some(cycles) = self.handle_interrupts() {
+            return self.tick(cycles);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            if !self.halt {
+                let pc = self.registers.pc;
+                let pcmem = [0, 1, 2, 3].map(|i| self.mmu.peek(pc.wrapping_add(i)));
+                tracer.trace(&self.registers, pcmem, self.mmu.bank(pc), self.cycles);
+            }
+        }
+        if self.halt {
+            self.idle();
+            self.mmu.tick_halted(1); // Emulate an noop instruction
+            self.cycles += 1;
+            return 1;
+        }
         let instruction = self.fetch_byte();
+        let cycles = self.execute(instruction);
+        self.tick(cycles)
+    }
 
-        if self.halt {
-            1 // Emulate an noop instruction
+    /// Advance the hardware by the M-cycles of a step and the DMA stalls it caused, returning
+    /// the total.
+    fn tick(&mut self, cycles: u32) -> u32 {
+        if let Some(log) = &mut self.bus_log {
+            log.resize(log.len().max(cycles as usize), BusCycle::Idle);
+        }
+        self.mmu.tick(cycles);
+        let stall = self.mmu.take_stall();
+        if stall > 0 {
+            self.mmu.tick(stall);
+        }
+        self.cycles += (cycles + stall) as u64;
+        cycles + stall
+    }
+
+    /// Interrupts both requested (IF) and enabled (IE).
+    fn pending_interrupts(&self) -> u8 {
+        self.mmu.peek(INTERRUPT_FLAG) & self.mmu.peek(INTERRUPT_ENABLE) & 0x1F
+    }
+
+    /// Wake up from HALT on a pending interrupt and, when IME is set, jump to the vector
+    /// of the highest priority one. Returns the M-cycles taken by the dispatch.
+    fn handle_interrupts(&mut self) -> Option<u32> {
+        let pending = self.pending_interrupts();
+        if pending == 0 {
+            return None;
+        }
+        self.halt = false;
+        if !self.ime {
+            return None;
+        }
+
+        let requested = pending & pending.wrapping_neg(); // lowest bit
+        let flag = self.mmu.peek(INTERRUPT_FLAG);
+        self.mmu.poke(INTERRUPT_FLAG, flag & !requested);
+        self.ime = false;
+        self.idle();
+        self.push(self.registers.pc);
+        self.registers.pc = interrupt::vector(requested);
+        Some(5)
+    }
+
+    fn halt(&mut self) {
+        if !self.ime && self.pending_interrupts() != 0 {
+            self.halt_bug = true;
         } else {
-            self.execute(instruction)
+            self.halt = true;
         }
     }
 
@@ -67,15 +248,165 @@ impl CPU {
 
             _ => 0,
         };
+    }
+}
 
-        self.ime_timer.di = match self.ime_timer.ei {
-            2 => 1,
-            1 => {
-                self.ime = false;
-                0
-            }
+/// The CPU's own state, the bus behind it is saved separately.
+impl<M: Memory> Snapshot for CPU<M> {
+    fn save(&self, writer: &mut StateWriter) {
+        self.registers.save(writer);
+        writer.u64(self.cycles);
+        writer.bool(self.halt);
+        writer.bool(self.halt_bug);
+        writer.bool(self.ime);
+        writer.u8(self.ime_timer.ei);
+    }
 
-            _ => 0,
-        };
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.registers.load(reader)?;
+        self.cycles = reader.u64()?;
+        self.halt = reader.bool()?;
+        self.halt_bug = reader.bool()?;
+        self.ime = reader.bool()?;
+        self.ime_timer.ei = reader.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::FlatMemory;
+
+    struct CountingMemory {
+        memory: FlatMemory,
+        ticks: u32,
+    }
+
+    impl Memory for CountingMemory {
+        fn read_byte(&self, address: u16) -> u8 {
+            self.memory.read_byte(address)
+        }
+
+        fn write_byte(&mut self, address: u16, value: u8) {
+            self.memory.write_byte(address, value)
+        }
+
+        fn tick(&mut self, cycles: u32) {
+            self.ticks += cycles;
+        }
+    }
+
+    #[test]
+    fn test_run_on_flat_memory() {
+        let mut memory = FlatMemory::new();
+        // LD A, $05; LD B, $03; ADD A, B; LD [$8000], A
+        memory.load(0x0000, &[0x3E, 0x05, 0x06, 0x03, 0x80, 0xEA, 0x00, 0x80]);
+        let mut cpu = CPU::new(memory);
+
+        for _ in 0..4 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.registers().a, 0x08);
+        assert_eq!(cpu.mmu().read_byte(0x8000), 0x08);
+        assert_eq!(cpu.registers().pc, 0x0008);
+    }
+
+    #[test]
+    fn test_tick_after_each_step() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0000, &[0x00, 0xC3, 0x00, 0x00]); // NOP; JP $0000
+        let mut cpu = CPU::new(CountingMemory { memory, ticks: 0 });
+
+        cpu.step();
+        assert_eq!(cpu.mmu().ticks, 1);
+        cpu.step();
+        assert_eq!(cpu.mmu().ticks, 5);
+        assert_eq!(cpu.cycles(), 5);
+    }
+
+    #[test]
+    fn test_interrupt_dispatch() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0100, &[0x00]);
+        memory.write_byte(INTERRUPT_ENABLE, interrupt::TIMER | interrupt::SERIAL);
+        memory.write_byte(INTERRUPT_FLAG, interrupt::SERIAL | interrupt::TIMER);
+        let mut cpu = CPU::new(memory);
+        cpu.registers_mut().pc = 0x0100;
+        cpu.registers_mut().sp = 0xD000;
+
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(cpu.registers().pc, 0x0050);
+        assert_eq!(cpu.mmu_mut().read_word(0xCFFE), 0x0100);
+        assert_eq!(cpu.mmu().read_byte(INTERRUPT_FLAG), interrupt::SERIAL);
+        assert!(!cpu.ime());
+    }
+
+    #[test]
+    fn test_halt_wakes_up_without_ime() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0000, &[0x76, 0x04]); // HALT; INC B
+        memory.write_byte(INTERRUPT_ENABLE, interrupt::VBLANK);
+        let mut cpu = CPU::new(memory);
+        cpu.set_ime(false);
+
+        cpu.step();
+        cpu.step();
+        assert!(cpu.is_halted());
+        cpu.mmu_mut().write_byte(INTERRUPT_FLAG, interrupt::VBLANK);
+        cpu.step();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.registers().b, 1);
+        assert_eq!(cpu.registers().pc, 0x0002);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0000, &[0x76, 0x04]); // HALT; INC B
+        memory.write_byte(INTERRUPT_ENABLE, interrupt::VBLANK);
+        memory.write_byte(INTERRUPT_FLAG, interrupt::VBLANK);
+        let mut cpu = CPU::new(memory);
+        cpu.set_ime(false);
+
+        cpu.step();
+        assert!(!cpu.is_halted());
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers().b, 2); // INC B runs twice
+        assert_eq!(cpu.registers().pc, 0x0002);
+    }
+
+    #[test]
+    fn test_bus_log_of_conditional_return() {
+        let mut memory = FlatMemory::new();
+        memory.load(0x0100, &[0xC0, 0xC0]); // RET NZ; RET NZ
+        memory.load(0xD000, &[0x01, 0x01]);
+        let mut cpu = CPU::new(memory);
+        cpu.set_bus_log(true);
+        cpu.registers_mut().pc = 0x0100;
+        cpu.registers_mut().sp = 0xD000;
+
+        cpu.registers_mut().f.set_z(true);
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(
+            cpu.bus_log(),
+            [BusCycle::Read(0x0100, 0xC0), BusCycle::Idle]
+        );
+
+        cpu.registers_mut().f.set_z(false);
+        assert_eq!(cpu.step(), 5);
+        assert_eq!(
+            cpu.bus_log(),
+            [
+                BusCycle::Read(0x0101, 0xC0),
+                BusCycle::Idle,
+                BusCycle::Read(0xD000, 0x01),
+                BusCycle::Read(0xD001, 0x01),
+                BusCycle::Idle,
+            ]
+        );
+        assert_eq!(cpu.registers().pc, 0x0101);
     }
 }
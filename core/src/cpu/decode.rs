@@ -1,6 +1,6 @@
 use crate::{memory::Memory, mmu::IO_REGISTERS_BEGIN};
 
-impl super::CPU {
+impl<M: Memory> super::CPU<M> {
     /// Decode op code and execute instruction. Returns how many clocks were necessary to run the instruction.
     pub fn execute(&mut self, op: u8) -> u32 {
         match op {
@@ -14,7 +14,7 @@ impl super::CPU {
                 3
             }
             0x02 => {
-                self.mmu.write_byte(self.registers.bc(), self.registers.a);
+                self.write(self.registers.bc(), self.registers.a);
                 2
             }
             0x03 => {
@@ -36,21 +36,22 @@ impl super::CPU {
             }
             0x07 => {
                 self.rlca();
-                2
+                1
             }
             0x08 => {
                 let address = self.fetch_word();
-                let data = self.registers.sp;
-                self.mmu.write_word(address, data);
+                let [low, high] = self.registers.sp.to_le_bytes();
+                self.write(address, low);
+                self.write(address.wrapping_add(1), high);
                 5
             }
             0x09 => {
                 self.add16_hl(self.registers.bc());
-                1
+                2
             }
             0x0A => {
                 let address = self.registers.bc();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 2
             }
@@ -73,7 +74,12 @@ impl super::CPU {
             }
             0x0F => {
                 self.rrca();
-                2
+                1
+            }
+            0x10 => {
+                self.fetch_byte();
+                self.mmu.stop();
+                1
             }
             0x11 => {
                 let data = self.fetch_word();
@@ -81,7 +87,7 @@ impl super::CPU {
                 3
             }
             0x12 => {
-                self.mmu.write_byte(self.registers.de(), self.registers.a);
+                self.write(self.registers.de(), self.registers.a);
                 2
             }
             0x13 => {
@@ -103,7 +109,7 @@ impl super::CPU {
             }
             0x17 => {
                 self.rla();
-                2
+                1
             }
             0x18 => {
                 self.jr();
@@ -115,7 +121,7 @@ impl super::CPU {
             }
             0x1A => {
                 let address = self.registers.de();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 2
             }
@@ -138,13 +144,14 @@ impl super::CPU {
             }
             0x1F => {
                 self.rra();
-                2
+                1
             }
             0x20 => {
                 if !self.registers.f.z() {
                     self.jr();
                     3
                 } else {
+                    self.fetch_byte();
                     2
                 }
             }
@@ -154,7 +161,7 @@ impl super::CPU {
                 3
             }
             0x22 => {
-                self.mmu.write_byte(self.registers.hl(), self.registers.a);
+                self.write(self.registers.hl(), self.registers.a);
                 let inc = self.registers.hl().wrapping_add(1);
                 self.registers.set_hl(inc);
                 2
@@ -185,6 +192,7 @@ impl super::CPU {
                     self.jr();
                     3
                 } else {
+                    self.fetch_byte();
                     2
                 }
             }
@@ -197,7 +205,7 @@ impl super::CPU {
                 let new_address = self.registers.hl().wrapping_add(1);
                 self.registers.set_hl(new_address);
 
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 2
             }
@@ -227,6 +235,7 @@ impl super::CPU {
                     self.jr();
                     3
                 } else {
+                    self.fetch_byte();
                     2
                 }
             }
@@ -236,7 +245,7 @@ impl super::CPU {
                 3
             }
             0x32 => {
-                self.mmu.write_byte(self.registers.hl(), self.registers.a);
+                self.write(self.registers.hl(), self.registers.a);
                 let dec = self.registers.hl().wrapping_sub(1);
                 self.registers.set_hl(dec);
                 2
@@ -248,20 +257,22 @@ impl super::CPU {
             }
             0x34 => {
                 let address = self.registers.hl();
-                let result = self.inc(self.mmu.read_byte(address));
-                self.mmu.write_byte(address, result);
+                let data = self.read(address);
+                let result = self.inc(data);
+                self.write(address, result);
                 3
             }
             0x35 => {
                 let address = self.registers.hl();
-                let result = self.dec(self.mmu.read_byte(address));
-                self.mmu.write_byte(address, result);
+                let data = self.read(address);
+                let result = self.dec(data);
+                self.write(address, result);
                 3
             }
             0x36 => {
                 let imm = self.fetch_byte();
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, imm);
+                self.write(address, imm);
                 3
             }
             0x37 => {
@@ -273,6 +284,7 @@ impl super::CPU {
                     self.jr();
                     3
                 } else {
+                    self.fetch_byte();
                     2
                 }
             }
@@ -285,7 +297,7 @@ impl super::CPU {
                 let new_address = self.registers.hl().wrapping_sub(1);
                 self.registers.set_hl(new_address);
 
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 2
             }
@@ -333,7 +345,7 @@ impl super::CPU {
             }
             0x46 => {
                 let address = self.registers.hl();
-                self.registers.b = self.mmu.read_byte(address);
+                self.registers.b = self.read(address);
                 2
             }
             0x47 => {
@@ -363,7 +375,7 @@ impl super::CPU {
             }
             0x4E => {
                 let address = self.registers.hl();
-                self.registers.c = self.mmu.read_byte(address);
+                self.registers.c = self.read(address);
                 2
             }
             0x4F => {
@@ -393,7 +405,7 @@ impl super::CPU {
             }
             0x56 => {
                 let address = self.registers.hl();
-                self.registers.d = self.mmu.read_byte(address);
+                self.registers.d = self.read(address);
                 2
             }
             0x57 => {
@@ -423,7 +435,7 @@ impl super::CPU {
             }
             0x5E => {
                 let address = self.registers.hl();
-                self.registers.e = self.mmu.read_byte(address);
+                self.registers.e = self.read(address);
                 2
             }
             0x5F => {
@@ -453,7 +465,7 @@ impl super::CPU {
             }
             0x66 => {
                 let address = self.registers.hl();
-                self.registers.h = self.mmu.read_byte(address);
+                self.registers.h = self.read(address);
                 2
             }
             0x67 => {
@@ -483,7 +495,7 @@ impl super::CPU {
             0x6D => 1,
             0x6E => {
                 let address = self.registers.hl();
-                self.registers.l = self.mmu.read_byte(address);
+                self.registers.l = self.read(address);
                 2
             }
             0x6F => {
@@ -492,41 +504,41 @@ impl super::CPU {
             }
             0x70 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.b);
+                self.write(address, self.registers.b);
                 2
             }
             0x71 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.c);
+                self.write(address, self.registers.c);
                 2
             }
             0x72 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.d);
+                self.write(address, self.registers.d);
                 2
             }
             0x73 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.e);
+                self.write(address, self.registers.e);
                 2
             }
             0x74 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.h);
+                self.write(address, self.registers.h);
                 2
             }
             0x75 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.l);
+                self.write(address, self.registers.l);
                 2
             }
             0x76 => {
-                self.halt = true;
+                self.halt();
                 1
             }
             0x77 => {
                 let address = self.registers.hl();
-                self.mmu.write_byte(address, self.registers.a);
+                self.write(address, self.registers.a);
                 2
             }
             0x78 => {
@@ -555,7 +567,7 @@ impl super::CPU {
             }
             0x7E => {
                 let address = self.registers.hl();
-                self.registers.a = self.mmu.read_byte(address);
+                self.registers.a = self.read(address);
                 2
             }
             0x7F => 1,
@@ -585,7 +597,8 @@ impl super::CPU {
             }
             0x86 => {
                 let address = self.registers.hl();
-                self.add(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.add(data);
                 2
             }
             0x87 => {
@@ -618,7 +631,8 @@ impl super::CPU {
             }
             0x8E => {
                 let address = self.registers.hl();
-                self.adc(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.adc(data);
                 2
             }
             0x8F => {
@@ -651,7 +665,8 @@ impl super::CPU {
             }
             0x96 => {
                 let address = self.registers.hl();
-                self.sub(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.sub(data);
                 2
             }
             0x97 => {
@@ -659,36 +674,37 @@ impl super::CPU {
                 1
             }
             0x98 => {
-                self.sub(self.registers.b);
+                self.sbc(self.registers.b);
                 1
             }
             0x99 => {
-                self.sub(self.registers.c);
+                self.sbc(self.registers.c);
                 1
             }
             0x9A => {
-                self.sub(self.registers.d);
+                self.sbc(self.registers.d);
                 1
             }
             0x9B => {
-                self.sub(self.registers.e);
+                self.sbc(self.registers.e);
                 1
             }
             0x9C => {
-                self.sub(self.registers.h);
+                self.sbc(self.registers.h);
                 1
             }
             0x9D => {
-                self.sub(self.registers.l);
+                self.sbc(self.registers.l);
                 1
             }
             0x9E => {
                 let address = self.registers.hl();
-                self.sub(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.sbc(data);
                 2
             }
             0x9F => {
-                self.sub(self.registers.a);
+                self.sbc(self.registers.a);
                 1
             }
             0xA0 => {
@@ -717,7 +733,8 @@ impl super::CPU {
             }
             0xA6 => {
                 let address = self.registers.hl();
-                self.and(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.and(data);
                 2
             }
             0xA7 => {
@@ -750,7 +767,8 @@ impl super::CPU {
             }
             0xAE => {
                 let address = self.registers.hl();
-                self.xor(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.xor(data);
                 2
             }
             0xAF => {
@@ -783,7 +801,8 @@ impl super::CPU {
             }
             0xB6 => {
                 let address = self.registers.hl();
-                self.or(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.or(data);
                 2
             }
             0xB7 => {
@@ -816,7 +835,8 @@ impl super::CPU {
             }
             0xBE => {
                 let address = self.registers.hl();
-                self.cp(self.mmu.read_byte(address));
+                let data = self.read(address);
+                self.cp(data);
                 2
             }
             0xBF => {
@@ -824,6 +844,7 @@ impl super::CPU {
                 1
             }
             0xC0 => {
+                self.idle(); // Checking the condition
                 if !self.registers.f.z() {
                     self.ret();
                     5
@@ -841,6 +862,7 @@ impl super::CPU {
                     self.jp();
                     4
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -853,6 +875,7 @@ impl super::CPU {
                     self.call();
                     6
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -871,6 +894,7 @@ impl super::CPU {
                 4
             }
             0xC8 => {
+                self.idle(); // Checking the condition
                 if self.registers.f.z() {
                     self.ret();
                     5
@@ -887,14 +911,17 @@ impl super::CPU {
                     self.jp();
                     4
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
+            0xCB => self.execute_cb(),
             0xCC => {
                 if self.registers.f.z() {
                     self.call();
                     6
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -912,6 +939,7 @@ impl super::CPU {
                 4
             }
             0xD0 => {
+                self.idle(); // Checking the condition
                 if !self.registers.f.c() {
                     self.ret();
                     5
@@ -929,6 +957,7 @@ impl super::CPU {
                     self.jp();
                     4
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -937,6 +966,7 @@ impl super::CPU {
                     self.call();
                     6
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -955,6 +985,7 @@ impl super::CPU {
                 4
             }
             0xD8 => {
+                self.idle(); // Checking the condition
                 if self.registers.f.c() {
                     self.ret();
                     5
@@ -972,6 +1003,7 @@ impl super::CPU {
                     self.jp();
                     4
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -980,6 +1012,7 @@ impl super::CPU {
                     self.call();
                     6
                 } else {
+                    self.fetch_word();
                     3
                 }
             }
@@ -996,7 +1029,7 @@ impl super::CPU {
                 let partial_address = self.fetch_byte();
                 let address = IO_REGISTERS_BEGIN | partial_address as u16;
                 let data = self.registers.a;
-                self.mmu.write_byte(address, data);
+                self.write(address, data);
                 3
             }
             0xE1 => {
@@ -1007,7 +1040,7 @@ impl super::CPU {
             0xE2 => {
                 let address = IO_REGISTERS_BEGIN + (self.registers.c as u16);
                 let data = self.registers.a;
-                self.mmu.write_byte(address, data);
+                self.write(address, data);
                 2
             }
             0xE5 => {
@@ -1036,7 +1069,7 @@ impl super::CPU {
             0xEA => {
                 let address = self.fetch_word();
                 let data = self.registers.a;
-                self.mmu.write_byte(address, data);
+                self.write(address, data);
                 4
             }
             0xEE => {
@@ -1051,7 +1084,7 @@ impl super::CPU {
             0xF0 => {
                 let partial_address = self.fetch_byte();
                 let address = IO_REGISTERS_BEGIN | partial_address as u16;
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 3
             }
@@ -1061,12 +1094,12 @@ impl super::CPU {
                 3
             }
             0xF3 => {
-                self.ime_timer.di = 2;
+                self.set_ime(false);
                 1
             }
             0xF2 => {
                 let address = IO_REGISTERS_BEGIN + (self.registers.c as u16);
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 2
             }
@@ -1085,18 +1118,11 @@ impl super::CPU {
                 4
             }
             0xF8 => {
-                let offset = self.fetch_byte() as u16;
-                let sum = self.registers.sp.wrapping_add(offset);
-                self.registers.f.set_z(false);
-                self.registers.f.set_n(false);
-                self.registers
-                    .f
-                    .set_h((self.registers.sp & 0x000F) + (offset & 0x000F) > 0x000F);
-                self.registers
-                    .f
-                    .set_c((self.registers.sp & 0x00FF) + (offset & 0x00FF) > 0x00FF);
-
-                self.registers.set_hl(sum);
+                let offset = self.fetch_byte();
+                let sp = self.registers.sp;
+                self.add16_sp(offset);
+                self.registers.set_hl(self.registers.sp);
+                self.registers.sp = sp;
                 3
             }
             0xF9 => {
@@ -1105,7 +1131,7 @@ impl super::CPU {
             }
             0xFA => {
                 let address = self.fetch_word();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.registers.a = data;
                 4
             }
@@ -1127,7 +1153,8 @@ impl super::CPU {
         }
     }
 
-    pub fn execute_cb(&mut self, op: u8) -> u32 {
+    /// Decode and execute an instruction prefixed by 0xCB. Returns how many clocks were necessary to run the instruction, including the prefix.
+    pub fn execute_cb(&mut self) -> u32 {
         let op = self.fetch_byte();
         match op {
             0x00 => {
@@ -1156,9 +1183,9 @@ impl super::CPU {
             }
             0x06 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.rlc(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x07 => {
@@ -1191,9 +1218,9 @@ impl super::CPU {
             }
             0x0E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.rrc(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x0F => {
@@ -1226,9 +1253,9 @@ impl super::CPU {
             }
             0x16 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.rl(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x17 => {
@@ -1261,9 +1288,9 @@ impl super::CPU {
             }
             0x1E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.rr(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x1F => {
@@ -1296,9 +1323,9 @@ impl super::CPU {
             }
             0x26 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.sla(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x27 => {
@@ -1331,9 +1358,9 @@ impl super::CPU {
             }
             0x2E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.sra(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x2F => {
@@ -1366,9 +1393,9 @@ impl super::CPU {
             }
             0x36 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.swap(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x37 => {
@@ -1401,9 +1428,9 @@ impl super::CPU {
             }
             0x3E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 let rotation = self.srl(data);
-                self.mmu.write_byte(address, rotation);
+                self.write(address, rotation);
                 4
             }
             0x3F => {
@@ -1436,7 +1463,7 @@ impl super::CPU {
             }
             0x46 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 0);
                 3
             }
@@ -1470,7 +1497,7 @@ impl super::CPU {
             }
             0x4E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 1);
                 3
             }
@@ -1504,7 +1531,7 @@ impl super::CPU {
             }
             0x56 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 2);
                 3
             }
@@ -1538,7 +1565,7 @@ impl super::CPU {
             }
             0x5E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 3);
                 3
             }
@@ -1572,7 +1599,7 @@ impl super::CPU {
             }
             0x66 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 4);
                 3
             }
@@ -1606,7 +1633,7 @@ impl super::CPU {
             }
             0x6E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 5);
                 3
             }
@@ -1640,7 +1667,7 @@ impl super::CPU {
             }
             0x76 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 6);
                 3
             }
@@ -1674,7 +1701,7 @@ impl super::CPU {
             }
             0x7E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
+                let data = self.read(address);
                 self.bit(data, 7);
                 3
             }
@@ -1708,9 +1735,9 @@ impl super::CPU {
             }
             0x86 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.res(data, 0);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.res(data, 0);
+                self.write(address, result);
                 4
             }
             0x87 => {
@@ -1743,8 +1770,9 @@ impl super::CPU {
             }
             0x8E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.res(data, 1);
+                let data = self.read(address);
+                let result = self.res(data, 1);
+                self.write(address, result);
                 4
             }
             0x8F => {
@@ -1777,9 +1805,9 @@ impl super::CPU {
             }
             0x96 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.res(data, 2);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.res(data, 2);
+                self.write(address, result);
                 4
             }
             0x97 => {
@@ -1812,8 +1840,9 @@ impl super::CPU {
             }
             0x9E => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.res(data, 3);
+                let data = self.read(address);
+                let result = self.res(data, 3);
+                self.write(address, result);
                 4
             }
             0x9F => {
@@ -1846,9 +1875,9 @@ impl super::CPU {
             }
             0xA6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.res(data, 4);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.res(data, 4);
+                self.write(address, result);
                 4
             }
             0xA7 => {
@@ -1881,8 +1910,9 @@ impl super::CPU {
             }
             0xAE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.res(data, 5);
+                let data = self.read(address);
+                let result = self.res(data, 5);
+                self.write(address, result);
                 4
             }
             0xAF => {
@@ -1915,9 +1945,9 @@ impl super::CPU {
             }
             0xB6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.res(data, 6);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.res(data, 6);
+                self.write(address, result);
                 4
             }
             0xB7 => {
@@ -1950,8 +1980,9 @@ impl super::CPU {
             }
             0xBE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.res(data, 7);
+                let data = self.read(address);
+                let result = self.res(data, 7);
+                self.write(address, result);
                 4
             }
             0xBF => {
@@ -1984,9 +2015,9 @@ impl super::CPU {
             }
             0xC6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.set(data, 0);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.set(data, 0);
+                self.write(address, result);
                 4
             }
             0xC7 => {
@@ -2019,8 +2050,9 @@ impl super::CPU {
             }
             0xCE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.set(data, 1);
+                let data = self.read(address);
+                let result = self.set(data, 1);
+                self.write(address, result);
                 4
             }
             0xCF => {
@@ -2053,9 +2085,9 @@ impl super::CPU {
             }
             0xD6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.set(data, 2);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.set(data, 2);
+                self.write(address, result);
                 4
             }
             0xD7 => {
@@ -2088,8 +2120,9 @@ impl super::CPU {
             }
             0xDE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.set(data, 3);
+                let data = self.read(address);
+                let result = self.set(data, 3);
+                self.write(address, result);
                 4
             }
             0xDF => {
@@ -2122,9 +2155,9 @@ impl super::CPU {
             }
             0xE6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.set(data, 4);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.set(data, 4);
+                self.write(address, result);
                 4
             }
             0xE7 => {
@@ -2157,8 +2190,9 @@ impl super::CPU {
             }
             0xEE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.set(data, 5);
+                let data = self.read(address);
+                let result = self.set(data, 5);
+                self.write(address, result);
                 4
             }
             0xEF => {
@@ -2191,9 +2225,9 @@ impl super::CPU {
             }
             0xF6 => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                let reset = self.set(data, 6);
-                self.mmu.write_byte(address, reset);
+                let data = self.read(address);
+                let result = self.set(data, 6);
+                self.write(address, result);
                 4
             }
             0xF7 => {
@@ -2226,8 +2260,9 @@ impl super::CPU {
             }
             0xFE => {
                 let address = self.registers.hl();
-                let data = self.mmu.read_byte(address);
-                self.set(data, 7);
+                let data = self.read(address);
+                let result = self.set(data, 7);
+                self.write(address, result);
                 4
             }
             0xFF => {
@@ -1,6 +1,6 @@
 use crate::memory::Memory;
 
-impl super::CPU {
+impl<M: Memory> super::CPU<M> {
     /// 8-bit add operation with register A.
     pub fn add(&mut self, value: u8) {
         let (result, carry) = self.registers.a.overflowing_add(value);
@@ -24,9 +24,9 @@ impl super::CPU {
         self.registers.set_hl(result);
     }
 
-    /// 16-bit add operation of a byte with register SP.
+    /// 16-bit add operation of a signed byte with register SP.
     pub fn add16_sp(&mut self, value: u8) {
-        let value_16 = value as u16;
+        let value_16 = value as i8 as u16;
         let result = self.registers.sp.wrapping_add(value_16);
         self.registers.f.set_z(false);
         self.registers.f.set_n(false);
@@ -57,9 +57,7 @@ impl super::CPU {
     /// Bitwise AND operation with register A.
     pub fn and(&mut self, value: u8) {
         let result = self.registers.a & value;
-        if result == 0 {
-            self.registers.f.set_z(true);
-        }
+        self.registers.f.set_z(result == 0);
         self.registers.a = result;
         self.registers.f.set_n(false); // reset
         self.registers.f.set_h(true); // set
@@ -77,10 +75,9 @@ impl super::CPU {
 
     /// Push address of next instruction onto stack and then jump to address in the next memory word.
     pub fn call(&mut self) {
-        let next_instruction = self.registers.pc.wrapping_add(2);
-        self.push(next_instruction);
-
-        self.registers.pc = self.fetch_word();
+        let address = self.fetch_word();
+        self.push(self.registers.pc);
+        self.registers.pc = address;
     }
 
     /// Compare A with a value. This is basically an A - value subtraction instruction but the results are thrown away.
@@ -137,9 +134,7 @@ impl super::CPU {
     /// Decrement 8bit value.
     pub fn dec(&mut self, value: u8) -> u8 {
         let result = value.wrapping_sub(1);
-        if result == 0 {
-            self.registers.f.set_z(true);
-        }
+        self.registers.f.set_z(result == 0);
         self.registers.f.set_n(true);
         self.registers.f.set_h(value.trailing_zeros() >= 4); // Check for Half-Carry in bit 4 (borrow)
         result
@@ -147,16 +142,13 @@ impl super::CPU {
 
     /// Decrement 16-bit value.
     pub fn dec16(&mut self, value: u16) -> u16 {
-        let result = value.wrapping_sub(1);
-        result
+        value.wrapping_sub(1)
     }
 
     /// Increment 8bit value.
     pub fn inc(&mut self, value: u8) -> u8 {
         let result = value.wrapping_add(1);
-        if result == 0 {
-            self.registers.f.set_z(true);
-        }
+        self.registers.f.set_z(result == 0);
         self.registers.f.set_n(false);
         self.registers.f.set_h((value & 0x0F) + 1 > 0x0F); // Check for Half-Carry
         result
@@ -164,8 +156,7 @@ impl super::CPU {
 
     /// Increment 16bit value.
     pub fn inc16(&mut self, value: u16) -> u16 {
-        let result = value.wrapping_add(1);
-        result
+        value.wrapping_add(1)
     }
 
     /// Jump to address provided in the next memory word.
@@ -174,9 +165,9 @@ impl super::CPU {
         self.registers.pc = address;
     }
 
-    /// Jump to PC + (next byte).
+    /// Jump to PC + (next byte as a signed offset).
     pub fn jr(&mut self) {
-        let offset = self.fetch_byte();
+        let offset = self.fetch_byte() as i8;
         self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
     }
 
@@ -186,9 +177,7 @@ impl super::CPU {
     /// Bitwise OR operation with register A.
     pub fn or(&mut self, value: u8) {
         let result = self.registers.a | value;
-        if result == 0 {
-            self.registers.f.set_z(true);
-        }
+        self.registers.f.set_z(result == 0);
         self.registers.a = result;
         self.registers.f.set_n(false); // reset
         self.registers.f.set_h(false); // reset
@@ -197,21 +186,27 @@ impl super::CPU {
 
     /// Pop a 16bit value from the stack.
     pub fn pop(&mut self) -> u16 {
-        let value = self.mmu.read_word(self.registers.sp);
-        self.registers.sp += 2;
+        let low = self.read(self.registers.sp);
+        let high = self.read(self.registers.sp.wrapping_add(1));
+        let value = u16::from_le_bytes([low, high]);
+        self.registers.sp = self.registers.sp.wrapping_add(2);
         value
     }
 
     /// Push a 16bit value to the stack.
     pub fn push(&mut self, value: u16) {
-        self.registers.sp -= 2;
-        self.mmu.write_word(self.registers.sp, value);
+        // SP is decremented in an M-cycle of its own, then the high byte is written first.
+        self.idle();
+        let [low, high] = value.to_le_bytes();
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(self.registers.sp, high);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write(self.registers.sp, low);
     }
 
     /// Reset bit in register.
     pub fn res(&mut self, register: u8, value: u8) -> u8 {
-        let result = register & !(1 << value);
-        result
+        register & !(1 << value)
     }
 
     /// Update PC register to return to instruction stored on the stack.
@@ -318,7 +313,7 @@ impl super::CPU {
     /// Push present address onto stack and jump to address 0x0000 + arg.
     pub fn rst(&mut self, address: u16) {
         self.push(self.registers.pc);
-        self.registers.pc = 0x0000 + address;
+        self.registers.pc = address;
     }
 
     /// Sub operation with carry with register A.
@@ -333,7 +328,7 @@ impl super::CPU {
             .set_h((self.registers.a & 0x0F) < (value & 0x0F) + carry);
         self.registers
             .f
-            .set_h(u16::from(self.registers.a) < u16::from(value) + u16::from(carry));
+            .set_c(u16::from(self.registers.a) < u16::from(value) + u16::from(carry));
         self.registers.a = result;
     }
 
@@ -346,8 +341,7 @@ impl super::CPU {
 
     /// Set bit in register.
     pub fn set(&mut self, register: u8, value: u8) -> u8 {
-        let result = register | (1 << value);
-        result
+        register | (1 << value)
     }
 
     /// Shift left into Carry. LSB of set to 0.
@@ -407,15 +401,13 @@ impl super::CPU {
         self.registers.f.set_n(false);
         self.registers.f.set_h(false);
         self.registers.f.set_c(false);
-        (value >> 4) | (value << 4)
+        value.rotate_left(4)
     }
 
     /// Bitwise XOR operation with register A.
     pub fn xor(&mut self, value: u8) {
         let result = self.registers.a ^ value;
-        if result == 0 {
-            self.registers.f.set_z(true);
-        }
+        self.registers.f.set_z(result == 0);
         self.registers.a = result;
         self.registers.f.set_n(false); // reset
         self.registers.f.set_h(false); // reset
@@ -423,84 +415,83 @@ impl super::CPU {
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::cpu::CPU;
-//     use crate::mmu::MMU;
+#[cfg(test)]
+mod tests {
+    use crate::cpu::CPU;
+    use crate::memory::FlatMemory;
 
-//     fn assert_flags(cpu: CPU, z: bool, n: bool, h: bool, c: bool) {
-//         let flags = cpu.registers.f;
-//         println!("Flags: {:?}", flags.0);
-//         assert_eq!(flags.z(), z);
-//         assert_eq!(flags.n(), n);
-//         assert_eq!(flags.h(), h);
-//         assert_eq!(flags.c(), c);
-//     }
+    fn assert_flags(cpu: CPU<FlatMemory>, z: bool, n: bool, h: bool, c: bool) {
+        let flags = cpu.registers.f;
+        assert_eq!(flags.z(), z);
+        assert_eq!(flags.n(), n);
+        assert_eq!(flags.h(), h);
+        assert_eq!(flags.c(), c);
+    }
 
-//     // INC
-//     #[test]
-//     fn test_inc() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0x07;
+    // INC
+    #[test]
+    fn test_inc() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0x07;
 
-//         cpu.registers.a = cpu.inc(cpu.registers.a);
+        cpu.registers.a = cpu.inc(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0x08);
-//         assert_flags(cpu, false, false, false, false);
-//     }
+        assert_eq!(cpu.registers.a, 0x08);
+        assert_flags(cpu, false, false, false, false);
+    }
 
-//     #[test]
-//     fn test_inc_half_carry() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0x0F;
+    #[test]
+    fn test_inc_half_carry() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0x0F;
 
-//         cpu.registers.a = cpu.inc(cpu.registers.a);
+        cpu.registers.a = cpu.inc(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0x10);
-//         assert_flags(cpu, false, false, true, false);
-//     }
+        assert_eq!(cpu.registers.a, 0x10);
+        assert_flags(cpu, false, false, true, false);
+    }
 
-//     #[test]
-//     fn test_inc_overflow() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0xFF;
+    #[test]
+    fn test_inc_overflow() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0xFF;
 
-//         cpu.registers.a = cpu.inc(cpu.registers.a);
+        cpu.registers.a = cpu.inc(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0x00);
-//         assert_flags(cpu, true, false, true, false);
-//     }
+        assert_eq!(cpu.registers.a, 0x00);
+        assert_flags(cpu, true, false, true, false);
+    }
 
-//     #[test]
-//     fn test_dec() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0x07;
+    #[test]
+    fn test_dec() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0x07;
 
-//         cpu.registers.a = cpu.dec(cpu.registers.a);
+        cpu.registers.a = cpu.dec(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0x06);
-//         assert_flags(cpu, false, true, false, false);
-//     }
+        assert_eq!(cpu.registers.a, 0x06);
+        assert_flags(cpu, false, true, false, false);
+    }
 
-//     #[test]
-//     fn test_dec_half_carry() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0x80;
+    #[test]
+    fn test_dec_half_carry() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0x80;
 
-//         cpu.registers.a = cpu.dec(cpu.registers.a);
+        cpu.registers.a = cpu.dec(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0x7F);
-//         assert_flags(cpu, false, true, true, false);
-//     }
+        assert_eq!(cpu.registers.a, 0x7F);
+        assert_flags(cpu, false, true, true, false);
+    }
 
-//     #[test]
-//     fn test_dec_overflow() {
-//         let mut cpu = CPU::new();
-//         cpu.registers.a = 0x00;
+    #[test]
+    fn test_dec_overflow() {
+        let mut cpu = CPU::new(FlatMemory::new());
+        cpu.registers.a = 0x00;
 
-//         cpu.registers.a = cpu.dec(cpu.registers.a);
+        cpu.registers.a = cpu.dec(cpu.registers.a);
 
-//         assert_eq!(cpu.registers.a, 0xFF);
-//         assert_flags(cpu, false, true, true, false);
-//     }
-// }
+        assert_eq!(cpu.registers.a, 0xFF);
+        assert_flags(cpu, false, true, true, false);
+    }
+}
@@ -1,6 +1,10 @@
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
 /// Flags = (Zero flag, Subtraction flag (BCD), Half Carry flag (BCD), Carry flag)
+#[derive(Default)]
 pub struct Flags(pub u8);
 
+#[derive(Default)]
 pub struct Registers {
     pub a: u8,
     pub b: u8,
@@ -121,6 +125,32 @@ impl Registers {
     }
 }
 
+impl Snapshot for Registers {
+    fn save(&self, writer: &mut StateWriter) {
+        for value in [
+            self.a, self.f.0, self.b, self.c, self.d, self.e, self.h, self.l,
+        ] {
+            writer.u8(value);
+        }
+        writer.u16(self.sp);
+        writer.u16(self.pc);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.a = reader.u8()?;
+        self.f.0 = reader.u8()?;
+        self.b = reader.u8()?;
+        self.c = reader.u8()?;
+        self.d = reader.u8()?;
+        self.e = reader.u8()?;
+        self.h = reader.u8()?;
+        self.l = reader.u8()?;
+        self.sp = reader.u16()?;
+        self.pc = reader.u16()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,7 +167,7 @@ mod tests {
     fn test_set_f_as_u8() {
         let mut registers = Registers::new();
         let value = 0b1100_0000;
-        registers.f = Flags(value.into());
+        registers.f = Flags(value);
         let result: u8 = registers.f.0;
         assert_eq!(result, value);
     }
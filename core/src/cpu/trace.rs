@@ -0,0 +1,281 @@
+// Instruction trace logging in the gameboy-doctor format, one line per executed instruction:
+// A:00 F:11 B:22 C:33 D:44 E:55 H:66 L:77 SP:8888 PC:9999 PCMEM:AA,BB,CC,DD
+// With symbols, the label of PC is appended as a comment: `... PCMEM:AA,BB,CC,DD ; Main+3`.
+// https://github.com/robert/gameboy-doctor
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::registers::Registers;
+use crate::symbols::Symbols;
+
+/// Point of the execution where tracing starts or stops.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracePoint {
+    /// The instruction at this address is about to run.
+    Pc(u16),
+    /// The CPU has run for at least this many M-cycles.
+    Cycle(u64),
+}
+
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    start: Option<TracePoint>,
+    stop: Option<TracePoint>,
+    active: bool,
+    error: Option<io::Error>,
+    symbols: Option<Symbols>,
+}
+
+impl TracePoint {
+    fn reached(&self, pc: u16, cycles: u64) -> bool {
+        match *self {
+            TracePoint::Pc(address) => pc == address,
+            TracePoint::Cycle(cycle) => cycles >= cycle,
+        }
+    }
+}
+
+impl Tracer {
+    /// Trace every instruction into `writer`.
+    pub fn new<W: Write + 'static>(writer: W) -> Self {
+        Tracer {
+            writer: Box::new(writer),
+            start: None,
+            stop: None,
+            active: true,
+            error: None,
+            symbols: None,
+        }
+    }
+
+    /// Trace every instruction into a file, truncating it.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Only start writing once `point` is reached.
+    pub fn start_at(mut self, point: TracePoint) -> Self {
+        self.start = Some(point);
+        self.active = false;
+        self
+    }
+
+    /// Stop writing once `point` is reached. The instruction at `point` is not traced.
+    pub fn stop_at(mut self, point: TracePoint) -> Self {
+        self.stop = Some(point);
+        self
+    }
+
+    /// Name PC with these labels at the end of each line.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Whether lines are currently being written.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// The first write error, after which tracing is disabled.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Record the state before running the instruction at PC. `pcmem` holds the 4 bytes at PC
+    /// and `bank` the bank mapped at PC.
+    pub fn trace(&mut self, registers: &Registers, pcmem: [u8; 4], bank: u16, cycles: u64) {
+        if let Some(start) = self.start {
+            if start.reached(registers.pc, cycles) {
+                self.active = true;
+                self.start = None;
+            }
+        }
+        if let Some(stop) = self.stop {
+            if self.active && stop.reached(registers.pc, cycles) {
+                self.active = false;
+                self.stop = None;
+                self.flush();
+            }
+        }
+        if !self.active {
+            return;
+        }
+
+        let mut line = format_line(registers, pcmem);
+        if let Some(name) = self
+            .symbols
+            .as_ref()
+            .and_then(|symbols| symbols.name_in(bank, registers.pc))
+        {
+            line = format!("{} ; {}", line, name);
+        }
+        if let Err(error) = writeln!(self.writer, "{}", line) {
+            self.active = false;
+            self.error = Some(error);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            self.error.get_or_insert(error);
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Format the CPU state as a gameboy-doctor line.
+pub fn format_line(registers: &Registers, pcmem: [u8; 4]) -> String {
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        registers.a,
+        registers.f.0,
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+        registers.sp,
+        registers.pc,
+        pcmem[0],
+        pcmem[1],
+        pcmem[2],
+        pcmem[3]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{cpu::CPU, memory::FlatMemory};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    fn cpu_with_program(program: &[u8]) -> CPU<FlatMemory> {
+        let mut memory = FlatMemory::new();
+        memory.load(0xC000, program);
+        let mut cpu = CPU::new(memory);
+        cpu.registers.pc = 0xC000;
+        cpu.registers.sp = 0xDFFE;
+        cpu
+    }
+
+    #[test]
+    fn test_format_line() {
+        let mut registers = Registers::new();
+        registers.a = 0x01;
+        registers.f.0 = 0xB0;
+        registers.c = 0x13;
+        registers.e = 0xD8;
+        registers.h = 0x01;
+        registers.l = 0x4D;
+        registers.sp = 0xFFFE;
+        registers.pc = 0x0100;
+
+        assert_eq!(
+            format_line(&registers, [0x00, 0xC3, 0x13, 0x02]),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02"
+        );
+    }
+
+    #[test]
+    fn test_trace_each_step() {
+        let buffer = SharedBuffer::default();
+        let mut cpu = cpu_with_program(&[0x3E, 0x42, 0x00]); // LD A, $42; NOP
+        cpu.set_tracer(Some(Tracer::new(buffer.clone())));
+
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(
+            buffer.lines(),
+            vec![
+                "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:DFFE PC:C000 PCMEM:3E,42,00,00",
+                "A:42 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:DFFE PC:C002 PCMEM:00,00,00,00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_symbols() {
+        let buffer = SharedBuffer::default();
+        let mut cpu = cpu_with_program(&[0x00, 0x00]);
+        let symbols = Symbols::parse("00:C000 wCode").unwrap();
+        cpu.set_tracer(Some(Tracer::new(buffer.clone()).with_symbols(symbols)));
+
+        cpu.step();
+        cpu.step();
+
+        let lines = buffer.lines();
+        assert!(lines[0].ends_with("PCMEM:00,00,00,00 ; wCode"));
+        assert!(lines[1].ends_with(" ; wCode+1"));
+    }
+
+    #[test]
+    fn test_trace_window_by_pc() {
+        let buffer = SharedBuffer::default();
+        let mut cpu = cpu_with_program(&[0x00; 8]);
+        let tracer = Tracer::new(buffer.clone())
+            .start_at(TracePoint::Pc(0xC002))
+            .stop_at(TracePoint::Pc(0xC005));
+        cpu.set_tracer(Some(tracer));
+
+        for _ in 0..8 {
+            cpu.step();
+        }
+
+        let pcs: Vec<String> = buffer
+            .lines()
+            .iter()
+            .map(|l| l[48..55].to_string())
+            .collect();
+        assert_eq!(pcs, vec!["PC:C002", "PC:C003", "PC:C004"]);
+    }
+
+    #[test]
+    fn test_trace_window_by_cycle() {
+        let buffer = SharedBuffer::default();
+        let mut cpu = cpu_with_program(&[0x00; 8]);
+        let tracer = Tracer::new(buffer.clone()).start_at(TracePoint::Cycle(6));
+        cpu.set_tracer(Some(tracer));
+
+        for _ in 0..8 {
+            cpu.step();
+        }
+
+        assert_eq!(buffer.lines().len(), 2);
+    }
+}
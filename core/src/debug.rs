@@ -0,0 +1,661 @@
+// Debug interface: breakpoints, watchpoints and stepping on top of `GameBoy::step`.
+// Watchpoints are checked by the MMU, which records the watched accesses of each step;
+// everything else only looks at the machine between instructions. `gdb` serves the same
+// interface to GDB remote protocol clients.
+
+use std::fmt;
+
+pub mod gdb;
+
+use crate::{cpu::registers::Registers, disasm, gameboy::GameBoy, memory::Memory};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterName {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Register compared with a value, e.g. `a == $10`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: RegisterName,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    /// Only break when this bank is mapped at the address, see `Memory::bank`.
+    pub bank: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Reads and writes.
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub access: Access,
+}
+
+/// A watched access made by the CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub value: u8,
+    /// `Read` or `Write`.
+    pub access: Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested steps are done.
+    Step,
+    /// About to run the instruction at a breakpoint, holds its index.
+    Breakpoint(usize),
+    /// The last instruction made a watched access, holds the watchpoint index.
+    Watchpoint(usize, WatchHit),
+    /// The cycle budget ran out.
+    Timeout,
+}
+
+/// A call, `rst` or interrupt dispatch that has not returned yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Address of the call instruction, or of the interrupted one.
+    pub call_site: u16,
+    /// Bank mapped at the call site when the call was made.
+    pub bank: u16,
+    pub target: u16,
+    pub return_address: u16,
+    /// Where the return address was pushed. The frame is gone once SP moves above it.
+    pub sp: u16,
+}
+
+/// Breakpoints and watchpoints of a debugging session, identified by their index.
+/// Removed ones leave an empty slot so the other indexes do not change.
+/// Calls are followed while the debugger runs the machine, to give a call stack.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Option<Breakpoint>>,
+    watchpoints: Vec<Option<Watchpoint>>,
+    call_stack: Vec<Frame>,
+}
+
+impl RegisterName {
+    pub fn parse(name: &str) -> Option<Self> {
+        let register = match name.to_ascii_lowercase().as_str() {
+            "a" => RegisterName::A,
+            "f" => RegisterName::F,
+            "b" => RegisterName::B,
+            "c" => RegisterName::C,
+            "d" => RegisterName::D,
+            "e" => RegisterName::E,
+            "h" => RegisterName::H,
+            "l" => RegisterName::L,
+            "af" => RegisterName::AF,
+            "bc" => RegisterName::BC,
+            "de" => RegisterName::DE,
+            "hl" => RegisterName::HL,
+            "sp" => RegisterName::SP,
+            "pc" => RegisterName::PC,
+            _ => return None,
+        };
+        Some(register)
+    }
+
+    pub fn read(&self, registers: &Registers) -> u16 {
+        match self {
+            RegisterName::A => registers.a.into(),
+            RegisterName::F => registers.f.0.into(),
+            RegisterName::B => registers.b.into(),
+            RegisterName::C => registers.c.into(),
+            RegisterName::D => registers.d.into(),
+            RegisterName::E => registers.e.into(),
+            RegisterName::H => registers.h.into(),
+            RegisterName::L => registers.l.into(),
+            RegisterName::AF => registers.af(),
+            RegisterName::BC => registers.bc(),
+            RegisterName::DE => registers.de(),
+            RegisterName::HL => registers.hl(),
+            RegisterName::SP => registers.sp,
+            RegisterName::PC => registers.pc,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn parse(operator: &str) -> Option<Self> {
+        let comparison = match operator {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            _ => return None,
+        };
+        Some(comparison)
+    }
+
+    fn operator(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, registers: &Registers) -> bool {
+        let value = self.register.read(registers);
+        match self.comparison {
+            Comparison::Equal => value == self.value,
+            Comparison::NotEqual => value != self.value,
+            Comparison::Less => value < self.value,
+            Comparison::LessOrEqual => value <= self.value,
+            Comparison::Greater => value > self.value,
+            Comparison::GreaterOrEqual => value >= self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = format!("{:?}", self.register).to_ascii_lowercase();
+        write!(
+            f,
+            "{} {} ${:X}",
+            name,
+            self.comparison.operator(),
+            self.value
+        )
+    }
+}
+
+impl Watchpoint {
+    pub fn matches(&self, address: u16, access: Access) -> bool {
+        (self.start..=self.end).contains(&address)
+            && (self.access == Access::ReadWrite || self.access == access)
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    /// Add a breakpoint and return its index.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len() - 1
+    }
+
+    /// Add a watchpoint and return its index. `update_watchpoints` must be called after.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.watchpoints.push(Some(watchpoint));
+        self.watchpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> bool {
+        self.breakpoints
+            .get_mut(index)
+            .and_then(Option::take)
+            .is_some()
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> bool {
+        self.watchpoints
+            .get_mut(index)
+            .and_then(Option::take)
+            .is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.as_ref().map(|b| (i, b)))
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| w.as_ref().map(|w| (i, w)))
+    }
+
+    /// Give the watchpoints to the MMU, which checks them on every access.
+    pub fn update_watchpoints(&self, gameboy: &mut GameBoy) {
+        let watchpoints = self.watchpoints().map(|(_, w)| *w).collect();
+        gameboy.cpu_mut().mmu_mut().set_watchpoints(watchpoints);
+    }
+
+    /// Calls made while the debugger ran the machine, the innermost last.
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /// Forget the call stack, after the machine state was replaced (e.g. a loaded state).
+    pub fn clear_call_stack(&mut self) {
+        self.call_stack.clear();
+    }
+
+    /// Index of a breakpoint at PC whose bank is mapped and whose condition holds.
+    pub fn breakpoint_hit(&self, gameboy: &GameBoy) -> Option<usize> {
+        let registers = gameboy.cpu().registers();
+        let bank = gameboy.mmu().bank(registers.pc);
+        self.breakpoints().find_map(|(i, breakpoint)| {
+            let hit = breakpoint.address == registers.pc
+                && breakpoint.bank.is_none_or(|b| b == bank)
+                && breakpoint.condition.is_none_or(|c| c.holds(registers));
+            hit.then_some(i)
+        })
+    }
+
+    /// Run one instruction and report the first watched access it made.
+    fn step_watched(&mut self, gameboy: &mut GameBoy) -> Option<StopReason> {
+        let cpu = gameboy.cpu();
+        let (pc, sp) = (cpu.registers().pc, cpu.registers().sp);
+        let instruction = disasm::decode(cpu.mmu(), pc);
+        let bank = cpu.mmu().bank(pc);
+
+        gameboy.cpu_mut().mmu_mut().take_watch_hits();
+        gameboy.step();
+        let hits = gameboy.cpu_mut().mmu_mut().take_watch_hits();
+        self.follow_calls(gameboy, &instruction, bank, sp);
+
+        let hit = *hits.first()?;
+        let (index, _) = self
+            .watchpoints()
+            .find(|(_, w)| w.matches(hit.address, hit.access))?;
+        Some(StopReason::Watchpoint(index, hit))
+    }
+
+    /// Update the call stack after running `instruction` with SP at `sp`.
+    fn follow_calls(
+        &mut self,
+        gameboy: &GameBoy,
+        instruction: &disasm::Instruction,
+        bank: u16,
+        sp: u16,
+    ) {
+        let registers = gameboy.cpu().registers();
+        while self
+            .call_stack
+            .last()
+            .is_some_and(|frame| frame.sp < registers.sp)
+        {
+            self.call_stack.pop();
+        }
+        if registers.sp != sp.wrapping_sub(2) {
+            return;
+        }
+
+        let memory = gameboy.mmu();
+        let pushed = u16::from(memory.read_byte(registers.sp))
+            | u16::from(memory.read_byte(registers.sp.wrapping_add(1))) << 8;
+        let call = instruction.is_call() && pushed == instruction.next_address();
+        // An interrupt dispatch pushes the address of the instruction it interrupted.
+        let interrupt = pushed == instruction.address
+            && (0x40..=0x60).contains(&registers.pc)
+            && registers.pc.is_multiple_of(8);
+        if call || interrupt {
+            self.call_stack.push(Frame {
+                call_site: instruction.address,
+                bank,
+                target: registers.pc,
+                return_address: pushed,
+                sp: registers.sp,
+            });
+        }
+    }
+
+    /// Run until `done` returns true after an instruction, a breakpoint or watchpoint is hit,
+    /// or `max_cycles` M-cycles have run. The breakpoint at the starting PC is ignored.
+    pub fn run_until<F: FnMut(&GameBoy) -> bool>(
+        &mut self,
+        gameboy: &mut GameBoy,
+        max_cycles: u64,
+        mut done: F,
+    ) -> StopReason {
+        let start = gameboy.cpu().cycles();
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some(index) = self.breakpoint_hit(gameboy) {
+                    return StopReason::Breakpoint(index);
+                }
+            }
+            first = false;
+            if let Some(reason) = self.step_watched(gameboy) {
+                return reason;
+            }
+            if done(gameboy) {
+                return StopReason::Step;
+            }
+            if gameboy.cpu().cycles() - start >= max_cycles {
+                return StopReason::Timeout;
+            }
+        }
+    }
+
+    /// Run one instruction.
+    pub fn step(&mut self, gameboy: &mut GameBoy) -> StopReason {
+        self.run_until(gameboy, u64::MAX, |_| true)
+    }
+
+    /// Run one instruction, running calls and `rst` until they return.
+    pub fn step_over(&mut self, gameboy: &mut GameBoy, max_cycles: u64) -> StopReason {
+        let cpu = gameboy.cpu();
+        let instruction = disasm::decode(cpu.mmu(), cpu.registers().pc);
+        if !instruction.is_call() {
+            return self.step(gameboy);
+        }
+        let return_address = instruction.next_address();
+        let sp = cpu.registers().sp;
+        self.run_until(gameboy, max_cycles, |gameboy| {
+            let registers = gameboy.cpu().registers();
+            registers.pc == return_address && registers.sp >= sp
+        })
+    }
+
+    /// Run until the current function returns, that is until SP goes above its current value.
+    pub fn step_out(&mut self, gameboy: &mut GameBoy, max_cycles: u64) -> StopReason {
+        let sp = gameboy.cpu().registers().sp;
+        self.run_until(gameboy, max_cycles, |gameboy| {
+            gameboy.cpu().registers().sp > sp
+        })
+    }
+
+    /// Run until a breakpoint or watchpoint, or for at most `max_cycles` M-cycles.
+    pub fn resume(&mut self, gameboy: &mut GameBoy, max_cycles: u64) -> StopReason {
+        self.run_until(gameboy, max_cycles, |_| false)
+    }
+}
+
+/// Disassemble `count` instructions starting `before` instructions ahead of `address`.
+/// Decoding backwards is ambiguous, so decoding starts a few bytes further back and the
+/// first start whose instructions line up on `address` wins: misaligned streams tend to
+/// fall back in line after a few instructions. The search looks back at most 64KB.
+pub fn disassemble_around<M: Memory + ?Sized>(
+    memory: &M,
+    address: u16,
+    before: usize,
+    count: usize,
+) -> Vec<disasm::Instruction> {
+    if before == 0 {
+        return disasm::disassemble(memory, address, count);
+    }
+    let window = before.saturating_mul(3).saturating_add(8).min(0xFFFF) as u16;
+    let start = (1..=window)
+        .rev()
+        .map(|back| address.wrapping_sub(back))
+        .find_map(|start| {
+            let mut starts = Vec::new();
+            let mut at = start;
+            while at.wrapping_sub(start) < address.wrapping_sub(start) {
+                starts.push(at);
+                at = disasm::decode(memory, at).next_address();
+            }
+            (at == address).then(|| starts[starts.len().saturating_sub(before)])
+        })
+        .unwrap_or(address);
+    disasm::disassemble(memory, start, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::trace::Tracer, model::Model};
+
+    /// Machine running `program` at 0x0150 with SP at 0xDFFE.
+    fn gameboy(program: &[u8]) -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+        let mut gameboy = GameBoy::new(rom).unwrap();
+        gameboy.cpu_mut().registers_mut().pc = 0x0150;
+        gameboy.cpu_mut().registers_mut().sp = 0xDFFE;
+        gameboy
+    }
+
+    fn program() -> GameBoy {
+        gameboy(&[
+            0x06, 0x00, //       0150 ld b, 0
+            0x04, //             0152 inc b
+            0xCD, 0x58, 0x01, // 0153 call $0158
+            0x18, 0xFA, //       0156 jr $0152
+            0xEA, 0x00, 0xC0, // 0158 ld [$C000], a
+            0x3C, //             015B inc a
+            0xC9, //             015C ret
+        ])
+    }
+
+    #[test]
+    fn test_breakpoint_with_condition() {
+        let mut gameboy = program();
+        let mut debugger = Debugger::new();
+        let index = debugger.add_breakpoint(Breakpoint {
+            address: 0x0153,
+            bank: None,
+            condition: Some(Condition {
+                register: RegisterName::B,
+                comparison: Comparison::Equal,
+                value: 3,
+            }),
+        });
+
+        assert_eq!(
+            debugger.resume(&mut gameboy, 10_000),
+            StopReason::Breakpoint(index)
+        );
+        assert_eq!(gameboy.cpu().registers().pc, 0x0153);
+        assert_eq!(gameboy.cpu().registers().b, 3);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut gameboy = program();
+        let mut debugger = Debugger::new();
+        let index = debugger.add_watchpoint(Watchpoint {
+            start: 0xC000,
+            end: 0xC000,
+            access: Access::Write,
+        });
+        debugger.update_watchpoints(&mut gameboy);
+
+        let reason = debugger.resume(&mut gameboy, 10_000);
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint(
+                index,
+                WatchHit {
+                    address: 0xC000,
+                    value: 0x01,
+                    access: Access::Write
+                }
+            )
+        );
+        assert_eq!(gameboy.cpu().registers().pc, 0x015B);
+    }
+
+    /// Machine of `model` running `program` at 0x0150, with `reti` at the V-Blank vector.
+    fn machine(model: Model, program: &[u8]) -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0040] = 0xD9;
+        rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+        let mut gameboy = GameBoy::with_model(rom, model).unwrap();
+        gameboy.cpu_mut().registers_mut().pc = 0x0150;
+        gameboy
+    }
+
+    fn watch(debugger: &mut Debugger, start: u16, end: u16, access: Access) {
+        debugger.add_watchpoint(Watchpoint { start, end, access });
+    }
+
+    #[test]
+    fn test_watchpoints_ignore_interrupts_and_tracing() {
+        let mut gameboy = machine(
+            Model::Dmg,
+            &[
+                0x3E, 0x01, //       0150 ld a, $01
+                0xE0, 0xFF, //       0152 ldh [IE], a
+                0xFB, //             0154 ei
+                0x00, //             0155 nop
+                0x18, 0xFD, //       0156 jr $0155
+            ],
+        );
+        gameboy
+            .cpu_mut()
+            .set_tracer(Some(Tracer::new(std::io::sink())));
+        let mut debugger = Debugger::new();
+        // The V-Blank interrupt is polled and dispatched, and the tracer reads past the loop.
+        watch(&mut debugger, 0xFF0F, 0xFF0F, Access::ReadWrite);
+        watch(&mut debugger, 0xFFFF, 0xFFFF, Access::Read);
+        watch(&mut debugger, 0x0158, 0x0159, Access::Read);
+        debugger.update_watchpoints(&mut gameboy);
+        assert_eq!(debugger.resume(&mut gameboy, 50_000), StopReason::Timeout);
+        assert!(gameboy.cpu().cycles() >= 50_000);
+    }
+
+    #[test]
+    fn test_watchpoints_ignore_dma() {
+        let mut gameboy = machine(
+            Model::Cgb,
+            &[
+                0x3E, 0xC0, // ld a, $C0
+                0xE0, 0x46, // ldh [DMA], a
+                0xE0, 0x51, // ldh [HDMA1], a
+                0xAF, //       xor a
+                0xE0, 0x52, // ldh [HDMA2], a
+                0xE0, 0x54, // ldh [HDMA4], a
+                0x3E, 0x80, // ld a, $80
+                0xE0, 0x53, // ldh [HDMA3], a
+                0x3E, 0x01, // ld a, $01
+                0xE0, 0x55, // ldh [HDMA5], a (32 bytes now)
+                0x18, 0xFE, // jr @
+            ],
+        );
+        let mut debugger = Debugger::new();
+        watch(&mut debugger, 0xC000, 0xC09F, Access::Read);
+        debugger.update_watchpoints(&mut gameboy);
+        assert_eq!(debugger.resume(&mut gameboy, 1_000), StopReason::Timeout);
+        assert_eq!(gameboy.mmu().peek(0xFF55), 0xFF); // HDMA done
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut gameboy = program();
+        let mut debugger = Debugger::new();
+        debugger.step(&mut gameboy);
+        debugger.step(&mut gameboy);
+        assert_eq!(gameboy.cpu().registers().pc, 0x0153);
+
+        assert_eq!(debugger.step_over(&mut gameboy, 10_000), StopReason::Step);
+        assert_eq!(gameboy.cpu().registers().pc, 0x0156);
+        assert_eq!(gameboy.cpu().registers().a, 0x02);
+
+        debugger.step(&mut gameboy);
+        debugger.step(&mut gameboy);
+        debugger.step(&mut gameboy);
+        assert_eq!(gameboy.cpu().registers().pc, 0x0158);
+        assert_eq!(
+            debugger.call_stack(),
+            &[Frame {
+                call_site: 0x0153,
+                bank: 0,
+                target: 0x0158,
+                return_address: 0x0156,
+                sp: 0xDFFC,
+            }]
+        );
+        assert_eq!(debugger.step_out(&mut gameboy, 10_000), StopReason::Step);
+        assert_eq!(gameboy.cpu().registers().pc, 0x0156);
+        assert!(debugger.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_interrupt_frame() {
+        let mut gameboy = gameboy(&[0xFB, 0x00, 0x00]); // ei; nop; nop
+        gameboy.cpu_mut().mmu_mut().write_byte(0xFFFF, 0x01);
+        let mut debugger = Debugger::new();
+        for _ in 0..3 {
+            debugger.step(&mut gameboy);
+        }
+        assert_eq!(gameboy.cpu().registers().pc, 0x0040);
+        let frame = debugger.call_stack()[0];
+        assert_eq!((frame.call_site, frame.target), (0x0152, 0x0040));
+    }
+
+    #[test]
+    fn test_breakpoint_bank() {
+        let mut gameboy = program();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint {
+            address: 0x0153,
+            bank: Some(1),
+            condition: None,
+        });
+        assert_eq!(debugger.resume(&mut gameboy, 1_000), StopReason::Timeout);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut gameboy = gameboy(&[0x18, 0xFE]);
+        assert_eq!(
+            Debugger::new().resume(&mut gameboy, 100),
+            StopReason::Timeout
+        );
+    }
+
+    #[test]
+    fn test_disassemble_around() {
+        let gameboy = program();
+        let instructions = disassemble_around(gameboy.mmu(), 0x0156, 2, 4);
+        let addresses: Vec<u16> = instructions.iter().map(|i| i.address).collect();
+        assert_eq!(addresses, vec![0x0152, 0x0153, 0x0156, 0x0158]);
+
+        let instructions = disassemble_around(gameboy.mmu(), 0x0156, 0, 2);
+        let addresses: Vec<u16> = instructions.iter().map(|i| i.address).collect();
+        assert_eq!(addresses, vec![0x0156, 0x0158]);
+
+        // More instructions than fit in memory start as far back as the search goes.
+        let instructions = disassemble_around(gameboy.mmu(), 0x0156, usize::MAX, 3);
+        assert_eq!(instructions.len(), 3);
+        assert!(instructions[0].address > 0x0156);
+    }
+}
@@ -0,0 +1,507 @@
+// GDB remote serial protocol (RSP) stub, serving one client over TCP.
+// Packets are `$data#checksum`, acknowledged with `+`. A 0x03 byte from the client interrupts
+// a running target. Registers are 16-bit little endian, in the order of `REGISTERS`, and
+// described to the client by target.xml as a Z80 subset.
+//
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use super::{Access, Breakpoint, Debugger, StopReason, Watchpoint};
+use crate::{
+    gameboy::{GameBoy, CYCLES_PER_FRAME},
+    memory::Memory,
+};
+
+const REGISTERS: [&str; 6] = ["af", "bc", "de", "hl", "sp", "pc"];
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<architecture>z80</architecture>
+<feature name="org.gnu.gdb.z80.cpu">
+<reg name="af" bitsize="16" type="int"/>
+<reg name="bc" bitsize="16" type="data_ptr"/>
+<reg name="de" bitsize="16" type="data_ptr"/>
+<reg name="hl" bitsize="16" type="data_ptr"/>
+<reg name="sp" bitsize="16" type="data_ptr"/>
+<reg name="pc" bitsize="16" type="code_ptr"/>
+</feature>
+</target>
+"#;
+
+/// Signals of stop replies.
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+pub struct GdbStub<'a> {
+    gameboy: &'a mut GameBoy,
+    debugger: Debugger,
+    stream: TcpStream,
+    /// Bytes received but not parsed yet.
+    input: Vec<u8>,
+}
+
+fn hex_byte(text: &str) -> Option<u8> {
+    u8::from_str_radix(text.get(..2)?, 16).ok()
+}
+
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| hex_byte(&text[i..]))
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse `addr,len` as sent by the client.
+fn address_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let address = u16::from_str_radix(address, 16).ok()?;
+    let length = usize::from_str_radix(length, 16).ok()?;
+    Some((address, length))
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(gameboy: &'a mut GameBoy, stream: TcpStream) -> Self {
+        GdbStub {
+            gameboy,
+            debugger: Debugger::new(),
+            stream,
+            input: Vec::new(),
+        }
+    }
+
+    /// Serve the client until it detaches, kills the target or disconnects.
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.as_str() {
+                "k" => return Ok(()),
+                "D" => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                _ => self.handle(&packet)?,
+            };
+            self.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Reply to a packet, an empty reply means it is not supported.
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let Some((command, args)) = packet.split_at_checked(1) else {
+            return Ok(String::new());
+        };
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| self.register(n))
+                .map_or_else(|| "E01".to_string(), |value| to_hex(&value.to_le_bytes())),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "c" | "s" => {
+                if let Ok(address) = u16::from_str_radix(args, 16) {
+                    self.gameboy.cpu_mut().registers_mut().pc = address;
+                }
+                if command == "s" {
+                    let reason = self.debugger.step(self.gameboy);
+                    self.stop_reply(reason)
+                } else {
+                    self.resume()?
+                }
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => "OK".to_string(),
+            "q" => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;swbreak+".to_string();
+        }
+        if packet == "qAttached" {
+            return "1".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = address_length(range) else {
+                return "E01".to_string();
+            };
+            let offset = (offset as usize).min(TARGET_XML.len());
+            let end = (offset + length).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &TARGET_XML[offset..end]);
+        }
+        String::new()
+    }
+
+    fn register(&self, number: usize) -> Option<u16> {
+        let registers = self.gameboy.cpu().registers();
+        let value = match number {
+            0 => registers.af(),
+            1 => registers.bc(),
+            2 => registers.de(),
+            3 => registers.hl(),
+            4 => registers.sp,
+            5 => registers.pc,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn set_register(&mut self, number: usize, value: u16) -> bool {
+        let registers = self.gameboy.cpu_mut().registers_mut();
+        match number {
+            0 => registers.set_af(value),
+            1 => registers.set_bc(value),
+            2 => registers.set_de(value),
+            3 => registers.set_hl(value),
+            4 => registers.sp = value,
+            5 => registers.pc = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTERS.len())
+            .filter_map(|n| self.register(n))
+            .map(|value| to_hex(&value.to_le_bytes()))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        match hex_bytes(args) {
+            Some(bytes) if bytes.len() >= REGISTERS.len() * 2 => {
+                for (n, value) in bytes.chunks(2).take(REGISTERS.len()).enumerate() {
+                    self.set_register(n, u16::from_le_bytes([value[0], value[1]]));
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let written = args.split_once('=').and_then(|(number, value)| {
+            let number = usize::from_str_radix(number, 16).ok()?;
+            let bytes = hex_bytes(value).filter(|bytes| bytes.len() == 2)?;
+            Some(self.set_register(number, u16::from_le_bytes([bytes[0], bytes[1]])))
+        });
+        match written {
+            Some(true) => "OK".to_string(),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = address_length(args) else {
+            return "E01".to_string();
+        };
+        let memory = self.gameboy.mmu();
+        let bytes: Vec<u8> = (0..length.min(0x10000))
+            .map(|i| memory.read_byte(address.wrapping_add(i as u16)))
+            .collect();
+        to_hex(&bytes)
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let Some((range, data)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let (Some((address, length)), Some(bytes)) = (address_length(range), hex_bytes(data))
+        else {
+            return "E01".to_string();
+        };
+        if bytes.len() != length {
+            return "E01".to_string();
+        }
+        let memory = self.gameboy.cpu_mut().mmu_mut();
+        for (i, &byte) in bytes.iter().enumerate() {
+            memory.write_byte(address.wrapping_add(i as u16), byte);
+        }
+        "OK".to_string()
+    }
+
+    /// Insert (`Z`) or remove (`z`) a breakpoint or watchpoint, `type,addr,kind`.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(length)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return "E01".to_string();
+        };
+        let (Ok(address), Ok(length)) = (
+            u16::from_str_radix(address, 16),
+            u16::from_str_radix(length, 16),
+        ) else {
+            return "E01".to_string();
+        };
+        let access = match kind {
+            "0" | "1" => None,
+            "2" => Some(Access::Write),
+            "3" => Some(Access::Read),
+            "4" => Some(Access::ReadWrite),
+            _ => return String::new(),
+        };
+
+        match access {
+            None if insert => {
+                self.debugger.add_breakpoint(Breakpoint {
+                    address,
+                    bank: None,
+                    condition: None,
+                });
+            }
+            None => {
+                let found = self
+                    .debugger
+                    .breakpoints()
+                    .find(|(_, b)| {
+                        b.address == address && b.bank.is_none() && b.condition.is_none()
+                    })
+                    .map(|(i, _)| i);
+                if let Some(index) = found {
+                    self.debugger.remove_breakpoint(index);
+                }
+            }
+            Some(access) => {
+                let watchpoint = Watchpoint {
+                    start: address,
+                    end: address.wrapping_add(length.max(1) - 1),
+                    access,
+                };
+                if insert {
+                    self.debugger.add_watchpoint(watchpoint);
+                } else {
+                    let found = self
+                        .debugger
+                        .watchpoints()
+                        .find(|(_, w)| **w == watchpoint)
+                        .map(|(i, _)| i);
+                    if let Some(index) = found {
+                        self.debugger.remove_watchpoint(index);
+                    }
+                }
+                self.debugger.update_watchpoints(self.gameboy);
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// Run a frame at a time until something stops the target or the client interrupts it.
+    fn resume(&mut self) -> io::Result<String> {
+        loop {
+            match self.debugger.resume(self.gameboy, CYCLES_PER_FRAME.into()) {
+                StopReason::Timeout => {
+                    if self.interrupted()? {
+                        return Ok(format!("S{:02x}", SIGINT));
+                    }
+                }
+                reason => return Ok(self.stop_reply(reason)),
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Watchpoint(index, hit) => {
+                let kind = match self.debugger.watchpoints().find(|(i, _)| *i == index) {
+                    Some((_, w)) if w.access == Access::ReadWrite => "awatch",
+                    _ if hit.access == Access::Read => "rwatch",
+                    _ => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.address)
+            }
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    /// Whether the client sent a break (0x03) while the target was running.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 256];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                let interrupted = buffer[..n].contains(&0x03);
+                self.input
+                    .extend(buffer[..n].iter().filter(|&&byte| byte != 0x03));
+                Ok(interrupted)
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Next packet with a valid checksum, or None when the client disconnected.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Acknowledgements and breaks outside a packet are dropped.
+            let start = self.input.iter().position(|&byte| byte == b'$');
+            if start.is_none() {
+                self.input.clear();
+            }
+            if let Some(start) = start {
+                self.input.drain(..start);
+                if let Some(end) = self.input.iter().position(|&byte| byte == b'#') {
+                    if self.input.len() >= end + 3 {
+                        let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                        let data = &packet[1..end];
+                        let checksum = std::str::from_utf8(&packet[end + 1..])
+                            .ok()
+                            .and_then(hex_byte);
+                        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+                        if checksum == Some(sum) {
+                            self.stream.write_all(b"+")?;
+                            return Ok(Some(String::from_utf8_lossy(data).into_owned()));
+                        }
+                        self.stream.write_all(b"-")?;
+                        continue;
+                    }
+                }
+            }
+
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer)? {
+                0 => return Ok(None),
+                n => self.input.extend_from_slice(&buffer[..n]),
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, checksum)?;
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    /// Client side of a connection to a stub running `program` at 0x0150.
+    struct Client {
+        stream: TcpStream,
+        server: thread::JoinHandle<()>,
+    }
+
+    impl Client {
+        fn new(program: &'static [u8]) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let server = thread::spawn(move || {
+                let mut rom = vec![0; 0x8000];
+                rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+                let mut gameboy = GameBoy::new(rom).unwrap();
+                gameboy.cpu_mut().registers_mut().pc = 0x0150;
+                let (stream, _) = listener.accept().unwrap();
+                GdbStub::new(&mut gameboy, stream).run().unwrap();
+            });
+            Client {
+                stream: TcpStream::connect(address).unwrap(),
+                server,
+            }
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let mut received = Vec::new();
+            let mut byte = [0];
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                received.push(byte[0]);
+                if received.len() >= 3 && received[received.len() - 3] == b'#' {
+                    break;
+                }
+            }
+            let text = String::from_utf8(received).unwrap();
+            let text = text.trim_start_matches('+');
+            text[1..text.len() - 3].to_string()
+        }
+
+        fn detach(mut self) {
+            assert_eq!(self.request("D"), "OK");
+            self.server.join().unwrap();
+        }
+    }
+
+    const PROGRAM: &[u8] = &[
+        0x3C, //             0150 inc a
+        0xEA, 0x00, 0xC0, // 0151 ld [$C000], a
+        0x18, 0xFA, //       0154 jr $0150
+    ];
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut client = Client::new(PROGRAM);
+        assert!(client.request("qSupported:swbreak+").contains("qXfer"));
+        assert!(client
+            .request("qXfer:features:read:target.xml:0,1000")
+            .starts_with("l<?xml"));
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("g"), "b0011300d8004d01feff5001");
+        assert_eq!(client.request("p5"), "5001");
+        assert_eq!(client.request("P1=3412"), "OK");
+        assert_eq!(client.request("p1"), "3412");
+        assert_eq!(client.request("m150,3"), "3cea00");
+        assert_eq!(client.request("Mc000,2:abcd"), "OK");
+        assert_eq!(client.request("mc000,2"), "abcd");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p0"), "1002");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+        client.detach();
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let mut client = Client::new(PROGRAM);
+        assert_eq!(client.request("Z0,154,1"), "OK");
+        assert_eq!(client.request("c"), "T05swbreak:;");
+        assert_eq!(client.request("p5"), "5401");
+        assert_eq!(client.request("z0,154,1"), "OK");
+
+        assert_eq!(client.request("Z2,c000,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:c000;");
+        assert_eq!(client.request("mc000,1"), "03");
+        assert_eq!(client.request("z2,c000,1"), "OK");
+        client.detach();
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut client = Client::new(PROGRAM);
+        client.stream.write_all(b"$c#63").unwrap();
+        thread::sleep(std::time::Duration::from_millis(50));
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(), "S02");
+        client.detach();
+    }
+}
@@ -0,0 +1,885 @@
+// SM83 disassembler.
+//
+// Opcodes are decoded from their bit fields rather than a 512 entry table:
+//   xx yyy zzz  (y = pp q)
+// x selects the instruction block, y and z select registers, conditions,
+// ALU operations or bit indexes. See https://gbdev.io/gb-opcodes/optables/
+// for the full tables this mirrors.
+//
+// Text output follows RGBDS syntax (lowercase mnemonics, `$` hex literals,
+// `[hl+]`, `ldh [c], a`).
+
+use std::fmt;
+
+use crate::memory::Memory;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mnemonic {
+    Adc,
+    Add,
+    And,
+    Bit,
+    Call,
+    Ccf,
+    Cp,
+    Cpl,
+    Daa,
+    Dec,
+    Di,
+    Ei,
+    Halt,
+    Inc,
+    Jp,
+    Jr,
+    Ld,
+    Ldh,
+    Nop,
+    Or,
+    Pop,
+    Push,
+    Res,
+    Ret,
+    Reti,
+    Rl,
+    Rla,
+    Rlc,
+    Rlca,
+    Rr,
+    Rra,
+    Rrc,
+    Rrca,
+    Rst,
+    Sbc,
+    Scf,
+    Set,
+    Sla,
+    Sra,
+    Srl,
+    Stop,
+    Sub,
+    Swap,
+    Xor,
+    /// Opcode not defined on the SM83, rendered as a `db` directive.
+    Illegal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    NZ,
+    Z,
+    NC,
+    C,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    /// Memory pointed by a register: `[bc]`, `[de]`, `[hl]` or `[c]` (0xFF00 + C).
+    Indirect(Register),
+    /// `[hl+]`, HL is incremented after the access.
+    IndirectIncrement,
+    /// `[hl-]`, HL is decremented after the access.
+    IndirectDecrement,
+    Immediate8(u8),
+    Immediate16(u16),
+    /// Signed immediate used by `add sp, e8`.
+    Signed(i8),
+    /// `[$nnnn]`
+    Address(u16),
+    /// `[$FFnn]`, used by `ldh`.
+    HighAddress(u8),
+    /// Branch target of `jr`, already resolved to an absolute address.
+    Relative {
+        offset: i8,
+        target: u16,
+    },
+    /// `sp + e8`, used by `ld hl, sp + e8`.
+    SpOffset(i8),
+    Condition(Condition),
+    Bit(u8),
+    Vector(u8),
+}
+
+/// How an instruction affects a single flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlagEffect {
+    Unaffected,
+    Reset,
+    Set,
+    /// Depends on the result of the operation.
+    Affected,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlagsAffected {
+    pub z: FlagEffect,
+    pub n: FlagEffect,
+    pub h: FlagEffect,
+    pub c: FlagEffect,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    /// Whether the opcode comes after the 0xCB prefix.
+    pub prefixed: bool,
+    pub mnemonic: Mnemonic,
+    pub operands: Vec<Operand>,
+    /// Size in bytes, including the prefix and immediates.
+    pub length: u8,
+    /// Machine cycles (1 M-cycle = 4 clocks). For conditional instructions
+    /// this is the cost when the branch is not taken.
+    pub cycles: u8,
+    /// Machine cycles when a conditional branch is taken.
+    pub branch_cycles: Option<u8>,
+    pub flags: FlagsAffected,
+}
+
+const R8: [Operand; 8] = [
+    Operand::Register(Register::B),
+    Operand::Register(Register::C),
+    Operand::Register(Register::D),
+    Operand::Register(Register::E),
+    Operand::Register(Register::H),
+    Operand::Register(Register::L),
+    Operand::Indirect(Register::HL),
+    Operand::Register(Register::A),
+];
+
+const R16: [Register; 4] = [Register::BC, Register::DE, Register::HL, Register::SP];
+const R16_STACK: [Register; 4] = [Register::BC, Register::DE, Register::HL, Register::AF];
+const CONDITIONS: [Condition; 4] = [Condition::NZ, Condition::Z, Condition::NC, Condition::C];
+
+const ALU: [Mnemonic; 8] = [
+    Mnemonic::Add,
+    Mnemonic::Adc,
+    Mnemonic::Sub,
+    Mnemonic::Sbc,
+    Mnemonic::And,
+    Mnemonic::Xor,
+    Mnemonic::Or,
+    Mnemonic::Cp,
+];
+
+const ROTATIONS: [Mnemonic; 8] = [
+    Mnemonic::Rlc,
+    Mnemonic::Rrc,
+    Mnemonic::Rl,
+    Mnemonic::Rr,
+    Mnemonic::Sla,
+    Mnemonic::Sra,
+    Mnemonic::Swap,
+    Mnemonic::Srl,
+];
+
+// M-cycles of each unprefixed opcode. Conditional branches hold the not taken
+// cost, 0xCB holds the cost of the prefix alone and illegal opcodes are 1.
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+    1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 1, 3, 6, 2, 4,
+    2, 3, 3, 1, 3, 4, 2, 4, 2, 4, 3, 1, 3, 1, 2, 4,
+    3, 3, 2, 1, 1, 4, 2, 4, 4, 1, 4, 1, 1, 1, 2, 4,
+    3, 3, 2, 1, 1, 4, 2, 4, 3, 2, 4, 1, 1, 1, 2, 4,
+];
+
+impl FlagEffect {
+    fn from_char(c: char) -> Self {
+        match c {
+            '0' => FlagEffect::Reset,
+            '1' => FlagEffect::Set,
+            '-' => FlagEffect::Unaffected,
+            _ => FlagEffect::Affected,
+        }
+    }
+}
+
+impl FlagsAffected {
+    /// Builds the flag effects from the usual "ZNHC" notation, where `-` is
+    /// unaffected, `0`/`1` are reset/set and a letter is affected.
+    fn from_str(notation: &str) -> Self {
+        let mut chars = notation.chars().map(FlagEffect::from_char);
+        FlagsAffected {
+            z: chars.next().unwrap(),
+            n: chars.next().unwrap(),
+            h: chars.next().unwrap(),
+            c: chars.next().unwrap(),
+        }
+    }
+}
+
+impl fmt::Display for FlagsAffected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let effects = [(self.z, 'Z'), (self.n, 'N'), (self.h, 'H'), (self.c, 'C')];
+        for (effect, name) in effects {
+            let c = match effect {
+                FlagEffect::Unaffected => '-',
+                FlagEffect::Reset => '0',
+                FlagEffect::Set => '1',
+                FlagEffect::Affected => name,
+            };
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Mnemonic::Illegal => "db".to_string(),
+            _ => format!("{:?}", self).to_lowercase(),
+        };
+        f.pad(&name)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+fn signed_hex(value: i8) -> String {
+    if value < 0 {
+        format!("-${:02X}", value.unsigned_abs())
+    } else {
+        format!("${:02X}", value)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Indirect(register) => write!(f, "[{}]", register),
+            Operand::IndirectIncrement => write!(f, "[hl+]"),
+            Operand::IndirectDecrement => write!(f, "[hl-]"),
+            Operand::Immediate8(value) => write!(f, "${:02X}", value),
+            Operand::Immediate16(value) => write!(f, "${:04X}", value),
+            Operand::Signed(value) => write!(f, "{}", signed_hex(*value)),
+            Operand::Address(address) => write!(f, "[${:04X}]", address),
+            Operand::HighAddress(offset) => write!(f, "[$FF{:02X}]", offset),
+            Operand::Relative { target, .. } => write!(f, "${:04X}", target),
+            Operand::SpOffset(offset) if *offset < 0 => {
+                write!(f, "sp - ${:02X}", offset.unsigned_abs())
+            }
+            Operand::SpOffset(offset) => write!(f, "sp + ${:02X}", offset),
+            Operand::Condition(condition) => write!(f, "{}", condition),
+            Operand::Bit(bit) => write!(f, "{}", bit),
+            Operand::Vector(vector) => write!(f, "${:02X}", vector),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, operand)?;
+        }
+        Ok(())
+    }
+}
+
+impl Instruction {
+    /// Address of the instruction that follows this one.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length as u16)
+    }
+
+    /// Whether the instruction can transfer control somewhere other than the next instruction.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self.mnemonic,
+            Mnemonic::Jp
+                | Mnemonic::Jr
+                | Mnemonic::Call
+                | Mnemonic::Ret
+                | Mnemonic::Reti
+                | Mnemonic::Rst
+        )
+    }
+
+    /// Whether the instruction returns to the next instruction through the stack (`call`/`rst`).
+    pub fn is_call(&self) -> bool {
+        matches!(self.mnemonic, Mnemonic::Call | Mnemonic::Rst)
+    }
+}
+
+/// Decode the instruction at `address` of a memory.
+pub fn decode<M: Memory + ?Sized>(memory: &M, address: u16) -> Instruction {
+    decode_with(|a| memory.read_byte(a), address)
+}
+
+/// Decode the instruction at the start of `bytes`, which is assumed to be
+/// located at `address`. Bytes past the end of the slice are read as 0x00.
+pub fn decode_bytes(bytes: &[u8], address: u16) -> Instruction {
+    decode_with(
+        |a| {
+            let index = a.wrapping_sub(address) as usize;
+            bytes.get(index).copied().unwrap_or(0x00)
+        },
+        address,
+    )
+}
+
+/// Decode `count` consecutive instructions starting at `address`.
+pub fn disassemble<M: Memory + ?Sized>(memory: &M, address: u16, count: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = address;
+    for _ in 0..count {
+        let instruction = decode(memory, address);
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Decode an instruction reading bytes through `read`.
+pub fn decode_with<F: Fn(u16) -> u8>(read: F, address: u16) -> Instruction {
+    let opcode = read(address);
+    let d8 = || read(address.wrapping_add(1));
+    let d16 = || {
+        u16::from(read(address.wrapping_add(1))) | (u16::from(read(address.wrapping_add(2))) << 8)
+    };
+
+    if opcode == 0xCB {
+        return decode_prefixed(read(address.wrapping_add(1)), address);
+    }
+
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 0b111;
+    let z = opcode & 0b111;
+    let p = (y >> 1) as usize;
+    let q = y & 1;
+
+    use Mnemonic::*;
+    use Operand::{
+        Address, HighAddress, Immediate16, Immediate8, Indirect, IndirectDecrement,
+        IndirectIncrement, Register as Reg, Relative, Signed, SpOffset, Vector,
+    };
+
+    let relative = || {
+        let offset = d8() as i8;
+        let target = address.wrapping_add(2).wrapping_add(offset as u16);
+        Relative { offset, target }
+    };
+    let a = Reg(Register::A);
+    let hl = Reg(Register::HL);
+    let sp = Reg(Register::SP);
+
+    let (mnemonic, operands, flags): (Mnemonic, Vec<Operand>, &str) = match x {
+        0 => match z {
+            0 => match y {
+                0 => (Nop, vec![], "----"),
+                1 => (Ld, vec![Address(d16()), sp], "----"),
+                2 => (Stop, vec![], "----"),
+                3 => (Jr, vec![relative()], "----"),
+                _ => (
+                    Jr,
+                    vec![Operand::Condition(CONDITIONS[(y - 4) as usize]), relative()],
+                    "----",
+                ),
+            },
+            1 if q == 0 => (Ld, vec![Reg(R16[p]), Immediate16(d16())], "----"),
+            1 => (Add, vec![hl, Reg(R16[p])], "-0HC"),
+            2 => {
+                let memory = match p {
+                    0 => Indirect(Register::BC),
+                    1 => Indirect(Register::DE),
+                    2 => IndirectIncrement,
+                    _ => IndirectDecrement,
+                };
+                if q == 0 {
+                    (Ld, vec![memory, a], "----")
+                } else {
+                    (Ld, vec![a, memory], "----")
+                }
+            }
+            3 if q == 0 => (Inc, vec![Reg(R16[p])], "----"),
+            3 => (Dec, vec![Reg(R16[p])], "----"),
+            4 => (Inc, vec![R8[y as usize]], "Z0H-"),
+            5 => (Dec, vec![R8[y as usize]], "Z1H-"),
+            6 => (Ld, vec![R8[y as usize], Immediate8(d8())], "----"),
+            _ => match y {
+                0 => (Rlca, vec![], "000C"),
+                1 => (Rrca, vec![], "000C"),
+                2 => (Rla, vec![], "000C"),
+                3 => (Rra, vec![], "000C"),
+                4 => (Daa, vec![], "Z-0C"),
+                5 => (Cpl, vec![], "-11-"),
+                6 => (Scf, vec![], "-001"),
+                _ => (Ccf, vec![], "-00C"),
+            },
+        },
+        1 if y == 6 && z == 6 => (Halt, vec![], "----"),
+        1 => (Ld, vec![R8[y as usize], R8[z as usize]], "----"),
+        2 => alu(ALU[y as usize], R8[z as usize]),
+        _ => match z {
+            0 => match y {
+                0..=3 => (
+                    Ret,
+                    vec![Operand::Condition(CONDITIONS[y as usize])],
+                    "----",
+                ),
+                4 => (Ldh, vec![HighAddress(d8()), a], "----"),
+                5 => (Add, vec![sp, Signed(d8() as i8)], "00HC"),
+                6 => (Ldh, vec![a, HighAddress(d8())], "----"),
+                _ => (Ld, vec![hl, SpOffset(d8() as i8)], "00HC"),
+            },
+            1 if q == 0 => {
+                let flags = if R16_STACK[p] == Register::AF {
+                    "ZNHC"
+                } else {
+                    "----"
+                };
+                (Pop, vec![Reg(R16_STACK[p])], flags)
+            }
+            1 => match p {
+                0 => (Ret, vec![], "----"),
+                1 => (Reti, vec![], "----"),
+                2 => (Jp, vec![hl], "----"),
+                _ => (Ld, vec![sp, hl], "----"),
+            },
+            2 => match y {
+                0..=3 => (
+                    Jp,
+                    vec![
+                        Operand::Condition(CONDITIONS[y as usize]),
+                        Immediate16(d16()),
+                    ],
+                    "----",
+                ),
+                4 => (Ldh, vec![Indirect(Register::C), a], "----"),
+                5 => (Ld, vec![Address(d16()), a], "----"),
+                6 => (Ldh, vec![a, Indirect(Register::C)], "----"),
+                _ => (Ld, vec![a, Address(d16())], "----"),
+            },
+            3 => match y {
+                0 => (Jp, vec![Immediate16(d16())], "----"),
+                6 => (Di, vec![], "----"),
+                7 => (Ei, vec![], "----"),
+                _ => (Illegal, vec![Immediate8(opcode)], "----"),
+            },
+            4 => match y {
+                0..=3 => (
+                    Call,
+                    vec![
+                        Operand::Condition(CONDITIONS[y as usize]),
+                        Immediate16(d16()),
+                    ],
+                    "----",
+                ),
+                _ => (Illegal, vec![Immediate8(opcode)], "----"),
+            },
+            5 if q == 0 => (Push, vec![Reg(R16_STACK[p])], "----"),
+            5 if p == 0 => (Call, vec![Immediate16(d16())], "----"),
+            5 => (Illegal, vec![Immediate8(opcode)], "----"),
+            6 => alu(ALU[y as usize], Immediate8(d8())),
+            _ => (Rst, vec![Vector(y * 8)], "----"),
+        },
+    };
+
+    let length = if mnemonic == Illegal {
+        1
+    } else {
+        1 + operands.iter().map(immediate_length).sum::<u8>() + u8::from(mnemonic == Stop)
+    };
+
+    let branch_cycles = if operands
+        .iter()
+        .any(|operand| matches!(operand, Operand::Condition(_)))
+    {
+        Some(match mnemonic {
+            Jr => 3,
+            Jp => 4,
+            Call => 6,
+            _ => 5,
+        })
+    } else {
+        None
+    };
+
+    Instruction {
+        address,
+        opcode,
+        prefixed: false,
+        mnemonic,
+        operands,
+        length,
+        cycles: CYCLES[opcode as usize],
+        branch_cycles,
+        flags: FlagsAffected::from_str(flags),
+    }
+}
+
+fn alu(mnemonic: Mnemonic, operand: Operand) -> (Mnemonic, Vec<Operand>, &'static str) {
+    let flags = match mnemonic {
+        Mnemonic::Add | Mnemonic::Adc => "Z0HC",
+        Mnemonic::Sub | Mnemonic::Sbc | Mnemonic::Cp => "Z1HC",
+        Mnemonic::And => "Z010",
+        _ => "Z000",
+    };
+    (
+        mnemonic,
+        vec![Operand::Register(Register::A), operand],
+        flags,
+    )
+}
+
+fn immediate_length(operand: &Operand) -> u8 {
+    match operand {
+        Operand::Immediate8(_)
+        | Operand::Signed(_)
+        | Operand::HighAddress(_)
+        | Operand::Relative { .. }
+        | Operand::SpOffset(_) => 1,
+        Operand::Immediate16(_) | Operand::Address(_) => 2,
+        _ => 0,
+    }
+}
+
+fn decode_prefixed(opcode: u8, address: u16) -> Instruction {
+    let x = opcode >> 6;
+    let y = (opcode >> 3) & 0b111;
+    let z = opcode & 0b111;
+    let register = R8[z as usize];
+    let is_hl = z == 6;
+
+    let (mnemonic, operands, flags, cycles) = match x {
+        0 => {
+            let mnemonic = ROTATIONS[y as usize];
+            let flags = if mnemonic == Mnemonic::Swap {
+                "Z000"
+            } else {
+                "Z00C"
+            };
+            (mnemonic, vec![register], flags, if is_hl { 4 } else { 2 })
+        }
+        1 => (
+            Mnemonic::Bit,
+            vec![Operand::Bit(y), register],
+            "Z01-",
+            if is_hl { 3 } else { 2 },
+        ),
+        2 => (
+            Mnemonic::Res,
+            vec![Operand::Bit(y), register],
+            "----",
+            if is_hl { 4 } else { 2 },
+        ),
+        _ => (
+            Mnemonic::Set,
+            vec![Operand::Bit(y), register],
+            "----",
+            if is_hl { 4 } else { 2 },
+        ),
+    };
+
+    Instruction {
+        address,
+        opcode,
+        prefixed: true,
+        mnemonic,
+        operands,
+        length: 2,
+        cycles,
+        branch_cycles: None,
+        flags: FlagsAffected::from_str(flags),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: u16 = 0x0150;
+
+    fn text(bytes: &[u8]) -> String {
+        decode_bytes(bytes, ADDRESS).to_string()
+    }
+
+    /// Parses the numeric literal in an operand (`$1F`, `-$02`, `[$FF44]`, `sp - $03`).
+    fn number(operand: &str) -> Option<i32> {
+        let start = operand.find('$')?;
+        let value = i32::from_str_radix(operand[start + 1..].trim_end_matches(']'), 16).ok()?;
+        Some(if operand.contains('-') { -value } else { value })
+    }
+
+    /// Minimal assembler derived from the decoder: tries every encoding whose
+    /// shape matches and keeps those that disassemble back to `source`.
+    fn assemble(source: &str, address: u16) -> Vec<Vec<u8>> {
+        let mut matches = Vec::new();
+        let (mnemonic, operands) = source.split_once(' ').unwrap_or((source, ""));
+        let operands: Vec<&str> = operands.split(", ").filter(|o| !o.is_empty()).collect();
+
+        for prefixed in [false, true] {
+            for opcode in 0..=0xFFu8 {
+                let template = if prefixed {
+                    decode_bytes(&[0xCB, opcode], address)
+                } else {
+                    decode_bytes(&[opcode], address)
+                };
+                if template.prefixed != prefixed
+                    || template.mnemonic.to_string() != mnemonic
+                    || template.operands.len() != operands.len()
+                {
+                    continue;
+                }
+
+                let mut bytes = match template.mnemonic {
+                    _ if prefixed => vec![0xCB, opcode],
+                    // RGBDS emits `stop` followed by a 0x00 padding byte.
+                    Mnemonic::Stop => vec![opcode, 0x00],
+                    _ => vec![opcode],
+                };
+                for (operand, text) in template.operands.iter().zip(&operands) {
+                    let length = immediate_length(operand);
+                    if length == 0 || template.mnemonic == Mnemonic::Illegal {
+                        continue;
+                    }
+                    let Some(value) = number(text) else {
+                        continue;
+                    };
+                    match operand {
+                        Operand::Relative { .. } => {
+                            bytes.push(value.wrapping_sub(address as i32 + 2) as u8)
+                        }
+                        _ if length == 1 => bytes.push(value as u8),
+                        _ => bytes.extend_from_slice(&(value as u16).to_le_bytes()),
+                    }
+                }
+
+                if decode_bytes(&bytes, address).to_string() == source {
+                    matches.push(bytes);
+                }
+            }
+        }
+        matches
+    }
+
+    fn all_encodings() -> Vec<Vec<u8>> {
+        let mut encodings = Vec::new();
+        for opcode in 0..=0xFFu8 {
+            if opcode == 0x10 {
+                encodings.push(vec![opcode, 0x00]);
+            } else if opcode != 0xCB {
+                encodings.push(vec![opcode, 0x34, 0x12]);
+                encodings.push(vec![opcode, 0xF0, 0xFF]);
+            }
+            encodings.push(vec![0xCB, opcode]);
+        }
+        encodings
+    }
+
+    // Checks that the text of every opcode keeps its operands and tells it apart from the
+    // others, the text itself is checked by test_decode_text and test_decode_prefixed_text.
+    #[test]
+    fn test_round_trip_all_opcodes() {
+        for encoding in all_encodings() {
+            let instruction = decode_bytes(&encoding, ADDRESS);
+            let source = instruction.to_string();
+            let assembled = assemble(&source, ADDRESS);
+            let expected = encoding[..instruction.length as usize].to_vec();
+
+            assert_eq!(assembled, vec![expected], "round trip of `{}`", source);
+        }
+    }
+
+    #[test]
+    fn test_opcodes_are_distinct() {
+        let unprefixed: std::collections::HashSet<String> = (0..=0xFFu8)
+            .filter(|&opcode| opcode != 0xCB)
+            .map(|opcode| text(&[opcode, 0x00, 0x00]))
+            .collect();
+        let prefixed: std::collections::HashSet<String> =
+            (0..=0xFFu8).map(|opcode| text(&[0xCB, opcode])).collect();
+
+        assert_eq!(unprefixed.len(), 255);
+        assert_eq!(prefixed.len(), 256);
+    }
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(text(&[0x00]), "nop");
+        assert_eq!(text(&[0x01, 0x34, 0x12]), "ld bc, $1234");
+        assert_eq!(text(&[0x02]), "ld [bc], a");
+        assert_eq!(text(&[0x03]), "inc bc");
+        assert_eq!(text(&[0x04]), "inc b");
+        assert_eq!(text(&[0x05]), "dec b");
+        assert_eq!(text(&[0x06, 0x12]), "ld b, $12");
+        assert_eq!(text(&[0x07]), "rlca");
+        assert_eq!(text(&[0x08, 0x00, 0xC0]), "ld [$C000], sp");
+        assert_eq!(text(&[0x09]), "add hl, bc");
+        assert_eq!(text(&[0x0A]), "ld a, [bc]");
+        assert_eq!(text(&[0x0B]), "dec bc");
+        assert_eq!(text(&[0x0F]), "rrca");
+        assert_eq!(text(&[0x10, 0x00]), "stop");
+        assert_eq!(text(&[0x17]), "rla");
+        assert_eq!(text(&[0x18, 0xFE]), "jr $0150");
+        assert_eq!(text(&[0x1A]), "ld a, [de]");
+        assert_eq!(text(&[0x1F]), "rra");
+        assert_eq!(text(&[0x20, 0x05]), "jr nz, $0157");
+        assert_eq!(text(&[0x21, 0x00, 0xC0]), "ld hl, $C000");
+        assert_eq!(text(&[0x22]), "ld [hl+], a");
+        assert_eq!(text(&[0x27]), "daa");
+        assert_eq!(text(&[0x2A]), "ld a, [hl+]");
+        assert_eq!(text(&[0x2F]), "cpl");
+        assert_eq!(text(&[0x31, 0xFE, 0xFF]), "ld sp, $FFFE");
+        assert_eq!(text(&[0x32]), "ld [hl-], a");
+        assert_eq!(text(&[0x34]), "inc [hl]");
+        assert_eq!(text(&[0x35]), "dec [hl]");
+        assert_eq!(text(&[0x36, 0x7F]), "ld [hl], $7F");
+        assert_eq!(text(&[0x37]), "scf");
+        assert_eq!(text(&[0x38, 0x10]), "jr c, $0162");
+        assert_eq!(text(&[0x39]), "add hl, sp");
+        assert_eq!(text(&[0x3A]), "ld a, [hl-]");
+        assert_eq!(text(&[0x3F]), "ccf");
+        assert_eq!(text(&[0x40]), "ld b, b");
+        assert_eq!(text(&[0x41]), "ld b, c");
+        assert_eq!(text(&[0x5F]), "ld e, a");
+        assert_eq!(text(&[0x70]), "ld [hl], b");
+        assert_eq!(text(&[0x76]), "halt");
+        assert_eq!(text(&[0x7E]), "ld a, [hl]");
+        assert_eq!(text(&[0x7F]), "ld a, a");
+        assert_eq!(text(&[0x80]), "add a, b");
+        assert_eq!(text(&[0x86]), "add a, [hl]");
+        assert_eq!(text(&[0x88]), "adc a, b");
+        assert_eq!(text(&[0x96]), "sub a, [hl]");
+        assert_eq!(text(&[0x9F]), "sbc a, a");
+        assert_eq!(text(&[0xA0]), "and a, b");
+        assert_eq!(text(&[0xAF]), "xor a, a");
+        assert_eq!(text(&[0xB1]), "or a, c");
+        assert_eq!(text(&[0xBE]), "cp a, [hl]");
+        assert_eq!(text(&[0xC0]), "ret nz");
+        assert_eq!(text(&[0xC1]), "pop bc");
+        assert_eq!(text(&[0xC2, 0x00, 0x40]), "jp nz, $4000");
+        assert_eq!(text(&[0xC3, 0x50, 0x01]), "jp $0150");
+        assert_eq!(text(&[0xC5]), "push bc");
+        assert_eq!(text(&[0xC6, 0x01]), "add a, $01");
+        assert_eq!(text(&[0xC7]), "rst $00");
+        assert_eq!(text(&[0xC9]), "ret");
+        assert_eq!(text(&[0xCA, 0x00, 0x40]), "jp z, $4000");
+        assert_eq!(text(&[0xCC, 0x00, 0x40]), "call z, $4000");
+        assert_eq!(text(&[0xCD, 0x00, 0x40]), "call $4000");
+        assert_eq!(text(&[0xCE, 0x02]), "adc a, $02");
+        assert_eq!(text(&[0xD3]), "db $D3");
+        assert_eq!(text(&[0xD6, 0x03]), "sub a, $03");
+        assert_eq!(text(&[0xD8]), "ret c");
+        assert_eq!(text(&[0xD9]), "reti");
+        assert_eq!(text(&[0xDE, 0x04]), "sbc a, $04");
+        assert_eq!(text(&[0xE0, 0x44]), "ldh [$FF44], a");
+        // ld [$ff00+c], a and ld a, [$ff00+c]
+        assert_eq!(text(&[0xE2]), "ldh [c], a");
+        assert_eq!(text(&[0xF2]), "ldh a, [c]");
+        assert_eq!(text(&[0xE6, 0x0F]), "and a, $0F");
+        assert_eq!(text(&[0xE8, 0x05]), "add sp, $05");
+        assert_eq!(text(&[0xE8, 0xFD]), "add sp, -$03");
+        assert_eq!(text(&[0xE9]), "jp hl");
+        assert_eq!(text(&[0xEA, 0x00, 0xC0]), "ld [$C000], a");
+        assert_eq!(text(&[0xEE, 0xFF]), "xor a, $FF");
+        assert_eq!(text(&[0xF0, 0x44]), "ldh a, [$FF44]");
+        assert_eq!(text(&[0xF1]), "pop af");
+        assert_eq!(text(&[0xF3]), "di");
+        assert_eq!(text(&[0xF6, 0x80]), "or a, $80");
+        // ld hl, sp+e8 with a positive and a negative offset
+        assert_eq!(text(&[0xF8, 0x02]), "ld hl, sp + $02");
+        assert_eq!(text(&[0xF8, 0xFE]), "ld hl, sp - $02");
+        assert_eq!(text(&[0xF9]), "ld sp, hl");
+        assert_eq!(text(&[0xFA, 0x00, 0xC0]), "ld a, [$C000]");
+        assert_eq!(text(&[0xFB]), "ei");
+        assert_eq!(text(&[0xFE, 0x90]), "cp a, $90");
+        assert_eq!(text(&[0xFF]), "rst $38");
+    }
+
+    #[test]
+    fn test_decode_prefixed_text() {
+        assert_eq!(text(&[0xCB, 0x00]), "rlc b");
+        assert_eq!(text(&[0xCB, 0x09]), "rrc c");
+        assert_eq!(text(&[0xCB, 0x12]), "rl d");
+        assert_eq!(text(&[0xCB, 0x1B]), "rr e");
+        assert_eq!(text(&[0xCB, 0x24]), "sla h");
+        assert_eq!(text(&[0xCB, 0x2D]), "sra l");
+        assert_eq!(text(&[0xCB, 0x36]), "swap [hl]");
+        assert_eq!(text(&[0xCB, 0x37]), "swap a");
+        assert_eq!(text(&[0xCB, 0x3F]), "srl a");
+        assert_eq!(text(&[0xCB, 0x46]), "bit 0, [hl]");
+        assert_eq!(text(&[0xCB, 0x7C]), "bit 7, h");
+        assert_eq!(text(&[0xCB, 0x7F]), "bit 7, a");
+        assert_eq!(text(&[0xCB, 0x80]), "res 0, b");
+        assert_eq!(text(&[0xCB, 0x86]), "res 0, [hl]");
+        assert_eq!(text(&[0xCB, 0xBE]), "res 7, [hl]");
+        assert_eq!(text(&[0xCB, 0xC0]), "set 0, b");
+        assert_eq!(text(&[0xCB, 0xDE]), "set 3, [hl]");
+        assert_eq!(text(&[0xCB, 0xFF]), "set 7, a");
+    }
+
+    #[test]
+    fn test_length_and_cycles() {
+        let call = decode_bytes(&[0xC4, 0x00, 0x40], ADDRESS);
+        assert_eq!(call.length, 3);
+        assert_eq!(call.cycles, 3);
+        assert_eq!(call.branch_cycles, Some(6));
+        assert_eq!(call.next_address(), 0x0153);
+
+        let bit = decode_bytes(&[0xCB, 0x46], ADDRESS);
+        assert_eq!(bit.length, 2);
+        assert_eq!(bit.cycles, 3);
+
+        let set = decode_bytes(&[0xCB, 0xC6], ADDRESS);
+        assert_eq!(set.cycles, 4);
+
+        assert_eq!(decode_bytes(&[0x08], ADDRESS).cycles, 5);
+        assert_eq!(decode_bytes(&[0xDD], ADDRESS).length, 1);
+        assert_eq!(decode_bytes(&[0x10], ADDRESS).length, 2);
+    }
+
+    #[test]
+    fn test_flags_affected() {
+        assert_eq!(decode_bytes(&[0x04], ADDRESS).flags.to_string(), "Z0H-");
+        assert_eq!(decode_bytes(&[0x03], ADDRESS).flags.to_string(), "----");
+        assert_eq!(decode_bytes(&[0xA0], ADDRESS).flags.to_string(), "Z010");
+        assert_eq!(decode_bytes(&[0x27], ADDRESS).flags.to_string(), "Z-0C");
+        assert_eq!(decode_bytes(&[0xF1], ADDRESS).flags.to_string(), "ZNHC");
+        assert_eq!(
+            decode_bytes(&[0xCB, 0x40], ADDRESS).flags.to_string(),
+            "Z01-"
+        );
+    }
+}
@@ -0,0 +1,482 @@
+// A Game Boy: the CPU running on the MMU with a cartridge inserted.
+// Without a boot ROM, the machine starts in the state the boot ROM of its model leaves it in.
+
+use crate::{
+    boot::BootRom,
+    cartridge::{Cartridge, CartridgeError},
+    cpu::CPU,
+    infrared::IrPeer,
+    joypad::Button,
+    mmu::MMU,
+    model::Model,
+    palette,
+    ppu::Color,
+    state::{self, Snapshot, StateError, StateHeader, StateReader, StateWriter},
+};
+
+/// M-cycles in a frame (154 lines of 114 M-cycles).
+pub const CYCLES_PER_FRAME: u32 = 17556;
+
+pub struct GameBoy {
+    cpu: CPU<MMU>,
+}
+
+impl GameBoy {
+    /// A DMG, see `with_model`.
+    pub fn new(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        GameBoy::with_model(rom, Model::Dmg)
+    }
+
+    pub fn with_model(rom: Vec<u8>, model: Model) -> Result<Self, CartridgeError> {
+        let cartridge = Cartridge::new(rom)?;
+        let mut cpu = CPU::new(MMU::with_model(cartridge, model));
+
+        let [a, f, b, c, d, e, h, l] = boot_registers(cpu.mmu());
+        let registers = cpu.registers_mut();
+        registers.a = a;
+        registers.f.0 = f;
+        registers.b = b;
+        registers.c = c;
+        registers.d = d;
+        registers.e = e;
+        registers.h = h;
+        registers.l = l;
+        registers.sp = 0xFFFE;
+        registers.pc = 0x0100;
+        cpu.set_ime(false);
+        cpu.mmu_mut().skip_boot();
+
+        Ok(GameBoy { cpu })
+    }
+
+    /// Power on with `boot_rom` running from 0x0000, see `boot`.
+    pub fn with_boot_rom(
+        rom: Vec<u8>,
+        model: Model,
+        boot_rom: BootRom,
+    ) -> Result<Self, CartridgeError> {
+        let cartridge = Cartridge::new(rom)?;
+        let mut cpu = CPU::new(MMU::with_boot_rom(cartridge, model, boot_rom));
+        cpu.set_ime(false);
+        Ok(GameBoy { cpu })
+    }
+
+    /// Run one instruction (or interrupt dispatch), returning its M-cycles.
+    pub fn step(&mut self) -> u32 {
+        self.cpu.step()
+    }
+
+    /// Run until the PPU completes a frame, or for a frame's worth of M-cycles when the LCD is off.
+    pub fn run_frame(&mut self) {
+        let frames = self.cpu.mmu().ppu().frames();
+        let mut cycles = 0;
+        let limit = CYCLES_PER_FRAME << self.cpu.mmu().double_speed() as u32;
+        while cycles < limit && self.cpu.mmu().ppu().frames() == frames {
+            cycles += self.cpu.step();
+        }
+    }
+
+    /// Snapshot of the whole machine, see `state` for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.mmu().cartridge().rom_checksum());
+        self.save_chunks(&mut writer);
+        writer.finish()
+    }
+
+    /// CRC-32 of the chunks of `save_state`, leaving out the header so it only changes with
+    /// the machine and not with the emulator version.
+    pub fn state_checksum(&self) -> u32 {
+        let mut writer = StateWriter::default();
+        self.save_chunks(&mut writer);
+        state::crc32(&writer.finish())
+    }
+
+    fn save_chunks(&self, writer: &mut StateWriter) {
+        writer.chunk(b"CPU ", |w| self.cpu.save(w));
+        writer.chunk(b"MMU ", |w| self.cpu.mmu().save(w));
+    }
+
+    /// Restore a snapshot taken with the same ROM. On error the machine is left unchanged.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<StateHeader, StateError> {
+        let (header, reader) = StateReader::new(data)?;
+        let checksum = self.mmu().cartridge().rom_checksum();
+        if header.rom_checksum != checksum {
+            return Err(StateError::RomMismatch(header.rom_checksum));
+        }
+
+        let backup = self.save_state();
+        let result = reader
+            .load_chunk(b"CPU ", &mut self.cpu)
+            .and_then(|_| reader.load_chunk(b"MMU ", self.cpu.mmu_mut()));
+        if let Err(error) = result {
+            let (_, reader) = StateReader::new(&backup)?;
+            reader.load_chunk(b"CPU ", &mut self.cpu)?;
+            reader.load_chunk(b"MMU ", self.cpu.mmu_mut())?;
+            return Err(error);
+        }
+        Ok(header)
+    }
+
+    pub fn cpu(&self) -> &CPU<MMU> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU<MMU> {
+        &mut self.cpu
+    }
+
+    pub fn mmu(&self) -> &MMU {
+        self.cpu.mmu()
+    }
+
+    pub fn model(&self) -> Model {
+        self.cpu.mmu().model()
+    }
+
+    /// The last frame drawn by the PPU, row by row.
+    pub fn frame(&self) -> &[Color] {
+        self.cpu.mmu().ppu().frame()
+    }
+
+    /// The last frame in the SGB border with the SGB colors, 256x224, on an SGB running a game
+    /// with SGB functions.
+    pub fn sgb_frame(&self) -> Option<&[Color]> {
+        self.cpu.mmu().sgb().map(|sgb| sgb.screen())
+    }
+
+    /// Bytes sent over the serial port.
+    pub fn serial_output(&self) -> &[u8] {
+        self.cpu.mmu().serial().output()
+    }
+
+    /// Set the image seen by the sensor of a Pocket Camera, see `Cartridge::set_camera_image`.
+    pub fn set_camera_image(&mut self, image: &[u8]) {
+        self.cpu.mmu_mut().cartridge_mut().set_camera_image(image);
+    }
+
+    /// Tilt the accelerometer of an MBC7 cartridge, see `Cartridge::set_accelerometer`.
+    pub fn set_accelerometer(&mut self, x: f32, y: f32) {
+        self.cpu.mmu_mut().cartridge_mut().set_accelerometer(x, y);
+    }
+
+    /// Contents of the save file of the cartridge, see `Cartridge::save_file`.
+    pub fn save_file(&self) -> Vec<u8> {
+        self.mmu().cartridge().save_file()
+    }
+
+    pub fn load_save_file(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        self.cpu.mmu_mut().cartridge_mut().load_save_file(data)
+    }
+
+    /// Connect the infrared port of the cartridge to `peer`, see `Cartridge::set_ir_peer`.
+    pub fn set_ir_peer(&mut self, peer: Option<Box<dyn IrPeer>>) {
+        self.cpu.mmu_mut().cartridge_mut().set_ir_peer(peer);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.cpu.mmu_mut().joypad_mut().press(button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.cpu.mmu_mut().joypad_mut().release(button);
+    }
+}
+
+/// A, F, B, C, D, E, H and L as the boot ROM of the model leaves them.
+/// https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+fn boot_registers(mmu: &MMU) -> [u8; 8] {
+    match mmu.model() {
+        Model::Dmg => [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Mgb => [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+        model if mmu.cgb_mode() => {
+            let registers = [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D];
+            if model == Model::Agb {
+                agb_registers(registers)
+            } else {
+                registers
+            }
+        }
+        model => {
+            // In DMG compatibility mode B holds the sum of the title bytes for Nintendo games,
+            // which the boot ROM uses to pick their palette.
+            let b = palette::title_checksum(mmu.cartridge()).unwrap_or(0x00);
+            let registers = [0x11, 0x80, b, 0x00, 0x00, 0x08, 0x00, 0x7C];
+            if model == Model::Agb {
+                agb_registers(registers)
+            } else {
+                registers
+            }
+        }
+    }
+}
+
+/// The AGB boot ROM ends with an extra INC B.
+fn agb_registers(mut registers: [u8; 8]) -> [u8; 8] {
+    let b = registers[2].wrapping_add(1);
+    registers[1] = if b == 0 { 0x80 } else { 0x00 } | if b & 0x0F == 0 { 0x20 } else { 0x00 };
+    registers[2] = b;
+    registers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{boot, infrared::IrLink, memory::Memory, ppu::ColorMode, sgb};
+
+    fn rom(program: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+        rom
+    }
+
+    #[test]
+    fn test_post_boot_state() {
+        let gameboy = GameBoy::new(rom(&[])).unwrap();
+        let registers = gameboy.cpu().registers();
+        assert_eq!(registers.af(), 0x01B0);
+        assert_eq!(registers.bc(), 0x0013);
+        assert_eq!(registers.de(), 0x00D8);
+        assert_eq!(registers.hl(), 0x014D);
+        assert_eq!(registers.pc, 0x0100);
+        assert_eq!(gameboy.mmu().read_byte(0xFF40), 0x91);
+        assert_eq!(gameboy.mmu().read_byte(0xFF04), 0xAB); // DIV
+        assert_eq!(gameboy.mmu().read_byte(0xFF26), 0xF1); // NR52
+        assert_eq!(gameboy.mmu().read_byte(0x9910), 0x19); // ® of the logo
+    }
+
+    #[test]
+    fn test_boot_rom() {
+        // ld a, $01; jp $00FE ... $00FE: ldh [BOOT], a
+        let mut boot = vec![0; boot::DMG_SIZE];
+        boot[..5].copy_from_slice(&[0x3E, 0x01, 0xC3, 0xFE, 0x00]);
+        boot[0xFE..].copy_from_slice(&[0xE0, 0x50]);
+        let boot_rom = BootRom::new(boot, Model::Dmg).unwrap();
+        let mut gameboy = GameBoy::with_boot_rom(rom(&[]), Model::Dmg, boot_rom).unwrap();
+        assert_eq!(gameboy.cpu().registers().pc, 0x0000);
+        assert_eq!(gameboy.cpu().registers().af(), 0x0000);
+        assert_eq!(gameboy.mmu().read_byte(0x0000), 0x3E);
+        assert_eq!(gameboy.mmu().read_byte(0xFF40), 0x00);
+
+        let state = gameboy.save_state();
+        for _ in 0..3 {
+            gameboy.step();
+        }
+        assert!(!gameboy.mmu().boot_rom_mapped());
+        assert_eq!(gameboy.mmu().read_byte(0x0000), 0x00);
+        assert_eq!(gameboy.cpu().registers().pc, 0x0100);
+
+        // The boot ROM is saved while it is mapped.
+        let mut other = GameBoy::new(rom(&[])).unwrap();
+        other.load_state(&state).unwrap();
+        assert!(other.mmu().boot_rom_mapped());
+        assert_eq!(other.mmu().read_byte(0x0002), 0xC3);
+    }
+
+    #[test]
+    fn test_cgb_boot_rom_picks_mode() {
+        // ld a, $04; ldh [KEY0], a; ld a, $01; ldh [BOOT], a
+        let mut boot = vec![0; boot::CGB_SIZE];
+        boot[..8].copy_from_slice(&[0x3E, 0x04, 0xE0, 0x4C, 0x3E, 0x01, 0xE0, 0x50]);
+        let boot_rom = BootRom::new(boot, Model::Cgb).unwrap();
+        let mut gameboy = GameBoy::with_boot_rom(rom(&[]), Model::Cgb, boot_rom).unwrap();
+        for _ in 0..3 {
+            gameboy.step();
+        }
+        assert!(gameboy.mmu().cgb_mode());
+        gameboy.step();
+        assert!(!gameboy.mmu().cgb_mode());
+        assert_eq!(gameboy.mmu().ppu().color_mode(), ColorMode::Compatibility);
+    }
+
+    #[test]
+    fn test_model_registers() {
+        let mut cgb_rom = rom(&[]);
+        cgb_rom[0x0143] = 0x80;
+        let gameboy = GameBoy::with_model(cgb_rom.clone(), Model::Cgb).unwrap();
+        assert!(gameboy.mmu().cgb_mode());
+        assert_eq!(gameboy.cpu().registers().af(), 0x1180);
+        assert_eq!(gameboy.cpu().registers().de(), 0xFF56);
+        let gameboy = GameBoy::with_model(cgb_rom, Model::Agb).unwrap();
+        assert_eq!(gameboy.cpu().registers().bc(), 0x0100);
+        assert_eq!(gameboy.cpu().registers().f.0, 0x00);
+
+        // A DMG game by Nintendo on a CGB, in compatibility mode.
+        let mut dmg_rom = rom(&[]);
+        dmg_rom[0x0134..0x0138].copy_from_slice(b"GAME");
+        dmg_rom[0x014B] = 0x01;
+        let gameboy = GameBoy::with_model(dmg_rom, Model::Cgb).unwrap();
+        assert!(!gameboy.mmu().cgb_mode());
+        let title_sum = b"GAME".iter().fold(0u8, |sum, &c| sum.wrapping_add(c));
+        assert_eq!(gameboy.cpu().registers().b, title_sum);
+        assert_eq!(gameboy.cpu().registers().de(), 0x0008);
+
+        let gameboy = GameBoy::with_model(rom(&[]), Model::Sgb).unwrap();
+        assert_eq!(gameboy.cpu().registers().hl(), 0xC060);
+    }
+
+    #[test]
+    fn test_double_speed() {
+        // Switch to double speed, then count frames while looping.
+        let program = [
+            0x3E, 0x01, 0xE0, 0x4D, // ld a, $01; ldh [KEY1], a
+            0x10, 0x00, // stop
+            0x18, 0xFE, // jr @
+        ];
+        let mut rom = rom(&program);
+        rom[0x0143] = 0x80;
+        let mut gameboy = GameBoy::with_model(rom, Model::Cgb).unwrap();
+        gameboy.run_frame();
+        gameboy.run_frame();
+        assert!(gameboy.mmu().double_speed());
+        let cycles = gameboy.cpu().cycles();
+        gameboy.run_frame();
+        let frame = gameboy.cpu().cycles() - cycles;
+        assert!(frame.abs_diff(2 * CYCLES_PER_FRAME as u64) <= 3);
+    }
+
+    #[test]
+    fn test_model_in_state() {
+        let mut rom = rom(&[0x18, 0xFE]);
+        rom[0x0143] = 0x80;
+        let cgb = GameBoy::with_model(rom.clone(), Model::Cgb).unwrap();
+        let mut dmg = GameBoy::new(rom).unwrap();
+        dmg.load_state(&cgb.save_state()).unwrap();
+        assert_eq!(dmg.model(), Model::Cgb);
+        assert!(dmg.mmu().cgb_mode());
+    }
+
+    #[test]
+    fn test_sgb_frame() {
+        let mut rom = rom(&[0x18, 0xFE]);
+        assert!(GameBoy::with_model(rom.clone(), Model::Sgb)
+            .unwrap()
+            .sgb_frame()
+            .is_none());
+        rom[0x0146] = 0x03;
+        rom[0x014B] = 0x33;
+        let mut gameboy = GameBoy::with_model(rom.clone(), Model::Sgb).unwrap();
+        gameboy.run_frame();
+        let frame = gameboy.sgb_frame().unwrap();
+        assert_eq!(frame.len(), sgb::SCREEN_WIDTH * sgb::SCREEN_HEIGHT);
+        assert!(frame.iter().all(|color| matches!(color, Color::Rgb555(_))));
+        assert!(GameBoy::with_model(rom, Model::Dmg)
+            .unwrap()
+            .sgb_frame()
+            .is_none());
+
+        let state = gameboy.save_state();
+        gameboy.load_state(&state).unwrap();
+        assert!(gameboy.sgb_frame().is_some());
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut gameboy = GameBoy::new(rom(&[0x18, 0xFE])).unwrap(); // JR -2
+        gameboy.run_frame();
+        gameboy.run_frame();
+        assert_eq!(gameboy.mmu().ppu().frames(), 2);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        // Counts in B and stores it into WRAM, with the timer running.
+        let program = [
+            0x3E, 0x05, 0xE0, 0x07, // ld a, $05; ldh [TAC], a
+            0x04, 0x78, 0xEA, 0x00, 0xC0, // .loop inc b; ld a, b; ld [$C000], a
+            0x18, 0xF9, // jr .loop
+        ];
+        let mut gameboy = GameBoy::new(rom(&program)).unwrap();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        gameboy.run_frame();
+        let expected = gameboy.save_state();
+        let frame = gameboy.frame().to_vec();
+
+        let mut other = GameBoy::new(rom(&program)).unwrap();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        other.run_frame();
+        assert_eq!(other.save_state(), expected);
+        assert_eq!(other.frame(), &frame[..]);
+        assert_eq!(
+            other.mmu().read_byte(0xC000),
+            gameboy.mmu().read_byte(0xC000)
+        );
+    }
+
+    #[test]
+    fn test_state_checksum_ignores_header() {
+        let mut gameboy = GameBoy::new(rom(&[0x04, 0x18, 0xFD])).unwrap();
+        gameboy.run_frame();
+        let state = gameboy.save_state();
+        let (header, reader) = StateReader::new(&state).unwrap();
+        assert_eq!(state::crc32(reader.remaining()), gameboy.state_checksum());
+
+        // The same machine saved by another version of the emulator.
+        let mut writer = StateWriter::default();
+        for byte in state::MAGIC {
+            writer.u8(byte);
+        }
+        writer.u16(header.version);
+        writer.u32(header.rom_checksum);
+        writer.u8(5);
+        for byte in *b"0.0.1" {
+            writer.u8(byte);
+        }
+        let mut data = writer.finish();
+        data.extend_from_slice(reader.remaining());
+        assert_ne!(data, state);
+
+        let mut other = GameBoy::new(rom(&[0x04, 0x18, 0xFD])).unwrap();
+        assert_eq!(other.load_state(&data).unwrap().emulator_version, "0.0.1");
+        assert_eq!(other.state_checksum(), gameboy.state_checksum());
+    }
+
+    #[test]
+    fn test_load_state_errors() {
+        let mut gameboy = GameBoy::new(rom(&[0x18, 0xFE])).unwrap();
+        let state = gameboy.save_state();
+        let mut other = GameBoy::new(rom(&[0x18, 0xFD])).unwrap();
+        assert!(matches!(
+            other.load_state(&state),
+            Err(StateError::RomMismatch(_))
+        ));
+
+        // A truncated state leaves the machine as it was.
+        gameboy.run_frame();
+        let before = gameboy.save_state();
+        assert_eq!(
+            gameboy.load_state(&state[..state.len() - 10]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(gameboy.save_state(), before);
+    }
+
+    #[test]
+    fn test_infrared_between_two_game_boys() {
+        let huc1 = |program: &[u8]| {
+            let mut rom = rom(program);
+            rom[0x0147] = 0xFF;
+            rom[0x0149] = 0x03;
+            GameBoy::new(rom).unwrap()
+        };
+        // ld a, $0E; ld [$0000], a; ld a, $01; ld [$A000], a; jr @
+        let mut sender = huc1(&[
+            0x3E, 0x0E, 0xEA, 0x00, 0x00, 0x3E, 0x01, 0xEA, 0x00, 0xA0, 0x18, 0xFE,
+        ]);
+        // ld a, $0E; ld [$0000], a; .loop ld a, [$A000]; ld [$C000], a; jr .loop
+        let mut receiver = huc1(&[
+            0x3E, 0x0E, 0xEA, 0x00, 0x00, 0xFA, 0x00, 0xA0, 0xEA, 0x00, 0xC0, 0x18, 0xF8,
+        ]);
+        let (a, b) = IrLink::pair();
+        sender.set_ir_peer(Some(Box::new(a)));
+        receiver.set_ir_peer(Some(Box::new(b)));
+
+        receiver.run_frame();
+        assert_eq!(receiver.mmu().read_byte(0xC000), 0xC0);
+        sender.run_frame();
+        receiver.run_frame();
+        assert_eq!(receiver.mmu().read_byte(0xC000), 0xC1);
+    }
+}
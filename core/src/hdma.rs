@@ -0,0 +1,150 @@
+// CGB VRAM DMA, copying blocks of 16 bytes into the selected VRAM bank.
+// FF51-FF52   HDMA1-2 - Source address, the low 4 bits are ignored
+// FF53-FF54   HDMA3-4 - Destination in VRAM, only bits 4-12 are used
+// FF55        HDMA5   - Write: bit 7 mode, bits 6-0 blocks - 1
+//                       Read: bit 7 set when no HBlank DMA runs, bits 6-0 blocks left - 1
+//
+// A general purpose DMA (bit 7 = 0) copies every block at once while the CPU waits. An HBlank
+// DMA (bit 7 = 1) copies a block at the start of each HBlank, and writing bit 7 = 0 while it
+// runs cancels it. The MMU does the copies, this only keeps the registers and addresses.
+//
+// HDMA1-4 are write-only, they read 0xFF.
+
+use crate::state::{Snapshot, StateError, StateReader, StateWriter};
+
+pub const HDMA1: u16 = 0xFF51;
+pub const HDMA2: u16 = 0xFF52;
+pub const HDMA3: u16 = 0xFF53;
+pub const HDMA4: u16 = 0xFF54;
+pub const HDMA5: u16 = 0xFF55;
+
+pub const BLOCK_SIZE: u16 = 0x10;
+
+pub struct Hdma {
+    source: u16,
+    destination: u16, // Offset in VRAM, 0x0000-0x1FF0
+    length: u8,       // Blocks left - 1, wraps to 0x7F after the last one
+    hblank: bool,     // An HBlank DMA is running
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Hdma::new()
+    }
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Hdma {
+            source: 0,
+            destination: 0,
+            length: 0x7F,
+            hblank: false,
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            HDMA5 => (!self.hblank as u8) << 7 | self.length,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write a register, returning the blocks to copy at once for a general purpose DMA.
+    pub fn write_byte(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            HDMA1 => self.source = (value as u16) << 8 | (self.source & 0x00F0),
+            HDMA2 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.destination = ((value & 0x1F) as u16) << 8 | (self.destination & 0x00F0),
+            HDMA4 => self.destination = (self.destination & 0x1F00) | (value & 0xF0) as u16,
+            // Cancelling keeps the length left, which HDMA5 then reads with bit 7 set.
+            _ if self.hblank && value & 0x80 == 0 => self.hblank = false,
+            _ => {
+                self.length = value & 0x7F;
+                if value & 0x80 != 0 {
+                    self.hblank = true;
+                } else {
+                    return self.length + 1;
+                }
+            }
+        }
+        0
+    }
+
+    /// Whether an HBlank DMA waits for the next HBlank.
+    pub fn hblank_active(&self) -> bool {
+        self.hblank
+    }
+
+    /// Source and VRAM destination of the next block, moving past it. The transfer ends after
+    /// the last block or at the end of VRAM.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & 0x1FF0;
+        self.length = self.length.wrapping_sub(1) & 0x7F;
+        if self.length == 0x7F || self.destination == 0 {
+            self.hblank = false;
+        }
+        block
+    }
+}
+
+impl Snapshot for Hdma {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u16(self.source);
+        writer.u16(self.destination);
+        writer.u8(self.length);
+        writer.bool(self.hblank);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.source = reader.u16()? & 0xFFF0;
+        self.destination = reader.u16()? & 0x1FF0;
+        self.length = reader.u8()? & 0x7F;
+        self.hblank = reader.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_general_purpose() {
+        let mut hdma = Hdma::new();
+        hdma.write_byte(HDMA1, 0xC1);
+        hdma.write_byte(HDMA2, 0x2F);
+        hdma.write_byte(HDMA3, 0xFF);
+        hdma.write_byte(HDMA4, 0x40);
+        assert_eq!(hdma.write_byte(HDMA5, 0x01), 2);
+        assert_eq!(hdma.next_block(), (0xC120, 0x9F40));
+        assert_eq!(hdma.next_block(), (0xC130, 0x9F50));
+        assert_eq!(hdma.read_byte(HDMA5), 0xFF);
+        assert_eq!(hdma.read_byte(HDMA1), 0xFF);
+    }
+
+    #[test]
+    fn test_hblank_and_cancel() {
+        let mut hdma = Hdma::new();
+        assert_eq!(hdma.write_byte(HDMA5, 0x82), 0);
+        assert!(hdma.hblank_active());
+        assert_eq!(hdma.read_byte(HDMA5), 0x02);
+        hdma.next_block();
+        assert_eq!(hdma.read_byte(HDMA5), 0x01);
+        assert_eq!(hdma.write_byte(HDMA5, 0x00), 0);
+        assert!(!hdma.hblank_active());
+        assert_eq!(hdma.read_byte(HDMA5), 0x81);
+    }
+
+    #[test]
+    fn test_end_of_vram() {
+        let mut hdma = Hdma::new();
+        hdma.write_byte(HDMA3, 0x1F);
+        hdma.write_byte(HDMA4, 0xF0);
+        hdma.write_byte(HDMA5, 0x85);
+        assert_eq!(hdma.next_block().1, 0x9FF0);
+        assert!(!hdma.hblank_active());
+    }
+}
@@ -0,0 +1,61 @@
+// Infrared link between the IR LED and receiver of a cartridge (HuC1, HuC3) and whatever it
+// points at. Only whether light is on matters: games time the pulses themselves, so two
+// Game Boys can talk when they run in step, frame by frame or instruction by instruction.
+
+use std::{cell::Cell, rc::Rc};
+
+/// The other end of an infrared link.
+pub trait IrPeer {
+    /// Called when the LED turns on or off.
+    fn set_led(&mut self, on: bool);
+    /// Whether the receiver sees light from the peer.
+    fn receiving(&self) -> bool;
+}
+
+/// One end of a link between two Game Boys, each sees the LED of the other. Made by
+/// `IrLink::pair`.
+pub struct IrLink {
+    leds: Rc<[Cell<bool>; 2]>,
+    end: usize,
+}
+
+impl IrLink {
+    /// The two ends of a link, to give to the `set_ir_peer` of each Game Boy.
+    pub fn pair() -> (IrLink, IrLink) {
+        let leds = Rc::new([Cell::new(false), Cell::new(false)]);
+        (
+            IrLink {
+                leds: leds.clone(),
+                end: 0,
+            },
+            IrLink { leds, end: 1 },
+        )
+    }
+}
+
+impl IrPeer for IrLink {
+    fn set_led(&mut self, on: bool) {
+        self.leds[self.end].set(on);
+    }
+
+    fn receiving(&self) -> bool {
+        self.leds[1 - self.end].get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link() {
+        let (mut a, mut b) = IrLink::pair();
+        a.set_led(true);
+        assert!(b.receiving());
+        assert!(!a.receiving());
+        b.set_led(true);
+        a.set_led(false);
+        assert!(a.receiving());
+        assert!(!b.receiving());
+    }
+}
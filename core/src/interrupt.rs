@@ -0,0 +1,16 @@
+// Interrupt bits, as laid out in IF (0xFF0F) and IE (0xFFFF).
+// When several are pending the lowest bit is serviced first.
+
+pub const VBLANK: u8 = 0b0000_0001;
+pub const LCD_STAT: u8 = 0b0000_0010;
+pub const TIMER: u8 = 0b0000_0100;
+pub const SERIAL: u8 = 0b0000_1000;
+pub const JOYPAD: u8 = 0b0001_0000;
+
+pub const INTERRUPT_FLAG: u16 = 0xFF0F;
+pub const INTERRUPT_ENABLE: u16 = 0xFFFF;
+
+/// Address the CPU jumps to when servicing `interrupt`.
+pub fn vector(interrupt: u8) -> u16 {
+    0x0040 + 8 * interrupt.trailing_zeros() as u16
+}
@@ -0,0 +1,168 @@
+// Joypad input register.
+// FF00   P1 - Bit 5: select action buttons, bit 4: select direction buttons (0 = selected)
+//             Bits 3-0: Start/Down, Select/Up, B/Left, A/Right (0 = pressed)
+
+use crate::{
+    interrupt,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const P1: u16 = 0xFF00;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    select: u8,     // bits 5-4 of P1
+    directions: u8, // pressed directions, bit set = pressed
+    actions: u8,    // pressed action buttons, bit set = pressed
+    interrupt: u8,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// Whether the button is read with the direction (rather than action) selection.
+    fn is_direction(&self) -> bool {
+        matches!(
+            self,
+            Button::Right | Button::Left | Button::Up | Button::Down
+        )
+    }
+
+    fn mask(&self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0x01,
+            Button::Left | Button::B => 0x02,
+            Button::Up | Button::Select => 0x04,
+            Button::Down | Button::Start => 0x08,
+        }
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            select: 0x30,
+            ..Joypad::default()
+        }
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        0xC0 | self.select | (!self.pressed() & 0x0F)
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        self.update(|joypad| joypad.select = value & 0x30);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.update(|joypad| *joypad.buttons(button) |= button.mask());
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.update(|joypad| *joypad.buttons(button) &= !button.mask());
+    }
+
+    /// Return the interrupts requested since the last call.
+    pub fn tick(&mut self) -> u8 {
+        std::mem::take(&mut self.interrupt)
+    }
+
+    fn buttons(&mut self, button: Button) -> &mut u8 {
+        if button.is_direction() {
+            &mut self.directions
+        } else {
+            &mut self.actions
+        }
+    }
+
+    /// Pressed buttons of the selected groups, bit set = pressed.
+    fn pressed(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & 0x10 == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & 0x20 == 0 {
+            pressed |= self.actions;
+        }
+        pressed
+    }
+
+    /// Apply a change and request an interrupt when an input line goes from high to low.
+    fn update<F: FnOnce(&mut Self)>(&mut self, change: F) {
+        let before = self.pressed();
+        change(self);
+        if self.pressed() & !before != 0 {
+            self.interrupt |= interrupt::JOYPAD;
+        }
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.select);
+        writer.u8(self.directions);
+        writer.u8(self.actions);
+        writer.u8(self.interrupt);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.select = reader.u8()? & 0x30;
+        self.directions = reader.u8()? & 0x0F;
+        self.actions = reader.u8()? & 0x0F;
+        self.interrupt = reader.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_selected_group() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::Start);
+        joypad.press(Button::Left);
+
+        joypad.write_byte(0x10); // actions
+        assert_eq!(joypad.read_byte(), 0xD7);
+        joypad.write_byte(0x20); // directions
+        assert_eq!(joypad.read_byte(), 0xED);
+        joypad.write_byte(0x30);
+        assert_eq!(joypad.read_byte(), 0xFF);
+    }
+
+    #[test]
+    fn test_interrupt_on_press() {
+        let mut joypad = Joypad::new();
+        joypad.press(Button::A);
+        assert_eq!(joypad.tick(), 0); // nothing selected
+
+        joypad.write_byte(0x10);
+        assert_eq!(joypad.tick(), interrupt::JOYPAD);
+        joypad.release(Button::A);
+        joypad.press(Button::Up);
+        assert_eq!(joypad.tick(), 0);
+    }
+}
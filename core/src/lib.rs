@@ -1,7 +1,25 @@
-mod cpu;
-mod memory;
-mod mmu;
-mod ppu;
+pub mod boot;
+pub mod cartridge;
+pub mod cpu;
+pub mod debug;
+pub mod disasm;
+pub mod gameboy;
+pub mod hdma;
+pub mod infrared;
+pub mod interrupt;
+pub mod joypad;
+pub mod memory;
+pub mod mmu;
+pub mod model;
+pub mod movie;
+pub mod palette;
+pub mod ppu;
+pub mod rewind;
+pub mod serial;
+pub mod sgb;
+pub mod state;
+pub mod symbols;
+pub mod timer;
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
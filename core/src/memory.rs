@@ -1,13 +1,84 @@
+/// Bus seen by the CPU. `MMU` maps it to the Game Boy hardware, `FlatMemory` is plain RAM.
 pub trait Memory {
     fn read_byte(&self, address: u16) -> u8;
     fn write_byte(&mut self, address: u16, value: u8);
 
+    /// Like `read_byte`, for reads made outside the bus cycles of the CPU (the interrupt
+    /// controller polling IF and IE, DMA, tracing), which debug watchpoints do not see.
+    fn peek(&self, address: u16) -> u8 {
+        self.read_byte(address)
+    }
+
+    /// Like `write_byte`, for writes made outside the bus cycles of the CPU.
+    fn poke(&mut self, address: u16, value: u8) {
+        self.write_byte(address, value)
+    }
+
     fn read_word(&mut self, address: u16) -> u16 {
-        u16::from(self.read_byte(address)) | (u16::from(self.read_byte(address + 1)) << 8)
+        u16::from(self.read_byte(address))
+            | (u16::from(self.read_byte(address.wrapping_add(1))) << 8)
     }
 
     fn write_word(&mut self, address: u16, value: u16) {
         self.write_byte(address, (value & 0xFF) as u8);
-        self.write_byte(address + 1, (value >> 8) as u8);
+        self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    /// Called by the CPU after each step with the M-cycles it took, so the
+    /// hardware behind the bus can advance by the same amount.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// Like `tick`, for M-cycles spent in HALT.
+    fn tick_halted(&mut self, cycles: u32) {
+        self.tick(cycles)
+    }
+
+    /// M-cycles the CPU has to wait for since the last call, while a DMA uses the bus.
+    fn take_stall(&mut self) -> u32 {
+        0
+    }
+
+    /// Called by the CPU on STOP. The CGB switches speed there when KEY1 asks for it.
+    fn stop(&mut self) {}
+
+    /// Bank mapped at `address`, as numbered in RGBDS symbol files.
+    fn bank(&self, _address: u16) -> u16 {
+        0
+    }
+}
+
+/// 64KB of flat RAM with nothing mapped, to run SM83 code outside a Game Boy.
+pub struct FlatMemory {
+    data: Box<[u8; 0x10000]>,
+}
+
+impl Memory for FlatMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.data[address as usize]
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.data[address as usize] = value;
+    }
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory {
+            data: Box::new([0; 0x10000]),
+        }
+    }
+
+    /// Copy `bytes` into memory starting at `address`.
+    pub fn load(&mut self, address: u16, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_byte(address.wrapping_add(i as u16), *byte);
+        }
     }
 }
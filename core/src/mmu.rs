@@ -12,8 +12,42 @@
 // FF80-FFFE   High RAM (HRAM)
 // FFFF        Interrupt Enable Register
 //
+// CGB Mode registers, which read 0xFF on other models and in DMG compatibility mode:
+// FF4C        KEY0, CGB or DMG compatibility mode, locked once the boot ROM is unmapped
+// FF4D        KEY1, bit 7 current speed, bit 0 switch speed on the next STOP
+// FF4F        VBK, VRAM bank
+// FF51-FF55   HDMA1-5, VRAM DMA, see `hdma`
+// FF50        BOOT, unmaps the boot ROM, see `boot`
+// FF68-FF6C   BCPS, BCPD, OCPS, OCPD and OPRI, see `ppu`
+// FF70        SVBK, WRAM bank at D000-DFFF (0 selects 1)
+//
+// On an SGB, P1 also carries the command packets of games with SGB functions, see `sgb`.
+//
+
+use std::cell::RefCell;
+
+use crate::{
+    boot::{self, BootRom},
+    cartridge::Cartridge,
+    debug::{Access, WatchHit, Watchpoint},
+    hdma::{self, Hdma},
+    interrupt::{INTERRUPT_ENABLE, INTERRUPT_FLAG},
+    joypad::{self, Joypad},
+    memory::Memory,
+    model::Model,
+    palette::CompatibilityPalettes,
+    ppu::{self, ColorMode, PPU},
+    serial::{self, Serial},
+    sgb::Sgb,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+    timer::{self, Timer},
+};
+
+pub const ROM_BEGIN: u16 = 0x0000;
+pub const ROM_END: u16 = 0x7FFF;
 
-use crate::{memory::Memory, ppu::PPU};
+pub const EXTERNAL_RAM_BEGIN: u16 = 0xA000;
+pub const EXTERNAL_RAM_END: u16 = 0xBFFF;
 
 pub const IO_REGISTERS_BEGIN: u16 = 0xFF00;
 pub const IO_REGISTERS_END: u16 = 0xFF7F;
@@ -27,40 +61,653 @@ pub const VRAM_END: u16 = 0x9FFF;
 pub const WRAM_BEGIN: u16 = 0xC000;
 pub const WRAM_END: u16 = 0xDFFF;
 
+pub const ECHO_BEGIN: u16 = 0xE000;
+pub const ECHO_END: u16 = 0xFDFF;
+
+pub const HRAM_BEGIN: u16 = 0xFF80;
+pub const HRAM_END: u16 = 0xFFFE;
+
+const OAM_DMA: u16 = 0xFF46;
+const KEY0: u16 = 0xFF4C;
+const KEY1: u16 = 0xFF4D;
+const SVBK: u16 = 0xFF70;
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+
 pub struct MMU {
+    model: Model,
+    key0: u8,
+    double_speed: bool,
+    speed_switch: bool, // KEY1 bit 0
+    half_cycle: bool,   // Odd M-cycle in double speed, not yet passed to the cartridge
+    cartridge: Cartridge,
+    boot_rom: Option<BootRom>, // Mapped until BOOT is written
+    hdma: Hdma,
+    stall: u32, // M-cycles of DMA the CPU has not waited for yet
+    joypad: Joypad,
+    sgb: Option<Sgb>,
     ppu: PPU,
-    wram: [u8; 0x2000],
+    serial: Serial,
+    timer: Timer,
+    wram: [u8; 8 * WRAM_BANK_SIZE],
+    svbk: u8,
+    hram: [u8; 0x7F],
+    io: [u8; 0x80], // I/O registers without a component yet (e.g. sound)
+    interrupt_flag: u8,
+    interrupt_enable: u8,
+    watchpoints: Vec<Watchpoint>,
+    watch_hits: RefCell<Vec<WatchHit>>,
 }
 
 impl Memory for MMU {
     fn read_byte(&self, address: u16) -> u8 {
+        let value = self.peek(address);
+        self.watch(address, value, Access::Read);
+        value
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.watch(address, value, Access::Write);
+        self.poke(address, value);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
         match address {
-            WRAM_BEGIN..=WRAM_END => self.wram[address as usize],
+            ROM_BEGIN..=ROM_END => match self.boot_rom.as_ref().and_then(|b| b.read(address)) {
+                Some(value) => value,
+                None => self.cartridge.read_rom(address),
+            },
             VRAM_BEGIN..=VRAM_END => self.ppu.read_byte(address),
+            EXTERNAL_RAM_BEGIN..=EXTERNAL_RAM_END => self.cartridge.read_ram(address),
+            WRAM_BEGIN..=WRAM_END | ECHO_BEGIN..=ECHO_END => self.wram[self.wram_index(address)],
             OAM_BEGIN..=OAM_END => self.ppu.read_byte(address),
-            0xFF40..=0xFF4B => self.ppu.read_byte(address),
-
-            _ => panic!("Unable to read from this address from the MMU"),
+            0xFEA0..=0xFEFF => 0x00,
+            IO_REGISTERS_BEGIN..=IO_REGISTERS_END => self.read_io(address),
+            HRAM_BEGIN..=HRAM_END => self.hram[(address - HRAM_BEGIN) as usize],
+            INTERRUPT_ENABLE => self.interrupt_enable,
         }
     }
 
-    fn write_byte(&mut self, address: u16, value: u8) {
+    fn poke(&mut self, address: u16, value: u8) {
         match address {
-            WRAM_BEGIN..=WRAM_END => self.wram[address as usize] = value,
+            ROM_BEGIN..=ROM_END => self.cartridge.write_rom(address, value),
             VRAM_BEGIN..=VRAM_END => self.ppu.write_byte(address, value),
+            EXTERNAL_RAM_BEGIN..=EXTERNAL_RAM_END => self.cartridge.write_ram(address, value),
+            WRAM_BEGIN..=WRAM_END | ECHO_BEGIN..=ECHO_END => {
+                self.wram[self.wram_index(address)] = value
+            }
             OAM_BEGIN..=OAM_END => self.ppu.write_byte(address, value),
-            0xFF40..=0xFF4B => self.ppu.write_byte(address, value),
+            0xFEA0..=0xFEFF => {}
+            IO_REGISTERS_BEGIN..=IO_REGISTERS_END => self.write_io(address, value),
+            HRAM_BEGIN..=HRAM_END => self.hram[(address - HRAM_BEGIN) as usize] = value,
+            INTERRUPT_ENABLE => self.interrupt_enable = value,
+        }
+    }
+
+    fn bank(&self, address: u16) -> u16 {
+        match address {
+            ROM_BEGIN..=ROM_END | EXTERNAL_RAM_BEGIN..=EXTERNAL_RAM_END => {
+                self.cartridge.bank(address) as u16
+            }
+            VRAM_BEGIN..=VRAM_END if self.cgb_mode() => {
+                (self.ppu.read_byte(ppu::VBK) & 0x01) as u16
+            }
+            0xD000..=0xDFFF | 0xF000..=ECHO_END => self.wram_bank() as u16,
+            _ => 0,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.advance(cycles, false);
+    }
+
+    fn tick_halted(&mut self, cycles: u32) {
+        self.advance(cycles, true);
+    }
+
+    fn take_stall(&mut self) -> u32 {
+        std::mem::take(&mut self.stall)
+    }
+
+    fn stop(&mut self) {
+        self.timer.write_byte(timer::DIV, 0);
+        if self.cgb_mode() && self.speed_switch {
+            self.double_speed = !self.double_speed;
+            self.speed_switch = false;
+        }
+    }
+}
 
-            _ => panic!("Unable to read from this address from the MMU"),
+impl MMU {
+    /// Advance every component by M-cycles of the CPU.
+    fn advance(&mut self, cycles: u32, halted: bool) {
+        // The PPU and the cartridge clocks keep their speed in double speed mode.
+        let (dots, clock_cycles) = if self.double_speed {
+            let halves = cycles + self.half_cycle as u32;
+            self.half_cycle = !halves.is_multiple_of(2);
+            (cycles * 2, halves / 2)
+        } else {
+            (cycles * 4, cycles)
+        };
+        self.cartridge.tick(clock_cycles);
+        let frames = self.ppu.frames();
+        self.interrupt_flag |= self.timer.tick(cycles)
+            | self.serial.tick(cycles)
+            | self.ppu.tick_dots(dots)
+            | self.joypad.tick();
+        if let Some(sgb) = self.sgb.as_mut().filter(|_| self.ppu.frames() != frames) {
+            sgb.frame_done(&self.ppu);
+        }
+
+        // The HBlank DMA pauses while the CPU is halted.
+        for _ in 0..self.ppu.take_hblanks() {
+            if self.hdma.hblank_active() && !halted {
+                self.hdma_block();
+            }
         }
     }
 }
 
+/// Sound registers are saved with the rest of the I/O area, there is no APU yet.
+impl Snapshot for MMU {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.wram);
+        writer.bytes(&self.hram);
+        writer.bytes(&self.io);
+        writer.u8(self.interrupt_flag);
+        writer.u8(self.interrupt_enable);
+        writer.u8(self.model.code());
+        writer.u8(self.key0);
+        writer.u8(self.svbk);
+        writer.bool(self.double_speed);
+        writer.bool(self.speed_switch);
+        writer.bool(self.half_cycle);
+        writer.chunk(b"PPU ", |w| self.ppu.save(w));
+        writer.chunk(b"TIMR", |w| self.timer.save(w));
+        writer.chunk(b"SERL", |w| self.serial.save(w));
+        writer.chunk(b"JOYP", |w| self.joypad.save(w));
+        writer.chunk(b"CART", |w| self.cartridge.save(w));
+        writer.chunk(b"HDMA", |w| self.hdma.save(w));
+        if let Some(sgb) = &self.sgb {
+            writer.chunk(b"SGB ", |w| sgb.save(w));
+        }
+        writer.chunk(b"BOOT", |w| {
+            w.bytes(
+                self.boot_rom
+                    .as_ref()
+                    .map_or(&[], |boot_rom| boot_rom.data()),
+            )
+        });
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.wram, "WRAM size")?;
+        reader.bytes_into(&mut self.hram, "HRAM size")?;
+        reader.bytes_into(&mut self.io, "I/O size")?;
+        self.interrupt_flag = reader.u8()? & 0x1F;
+        self.interrupt_enable = reader.u8()?;
+        self.model = Model::from_code(reader.u8()?).ok_or(StateError::Invalid("model"))?;
+        self.key0 = reader.u8()?;
+        self.svbk = reader.u8()? & 0x07;
+        self.double_speed = reader.bool()?;
+        self.speed_switch = reader.bool()?;
+        self.half_cycle = reader.bool()?;
+        reader.load_chunk(b"PPU ", &mut self.ppu)?;
+        reader.load_chunk(b"TIMR", &mut self.timer)?;
+        reader.load_chunk(b"SERL", &mut self.serial)?;
+        reader.load_chunk(b"JOYP", &mut self.joypad)?;
+        reader.load_chunk(b"CART", &mut self.cartridge)?;
+        reader.load_chunk(b"HDMA", &mut self.hdma)?;
+        self.sgb = sgb_for(self.model, &self.cartridge);
+        if let Some(sgb) = &mut self.sgb {
+            reader.load_chunk(b"SGB ", sgb)?;
+        }
+        let data = reader.chunk(b"BOOT")?.bytes()?;
+        self.boot_rom = if data.is_empty() {
+            None
+        } else {
+            Some(BootRom::from_state(data).ok_or(StateError::Invalid("boot ROM"))?)
+        };
+        Ok(())
+    }
+}
+
 impl MMU {
-    pub fn new(ppu: PPU) -> Self {
-        MMU {
-            wram: [0; 0x2000],
-            ppu: ppu,
+    /// MMU of a DMG.
+    pub fn new(cartridge: Cartridge) -> Self {
+        MMU::with_model(cartridge, Model::Dmg)
+    }
+
+    /// MMU of `model`, in DMG compatibility mode when a CGB runs a cartridge without
+    /// CGB functions.
+    pub fn with_model(cartridge: Cartridge, model: Model) -> Self {
+        let cgb_flag = cartridge.header().cgb_flag;
+        let key0 = match model.is_cgb() {
+            true if cgb_flag & 0x80 != 0 => cgb_flag,
+            true => 0x04,
+            false => 0x00,
+        };
+        let mut mmu = MMU {
+            model,
+            key0,
+            double_speed: false,
+            speed_switch: false,
+            half_cycle: false,
+            cartridge,
+            boot_rom: None,
+            hdma: Hdma::new(),
+            stall: 0,
+            joypad: Joypad::new(),
+            sgb: None,
+            ppu: PPU::new(),
+            serial: Serial::new(),
+            timer: Timer::new(),
+            wram: [0; 8 * WRAM_BANK_SIZE],
+            svbk: 0,
+            hram: [0; 0x7F],
+            io: [0; 0x80],
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+            watchpoints: Vec::new(),
+            watch_hits: RefCell::new(Vec::new()),
+        };
+        mmu.sgb = sgb_for(model, &mmu.cartridge);
+        // Palettes as the CGB boot ROM leaves them: white BG palettes for CGB games, the ones
+        // it picks by title for DMG games.
+        mmu.ppu.set_color_mode(mmu.color_mode());
+        match mmu.color_mode() {
+            ColorMode::Dmg => {}
+            ColorMode::Cgb => {
+                for palette in 0..8 {
+                    mmu.ppu.set_bg_palette(palette, [0x7FFF; 4]);
+                }
+            }
+            ColorMode::Compatibility => {
+                let palettes = CompatibilityPalettes::for_cartridge(&mmu.cartridge);
+                mmu.ppu.set_bg_palette(0, palettes.bg);
+                mmu.ppu.set_obj_palette(0, palettes.obj0);
+                mmu.ppu.set_obj_palette(1, palettes.obj1);
+            }
+        }
+        mmu
+    }
+
+    /// MMU at power on with `boot_rom` mapped. A CGB starts in CGB mode, the boot ROM picks
+    /// the mode in KEY0 and the palettes before unmapping itself.
+    pub fn with_boot_rom(cartridge: Cartridge, model: Model, boot_rom: BootRom) -> Self {
+        let mut mmu = MMU::with_model(cartridge, model);
+        mmu.key0 = 0x00;
+        mmu.boot_rom = Some(boot_rom);
+        mmu.ppu.set_color_mode(mmu.color_mode());
+        mmu
+    }
+
+    /// Set the I/O registers, the timer and VRAM as the boot ROM leaves them, for machines
+    /// started without one.
+    pub(crate) fn skip_boot(&mut self) {
+        if !self.model.is_cgb() {
+            for (address, value) in boot::logo_vram(&self.cartridge) {
+                self.ppu.write_byte(address, value);
+            }
+        }
+        self.cartridge.boot_done();
+        self.timer.set_counter(boot::div_counter(self.model));
+        for (address, value) in boot::io_registers(self.model) {
+            self.write_byte(address, value);
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Whether the boot ROM is still mapped over the cartridge.
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    /// Whether the CGB registers are mapped: a CGB model not in DMG compatibility mode. The
+    /// mode chosen in KEY0 only applies once the boot ROM is unmapped.
+    pub fn cgb_mode(&self) -> bool {
+        self.model.is_cgb() && (self.boot_rom.is_some() || self.key0 & 0x04 == 0)
+    }
+
+    /// Whether the CPU runs at twice the speed of the other components (CGB).
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    /// The SGB functions, on an SGB running a game that uses them.
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.sgb.as_ref()
+    }
+
+    pub fn joypad_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    /// Record the CPU accesses matching `watchpoints`, which are taken with `take_watch_hits`.
+    /// `peek` and `poke` are not recorded.
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+    }
+
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watch_hits.take()
+    }
+
+    fn watch(&self, address: u16, value: u8, access: Access) {
+        if self.watchpoints.iter().any(|w| w.matches(address, access)) {
+            self.watch_hits.borrow_mut().push(WatchHit {
+                address,
+                value,
+                access,
+            });
+        }
+    }
+
+    /// Where the PPU takes colors from in this model and mode.
+    fn color_mode(&self) -> ColorMode {
+        match (self.model.is_cgb(), self.cgb_mode()) {
+            (false, _) => ColorMode::Dmg,
+            (true, true) => ColorMode::Cgb,
+            (true, false) => ColorMode::Compatibility,
+        }
+    }
+
+    /// WRAM bank mapped at D000-DFFF.
+    fn wram_bank(&self) -> u8 {
+        if self.cgb_mode() {
+            self.svbk.max(1)
+        } else {
+            1
+        }
+    }
+
+    /// Index in `wram` of a WRAM or echo address.
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = (address & 0x1FFF) as usize;
+        if offset < WRAM_BANK_SIZE {
+            offset
+        } else {
+            self.wram_bank() as usize * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+        }
+    }
+
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            joypad::P1 => match &self.sgb {
+                Some(sgb) => sgb.read_p1(self.joypad.read_byte()),
+                None => self.joypad.read_byte(),
+            },
+            serial::SB | serial::SC => self.serial.read_byte(address),
+            timer::DIV..=timer::TAC => self.timer.read_byte(address),
+            INTERRUPT_FLAG => 0xE0 | self.interrupt_flag,
+            0xFF40..=0xFF4B => self.ppu.read_byte(address),
+            KEY1 if self.cgb_mode() => {
+                0x7E | (self.double_speed as u8) << 7 | self.speed_switch as u8
+            }
+            ppu::VBK | ppu::BCPS..=ppu::OPRI if self.cgb_mode() => self.ppu.read_byte(address),
+            hdma::HDMA1..=hdma::HDMA5 if self.cgb_mode() => self.hdma.read_byte(address),
+            SVBK if self.cgb_mode() => 0xF8 | self.svbk,
+            boot::BOOT => 0xFF,
+            KEY0 | KEY1 | ppu::VBK | hdma::HDMA1..=hdma::HDMA5 | ppu::BCPS..=ppu::OPRI | SVBK => {
+                0xFF
+            }
+            _ => self.io[(address - IO_REGISTERS_BEGIN) as usize],
+        }
+    }
+
+    fn write_io(&mut self, address: u16, value: u8) {
+        match address {
+            joypad::P1 => {
+                self.joypad.write_byte(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_p1(value);
+                }
+            }
+            serial::SB | serial::SC => self.serial.write_byte(address, value),
+            timer::DIV..=timer::TAC => self.timer.write_byte(address, value),
+            INTERRUPT_FLAG => self.interrupt_flag = value & 0x1F,
+            OAM_DMA => {
+                self.ppu.write_byte(address, value);
+                self.oam_dma(value);
+            }
+            0xFF40..=0xFF4B => self.ppu.write_byte(address, value),
+            KEY1 if self.cgb_mode() => self.speed_switch = value & 0x01 != 0,
+            ppu::VBK | ppu::BCPS..=ppu::OPRI if self.cgb_mode() => {
+                self.ppu.write_byte(address, value)
+            }
+            hdma::HDMA1..=hdma::HDMA5 if self.cgb_mode() => {
+                for _ in 0..self.hdma.write_byte(address, value) {
+                    self.hdma_block();
+                }
+                // Without an H-Blank to wait for, the first block of an HBlank DMA is copied
+                // at once.
+                let started = address == hdma::HDMA5 && self.hdma.hblank_active();
+                if started && (!self.ppu.lcd_enabled() || self.ppu.in_hblank()) {
+                    self.hdma_block();
+                }
+            }
+            SVBK if self.cgb_mode() => self.svbk = value & 0x07,
+            KEY0 if self.model.is_cgb() && self.boot_rom.is_some() => self.key0 = value,
+            boot::BOOT => {
+                if value & 0x01 != 0 && self.boot_rom.take().is_some() {
+                    self.ppu.set_color_mode(self.color_mode());
+                    self.cartridge.boot_done();
+                }
+            }
+            KEY0 | KEY1 | ppu::VBK | hdma::HDMA1..=hdma::HDMA5 | ppu::BCPS..=ppu::OPRI | SVBK => {}
+            _ => self.io[(address - IO_REGISTERS_BEGIN) as usize] = value,
+        }
+    }
+
+    /// Copy the next block of the VRAM DMA. It takes 8 M-cycles at normal speed, 16 in double
+    /// speed, during which the CPU waits.
+    fn hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for i in 0..hdma::BLOCK_SIZE {
+            let value = self.peek(source.wrapping_add(i));
+            self.ppu.write_byte(destination + i, value);
+        }
+        self.stall += 8 << self.double_speed as u32;
+    }
+
+    /// Copy 160 bytes from `source` * 0x100 into OAM. The transfer is done at once instead of
+    /// over 160 M-cycles, and the CPU keeps access to the whole bus.
+    fn oam_dma(&mut self, source: u8) {
+        let source = (source as u16) << 8;
+        for i in 0..(OAM_END - OAM_BEGIN + 1) {
+            let value = self.peek(source.wrapping_add(i));
+            self.ppu.write_byte(OAM_BEGIN + i, value);
+        }
+    }
+}
+
+/// The SGB listens to packets of games declaring SGB functions: 0x03 at 0x0146 and the old
+/// licensee code 0x33 at 0x014B.
+fn sgb_for(model: Model, cartridge: &Cartridge) -> Option<Sgb> {
+    let supported = cartridge.header().sgb_flag == 0x03 && cartridge.read_rom(0x014B) == 0x33;
+    (model == Model::Sgb && supported).then(Sgb::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cartridge::Cartridge, interrupt};
+
+    fn mmu() -> MMU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0xAA;
+        MMU::new(Cartridge::new(rom).unwrap())
+    }
+
+    fn cgb_mmu(cgb_flag: u8) -> MMU {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = cgb_flag;
+        MMU::with_model(Cartridge::new(rom).unwrap(), Model::Cgb)
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut mmu = mmu();
+        assert_eq!(mmu.read_byte(0x0100), 0xAA);
+        mmu.write_byte(0xC123, 0x42);
+        assert_eq!(mmu.read_byte(0xE123), 0x42);
+        mmu.write_byte(0xFF80, 0x12);
+        assert_eq!(mmu.read_byte(0xFF80), 0x12);
+        mmu.write_byte(0xFF26, 0x80);
+        assert_eq!(mmu.read_byte(0xFF26), 0x80);
+        assert_eq!(mmu.read_byte(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut mmu = cgb_mmu(0x80);
+        assert!(mmu.cgb_mode());
+        mmu.write_byte(0xD000, 0x01);
+        mmu.write_byte(SVBK, 0x02);
+        assert_eq!(mmu.read_byte(SVBK), 0xFA);
+        assert_eq!(mmu.read_byte(0xD000), 0x00);
+        mmu.write_byte(0xD000, 0x02);
+        assert_eq!(mmu.read_byte(0xF000), 0x02);
+        assert_eq!(mmu.bank(0xD000), 2);
+        mmu.write_byte(SVBK, 0x00);
+        assert_eq!(mmu.read_byte(0xD000), 0x01);
+        assert_eq!(mmu.bank(0x8000), 0);
+        mmu.write_byte(ppu::VBK, 0x01);
+        assert_eq!(mmu.read_byte(ppu::VBK), 0xFF);
+        assert_eq!(mmu.bank(0x9FFF), 1);
+    }
+
+    #[test]
+    fn test_dmg_compatibility_mode() {
+        for mut mmu in [mmu(), cgb_mmu(0x00)] {
+            assert!(!mmu.cgb_mode());
+            mmu.write_byte(0xD000, 0x01);
+            mmu.write_byte(SVBK, 0x02);
+            assert_eq!(mmu.read_byte(SVBK), 0xFF);
+            assert_eq!(mmu.read_byte(0xD000), 0x01);
+            mmu.write_byte(ppu::VBK, 0x01);
+            assert_eq!(mmu.bank(0x8000), 0);
+            mmu.write_byte(KEY1, 0x01);
+            assert_eq!(mmu.read_byte(KEY1), 0xFF);
+            mmu.stop();
+            assert!(!mmu.double_speed());
+        }
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        let mut mmu = cgb_mmu(0xC0);
+        mmu.write_byte(0xFF40, 0x80);
+        mmu.write_byte(KEY1, 0x01);
+        assert_eq!(mmu.read_byte(KEY1), 0x7F);
+        mmu.stop();
+        assert!(mmu.double_speed());
+        assert_eq!(mmu.read_byte(KEY1), 0xFE);
+        // A line takes 114 M-cycles at normal speed, 228 in double speed.
+        mmu.tick(114);
+        assert_eq!(mmu.read_byte(0xFF44), 0);
+        mmu.tick(114);
+        assert_eq!(mmu.read_byte(0xFF44), 1);
+    }
+
+    #[test]
+    fn test_general_purpose_dma() {
+        let mut mmu = cgb_mmu(0xC0);
+        for i in 0..0x20 {
+            mmu.write_byte(0xC100 + i, i as u8);
+        }
+        mmu.write_byte(hdma::HDMA1, 0xC1);
+        mmu.write_byte(hdma::HDMA2, 0x00);
+        mmu.write_byte(hdma::HDMA3, 0x00);
+        mmu.write_byte(hdma::HDMA4, 0x10);
+        mmu.write_byte(hdma::HDMA5, 0x01);
+        assert_eq!(mmu.read_byte(0x8010), 0x00);
+        assert_eq!(mmu.read_byte(0x802F), 0x1F);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0xFF);
+        assert_eq!(mmu.take_stall(), 16);
+        assert_eq!(mmu.take_stall(), 0);
+    }
+
+    #[test]
+    fn test_hblank_dma() {
+        let mut mmu = cgb_mmu(0xC0);
+        for i in 0..0x20 {
+            mmu.write_byte(0xC000 + i, 0x80 | i as u8);
+        }
+        mmu.write_byte(hdma::HDMA1, 0xC0);
+        mmu.write_byte(hdma::HDMA2, 0x00);
+        mmu.write_byte(0xFF40, 0x80);
+        mmu.write_byte(hdma::HDMA5, 0x81);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0x01);
+        mmu.tick(114);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0x00);
+        assert_eq!(mmu.take_stall(), 8);
+        // Paused while the CPU is halted.
+        mmu.tick_halted(114);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0x00);
+        mmu.tick(114);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0xFF);
+        mmu.write_byte(0xFF40, 0x00);
+        assert_eq!(mmu.read_byte(0x8000), 0x80);
+        assert_eq!(mmu.read_byte(0x801F), 0x9F);
+    }
+
+    #[test]
+    fn test_hblank_dma_with_lcd_off() {
+        let mut mmu = cgb_mmu(0xC0);
+        mmu.write_byte(0xC000, 0x42);
+        mmu.write_byte(hdma::HDMA1, 0xC0);
+        mmu.write_byte(hdma::HDMA2, 0x00);
+        mmu.write_byte(hdma::HDMA5, 0x81);
+        assert_eq!(mmu.read_byte(0x8000), 0x42);
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0x00);
+        mmu.write_byte(hdma::HDMA5, 0x00); // Cancel
+        assert_eq!(mmu.read_byte(hdma::HDMA5), 0x80);
+        assert_eq!(cgb_mmu(0x00).read_byte(hdma::HDMA5), 0xFF);
+    }
+
+    #[test]
+    fn test_interrupt_flag() {
+        let mut mmu = mmu();
+        assert_eq!(mmu.read_byte(INTERRUPT_FLAG), 0xE0);
+        mmu.write_byte(timer::TAC, 0x05);
+        mmu.write_byte(timer::TIMA, 0xFF);
+        mmu.tick(4);
+        assert_eq!(mmu.read_byte(INTERRUPT_FLAG), 0xE0 | interrupt::TIMER);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut mmu = mmu();
+        for i in 0..0xA0 {
+            mmu.write_byte(0xC000 + i, i as u8);
         }
+        mmu.write_byte(OAM_DMA, 0xC0);
+        assert_eq!(mmu.read_byte(0xFE00), 0x00);
+        assert_eq!(mmu.read_byte(0xFE9F), 0x9F);
+        assert_eq!(mmu.read_byte(OAM_DMA), 0xC0);
     }
 }
@@ -0,0 +1,89 @@
+// Game Boy hardware models. They all run DMG cartridges, but the boot ROM of each leaves the
+// CPU in a different state, and the CGB and AGB add color hardware: a second VRAM bank,
+// WRAM banks 1-7 at D000-DFFF and a double speed mode (KEY1).
+//
+// A cartridge without the CGB flag (0x0143 bit 7) runs on a CGB or AGB in DMG compatibility
+// mode: the boot ROM sets KEY0 bit 2 and the CGB registers are not mapped.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Model {
+    /// Original Game Boy.
+    #[default]
+    Dmg,
+    /// Game Boy Pocket and Light.
+    Mgb,
+    /// Super Game Boy.
+    Sgb,
+    /// Game Boy Color.
+    Cgb,
+    /// Game Boy Advance, in its Game Boy Color mode.
+    Agb,
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Model::Dmg => "DMG",
+            Model::Mgb => "MGB",
+            Model::Sgb => "SGB",
+            Model::Cgb => "CGB",
+            Model::Agb => "AGB",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Model {
+    pub const ALL: [Model; 5] = [Model::Dmg, Model::Mgb, Model::Sgb, Model::Cgb, Model::Agb];
+
+    /// Parse a model name as displayed, in any case.
+    pub fn parse(text: &str) -> Option<Model> {
+        Model::ALL
+            .into_iter()
+            .find(|model| model.to_string().eq_ignore_ascii_case(text))
+    }
+
+    /// The CGB for ROMs with CGB functions (header byte 0x0143 bit 7), the DMG for the others.
+    pub fn for_rom(rom: &[u8]) -> Model {
+        if rom.get(0x0143).is_some_and(|flag| flag & 0x80 != 0) {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    /// Whether the model has the CGB hardware.
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    /// Number saved in states and movies.
+    pub(crate) fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_code(code: u8) -> Option<Model> {
+        Model::ALL.get(code as usize).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Model::parse("cgb"), Some(Model::Cgb));
+        assert_eq!(Model::parse("SGB"), Some(Model::Sgb));
+        assert_eq!(Model::parse("gba"), None);
+        let mut rom = vec![0; 0x150];
+        assert_eq!(Model::for_rom(&rom), Model::Dmg);
+        rom[0x0143] = 0xC0;
+        assert_eq!(Model::for_rom(&rom), Model::Cgb);
+        for model in Model::ALL {
+            assert_eq!(Model::from_code(model.code()), Some(model));
+        }
+    }
+}
@@ -0,0 +1,484 @@
+// Input movies: the joypad input of every frame from power on (or from a save state), played
+// back by feeding the same input to the same ROM on the same model. The machine is checksummed
+// every `checksum_interval` frames while recording so playback can tell when it desyncs.
+//
+// File format, numbers little endian as in save states:
+// 0   "RBMV"
+// 4   u16 format version
+// 6   u32 CRC-32 of the ROM
+// 10  u8 model, as in save states
+// 11  u32 CRC-32 of the boot ROM run at power on, 0 without one
+// 15  u32 checksum interval in frames
+// 19  u32 rerecord count
+// 23  u32 length + save state to start from (empty to start from power on)
+// ..  u32 length + one byte of buttons per frame (bit set = pressed, in `Button::ALL` order)
+// ..  u32 count + (u32 frame, u32 `GameBoy::state_checksum` after that frame) pairs
+
+use std::fmt;
+
+use crate::{
+    boot::BootRom,
+    gameboy::GameBoy,
+    joypad::Button,
+    model::Model,
+    state::{self, StateError, StateReader, StateWriter},
+};
+
+pub const MAGIC: [u8; 4] = *b"RBMV";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The movie file is malformed.
+    Format(StateError),
+    /// Recorded with another ROM, holds the CRC-32 of the ROM in the movie.
+    RomMismatch(u32),
+    /// Recorded from power on with a boot ROM that was not given, holds its CRC-32.
+    BootRomMismatch(u32),
+    /// The embedded save state could not be loaded.
+    State(StateError),
+    /// Read-only playback cannot be branched.
+    ReadOnly,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Format(error) => write!(f, "invalid movie: {}", error),
+            MovieError::RomMismatch(checksum) => write!(
+                f,
+                "movie was recorded with another ROM (CRC-32 {:08X})",
+                checksum
+            ),
+            MovieError::BootRomMismatch(checksum) => write!(
+                f,
+                "movie was recorded with a boot ROM (CRC-32 {:08X})",
+                checksum
+            ),
+            MovieError::State(error) => write!(f, "movie start state: {}", error),
+            MovieError::ReadOnly => write!(f, "movie is played back read-only"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_checksum: u32,
+    pub model: Model,
+    /// CRC-32 of the boot ROM the movie powers on with, None to skip the boot ROM.
+    pub boot_rom_checksum: Option<u32>,
+    pub checksum_interval: u32,
+    /// Times recording restarted from a point of the movie.
+    pub rerecords: u32,
+    /// Save state the movie starts from, None to start from power on.
+    pub start_state: Option<Vec<u8>>,
+    inputs: Vec<u8>,
+    checksums: Vec<(u32, u32)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    /// Play the movie back, the input given to the session is ignored.
+    ReadOnly,
+    /// Play the movie back until input is given, then branch and record from that frame.
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+    Recorded,
+    Played,
+    /// Played back, but the state differs from the recording.
+    Desync {
+        frame: u32,
+        expected: u32,
+        actual: u32,
+    },
+    /// Past the end of the movie, the frame ran with the session input.
+    Finished,
+}
+
+/// A movie being recorded or played back, one frame at a time.
+pub struct Session {
+    movie: Movie,
+    mode: Mode,
+    frame: u32,
+}
+
+/// Joypad byte of the pressed buttons.
+pub fn buttons_to_input(buttons: &[Button]) -> u8 {
+    Button::ALL
+        .iter()
+        .enumerate()
+        .filter(|(_, button)| buttons.contains(button))
+        .fold(0, |input, (bit, _)| input | 1 << bit)
+}
+
+/// Press the buttons set in `input` and release the others.
+pub fn apply_input(gameboy: &mut GameBoy, input: u8) {
+    for (bit, &button) in Button::ALL.iter().enumerate() {
+        if input & (1 << bit) != 0 {
+            gameboy.press(button);
+        } else {
+            gameboy.release(button);
+        }
+    }
+}
+
+impl Movie {
+    /// Empty movie recorded from power on, without a boot ROM until `boot_rom_checksum` is set.
+    pub fn new(rom_checksum: u32, model: Model, checksum_interval: u32) -> Self {
+        Movie {
+            rom_checksum,
+            model,
+            boot_rom_checksum: None,
+            checksum_interval: checksum_interval.max(1),
+            rerecords: 0,
+            start_state: None,
+            inputs: Vec::new(),
+            checksums: Vec::new(),
+        }
+    }
+
+    /// Empty movie recorded from the current state of `gameboy`.
+    pub fn from_state(gameboy: &GameBoy, checksum_interval: u32) -> Self {
+        Movie {
+            start_state: Some(gameboy.save_state()),
+            ..Movie::new(
+                gameboy.mmu().cartridge().rom_checksum(),
+                gameboy.model(),
+                checksum_interval,
+            )
+        }
+    }
+
+    /// Frames in the movie.
+    pub fn len(&self) -> u32 {
+        self.inputs.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn input(&self, frame: u32) -> Option<u8> {
+        self.inputs.get(frame as usize).copied()
+    }
+
+    /// Create the machine the movie starts on. `boot_rom` is only used, and then required, when
+    /// the movie was recorded from power on with a boot ROM.
+    pub fn start(&self, rom: Vec<u8>, boot_rom: Option<BootRom>) -> Result<GameBoy, MovieError> {
+        let rom_mismatch = |_| MovieError::RomMismatch(self.rom_checksum);
+        let mut gameboy = match self.boot_rom_checksum {
+            Some(checksum) => match boot_rom {
+                Some(boot_rom) if state::crc32(boot_rom.data()) == checksum => {
+                    GameBoy::with_boot_rom(rom, self.model, boot_rom).map_err(rom_mismatch)?
+                }
+                _ => return Err(MovieError::BootRomMismatch(checksum)),
+            },
+            None => GameBoy::with_model(rom, self.model).map_err(rom_mismatch)?,
+        };
+        if gameboy.mmu().cartridge().rom_checksum() != self.rom_checksum {
+            return Err(MovieError::RomMismatch(self.rom_checksum));
+        }
+        if let Some(state) = &self.start_state {
+            gameboy.load_state(state).map_err(MovieError::State)?;
+        }
+        Ok(gameboy)
+    }
+
+    /// Drop every frame from `frame` on, keeping the checksum of the state they start from.
+    pub fn truncate(&mut self, frame: u32) {
+        self.inputs.truncate(frame as usize);
+        self.checksums.retain(|&(at, _)| at <= frame);
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        for byte in MAGIC {
+            writer.u8(byte);
+        }
+        writer.u16(VERSION);
+        writer.u32(self.rom_checksum);
+        writer.u8(self.model.code());
+        writer.u32(self.boot_rom_checksum.unwrap_or(0));
+        writer.u32(self.checksum_interval);
+        writer.u32(self.rerecords);
+        writer.bytes(self.start_state.as_deref().unwrap_or(&[]));
+        writer.bytes(&self.inputs);
+        writer.u32(self.checksums.len() as u32);
+        for &(frame, checksum) in &self.checksums {
+            writer.u32(frame);
+            writer.u32(checksum);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::Format(StateError::InvalidMagic));
+        }
+        let mut reader = StateReader::raw(&data[MAGIC.len()..]);
+        let version = reader.u16().map_err(MovieError::Format)?;
+        if version > VERSION {
+            return Err(MovieError::Format(StateError::UnsupportedVersion(version)));
+        }
+        Movie::read(&mut reader).map_err(MovieError::Format)
+    }
+
+    fn read(reader: &mut StateReader) -> Result<Self, StateError> {
+        let rom_checksum = reader.u32()?;
+        let model = Model::from_code(reader.u8()?).ok_or(StateError::Invalid("model"))?;
+        let boot_rom_checksum = Some(reader.u32()?).filter(|&checksum| checksum != 0);
+        let checksum_interval = reader.u32()?.max(1);
+        let rerecords = reader.u32()?;
+        let start_state = reader.bytes()?;
+        let inputs = reader.bytes()?.to_vec();
+        let count = reader.u32()?;
+        let mut checksums = Vec::new();
+        for _ in 0..count {
+            checksums.push((reader.u32()?, reader.u32()?));
+        }
+        Ok(Movie {
+            rom_checksum,
+            model,
+            boot_rom_checksum,
+            checksum_interval,
+            rerecords,
+            start_state: (!start_state.is_empty()).then(|| start_state.to_vec()),
+            inputs,
+            checksums,
+        })
+    }
+}
+
+impl Session {
+    /// Record into `movie` from its start. `gameboy` must be at the start of the movie, see
+    /// `Movie::start`.
+    pub fn record(mut movie: Movie) -> Self {
+        movie.truncate(0);
+        Session {
+            movie,
+            mode: Mode::Record,
+            frame: 0,
+        }
+    }
+
+    /// Play `movie` back from its start in `ReadOnly` or `ReadWrite` mode.
+    pub fn play(movie: Movie, mode: Mode) -> Self {
+        Session {
+            movie,
+            mode,
+            frame: 0,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Frames run since the start of the movie.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Stop playing back and record from the current frame, dropping the rest of the movie.
+    pub fn branch(&mut self) -> Result<(), MovieError> {
+        match self.mode {
+            Mode::ReadOnly => Err(MovieError::ReadOnly),
+            Mode::Record => Ok(()),
+            Mode::ReadWrite => {
+                self.movie.truncate(self.frame);
+                self.movie.rerecords += 1;
+                self.mode = Mode::Record;
+                Ok(())
+            }
+        }
+    }
+
+    /// Run a frame with the recorded input, or with `input` when recording.
+    pub fn run_frame(&mut self, gameboy: &mut GameBoy, input: u8) -> FrameStatus {
+        if self.mode == Mode::ReadWrite && input != 0 && self.movie.input(self.frame) != Some(input)
+        {
+            self.branch().unwrap();
+        }
+
+        let (input, mut status) = match self.mode {
+            Mode::Record => {
+                self.movie.truncate(self.frame);
+                self.movie.inputs.push(input);
+                (input, FrameStatus::Recorded)
+            }
+            _ => match self.movie.input(self.frame) {
+                Some(recorded) => (recorded, FrameStatus::Played),
+                None => (input, FrameStatus::Finished),
+            },
+        };
+        apply_input(gameboy, input);
+        gameboy.run_frame();
+        self.frame += 1;
+
+        if self.frame.is_multiple_of(self.movie.checksum_interval) {
+            let actual = gameboy.state_checksum();
+            let expected = self
+                .movie
+                .checksums
+                .iter()
+                .find(|&&(frame, _)| frame == self.frame)
+                .map(|&(_, checksum)| checksum);
+            match (self.mode, expected) {
+                (Mode::Record, _) => self.movie.checksums.push((self.frame, actual)),
+                (_, Some(expected)) if status == FrameStatus::Played && expected != actual => {
+                    status = FrameStatus::Desync {
+                        frame: self.frame,
+                        expected,
+                        actual,
+                    };
+                }
+                _ => {}
+            }
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    /// ROM adding the joypad direction bits into B every frame, so the state depends on input.
+    fn rom() -> Vec<u8> {
+        let program = [
+            0x3E, 0x20, 0xE0, 0x00, // ld a, $20; ldh [P1], a (directions)
+            0xAF, 0xE0, 0x0F, // .loop xor a; ldh [IF], a
+            0x76, 0x00, // halt; nop
+            0xF0, 0x00, 0x2F, 0xE6, 0x0F, // ldh a, [P1]; cpl; and $0F
+            0x80, 0x47, // add b; ld b, a
+            0x18, 0xF2, // jr .loop
+        ];
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+        rom
+    }
+
+    fn gameboy() -> GameBoy {
+        let mut gameboy = GameBoy::new(rom()).unwrap();
+        gameboy.cpu_mut().mmu_mut().write_byte(0xFFFF, 0x01); // wake up on V-Blank
+        gameboy
+    }
+
+    fn record(inputs: &[u8]) -> (Movie, u8) {
+        let mut gameboy = gameboy();
+        let checksum = gameboy.mmu().cartridge().rom_checksum();
+        let mut session = Session::record(Movie::new(checksum, Model::Dmg, 2));
+        for &input in inputs {
+            assert_eq!(
+                session.run_frame(&mut gameboy, input),
+                FrameStatus::Recorded
+            );
+        }
+        (session.into_movie(), gameboy.cpu().registers().b)
+    }
+
+    #[test]
+    fn test_buttons_to_input() {
+        assert_eq!(buttons_to_input(&[Button::Right, Button::Start]), 0x81);
+    }
+
+    #[test]
+    fn test_record_and_play_back() {
+        let (movie, b) = record(&[0x01, 0x00, 0x02, 0x02, 0x08, 0x00]);
+        assert_ne!(b, 0);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.len(), 6);
+
+        let mut gameboy = gameboy();
+        let mut session = Session::play(movie, Mode::ReadOnly);
+        for _ in 0..6 {
+            assert_eq!(session.run_frame(&mut gameboy, 0x04), FrameStatus::Played);
+        }
+        assert_eq!(session.run_frame(&mut gameboy, 0x00), FrameStatus::Finished);
+        assert_eq!(gameboy.cpu().registers().b, b);
+        assert_eq!(session.branch(), Err(MovieError::ReadOnly));
+    }
+
+    #[test]
+    fn test_desync() {
+        let (movie, _) = record(&[0x01, 0x01, 0x01, 0x01]);
+        let mut gameboy = gameboy();
+        gameboy.cpu_mut().registers_mut().b = 0x10;
+
+        let mut session = Session::play(movie, Mode::ReadOnly);
+        assert_eq!(session.run_frame(&mut gameboy, 0), FrameStatus::Played);
+        assert!(matches!(
+            session.run_frame(&mut gameboy, 0),
+            FrameStatus::Desync { frame: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_read_write_branches() {
+        let (movie, _) = record(&[0x01, 0x01, 0x01, 0x01]);
+        let mut gameboy = gameboy();
+        let mut session = Session::play(movie, Mode::ReadWrite);
+        session.run_frame(&mut gameboy, 0);
+        assert_eq!(session.run_frame(&mut gameboy, 0x02), FrameStatus::Recorded);
+        assert_eq!(session.mode(), Mode::Record);
+
+        let movie = session.into_movie();
+        assert_eq!(movie.len(), 2);
+        assert_eq!(movie.input(1), Some(0x02));
+        assert_eq!(movie.rerecords, 1);
+    }
+
+    #[test]
+    fn test_start_from_state() {
+        let mut gameboy = gameboy();
+        apply_input(&mut gameboy, 0x01);
+        gameboy.run_frame();
+        gameboy.run_frame();
+
+        let mut session = Session::record(Movie::from_state(&gameboy, 1));
+        session.run_frame(&mut gameboy, 0x02);
+        let b = gameboy.cpu().registers().b;
+
+        let movie = session.into_movie();
+        let mut replay = movie.start(rom(), None).unwrap();
+        let mut session = Session::play(movie, Mode::ReadOnly);
+        assert_eq!(session.run_frame(&mut replay, 0), FrameStatus::Played);
+        assert_eq!(replay.cpu().registers().b, b);
+    }
+
+    #[test]
+    fn test_model_and_boot_rom() {
+        let mut movie = Movie::new(0x1234_5678, Model::Cgb, 60);
+        movie.boot_rom_checksum = Some(0x9ABC_DEF0);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.model, Model::Cgb);
+        assert_eq!(movie.boot_rom_checksum, Some(0x9ABC_DEF0));
+
+        let (mut movie, _) = record(&[0x01]);
+        movie.model = Model::Cgb;
+        assert_eq!(movie.start(rom(), None).unwrap().model(), Model::Cgb);
+
+        let boot_rom = BootRom::new(vec![0; crate::boot::CGB_SIZE], Model::Cgb).unwrap();
+        movie.boot_rom_checksum = Some(state::crc32(boot_rom.data()));
+        assert!(movie.start(rom(), Some(boot_rom)).is_ok());
+        assert_eq!(
+            movie.start(rom(), None).err(),
+            movie.boot_rom_checksum.map(MovieError::BootRomMismatch)
+        );
+    }
+}
@@ -0,0 +1,551 @@
+// Colors of the LCD output. The PPU leaves DMG shades and CGB RGB555 colors in the frame, a
+// `ColorStage` turns them into 8-bit RGB: shades through a 4-color DMG palette, CGB colors
+// as they are or through the color correction curve of the CGB LCD.
+//
+// Palette files hold the 4 colors from the lightest shade to the darkest, one `RRGGBB` line
+// each (an optional `#` before, `;` starts a comment).
+//
+// A DMG game on a CGB gets its palettes from the boot ROM, looked up by the sum of the title
+// bytes for Nintendo games, with the 4th title letter telling apart titles of the same sum.
+// https://gbdev.io/pandocs/Power_Up_Sequence.html#compatibility-palettes
+
+use std::fmt;
+
+use crate::{cartridge::Cartridge, ppu::Color};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    /// A line is not an `RRGGBB` color, holds the line number.
+    Syntax(usize),
+    /// The file does not hold 4 colors, holds how many it has.
+    Count(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Syntax(line) => write!(f, "line {}: expected an `RRGGBB` color", line),
+            PaletteError::Count(count) => write!(f, "expected 4 colors, found {}", count),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// 8-bit RGB colors of the DMG shades, from white to black.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 4]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREY
+    }
+}
+
+impl Palette {
+    /// Even steps from white to black.
+    pub const GREY: Palette = Palette([[0xFF; 3], [0xAA; 3], [0x55; 3], [0x00; 3]]);
+    /// The green LCD of the DMG.
+    pub const GREEN: Palette = Palette([
+        [0x9B, 0xBC, 0x0F],
+        [0x8B, 0xAC, 0x0F],
+        [0x30, 0x62, 0x30],
+        [0x0F, 0x38, 0x0F],
+    ]);
+    /// The grey-green LCD of the Game Boy Pocket.
+    pub const POCKET: Palette = Palette([
+        [0xC4, 0xCF, 0xA1],
+        [0x8B, 0x95, 0x6D],
+        [0x4D, 0x53, 0x3C],
+        [0x1F, 0x1F, 0x1F],
+    ]);
+
+    pub const NAMES: [&'static str; 3] = ["grey", "green", "pocket"];
+
+    /// One of the built-in palettes by name.
+    pub fn named(name: &str) -> Option<Palette> {
+        match name.to_ascii_lowercase().as_str() {
+            "grey" | "gray" => Some(Palette::GREY),
+            "green" => Some(Palette::GREEN),
+            "pocket" => Some(Palette::POCKET),
+            _ => None,
+        }
+    }
+
+    /// Parse a palette file.
+    pub fn parse(text: &str) -> Result<Palette, PaletteError> {
+        let mut colors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let hex = line.strip_prefix('#').unwrap_or(line);
+            let rgb = (hex.len() == 6)
+                .then(|| u32::from_str_radix(hex, 16).ok())
+                .flatten()
+                .ok_or(PaletteError::Syntax(number + 1))?;
+            colors.push([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]);
+        }
+        colors
+            .as_slice()
+            .try_into()
+            .map(Palette)
+            .map_err(|_| PaletteError::Count(colors.len()))
+    }
+}
+
+/// Turns the colors of a frame into 8-bit RGB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColorStage {
+    pub palette: Palette,
+    /// Mimic the washed out colors of the CGB LCD instead of showing RGB555 at full range.
+    pub correction: bool,
+}
+
+impl ColorStage {
+    pub fn rgb(&self, color: Color) -> [u8; 3] {
+        match color {
+            Color::Shade(shade) => self.palette.0[shade.min(3) as usize],
+            Color::Rgb555(rgb) if self.correction => correct(rgb),
+            Color::Rgb555(_) => color.to_rgb(),
+        }
+    }
+
+    /// The frame as 8-bit RGB bytes.
+    pub fn render(&self, frame: &[Color]) -> Vec<u8> {
+        frame.iter().flat_map(|&color| self.rgb(color)).collect()
+    }
+}
+
+/// Color correction curve mixing the components like the CGB LCD does, as used by higan.
+fn correct(color: u16) -> [u8; 3] {
+    let r = (color & 0x1F) as u32;
+    let g = (color >> 5 & 0x1F) as u32;
+    let b = (color >> 10 & 0x1F) as u32;
+    [
+        r * 26 + g * 4 + b * 2,
+        g * 24 + b * 8,
+        r * 6 + g * 4 + b * 22,
+    ]
+    .map(|c| (c.min(960) >> 2) as u8)
+}
+
+/// BG, OBJ0 and OBJ1 palettes the CGB boot ROM gives to a DMG game, in RGB555.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompatibilityPalettes {
+    pub bg: [u16; 4],
+    pub obj0: [u16; 4],
+    pub obj1: [u16; 4],
+}
+
+const fn rgb555(rgb: u32) -> u16 {
+    ((rgb >> 19 & 0x1F) | (rgb >> 11 & 0x1F) << 5 | (rgb >> 3 & 0x1F) << 10) as u16
+}
+
+const fn colors(rgb: [u32; 4]) -> [u16; 4] {
+    [
+        rgb555(rgb[0]),
+        rgb555(rgb[1]),
+        rgb555(rgb[2]),
+        rgb555(rgb[3]),
+    ]
+}
+
+const fn palettes(bg: [u32; 4], obj0: [u32; 4], obj1: [u32; 4]) -> CompatibilityPalettes {
+    CompatibilityPalettes {
+        bg: colors(bg),
+        obj0: colors(obj0),
+        obj1: colors(obj1),
+    }
+}
+
+const WHITE_RED_BLACK: [u32; 4] = [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000];
+const WHITE_GREEN_BLACK: [u32; 4] = [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000];
+const WHITE_BLUE_BLACK: [u32; 4] = [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000];
+const BROWN: [u32; 4] = [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000];
+
+/// The palettes the boot ROM lets the player pick by holding a direction, and A or B, while
+/// the logo shows.
+pub const SELECTABLE: [(&str, CompatibilityPalettes); 12] = [
+    ("brown", palettes(BROWN, BROWN, BROWN)),
+    (
+        "red",
+        palettes(WHITE_RED_BLACK, WHITE_GREEN_BLACK, WHITE_BLUE_BLACK),
+    ),
+    (
+        "dark-brown",
+        palettes([0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108], BROWN, BROWN),
+    ),
+    (
+        "blue",
+        palettes(WHITE_BLUE_BLACK, WHITE_RED_BLACK, WHITE_GREEN_BLACK),
+    ),
+    (
+        "dark-blue",
+        palettes(
+            [0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000],
+            WHITE_RED_BLACK,
+            BROWN,
+        ),
+    ),
+    ("grey", {
+        let colors = [0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000];
+        palettes(colors, colors, colors)
+    }),
+    ("pale-yellow", {
+        let colors = [0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000];
+        palettes(colors, colors, colors)
+    }),
+    ("orange", {
+        let colors = [0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000];
+        palettes(colors, colors, colors)
+    }),
+    (
+        "yellow",
+        palettes(
+            [0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000],
+            WHITE_BLUE_BLACK,
+            WHITE_GREEN_BLACK,
+        ),
+    ),
+    ("green", {
+        let colors = [0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000];
+        palettes(colors, colors, colors)
+    }),
+    (
+        "dark-green",
+        palettes(
+            [0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000],
+            WHITE_RED_BLACK,
+            WHITE_RED_BLACK,
+        ),
+    ),
+    ("inverted", {
+        let colors = [0x000000, 0x008484, 0xFFDE00, 0xFFFFFF];
+        palettes(colors, colors, colors)
+    }),
+];
+
+/// Palettes of games missing from the table, and of every game from other licensees.
+pub const DEFAULT: CompatibilityPalettes = SELECTABLE[10].1;
+
+/// Offset of the first color of a palette in `BOOT_PALETTES`.
+const fn at(palette: u8) -> u8 {
+    palette * 4
+}
+
+/// The palettes in the boot ROM, in RGB555.
+const BOOT_PALETTES: [[u16; 4]; 30] = [
+    [0x7FFF, 0x32BF, 0x00D0, 0x0000],
+    [0x639F, 0x4279, 0x15B0, 0x04CB],
+    [0x7FFF, 0x6E31, 0x454A, 0x0000],
+    [0x7FFF, 0x1BEF, 0x0200, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x5294, 0x294A, 0x0000],
+    [0x7FFF, 0x03FF, 0x012F, 0x0000],
+    [0x7FFF, 0x03EF, 0x01D6, 0x0000],
+    [0x7FFF, 0x42B5, 0x3DC8, 0x0000],
+    [0x7E74, 0x03FF, 0x0180, 0x0000],
+    [0x67FF, 0x77AC, 0x1A13, 0x2D6B],
+    [0x7ED6, 0x4BFF, 0x2175, 0x0000],
+    [0x53FF, 0x4A5F, 0x7E52, 0x0000],
+    [0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0],
+    [0x03ED, 0x7FFF, 0x255F, 0x0000],
+    [0x036A, 0x021F, 0x03FF, 0x7FFF],
+    [0x7FFF, 0x01DF, 0x0112, 0x0000],
+    [0x231F, 0x035F, 0x00F2, 0x0009],
+    [0x7FFF, 0x03EA, 0x011F, 0x0000],
+    [0x299F, 0x001A, 0x000C, 0x0000],
+    [0x7FFF, 0x027F, 0x001F, 0x0000],
+    [0x7FFF, 0x03E0, 0x0206, 0x0120],
+    [0x7FFF, 0x7EEB, 0x001F, 0x7C00],
+    [0x7FFF, 0x3FFF, 0x7E00, 0x001F],
+    [0x7FFF, 0x03FF, 0x001F, 0x0000],
+    [0x03FF, 0x001F, 0x000C, 0x0000],
+    [0x7FFF, 0x033F, 0x0193, 0x0000],
+    [0x0000, 0x4200, 0x037F, 0x7FFF],
+    [0x7FFF, 0x7E8C, 0x7C00, 0x0000],
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+];
+
+/// The OBJ0, OBJ1 and BG palettes the boot ROM can give a game, as offsets of their first
+/// color in `BOOT_PALETTES`.
+const COMBINATIONS: [[u8; 3]; 51] = [
+    [at(4), at(4), at(29)],
+    [at(18), at(18), at(18)],
+    [at(20), at(20), at(20)],
+    [at(24), at(24), at(24)],
+    [at(9), at(9), at(9)],
+    [at(0), at(0), at(0)],
+    [at(27), at(27), at(27)],
+    [at(5), at(5), at(5)],
+    [at(12), at(12), at(12)],
+    [at(26), at(26), at(26)],
+    [at(16), at(8), at(8)],
+    [at(4), at(28), at(28)],
+    [at(4), at(2), at(2)],
+    [at(3), at(4), at(4)],
+    [at(4), at(29), at(29)],
+    [at(28), at(4), at(28)],
+    [at(2), at(17), at(2)],
+    [at(16), at(16), at(8)],
+    [at(4), at(4), at(7)],
+    [at(4), at(4), at(18)],
+    [at(4), at(4), at(20)],
+    [at(19), at(19), at(9)],
+    [15, 15, 44], // Straddling two palettes
+    [at(17), at(17), at(2)],
+    [at(4), at(4), at(2)],
+    [at(4), at(4), at(3)],
+    [at(28), at(28), at(0)],
+    [at(3), at(3), at(0)],
+    [at(0), at(0), at(1)],
+    [at(18), at(22), at(18)],
+    [at(20), at(22), at(20)],
+    [at(24), at(22), at(24)],
+    [at(16), at(22), at(8)],
+    [at(17), at(4), at(13)],
+    [111, 0, 56],  // Straddling two palettes
+    [111, 16, 60], // Straddling two palettes
+    [at(19), at(22), at(9)],
+    [at(16), at(28), at(10)],
+    [at(4), at(23), at(28)],
+    [at(17), at(22), at(2)],
+    [at(4), at(0), at(2)],
+    [at(4), at(28), at(3)],
+    [at(28), at(3), at(0)],
+    [at(3), at(28), at(4)],
+    [at(21), at(28), at(4)],
+    [at(3), at(28), at(0)],
+    [at(25), at(3), at(28)],
+    [at(0), at(28), at(8)],
+    [at(4), at(3), at(28)],
+    [at(28), at(3), at(6)],
+    [at(4), at(28), at(29)],
+];
+
+/// The title checksums the boot ROM knows with the index of their palettes in `COMBINATIONS`.
+/// Checksums shared by several titles come last, with the 4th title letter telling them apart.
+const TITLES: [(u8, Option<u8>, u8); 94] = [
+    (0x00, None, 0),  // Default
+    (0x88, None, 4),  // ALLEY WAY
+    (0x16, None, 5),  // YAKUMAN
+    (0x36, None, 35), // BASEBALL, GAME AND WATCH 2
+    (0xD1, None, 34), // TENNIS
+    (0xDB, None, 3),  // TETRIS
+    (0xF2, None, 31), // QIX
+    (0x3C, None, 15), // DR.MARIO
+    (0x8C, None, 10), // RADARMISSION
+    (0x92, None, 5),  // F1RACE
+    (0x3D, None, 19), // YOSSY NO TAMAGO
+    (0x5C, None, 36),
+    (0x58, None, 7),  // X
+    (0xC9, None, 37), // MARIOLAND2
+    (0x3E, None, 30), // YOSSY NO COOKIE
+    (0x70, None, 44), // ZELDA
+    (0x1D, None, 21),
+    (0x59, None, 32),
+    (0x69, None, 31), // TETRIS FLASH
+    (0x19, None, 20), // DONKEY KONG
+    (0x35, None, 5),  // MARIO'S PICROSS
+    (0xA8, None, 33),
+    (0x14, None, 13), // POKEMON RED, GAMEBOYCAMERA G
+    (0xAA, None, 14), // POKEMON GREEN
+    (0x75, None, 5),  // PICROSS 2
+    (0x95, None, 29), // YOSSY NO PANEPON
+    (0x99, None, 5),  // KIRAKIRA KIDS
+    (0x34, None, 18), // GAMEBOY GALLERY
+    (0x6F, None, 9),  // POCKETCAMERA
+    (0x15, None, 3),
+    (0xFF, None, 2),  // BALLOON KID
+    (0x97, None, 26), // KINGOFTHEZOO
+    (0x4B, None, 25), // DMG FOOTBALL
+    (0x90, None, 25), // WORLD CUP
+    (0x17, None, 41), // OTHELLO
+    (0x10, None, 42), // SUPER RC PRO-AM
+    (0x39, None, 26), // DYNABLASTER
+    (0xF7, None, 45), // BOY AND BLOB GB2
+    (0xF6, None, 42), // MEGAMAN
+    (0xA2, None, 45), // STAR WARS-NOA
+    (0x49, None, 36),
+    (0x4E, None, 38), // WAVERACE
+    (0x43, None, 26),
+    (0x68, None, 42), // LOLO2
+    (0xE0, None, 30), // YOSHI'S COOKIE
+    (0x8B, None, 41), // MYSTIC QUEST
+    (0xF0, None, 34),
+    (0xCE, None, 34), // TOPRANKINGTENNIS
+    (0x0C, None, 5),  // MANSELL
+    (0x29, None, 42), // MEGAMAN3
+    (0xE8, None, 6),  // SPACE INVADERS
+    (0xB7, None, 5),  // GAME&WATCH
+    (0x86, None, 33), // DONKEYKONGLAND95
+    (0x9A, None, 25), // ASTEROIDS/MISCMD
+    (0x52, None, 42), // STREET FIGHTER 2
+    (0x01, None, 42), // DEFENDER/JOUST
+    (0x9D, None, 40), // KILLERINSTINCT95
+    (0x71, None, 2),  // TETRIS BLAST
+    (0x9C, None, 16), // PINOCCHIO
+    (0xBD, None, 25),
+    (0x5D, None, 42), // BA.TOSHINDEN
+    (0x6D, None, 42), // NETTOU KOF 95
+    (0x67, None, 5),
+    (0x3F, None, 0),  // TETRIS PLUS
+    (0x6B, None, 39), // DONKEYKONGLAND 3
+    (0xB3, Some(b'B'), 36),
+    (0x46, Some(b'E'), 22), // SUPER MARIOLAND
+    (0x28, Some(b'F'), 25), // GOLF
+    (0xA5, Some(b'A'), 6),  // SOLARSTRIKER
+    (0xC6, Some(b'A'), 32), // GBWARS
+    (0xD3, Some(b'R'), 12), // KAERUNOTAMENI
+    (0x27, Some(b'B'), 36),
+    (0x61, Some(b'E'), 11), // POKEMON BLUE
+    (0x18, Some(b'K'), 39), // DONKEYKONGLAND
+    (0x66, Some(b'E'), 18), // GAMEBOY GALLERY2
+    (0x6A, Some(b'K'), 39), // DONKEYKONGLAND 2
+    (0xBF, Some(b' '), 24), // KID ICARUS
+    (0x0D, Some(b'R'), 31), // TETRIS2
+    (0xF4, Some(b'-'), 50),
+    (0xB3, Some(b'U'), 17), // MOGURANYA
+    (0x46, Some(b'R'), 46),
+    (0x28, Some(b'A'), 6),  // GALAGA&GALAXIAN
+    (0xA5, Some(b'R'), 27), // BT2RAGNAROKWORLD
+    (0xC6, Some(b' '), 0),  // KEN GRIFFEY JR
+    (0xD3, Some(b'I'), 47),
+    (0x27, Some(b'N'), 41), // MAGNETIC SOCCER
+    (0x61, Some(b'A'), 41), // VEGAS STAKES
+    (0x18, Some(b'I'), 0),
+    (0x66, Some(b'L'), 0),  // MILLI/CENTI/PEDE
+    (0x6A, Some(b'I'), 19), // MARIO & YOSHI
+    (0xBF, Some(b'C'), 34), // SOCCER
+    (0x0D, Some(b'E'), 23), // POKEBOM
+    (0xF4, Some(b' '), 18), // G&W GALLERY
+    (0xB3, Some(b'R'), 29), // TETRIS ATTACK
+];
+
+impl CompatibilityPalettes {
+    /// One of `SELECTABLE` by name.
+    pub fn named(name: &str) -> Option<CompatibilityPalettes> {
+        SELECTABLE
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|&(_, palettes)| palettes)
+    }
+
+    /// Palettes the boot ROM picks for a cartridge.
+    pub fn for_cartridge(cartridge: &Cartridge) -> CompatibilityPalettes {
+        let Some(sum) = title_checksum(cartridge) else {
+            return DEFAULT;
+        };
+        let fourth = cartridge.read_rom(0x0137);
+        TITLES
+            .iter()
+            .find(|&&(checksum, letter, _)| {
+                checksum == sum && letter.is_none_or(|letter| letter == fourth)
+            })
+            .map_or(DEFAULT, |&(_, _, index)| combination(index as usize))
+    }
+}
+
+/// The palettes of an entry of `COMBINATIONS`.
+fn combination(index: usize) -> CompatibilityPalettes {
+    let palette = |offset: u8| {
+        let start = offset as usize;
+        BOOT_PALETTES.as_flattened()[start..start + 4]
+            .try_into()
+            .unwrap()
+    };
+    let [obj0, obj1, bg] = COMBINATIONS[index];
+    CompatibilityPalettes {
+        bg: palette(bg),
+        obj0: palette(obj0),
+        obj1: palette(obj1),
+    }
+}
+
+/// Sum of the title bytes (0x0134-0x0143) of a Nintendo game, `None` for other licensees.
+pub(crate) fn title_checksum(cartridge: &Cartridge) -> Option<u8> {
+    let read = |address| cartridge.read_rom(address);
+    let nintendo = read(0x014B) == 0x01
+        || (read(0x014B) == 0x33 && read(0x0144) == b'0' && read(0x0145) == b'1');
+    nintendo.then(|| (0x0134..=0x0143).fold(0u8, |sum, address| sum.wrapping_add(read(address))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge(title: &[u8], licensee: u8) -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x014B] = licensee;
+        Cartridge::new(rom).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "; DMG green\n#9BBC0F\n8bac0f ; light\n\n306230\n0F380F\n";
+        assert_eq!(Palette::parse(text), Ok(Palette::GREEN));
+        assert_eq!(
+            Palette::parse("FFFFFF\nGGGGGG"),
+            Err(PaletteError::Syntax(2))
+        );
+        assert_eq!(
+            Palette::parse("FFFFFF\n000000"),
+            Err(PaletteError::Count(2))
+        );
+        assert_eq!(Palette::named("Pocket"), Some(Palette::POCKET));
+    }
+
+    #[test]
+    fn test_color_stage() {
+        let mut stage = ColorStage {
+            palette: Palette::GREEN,
+            correction: false,
+        };
+        assert_eq!(stage.rgb(Color::Shade(3)), [0x0F, 0x38, 0x0F]);
+        assert_eq!(stage.rgb(Color::Rgb555(0x001F)), [0xFF, 0x00, 0x00]);
+        stage.correction = true;
+        assert_eq!(stage.rgb(Color::Rgb555(0x7FFF)), [240, 240, 240]);
+        assert_eq!(stage.rgb(Color::Rgb555(0x001F)), [201, 0, 46]);
+        assert_eq!(stage.render(&[Color::Shade(0)]), vec![0x9B, 0xBC, 0x0F]);
+    }
+
+    #[test]
+    fn test_compatibility_palettes() {
+        assert_eq!(rgb555(0xFF8484), 0x4210 | 0x001F);
+        let red = CompatibilityPalettes::for_cartridge(&cartridge(b"POKEMON RED", 0x01));
+        assert_eq!(red.bg, colors(WHITE_RED_BLACK));
+        assert_eq!(red.obj0, colors(WHITE_GREEN_BLACK));
+        assert_eq!(red.obj1, colors(WHITE_RED_BLACK));
+        let other = CompatibilityPalettes::for_cartridge(&cartridge(b"POKEMON RED", 0x00));
+        assert_eq!(other, DEFAULT);
+        let unknown = CompatibilityPalettes::for_cartridge(&cartridge(b"HOMEBREW GAME", 0x01));
+        assert_eq!(unknown, DEFAULT);
+    }
+
+    #[test]
+    fn test_compatibility_palettes_by_4th_letter() {
+        // POKEMON BLUE and VEGAS STAKES have the same title checksum.
+        let blue = CompatibilityPalettes::for_cartridge(&cartridge(b"POKEMON BLUE", 0x01));
+        assert_eq!(blue.bg, colors(WHITE_BLUE_BLACK));
+        assert_eq!(blue.obj0, colors(WHITE_RED_BLACK));
+        assert_eq!(blue.obj1, colors(WHITE_BLUE_BLACK));
+        let vegas = CompatibilityPalettes::for_cartridge(&cartridge(b"VEGAS STAKES", 0x01));
+        assert_eq!(vegas, combination(41));
+        assert_ne!(vegas, blue);
+        // Same checksum, but a 4th letter the table does not have.
+        let other = CompatibilityPalettes::for_cartridge(&cartridge(b"POKxMON BLUE", 0x01));
+        assert_eq!(other, DEFAULT);
+    }
+
+    #[test]
+    fn test_selectable_palettes_in_boot_rom() {
+        // Combination picked by each button, in `SELECTABLE` order.
+        let buttons = [5, 43, 28, 48, 40, 7, 8, 3, 49, 1, 0, 6];
+        for ((name, palettes), index) in SELECTABLE.iter().zip(buttons) {
+            assert_eq!(*palettes, combination(index), "{}", name);
+        }
+    }
+}
@@ -1,69 +1,201 @@
+// Pixel Processing Unit. Each scanline takes 456 dots (4 dots per M-cycle):
+// Mode 2   80 dots    OAM scan
+// Mode 3   172 dots   Drawing pixels (the line is rendered when it ends)
+// Mode 0   204 dots   H-Blank
+// Lines 144-153 are V-Blank (mode 1), a frame takes 154 lines or 17556 M-cycles.
+// The CGB has two VRAM banks, selected by VBK (0xFF4F). Bank 1 holds more tiles and, at the
+// offsets of the tile maps of bank 0, their attributes:
+// Bit 7    BG over OBJ priority
+// Bit 6    Y flip
+// Bit 5    X flip
+// Bit 3    Tile VRAM bank
+// Bit 2-0  BG palette
+// Its 8 BG and 8 OBJ palettes of 4 RGB555 colors are accessed through BCPS/BCPD and
+// OCPS/OCPD (0xFF68-0xFF6B), and OPRI (0xFF6C) selects the OBJ priority by OAM index.
+
 use crate::{
+    interrupt,
     memory::Memory,
     mmu::{OAM_BEGIN, OAM_END, VRAM_BEGIN, VRAM_END},
+    state::{Snapshot, StateError, StateReader, StateWriter},
 };
 
-struct LCDC {
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+const DRAWING_DOTS: u16 = 172;
+const LINES_PER_FRAME: u8 = 154;
+const VRAM_BANK_SIZE: usize = 0x2000;
+
+pub const VBK: u16 = 0xFF4F;
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+pub const OPRI: u16 = 0xFF6C;
+
+const PALETTE_RAM_SIZE: usize = 64;
+
+/// Sprites drawn on a single line.
+const SPRITES_PER_LINE: usize = 10;
+
+// LCD Control Register.
+struct Lcdc {
     data: u8,
 }
 
-impl LCDC {
+impl Lcdc {
     pub fn new() -> Self {
-        LCDC { data: 0 }
+        Lcdc { data: 0 }
     }
 
+    // Bit 7 - LCD and PPU enable
     fn bit7(&self) -> bool {
         self.data & 0b1000_0000 != 0x00
     }
 
+    // Bit 6 - Window tile map area (0=9800-9BFF, 1=9C00-9FFF)
     fn bit6(&self) -> bool {
         self.data & 0b0100_0000 != 0x00
     }
 
+    // Bit 5 - Window enable
     fn bit5(&self) -> bool {
         self.data & 0b0010_0000 != 0x00
     }
 
+    // Bit 4 - BG and Window tile data area (0=8800-97FF, 1=8000-8FFF)
     fn bit4(&self) -> bool {
         self.data & 0b0001_0000 != 0x00
     }
 
+    // Bit 3 - BG tile map area (0=9800-9BFF, 1=9C00-9FFF)
     fn bit3(&self) -> bool {
         self.data & 0b0000_1000 != 0x00
     }
 
+    // Bit 2 - OBJ size (0=8x8, 1=8x16)
     fn bit2(&self) -> bool {
         self.data & 0b0000_0100 != 0x00
     }
 
+    // Bit 1 - OBJ enable
     fn bit1(&self) -> bool {
         self.data & 0b0000_0010 != 0x00
     }
 
+    // Bit 0 - BG and Window enable
     fn bit0(&self) -> bool {
         self.data & 0b0000_0001 != 0x00
     }
 }
 
-enum Pixel {
-    White,
-    LightGray,
-    DarkGray,
-    Black,
+/// Color of a pixel on the LCD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// DMG shade, 0 (white) to 3 (black).
+    Shade(u8),
+    /// CGB color, 5 bits per component with red in the low bits.
+    Rgb555(u16),
+}
+
+impl Color {
+    pub const WHITE: Color = Color::Shade(0);
+
+    /// Color as 8-bit RGB, shades from white to black in even steps.
+    pub fn to_rgb(self) -> [u8; 3] {
+        match self {
+            Color::Shade(shade) => [0xFF - 0x55 * shade.min(3); 3],
+            Color::Rgb555(color) => {
+                let component = |shift: u16| {
+                    let value = (color >> shift & 0x1F) as u8;
+                    value << 3 | value >> 2
+                };
+                [component(0), component(5), component(10)]
+            }
+        }
+    }
+
+    /// 16-bit encoding for save states: RGB555, or bit 15 set and the shade.
+    fn to_bits(self) -> u16 {
+        match self {
+            Color::Shade(shade) => 0x8000 | shade as u16,
+            Color::Rgb555(color) => color & 0x7FFF,
+        }
+    }
+
+    fn from_bits(bits: u16) -> Color {
+        if bits & 0x8000 != 0 {
+            Color::Shade(bits as u8 & 0x03)
+        } else {
+            Color::Rgb555(bits)
+        }
+    }
+}
+
+/// Where the colors of pixels come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Shades from BGP, OBP0 and OBP1.
+    Dmg,
+    /// RGB555 colors from the palettes selected by the BG attributes and OAM.
+    Cgb,
+    /// A DMG game on a CGB: the shades from BGP, OBP0 and OBP1 index BG palette 0 and
+    /// OBJ palettes 0-1.
+    Compatibility,
 }
 
-impl Pixel {
-    pub fn from_u8(value: u8) -> Pixel {
-        match value {
-            1 => Pixel::LightGray,
-            2 => Pixel::DarkGray,
-            3 => Pixel::Black,
-            _ => Pixel::White,
+/// A palette RAM (BG or OBJ) with its BCPS/OCPS index register.
+struct PaletteRam {
+    data: [u8; PALETTE_RAM_SIZE],
+    index: u8,            // Bits 0-5
+    auto_increment: bool, // Bit 7, the index moves to the next byte after each write
+}
+
+impl PaletteRam {
+    fn new() -> Self {
+        PaletteRam {
+            data: [0; PALETTE_RAM_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    fn read_index(&self) -> u8 {
+        0x40 | (self.auto_increment as u8) << 7 | self.index
+    }
+
+    fn write_index(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 != 0;
+    }
+
+    fn write_data(&mut self, value: u8, blocked: bool) {
+        if !blocked {
+            self.data[self.index as usize] = value;
+        }
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    fn color(&self, palette: u8, color: u8) -> Color {
+        let index = (palette as usize & 0x07) * 8 + color as usize * 2;
+        Color::Rgb555(u16::from_le_bytes([self.data[index], self.data[index + 1]]) & 0x7FFF)
+    }
+
+    fn set_palette(&mut self, palette: usize, colors: [u16; 4]) {
+        for (i, color) in colors.iter().enumerate() {
+            let index = palette * 8 + i * 2;
+            self.data[index..index + 2].copy_from_slice(&color.to_le_bytes());
         }
     }
 }
 
 // LCD Status Register.
+#[derive(Default)]
 pub struct Stat {
     // Bit 6 - LYC=LY Coincidence Interrupt (1=Enable) (Read/Write)
     enable_ly_interrupt: bool,
@@ -95,28 +227,45 @@ impl Stat {
 
 pub struct PPU {
     oam: [u8; 0xA0], // "Object Attribute Memory", stores 40 sprites with 8x8 resolution.
-    vram: [u8; 0x2000],
+    vram: [u8; 2 * VRAM_BANK_SIZE],
+    vram_bank: u8,
+    color_mode: ColorMode,
+    bg_palettes: PaletteRam,
+    obj_palettes: PaletteRam,
+    opri: u8, // Bit 0: 0 = OBJ priority by OAM index, 1 = by X coordinate as on the DMG
 
-    lcdc: LCDC,
+    lcdc: Lcdc,
     stat: Stat,
 
     bgp: u8,  // BG palette data
     obp0: u8, // OBJ palette 0 data
     obp1: u8, // OBJ palette 1 data
 
+    dma: u8, // Last value written to the OAM DMA register
     ly: u8,
     lyc: u8,
     scx: u8,
     scy: u8,
     wx: u8,
     wy: u8,
+
+    dots: u16,       // Dots into the current line
+    window_line: u8, // Line of the window to draw next
+    stat_line: bool, // STAT interrupt line, the interrupt is requested on its rising edge
+    interrupt: u8,   // Interrupts requested since the last tick
+    hblanks: u32,    // H-Blanks entered since the last `take_hblanks`, for the HBlank DMA
+    frames: u64,
+
+    frame: Box<[Color; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+    bg_colors: [u8; SCREEN_WIDTH], // BG color index of the line, before the palette
+    bg_priority: [bool; SCREEN_WIDTH], // BG attribute bit 7 of the line
 }
 
 impl Memory for PPU {
     fn read_byte(&self, address: u16) -> u8 {
         match address {
-            VRAM_BEGIN..=VRAM_END => self.vram[(address as usize) - 0x1FFF],
-            OAM_BEGIN..=OAM_END => self.oam[(address as usize) - 0xFE00],
+            VRAM_BEGIN..=VRAM_END => self.vram[self.vram_index(address)],
+            OAM_BEGIN..=OAM_END => self.oam[(address - OAM_BEGIN) as usize],
             0xFF40 => self.lcdc.data,
             0xFF41 => {
                 let bit6 = if self.stat.enable_ly_interrupt {
@@ -140,18 +289,26 @@ impl Memory for PPU {
                     0x00
                 };
                 let bit2 = if self.ly == self.lyc { 0x04 } else { 0x00 };
-                bit6 | bit5 | bit4 | bit3 | bit2 | self.stat.mode
+                0x80 | bit6 | bit5 | bit4 | bit3 | bit2 | self.stat.mode
             }
             0xFF42 => self.scy,
             0xFF43 => self.scx,
             0xFF44 => self.ly,
             0xFF45 => self.lyc,
-            0xFF46 => 0, // write-only
+            0xFF46 => self.dma,
             0xFF47 => self.bgp,
             0xFF48 => self.obp0,
             0xFF49 => self.obp1,
-            0xFF4A => self.wx,
-            0xFF4B => self.wy,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            VBK => 0xFE | self.vram_bank,
+            BCPS => self.bg_palettes.read_index(),
+            BCPD if self.drawing() => 0xFF,
+            BCPD => self.bg_palettes.data[self.bg_palettes.index as usize],
+            OCPS => self.obj_palettes.read_index(),
+            OCPD if self.drawing() => 0xFF,
+            OCPD => self.obj_palettes.data[self.obj_palettes.index as usize],
+            OPRI => 0xFE | self.opri,
 
             _ => panic!("Unable to read from this address from the PPU"),
         }
@@ -159,50 +316,654 @@ impl Memory for PPU {
 
     fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            VRAM_BEGIN..=VRAM_END => self.vram[(address as usize) - 0x1FFF] = value,
-            OAM_BEGIN..=OAM_END => self.oam[(address as usize) - 0xFE00] = value,
-            0xFF40 => self.lcdc.data = value,
+            VRAM_BEGIN..=VRAM_END => self.vram[self.vram_index(address)] = value,
+            OAM_BEGIN..=OAM_END => self.oam[(address - OAM_BEGIN) as usize] = value,
+            0xFF40 => {
+                let was_enabled = self.lcdc.bit7();
+                self.lcdc.data = value;
+                if was_enabled && !self.lcdc.bit7() {
+                    self.ly = 0;
+                    self.dots = 0;
+                    self.window_line = 0;
+                    self.stat.mode = 0;
+                } else if !was_enabled && self.lcdc.bit7() {
+                    self.stat.mode = 2;
+                }
+                self.update_stat_line();
+            }
             0xFF41 => {
                 // Mode and LYC=LY are read-only
                 self.stat.enable_ly_interrupt = value & 0x40 != 0x00;
                 self.stat.enable_m2_interrupt = value & 0x20 != 0x00;
                 self.stat.enable_m1_interrupt = value & 0x10 != 0x00;
                 self.stat.enable_m0_interrupt = value & 0x08 != 0x00;
+                self.update_stat_line();
             }
             0xFF42 => self.scy = value,
             0xFF43 => self.scx = value,
             0xFF44 => {} // ready-only
-            0xFF45 => self.lyc = value,
+            0xFF45 => {
+                self.lyc = value;
+                self.update_stat_line();
+            }
+            0xFF46 => self.dma = value, // the MMU copies the data into OAM
             0xFF47 => self.bgp = value,
             0xFF48 => self.obp0 = value,
             0xFF49 => self.obp1 = value,
             0xFF4A => self.wy = value,
             0xFF4B => self.wx = value,
+            VBK => self.vram_bank = value & 0x01,
+            BCPS => self.bg_palettes.write_index(value),
+            BCPD => {
+                let blocked = self.drawing();
+                self.bg_palettes.write_data(value, blocked)
+            }
+            OCPS => self.obj_palettes.write_index(value),
+            OCPD => {
+                let blocked = self.drawing();
+                self.obj_palettes.write_data(value, blocked)
+            }
+            OPRI => self.opri = value & 0x01,
 
             _ => panic!("Unable to write to this address from the PPU"),
         }
     }
 }
 
+impl Snapshot for PPU {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.vram);
+        writer.bytes(&self.oam);
+        for value in [
+            self.lcdc.data,
+            self.read_byte(0xFF41),
+            self.bgp,
+            self.obp0,
+            self.obp1,
+            self.dma,
+            self.ly,
+            self.lyc,
+            self.scx,
+            self.scy,
+            self.wx,
+            self.wy,
+            self.window_line,
+            self.interrupt,
+        ] {
+            writer.u8(value);
+        }
+        writer.u16(self.dots);
+        writer.bool(self.stat_line);
+        writer.u64(self.frames);
+        let frame: Vec<u8> = self
+            .frame
+            .iter()
+            .flat_map(|color| color.to_bits().to_le_bytes())
+            .collect();
+        writer.bytes(&frame);
+        writer.u8(self.vram_bank);
+        writer.u8(self.color_mode as u8);
+        for palettes in [&self.bg_palettes, &self.obj_palettes] {
+            writer.bytes(&palettes.data);
+            writer.u8(palettes.read_index());
+        }
+        writer.u8(self.opri);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.bytes_into(&mut self.vram, "VRAM size")?;
+        reader.bytes_into(&mut self.oam, "OAM size")?;
+        self.lcdc.data = reader.u8()?;
+        let stat = reader.u8()?;
+        self.stat.enable_ly_interrupt = stat & 0x40 != 0x00;
+        self.stat.enable_m2_interrupt = stat & 0x20 != 0x00;
+        self.stat.enable_m1_interrupt = stat & 0x10 != 0x00;
+        self.stat.enable_m0_interrupt = stat & 0x08 != 0x00;
+        self.stat.mode = stat & 0x03;
+        self.bgp = reader.u8()?;
+        self.obp0 = reader.u8()?;
+        self.obp1 = reader.u8()?;
+        self.dma = reader.u8()?;
+        self.ly = reader.u8()?;
+        self.lyc = reader.u8()?;
+        self.scx = reader.u8()?;
+        self.scy = reader.u8()?;
+        self.wx = reader.u8()?;
+        self.wy = reader.u8()?;
+        self.window_line = reader.u8()?;
+        self.interrupt = reader.u8()?;
+        self.dots = reader.u16()?;
+        self.stat_line = reader.bool()?;
+        self.frames = reader.u64()?;
+
+        let frame = reader.bytes()?;
+        if frame.len() != self.frame.len() * 2
+            || self.ly >= LINES_PER_FRAME
+            || self.dots >= DOTS_PER_LINE
+        {
+            return Err(StateError::Invalid("PPU state"));
+        }
+        for (pixel, bytes) in self.frame.iter_mut().zip(frame.chunks_exact(2)) {
+            *pixel = Color::from_bits(u16::from_le_bytes([bytes[0], bytes[1]]));
+        }
+        self.vram_bank = reader.u8()? & 0x01;
+        self.color_mode = match reader.u8()? {
+            0 => ColorMode::Dmg,
+            1 => ColorMode::Cgb,
+            2 => ColorMode::Compatibility,
+            _ => return Err(StateError::Invalid("color mode")),
+        };
+        for palettes in [&mut self.bg_palettes, &mut self.obj_palettes] {
+            reader.bytes_into(&mut palettes.data, "palette RAM size")?;
+            palettes.write_index(reader.u8()?);
+        }
+        self.opri = reader.u8()? & 0x01;
+        Ok(())
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        PPU::new()
+    }
+}
+
 impl PPU {
-    pub fn new(&mut self) -> Self {
+    pub fn new() -> Self {
         PPU {
-            vram: [0; 0x2000],
+            vram: [0; 2 * VRAM_BANK_SIZE],
+            vram_bank: 0,
+            color_mode: ColorMode::Dmg,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            opri: 0,
             oam: [0; 0xA0],
-            lcdc: LCDC::new(),
+            lcdc: Lcdc::new(),
             stat: Stat::new(),
             bgp: 0,
             obp0: 0,
             obp1: 0,
+            dma: 0,
             ly: 0,
             lyc: 0,
             scx: 0,
             scy: 0,
             wx: 0,
             wy: 0,
+            dots: 0,
+            window_line: 0,
+            stat_line: false,
+            interrupt: 0,
+            hblanks: 0,
+            frames: 0,
+            frame: Box::new([Color::WHITE; SCREEN_WIDTH * SCREEN_HEIGHT]),
+            bg_colors: [0; SCREEN_WIDTH],
+            bg_priority: [false; SCREEN_WIDTH],
+        }
+    }
+
+    /// Advance by M-cycles and return the interrupts requested meanwhile.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        self.tick_dots(cycles * 4)
+    }
+
+    /// Advance by dots, which do not follow the CPU in double speed mode, and return the
+    /// interrupts requested meanwhile.
+    pub fn tick_dots(&mut self, dots: u32) -> u8 {
+        if self.lcdc.bit7() {
+            for _ in 0..dots {
+                self.tick_dot();
+            }
+        }
+        std::mem::take(&mut self.interrupt)
+    }
+
+    /// The last rendered frame, row by row.
+    pub fn frame(&self) -> &[Color] {
+        &self.frame[..]
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Set by the MMU for the model and mode it runs in. The compatibility mode also selects
+    /// the OBJ priority by X coordinate, as the CGB boot ROM does.
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.color_mode = mode;
+        self.opri = (mode == ColorMode::Compatibility) as u8;
+    }
+
+    /// Set the 4 RGB555 colors of a BG palette (0-7), as the boot ROM does.
+    pub fn set_bg_palette(&mut self, palette: usize, colors: [u16; 4]) {
+        self.bg_palettes.set_palette(palette, colors);
+    }
+
+    /// Set the 4 RGB555 colors of an OBJ palette (0-7), as the boot ROM does.
+    pub fn set_obj_palette(&mut self, palette: usize, colors: [u16; 4]) {
+        self.obj_palettes.set_palette(palette, colors);
+    }
+
+    /// H-Blank periods of visible lines entered since the last call.
+    pub fn take_hblanks(&mut self) -> u32 {
+        std::mem::take(&mut self.hblanks)
+    }
+
+    /// Whether the LCD is on and in the H-Blank of a visible line.
+    pub fn in_hblank(&self) -> bool {
+        self.lcdc.bit7() && self.stat.mode == 0 && self.ly < SCREEN_HEIGHT as u8
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc.bit7()
+    }
+
+    /// Whether the PPU is drawing (mode 3), when the CGB palette RAM cannot be accessed.
+    fn drawing(&self) -> bool {
+        self.lcdc.bit7() && self.stat.mode == 3
+    }
+
+    /// Frames completed (V-Blank periods entered) since power on.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Index in `vram` of an address in the selected bank.
+    fn vram_index(&self, address: u16) -> usize {
+        self.vram_bank as usize * VRAM_BANK_SIZE + (address - VRAM_BEGIN) as usize
+    }
+
+    fn tick_dot(&mut self) {
+        self.dots += 1;
+        if self.ly < SCREEN_HEIGHT as u8 {
+            if self.dots == OAM_SCAN_DOTS {
+                self.stat.mode = 3;
+            } else if self.dots == OAM_SCAN_DOTS + DRAWING_DOTS {
+                self.draw_bg();
+                self.draw_sprites();
+                self.stat.mode = 0;
+                self.hblanks += 1;
+            }
+        }
+
+        if self.dots == DOTS_PER_LINE {
+            self.dots = 0;
+            self.ly = (self.ly + 1) % LINES_PER_FRAME;
+            if self.ly == SCREEN_HEIGHT as u8 {
+                self.stat.mode = 1;
+                self.interrupt |= interrupt::VBLANK;
+                self.frames += 1;
+            } else if self.ly < SCREEN_HEIGHT as u8 {
+                if self.ly == 0 {
+                    self.window_line = 0;
+                }
+                self.stat.mode = 2;
+            }
+        }
+        self.update_stat_line();
+    }
+
+    fn update_stat_line(&mut self) {
+        let line = self.lcdc.bit7()
+            && ((self.stat.enable_ly_interrupt && self.ly == self.lyc)
+                || (self.stat.enable_m0_interrupt && self.stat.mode == 0)
+                || (self.stat.enable_m1_interrupt && self.stat.mode == 1)
+                || (self.stat.enable_m2_interrupt && self.stat.mode == 2));
+        if line && !self.stat_line {
+            self.interrupt |= interrupt::LCD_STAT;
+        }
+        self.stat_line = line;
+    }
+
+    /// Color index (0-3) of a pixel of a tile in a VRAM bank.
+    fn tile_color(&self, bank: u8, tile_address: u16, x: u8, y: u8) -> u8 {
+        let address =
+            bank as usize * VRAM_BANK_SIZE + (tile_address - VRAM_BEGIN) as usize + y as usize * 2;
+        let bit = 7 - x;
+        let low = (self.vram[address] >> bit) & 0x01;
+        let high = (self.vram[address + 1] >> bit) & 0x01;
+        high << 1 | low
+    }
+
+    /// Address of a BG or window tile, as selected by LCDC bit 4.
+    fn bg_tile_address(&self, tile: u8) -> u16 {
+        if self.lcdc.bit4() {
+            0x8000 + tile as u16 * 16
+        } else {
+            (0x9000 + (tile as i8 as i32) * 16) as u16
+        }
+    }
+
+    /// Color of a BG color index with the attributes of its tile.
+    fn bg_color(&self, attributes: u8, color: u8) -> Color {
+        let shade = self.bgp >> (color * 2) & 0x03;
+        match self.color_mode {
+            ColorMode::Dmg => Color::Shade(shade),
+            ColorMode::Cgb => self.bg_palettes.color(attributes & 0x07, color),
+            ColorMode::Compatibility => self.bg_palettes.color(0, shade),
+        }
+    }
+
+    /// Color of an OBJ color index with the OAM attributes of its sprite.
+    fn obj_color(&self, attributes: u8, color: u8) -> Color {
+        let dmg_palette = attributes >> 4 & 0x01;
+        let palette = if dmg_palette == 1 {
+            self.obp1
+        } else {
+            self.obp0
+        };
+        let shade = palette >> (color * 2) & 0x03;
+        match self.color_mode {
+            ColorMode::Dmg => Color::Shade(shade),
+            ColorMode::Cgb => self.obj_palettes.color(attributes & 0x07, color),
+            ColorMode::Compatibility => self.obj_palettes.color(dmg_palette, shade),
         }
     }
 
-    fn draw_bg(&mut self) {}
-    fn draw_sprites(&mut self) {}
+    fn draw_bg(&mut self) {
+        let y = self.ly as usize;
+        let cgb = self.color_mode == ColorMode::Cgb;
+        self.bg_priority = [false; SCREEN_WIDTH];
+        // In CGB mode LCDC bit 0 only takes the priority away from the BG and window.
+        if !self.lcdc.bit0() && !cgb {
+            self.bg_colors = [0; SCREEN_WIDTH];
+            let color = self.bg_color(0, 0);
+            self.frame[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH].fill(color);
+            return;
+        }
+
+        let window_visible = self.lcdc.bit5() && self.ly >= self.wy && self.wx <= 166;
+        let bg_map: u16 = if self.lcdc.bit3() { 0x9C00 } else { 0x9800 };
+        let window_map: u16 = if self.lcdc.bit6() { 0x9C00 } else { 0x9800 };
+
+        for x in 0..SCREEN_WIDTH {
+            let in_window = window_visible && x + 7 >= self.wx as usize;
+            let (map, map_x, map_y) = if in_window {
+                (
+                    window_map,
+                    (x + 7 - self.wx as usize) as u8,
+                    self.window_line,
+                )
+            } else {
+                (
+                    bg_map,
+                    self.scx.wrapping_add(x as u8),
+                    self.scy.wrapping_add(self.ly),
+                )
+            };
+
+            let tile_index =
+                map as usize - VRAM_BEGIN as usize + (map_y as usize / 8) * 32 + map_x as usize / 8;
+            let attributes = if cgb {
+                self.vram[VRAM_BANK_SIZE + tile_index]
+            } else {
+                0
+            };
+            let tile_address = self.bg_tile_address(self.vram[tile_index]);
+            let mut column = map_x % 8;
+            let mut row = map_y % 8;
+            if attributes & 0x20 != 0 {
+                column = 7 - column;
+            }
+            if attributes & 0x40 != 0 {
+                row = 7 - row;
+            }
+            let color = self.tile_color(attributes >> 3 & 0x01, tile_address, column, row);
+
+            self.bg_colors[x] = color;
+            self.bg_priority[x] = attributes & 0x80 != 0;
+            self.frame[y * SCREEN_WIDTH + x] = self.bg_color(attributes, color);
+        }
+
+        if window_visible {
+            self.window_line += 1;
+        }
+    }
+
+    fn draw_sprites(&mut self) {
+        if !self.lcdc.bit1() {
+            return;
+        }
+        let cgb = self.color_mode == ColorMode::Cgb;
+        let height: i16 = if self.lcdc.bit2() { 16 } else { 8 };
+        let ly = self.ly as i16;
+
+        // The first 10 sprites in OAM order on the line are drawn. On the DMG a lower X has
+        // priority and OAM order breaks ties, the CGB only uses the OAM order unless OPRI
+        // says otherwise.
+        let mut sprites: Vec<usize> = (0..40)
+            .filter(|&i| {
+                let top = self.oam[i * 4] as i16 - 16;
+                ly >= top && ly < top + height
+            })
+            .take(SPRITES_PER_LINE)
+            .collect();
+        if self.opri & 0x01 != 0 || self.color_mode == ColorMode::Dmg {
+            sprites.sort_by_key(|&i| self.oam[i * 4 + 1]);
+        }
+
+        let y = self.ly as usize;
+        let mut drawn = [false; SCREEN_WIDTH];
+        for i in sprites {
+            let top = self.oam[i * 4] as i16 - 16;
+            let left = self.oam[i * 4 + 1] as i16 - 8;
+            let mut tile = self.oam[i * 4 + 2];
+            let attributes = self.oam[i * 4 + 3];
+            let behind_bg = attributes & 0x80 != 0;
+            let y_flip = attributes & 0x40 != 0;
+            let x_flip = attributes & 0x20 != 0;
+            let bank = if cgb { attributes >> 3 & 0x01 } else { 0 };
+
+            let mut row = (ly - top) as u8;
+            if y_flip {
+                row = height as u8 - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let tile_address = 0x8000 + tile as u16 * 16;
+
+            for column in 0..8u8 {
+                let x = left + column as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&x) || drawn[x as usize] {
+                    continue;
+                }
+                let x = x as usize;
+                let pixel = if x_flip { 7 - column } else { column };
+                let color = self.tile_color(bank, tile_address, pixel, row);
+                if color == 0 {
+                    continue;
+                }
+                drawn[x] = true;
+                let bg_over_obj = if cgb {
+                    self.lcdc.bit0() && (behind_bg || self.bg_priority[x])
+                } else {
+                    behind_bg
+                };
+                if bg_over_obj && self.bg_colors[x] != 0 {
+                    continue;
+                }
+                self.frame[y * SCREEN_WIDTH + x] = self.obj_color(attributes, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ppu_with_lcd_on() -> PPU {
+        let mut ppu = PPU::new();
+        ppu.write_byte(0xFF40, 0x91);
+        ppu.write_byte(0xFF47, 0xE4);
+        ppu
+    }
+
+    #[test]
+    fn test_mode_timing() {
+        let mut ppu = ppu_with_lcd_on();
+        assert_eq!(ppu.read_byte(0xFF41) & 0x03, 2);
+        ppu.tick(20);
+        assert_eq!(ppu.read_byte(0xFF41) & 0x03, 3);
+        ppu.tick(43);
+        assert_eq!(ppu.read_byte(0xFF41) & 0x03, 0);
+        ppu.tick(51);
+        assert_eq!(ppu.read_byte(0xFF44), 1);
+        assert_eq!(ppu.read_byte(0xFF41) & 0x03, 2);
+    }
+
+    #[test]
+    fn test_vblank_interrupt() {
+        let mut ppu = ppu_with_lcd_on();
+        let mut interrupts = 0;
+        for _ in 0..144 {
+            interrupts |= ppu.tick(114);
+        }
+        assert_eq!(interrupts, interrupt::VBLANK);
+        assert_eq!(ppu.read_byte(0xFF44), 144);
+        assert_eq!(ppu.frames(), 1);
+
+        for _ in 0..10 {
+            ppu.tick(114);
+        }
+        assert_eq!(ppu.read_byte(0xFF44), 0);
+    }
+
+    #[test]
+    fn test_lyc_interrupt() {
+        let mut ppu = ppu_with_lcd_on();
+        ppu.write_byte(0xFF45, 2);
+        ppu.write_byte(0xFF41, 0x40);
+        assert_eq!(ppu.tick(114), 0);
+        assert_eq!(ppu.tick(114), interrupt::LCD_STAT);
+        assert_eq!(ppu.read_byte(0xFF41) & 0x04, 0x04);
+    }
+
+    #[test]
+    fn test_lcd_off_resets_ly() {
+        let mut ppu = ppu_with_lcd_on();
+        ppu.tick(114 * 3);
+        ppu.write_byte(0xFF40, 0x11);
+        assert_eq!(ppu.read_byte(0xFF44), 0);
+        assert_eq!(ppu.tick(114 * 200), 0);
+    }
+
+    #[test]
+    fn test_draw_bg_and_sprite() {
+        let mut ppu = ppu_with_lcd_on();
+        // Tile 1 is black (color 3), the map at 9800 is tile 0 (white) except the first entry.
+        for i in 0..16 {
+            ppu.write_byte(0x8010 + i, 0xFF);
+        }
+        ppu.write_byte(0x9800, 0x01);
+        // Sprite using tile 1 at screen (16, 0), with OBP0 mapping color 3 to light gray.
+        ppu.write_byte(0xFF40, 0x93);
+        ppu.write_byte(0xFF48, 0x40);
+        ppu.write_byte(0xFE00, 16);
+        ppu.write_byte(0xFE01, 24);
+        ppu.write_byte(0xFE02, 0x01);
+
+        ppu.tick(114);
+        let frame = ppu.frame();
+        assert_eq!(frame[0], Color::Shade(3));
+        assert_eq!(frame[8], Color::WHITE);
+        assert_eq!(frame[16], Color::Shade(1));
+        assert_eq!(frame[24], Color::WHITE);
+    }
+
+    #[test]
+    fn test_palette_ram() {
+        let mut ppu = ppu_with_lcd_on();
+        ppu.write_byte(BCPS, 0x80 | 0x3E);
+        ppu.write_byte(BCPD, 0x12);
+        ppu.write_byte(BCPD, 0x34);
+        assert_eq!(ppu.read_byte(BCPS), 0xC0);
+        ppu.write_byte(BCPS, 0x3E);
+        assert_eq!(ppu.read_byte(BCPD), 0x12);
+        assert_eq!(ppu.bg_palettes.color(7, 3), Color::Rgb555(0x3412));
+
+        // Mode 3 blocks the access, the index still moves on.
+        ppu.tick(20);
+        ppu.write_byte(OCPS, 0x80);
+        ppu.write_byte(OCPD, 0x55);
+        assert_eq!(ppu.read_byte(OCPD), 0xFF);
+        assert_eq!(ppu.read_byte(OCPS), 0xC1);
+        ppu.tick(43);
+        ppu.write_byte(OCPS, 0x00);
+        assert_eq!(ppu.read_byte(OCPD), 0x00);
+    }
+
+    #[test]
+    fn test_cgb_attributes_and_priority() {
+        let mut ppu = ppu_with_lcd_on();
+        ppu.set_color_mode(ColorMode::Cgb);
+        ppu.set_bg_palette(2, [0x0000, 0x001F, 0x03E0, 0x7C00]);
+        ppu.set_obj_palette(1, [0x0000, 0x7FFF, 0x7FFF, 0x7FFF]);
+        ppu.set_obj_palette(3, [0x0000, 0x0000, 0x0000, 0x0000]);
+        // Tile 1 of bank 1: color 1 in its leftmost column. Entry 0 of the map uses it,
+        // with palette 2 and an X flip so the column lands on the right.
+        ppu.write_byte(VBK, 1);
+        for row in 0..8 {
+            ppu.write_byte(0x8010 + row * 2, 0x80);
+        }
+        ppu.write_byte(0x9800, 0x20 | 0x08 | 0x02);
+        ppu.write_byte(VBK, 0);
+        ppu.write_byte(0x9800, 0x01);
+        // Sprites 0 and 1 on the same pixel, 1 further left: OAM order wins on the CGB.
+        for row in 0..16 {
+            ppu.write_byte(0x8020 + row, 0xFF);
+        }
+        ppu.write_byte(0xFF40, 0x93);
+        ppu.write_byte(0xFE00, 16);
+        ppu.write_byte(0xFE01, 24);
+        ppu.write_byte(0xFE02, 0x02);
+        ppu.write_byte(0xFE03, 0x01);
+        ppu.write_byte(0xFE04, 16);
+        ppu.write_byte(0xFE05, 20);
+        ppu.write_byte(0xFE06, 0x02);
+        ppu.write_byte(0xFE07, 0x03);
+
+        ppu.tick(114);
+        let frame = ppu.frame();
+        assert_eq!(frame[0], Color::Rgb555(0x0000));
+        assert_eq!(frame[7], Color::Rgb555(0x001F));
+        assert_eq!(frame[12], Color::Rgb555(0x0000)); // only sprite 1
+        assert_eq!(frame[16], Color::Rgb555(0x7FFF)); // sprite 0 over sprite 1
+
+        ppu.write_byte(OPRI, 0x01);
+        ppu.tick(114 * 154);
+        assert_eq!(ppu.frame()[16], Color::Rgb555(0x0000));
+    }
+
+    #[test]
+    fn test_color_to_rgb() {
+        assert_eq!(Color::Shade(2).to_rgb(), [0x55; 3]);
+        assert_eq!(Color::Rgb555(0x7C1F).to_rgb(), [0xFF, 0x00, 0xFF]);
+        for color in [Color::Shade(3), Color::Rgb555(0x1234)] {
+            assert_eq!(Color::from_bits(color.to_bits()), color);
+        }
+    }
+
+    #[test]
+    fn test_vram_banks() {
+        let mut ppu = PPU::new();
+        ppu.write_byte(0x8000, 0x11);
+        ppu.write_byte(VBK, 0xFF);
+        assert_eq!(ppu.read_byte(VBK), 0xFF);
+        assert_eq!(ppu.read_byte(0x8000), 0x00);
+        ppu.write_byte(0x8000, 0x22);
+        ppu.write_byte(VBK, 0x00);
+        assert_eq!(ppu.read_byte(VBK), 0xFE);
+        assert_eq!(ppu.read_byte(0x8000), 0x11);
+    }
+
+    #[test]
+    fn test_window_registers() {
+        let mut ppu = PPU::new();
+        ppu.write_byte(0xFF4A, 0x10);
+        ppu.write_byte(0xFF4B, 0x07);
+        assert_eq!(ppu.read_byte(0xFF4A), 0x10);
+        assert_eq!(ppu.read_byte(0xFF4B), 0x07);
+    }
 }
@@ -0,0 +1,387 @@
+// Rewind buffer: save states taken every few frames, kept in a bounded ring buffer.
+// Every `keyframe_interval`-th snapshot is stored whole (a keyframe), the ones in between
+// as the XOR against the previous keyframe, run-length encoded. Consecutive states differ
+// in few bytes, so most of the XOR is zeros.
+//
+// Delta encoding, repeated until the end: LEB128 count of zero bytes, LEB128 count of
+// literal bytes, the literal bytes.
+
+use std::collections::VecDeque;
+
+use crate::{gameboy::GameBoy, state::StateError};
+
+/// Zero bytes in a row that end a literal run, shorter runs are cheaper kept as literals.
+const MIN_ZERO_RUN: usize = 4;
+
+enum Snapshot {
+    Keyframe(Vec<u8>),
+    /// XOR against the previous keyframe, and the length of the state.
+    Delta(Vec<u8>, usize),
+}
+
+pub struct Rewind {
+    /// Frames between snapshots.
+    interval: u32,
+    capacity: usize,
+    keyframe_interval: usize,
+    snapshots: VecDeque<Snapshot>,
+    /// Snapshots since the last keyframe, including it.
+    since_keyframe: usize,
+    frames: u32,
+}
+
+impl Rewind {
+    /// Take a snapshot every `interval` frames and keep at least the last `capacity` of them.
+    /// Snapshots are dropped a keyframe and its deltas at a time, so up to a group more stay.
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            keyframe_interval: 30,
+            snapshots: VecDeque::new(),
+            since_keyframe: 0,
+            frames: 0,
+        }
+    }
+
+    /// Store a whole snapshot every `interval` snapshots, the others as deltas.
+    pub fn with_keyframe_interval(mut self, interval: usize) -> Self {
+        self.keyframe_interval = interval.max(1);
+        self
+    }
+
+    /// Call after every frame, takes a snapshot of `gameboy` when one is due.
+    pub fn frame_done(&mut self, gameboy: &GameBoy) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(gameboy.save_state());
+        }
+    }
+
+    /// Add a save state as the most recent snapshot, dropping the oldest group when full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.since_keyframe == 0 || self.since_keyframe >= self.keyframe_interval {
+            self.snapshots.push_back(Snapshot::Keyframe(state));
+            self.since_keyframe = 1;
+            if self.groups() > self.capacity.div_ceil(self.keyframe_interval) + 1 {
+                self.drop_oldest_group();
+            }
+        } else {
+            let length = state.len();
+            let delta = encode(&xor(self.last_keyframe(), &state));
+            self.snapshots.push_back(Snapshot::Delta(delta, length));
+            self.since_keyframe += 1;
+        }
+    }
+
+    /// Remove the most recent snapshot and return its save state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if self.snapshots.is_empty() {
+            return None;
+        }
+        let state = self.state(self.snapshots.len() - 1);
+        self.snapshots.pop_back();
+        self.since_keyframe = self.count_since_keyframe();
+        self.frames = 0;
+        Some(state)
+    }
+
+    /// Go back to the most recent snapshot, returning false when there is none left.
+    pub fn step_back(&mut self, gameboy: &mut GameBoy) -> Result<bool, StateError> {
+        match self.pop() {
+            Some(state) => gameboy.load_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.since_keyframe = 0;
+        self.frames = 0;
+    }
+
+    /// Bytes used by the stored snapshots.
+    pub fn memory_usage(&self) -> usize {
+        self.snapshots
+            .iter()
+            .map(|snapshot| match snapshot {
+                Snapshot::Keyframe(state) => state.len(),
+                Snapshot::Delta(delta, _) => delta.len(),
+            })
+            .sum()
+    }
+
+    /// Keyframes stored, each starting a group with the deltas after it.
+    fn groups(&self) -> usize {
+        self.snapshots
+            .iter()
+            .filter(|snapshot| matches!(snapshot, Snapshot::Keyframe(_)))
+            .count()
+    }
+
+    /// Drop the oldest keyframe and its deltas, the rest of the snapshots stay as they are.
+    fn drop_oldest_group(&mut self) {
+        self.snapshots.pop_front();
+        while let Some(Snapshot::Delta(..)) = self.snapshots.front() {
+            self.snapshots.pop_front();
+        }
+        self.since_keyframe = self.count_since_keyframe();
+    }
+
+    /// Snapshots from the last keyframe to the end, including it.
+    fn count_since_keyframe(&self) -> usize {
+        self.snapshots
+            .iter()
+            .rev()
+            .position(|snapshot| matches!(snapshot, Snapshot::Keyframe(_)))
+            .map_or(0, |position| position + 1)
+    }
+
+    fn last_keyframe(&self) -> &[u8] {
+        self.snapshots
+            .iter()
+            .rev()
+            .find_map(|snapshot| match snapshot {
+                Snapshot::Keyframe(state) => Some(&state[..]),
+                Snapshot::Delta(..) => None,
+            })
+            .unwrap_or(&[])
+    }
+
+    /// Decode the save state of the snapshot at `index`.
+    fn state(&self, index: usize) -> Vec<u8> {
+        match &self.snapshots[index] {
+            Snapshot::Keyframe(state) => state.clone(),
+            Snapshot::Delta(delta, length) => {
+                let keyframe = self
+                    .snapshots
+                    .range(..index)
+                    .rev()
+                    .find_map(|snapshot| match snapshot {
+                        Snapshot::Keyframe(state) => Some(&state[..]),
+                        Snapshot::Delta(..) => None,
+                    })
+                    .unwrap_or(&[]);
+                let mut state = decode(delta, *length);
+                for (byte, key) in state.iter_mut().zip(keyframe) {
+                    *byte ^= key;
+                }
+                state
+            }
+        }
+    }
+}
+
+/// XOR of `state` against `keyframe`, missing keyframe bytes count as zero.
+fn xor(keyframe: &[u8], state: &[u8]) -> Vec<u8> {
+    state
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ keyframe.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(&byte) = input.get(*position) {
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+
+        let start = i;
+        while i < data.len() {
+            let run = data[i..]
+                .iter()
+                .take(MIN_ZERO_RUN)
+                .take_while(|&&b| b == 0)
+                .count();
+            if run == MIN_ZERO_RUN || i + run == data.len() {
+                break;
+            }
+            i += run.max(1);
+        }
+        write_varint(&mut output, zeros);
+        write_varint(&mut output, i - start);
+        output.extend_from_slice(&data[start..i]);
+    }
+    output
+}
+
+fn decode(delta: &[u8], length: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(length);
+    let mut position = 0;
+    while position < delta.len() {
+        let zeros = read_varint(delta, &mut position);
+        output.resize(output.len() + zeros, 0);
+        let literals = read_varint(delta, &mut position);
+        output.extend_from_slice(&delta[position..position + literals]);
+        position += literals;
+    }
+    output.resize(length, 0);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_round_trip() {
+        let cases: [&[u8]; 5] = [
+            &[],
+            &[0; 100],
+            &[1, 2, 3],
+            &[0, 0, 5, 0, 0, 0, 0, 0, 7, 0, 8, 0, 0],
+            &[
+                9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+            ],
+        ];
+        for data in cases {
+            assert_eq!(decode(&encode(data), data.len()), data, "{:?}", data);
+        }
+        assert!(encode(&[0; 1000]).len() < 4);
+    }
+
+    fn state(seed: u8) -> Vec<u8> {
+        let mut state = vec![0xAA; 4096];
+        state[100] = seed;
+        state[2000] = seed.wrapping_mul(3);
+        state
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut rewind = Rewind::new(1, 100).with_keyframe_interval(4);
+        for seed in 0..10 {
+            rewind.push(state(seed));
+        }
+        assert_eq!(rewind.len(), 10);
+        assert!(rewind.memory_usage() < 4 * 4096);
+
+        for seed in (0..10).rev() {
+            assert_eq!(rewind.pop(), Some(state(seed)));
+        }
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn test_capacity_keeps_latest() {
+        let mut rewind = Rewind::new(1, 5).with_keyframe_interval(3);
+        for seed in 0..12 {
+            rewind.push(state(seed));
+        }
+        assert_eq!(rewind.len(), 9);
+        for seed in (3..12).rev() {
+            assert_eq!(rewind.pop(), Some(state(seed)));
+        }
+        assert!(rewind.is_empty());
+    }
+
+    fn buffers(rewind: &Rewind) -> Vec<*const u8> {
+        rewind
+            .snapshots
+            .iter()
+            .map(|snapshot| match snapshot {
+                Snapshot::Keyframe(state) => state.as_ptr(),
+                Snapshot::Delta(delta, _) => delta.as_ptr(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_push_at_capacity_keeps_snapshots() {
+        let mut rewind = Rewind::new(1, 6).with_keyframe_interval(3);
+        for seed in 0..9 {
+            rewind.push(state(seed));
+        }
+        assert_eq!(rewind.len(), 9);
+
+        let before = buffers(&rewind);
+        rewind.push(state(9));
+        assert_eq!(rewind.len(), 7);
+        assert_eq!(buffers(&rewind)[..6], before[3..]);
+
+        let before = buffers(&rewind);
+        rewind.push(state(10));
+        assert_eq!(buffers(&rewind)[..7], before[..]);
+        for seed in (3..11).rev() {
+            assert_eq!(rewind.pop(), Some(state(seed)));
+        }
+    }
+
+    #[test]
+    fn test_push_after_pop() {
+        let mut rewind = Rewind::new(1, 10).with_keyframe_interval(3);
+        for seed in 0..5 {
+            rewind.push(state(seed));
+        }
+        rewind.pop();
+        rewind.pop();
+        rewind.pop();
+        for seed in 20..25 {
+            rewind.push(state(seed));
+        }
+        for seed in (20..25).rev() {
+            assert_eq!(rewind.pop(), Some(state(seed)));
+        }
+        assert_eq!(rewind.pop(), Some(state(1)));
+        assert_eq!(rewind.pop(), Some(state(0)));
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0105].copy_from_slice(&[0x04, 0x18, 0xFD, 0x00, 0x00]); // inc b; jr -3
+        let mut gameboy = GameBoy::new(rom).unwrap();
+        let mut rewind = Rewind::new(2, 10);
+
+        let mut b_values = Vec::new();
+        for _ in 0..6 {
+            gameboy.run_frame();
+            rewind.frame_done(&gameboy);
+            b_values.push(gameboy.cpu().registers().b);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        for frame in [5, 3, 1] {
+            assert_eq!(rewind.step_back(&mut gameboy), Ok(true));
+            assert_eq!(gameboy.cpu().registers().b, b_values[frame]);
+        }
+        assert_eq!(rewind.step_back(&mut gameboy), Ok(false));
+    }
+}
@@ -0,0 +1,105 @@
+// Serial link port.
+// FF01   SB - Serial transfer data
+// FF02   SC - Bit 7: transfer start, bit 0: clock select (1 = internal clock)
+//
+// No link cable is attached: with the internal clock a transfer shifts out SB and shifts in
+// 0xFF from the disconnected line. The bytes sent are kept, test ROMs print their results there.
+
+use crate::{
+    interrupt,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+/// M-cycles to shift 8 bits at 8192 Hz.
+const TRANSFER_CYCLES: u32 = 1024;
+
+#[derive(Default)]
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    remaining: u32,
+    output: Vec<u8>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Serial::default()
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            SB => self.sb,
+            _ => 0x7E | self.sc,
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            SB => self.sb = value,
+            _ => {
+                self.sc = value & 0x81;
+                if self.sc == 0x81 {
+                    self.remaining = TRANSFER_CYCLES;
+                }
+            }
+        }
+    }
+
+    /// Advance by M-cycles and return the interrupts requested meanwhile.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if self.remaining == 0 {
+            return 0;
+        }
+        self.remaining = self.remaining.saturating_sub(cycles);
+        if self.remaining > 0 {
+            return 0;
+        }
+        self.output.push(self.sb);
+        self.sb = 0xFF;
+        self.sc &= 0x7F;
+        interrupt::SERIAL
+    }
+
+    /// Every byte sent so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+/// The bytes sent so far are a log of the session and are not part of the state.
+impl Snapshot for Serial {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.sb);
+        writer.u8(self.sc);
+        writer.u32(self.remaining);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.sb = reader.u8()?;
+        self.sc = reader.u8()?;
+        self.remaining = reader.u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer() {
+        let mut serial = Serial::new();
+        serial.write_byte(SB, b'P');
+        serial.write_byte(SC, 0x81);
+        assert_eq!(serial.read_byte(SC), 0xFF);
+
+        assert_eq!(serial.tick(TRANSFER_CYCLES - 1), 0);
+        assert_eq!(serial.tick(1), interrupt::SERIAL);
+        assert_eq!(serial.output(), b"P");
+        assert_eq!(serial.read_byte(SB), 0xFF);
+        assert_eq!(serial.read_byte(SC), 0x7F);
+    }
+}
@@ -0,0 +1,617 @@
+// Super Game Boy. Games talk to the SGB through P1: writing 0x00 is a reset pulse starting a
+// packet, then each bit is sent as 0x20 (0) or 0x10 (1) followed by 0x30. A packet is 16
+// bytes, least significant bit first, and a 0 stop bit. The first byte of a command holds
+// command * 8 + its number of packets, the data of the next packets follows on.
+//
+// Commands handled:
+// 00-03  PAL01, PAL23, PAL03, PAL12 - Colors of two palettes, color 0 is shared by all four
+// 04     ATTR_BLK - Palettes inside, on and outside of rectangles of the 20x18 cells
+// 05     ATTR_LIN - Palette of cell rows or columns
+// 06     ATTR_DIV - Palettes on either side of a row or column, and on it
+// 07     ATTR_CHR - Palettes of consecutive cells, 2 bits each
+// 0A     PAL_SET  - Palettes from the system palettes (without attribute files)
+// 0B     PAL_TRN  - System palettes, 512 of 4 colors
+// 11     MLT_REQ  - 1, 2 or 4 players, P1 reads 0xF - the player when no group is selected
+// 13     CHR_TRN  - Border tiles 0x00-0x7F or 0x80-0xFF, 4 bits per pixel
+// 14     PCT_TRN  - Border tile map (32x28 entries of 16 bits) and palettes 4-7
+// 17     MASK_EN  - Freeze, blacken or clear the game screen, or show it again
+// The others (sound, attribute files, ...) are ignored.
+//
+// The *_TRN commands copy 4KB of VRAM as the BG map shows it, 20 tiles per row, at the end of
+// the next frame. The output is 256x224 pixels, the game screen at (48, 40) in the border.
+
+use crate::{
+    memory::Memory,
+    ppu::{Color, PPU, SCREEN_HEIGHT as GAME_HEIGHT, SCREEN_WIDTH as GAME_WIDTH},
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 224;
+const GAME_X: usize = 48;
+const GAME_Y: usize = 40;
+
+const CELLS_WIDTH: usize = 20;
+const CELLS_HEIGHT: usize = 18;
+const TRANSFER_SIZE: usize = 0x1000;
+
+/// Colors of the SGB BIOS before a game sets its own.
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transfer {
+    /// Border tiles, of the upper half for 1.
+    Chr(u8),
+    Pct,
+    Pal,
+}
+
+impl Transfer {
+    fn code(transfer: Option<Transfer>) -> u8 {
+        match transfer {
+            None => 0,
+            Some(Transfer::Chr(half)) => 1 + half,
+            Some(Transfer::Pct) => 3,
+            Some(Transfer::Pal) => 4,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Transfer> {
+        match code {
+            1 | 2 => Some(Transfer::Chr(code - 1)),
+            3 => Some(Transfer::Pct),
+            4 => Some(Transfer::Pal),
+            _ => None,
+        }
+    }
+}
+
+/// What MASK_EN shows instead of the game screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mask {
+    None,
+    /// The last frame.
+    Freeze,
+    Black,
+    /// Color 0.
+    Color0,
+}
+
+pub struct Sgb {
+    p1: u8, // Bits 5-4 last written to P1
+    receiving: bool,
+    bit: usize, // Bits received of the packet, 128 waits for the stop bit
+    packet: [u8; 16],
+    command: Vec<u8>, // Packets received of the current command
+    players: u8,
+    player: u8,
+    palettes: [[u16; 4]; 4],
+    attributes: [u8; CELLS_WIDTH * CELLS_HEIGHT],
+    system_palettes: Vec<u16>,
+    mask: Mask,
+    transfer: Option<Transfer>,
+    tiles: Vec<u8>, // 256 border tiles of 32 bytes
+    border_map: Vec<u8>,
+    border_palettes: [u16; 64],
+    screen: Vec<Color>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb::new()
+    }
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Sgb {
+            p1: 0x30,
+            receiving: false,
+            bit: 0,
+            packet: [0; 16],
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            attributes: [0; CELLS_WIDTH * CELLS_HEIGHT],
+            system_palettes: vec![0; 512 * 4],
+            mask: Mask::None,
+            transfer: None,
+            tiles: vec![0; 256 * 32],
+            border_map: vec![0; 32 * 28 * 2],
+            border_palettes: [0; 64],
+            screen: vec![Color::Rgb555(DEFAULT_PALETTE[0]); SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// The last frame with its border, row by row.
+    pub fn screen(&self) -> &[Color] {
+        &self.screen
+    }
+
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    /// Palette (0-3) of a cell of 8x8 pixels of the game screen.
+    pub fn attribute(&self, x: usize, y: usize) -> u8 {
+        self.attributes[y * CELLS_WIDTH + x]
+    }
+
+    /// The P1 value read by the game, from the value of the joypad: the player when no group
+    /// is selected in multiplayer mode, and no buttons pressed for players 2-4.
+    pub fn read_p1(&self, value: u8) -> u8 {
+        if self.players == 1 {
+            value
+        } else if value & 0x30 == 0x30 {
+            value & 0xF0 | (0x0F - self.player)
+        } else if self.player != 0 {
+            value | 0x0F
+        } else {
+            value
+        }
+    }
+
+    pub fn write_p1(&mut self, value: u8) {
+        let select = value & 0x30;
+        let previous = std::mem::replace(&mut self.p1, select);
+        // The next player is selected when P15 goes high.
+        if self.players > 1 && previous & 0x20 == 0 && select & 0x20 != 0 {
+            self.player = (self.player + 1) % self.players;
+        }
+        match select {
+            0x00 => {
+                self.receiving = true;
+                self.bit = 0;
+                self.packet = [0; 16];
+            }
+            0x10 | 0x20 if self.receiving && previous == 0x30 => {
+                let one = select == 0x10;
+                if self.bit < 128 {
+                    self.packet[self.bit / 8] |= (one as u8) << (self.bit % 8);
+                    self.bit += 1;
+                } else {
+                    self.receiving = false;
+                    if !one {
+                        self.packet_done();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn packet_done(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= packets * 16 {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            0x00 => self.set_palettes(0, 1, &data[1..]),
+            0x01 => self.set_palettes(2, 3, &data[1..]),
+            0x02 => self.set_palettes(0, 3, &data[1..]),
+            0x03 => self.set_palettes(1, 2, &data[1..]),
+            0x04 => self.attr_blk(data),
+            0x05 => self.attr_lin(data),
+            0x06 => self.attr_div(data),
+            0x07 => self.attr_chr(data),
+            0x0A => self.pal_set(data),
+            0x0B => self.transfer = Some(Transfer::Pal),
+            0x11 => {
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            0x13 => self.transfer = Some(Transfer::Chr(data[1] & 0x01)),
+            0x14 => self.transfer = Some(Transfer::Pct),
+            0x17 => {
+                self.mask = match data[1] & 0x03 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Color 0 then colors 1-3 of `first` and of `second`, as little endian RGB555.
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) & 0x7FFF;
+        for palette in &mut self.palettes {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn set_cells<F: Fn(usize, usize) -> Option<u8>>(&mut self, palette: F) {
+        for y in 0..CELLS_HEIGHT {
+            for x in 0..CELLS_WIDTH {
+                if let Some(palette) = palette(x, y) {
+                    self.attributes[y * CELLS_WIDTH + x] = palette;
+                }
+            }
+        }
+    }
+
+    fn attr_blk(&mut self, data: &[u8]) {
+        let sets = (data[1] & 0x1F) as usize;
+        for set in data[2..].chunks_exact(6).take(sets) {
+            let control = set[0] & 0x07;
+            let [inside, line, outside] = [set[1] & 0x03, set[1] >> 2 & 0x03, set[1] >> 4 & 0x03];
+            // With only the inside or the outside changed, the line goes with it.
+            let line = match control {
+                0x01 => Some(inside),
+                0x04 => Some(outside),
+                _ => (control & 0x02 != 0).then_some(line),
+            };
+            let inside = (control & 0x01 != 0).then_some(inside);
+            let outside = (control & 0x04 != 0).then_some(outside);
+            let [x1, y1, x2, y2] = [set[2], set[3], set[4], set[5]].map(|c| (c & 0x1F) as usize);
+            self.set_cells(|x, y| {
+                if x < x1 || x > x2 || y < y1 || y > y2 {
+                    outside
+                } else if x == x1 || x == x2 || y == y1 || y == y2 {
+                    line
+                } else {
+                    inside
+                }
+            });
+        }
+    }
+
+    fn attr_lin(&mut self, data: &[u8]) {
+        let sets = data[1] as usize;
+        for &set in data[2..].iter().take(sets) {
+            let line = (set & 0x1F) as usize;
+            let palette = set >> 5 & 0x03;
+            let horizontal = set & 0x80 != 0;
+            self.set_cells(|x, y| {
+                let on_line = if horizontal { y == line } else { x == line };
+                on_line.then_some(palette)
+            });
+        }
+    }
+
+    fn attr_div(&mut self, data: &[u8]) {
+        let after = data[1] & 0x03;
+        let before = data[1] >> 2 & 0x03;
+        let on_line = data[1] >> 4 & 0x03;
+        let horizontal = data[1] & 0x40 != 0;
+        let line = (data[2] & 0x1F) as usize;
+        self.set_cells(|x, y| {
+            let position = if horizontal { y } else { x };
+            Some(match position.cmp(&line) {
+                std::cmp::Ordering::Less => before,
+                std::cmp::Ordering::Equal => on_line,
+                std::cmp::Ordering::Greater => after,
+            })
+        });
+    }
+
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = ((data[1] & 0x1F) as usize, (data[2] & 0x1F) as usize);
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 0x01 != 0;
+        let palettes = data[6..]
+            .iter()
+            .flat_map(|&byte| [6, 4, 2, 0].map(|shift| byte >> shift & 0x03));
+        for palette in palettes.take(count) {
+            if x >= CELLS_WIDTH || y >= CELLS_HEIGHT {
+                break;
+            }
+            self.attributes[y * CELLS_WIDTH + x] = palette;
+            if vertical {
+                y += 1;
+                if y == CELLS_HEIGHT {
+                    (x, y) = (x + 1, 0);
+                }
+            } else {
+                x += 1;
+                if x == CELLS_WIDTH {
+                    (x, y) = (0, y + 1);
+                }
+            }
+        }
+    }
+
+    fn pal_set(&mut self, data: &[u8]) {
+        for (i, palette) in self.palettes.iter_mut().enumerate() {
+            let number = (u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) & 0x01FF) as usize;
+            palette.copy_from_slice(&self.system_palettes[number * 4..number * 4 + 4]);
+        }
+        let color0 = self.palettes[0][0];
+        for palette in &mut self.palettes {
+            palette[0] = color0;
+        }
+        if data[9] & 0x40 != 0 {
+            self.mask = Mask::None;
+        }
+    }
+
+    /// Finish a frame of the PPU: run the pending VRAM transfer and draw the screen.
+    pub(crate) fn frame_done(&mut self, ppu: &PPU) {
+        if let Some(transfer) = self.transfer.take() {
+            let data = transfer_data(ppu);
+            let word = |i: usize| u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) & 0x7FFF;
+            match transfer {
+                Transfer::Chr(half) => {
+                    let start = half as usize * TRANSFER_SIZE;
+                    self.tiles[start..start + TRANSFER_SIZE].copy_from_slice(&data);
+                }
+                Transfer::Pct => {
+                    // The map has 32 rows, the last 4 are not shown.
+                    let size = self.border_map.len();
+                    self.border_map.copy_from_slice(&data[..size]);
+                    for (i, color) in self.border_palettes.iter_mut().enumerate() {
+                        *color = word(0x400 + i);
+                    }
+                }
+                Transfer::Pal => {
+                    for (i, color) in self.system_palettes.iter_mut().enumerate() {
+                        *color = word(i);
+                    }
+                }
+            }
+        }
+        if self.mask != Mask::Freeze {
+            self.draw(ppu.frame());
+        }
+    }
+
+    fn draw(&mut self, frame: &[Color]) {
+        let color0 = self.palettes[0][0];
+        for y in 0..GAME_HEIGHT {
+            for x in 0..GAME_WIDTH {
+                let color = match (self.mask, frame[y * GAME_WIDTH + x]) {
+                    (Mask::Black, _) => 0x0000,
+                    (Mask::Color0, _) => color0,
+                    (_, Color::Shade(shade)) => {
+                        let palette = self.attribute(x / 8, y / 8) as usize;
+                        self.palettes[palette][shade.min(3) as usize]
+                    }
+                    (_, Color::Rgb555(color)) => color,
+                };
+                self.screen[(y + GAME_Y) * SCREEN_WIDTH + x + GAME_X] = Color::Rgb555(color);
+            }
+        }
+
+        // The border covers the game screen where its color is not 0.
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let index = ((y / 8) * 32 + x / 8) * 2;
+                let entry =
+                    u16::from_le_bytes([self.border_map[index], self.border_map[index + 1]]);
+                let tile = (entry & 0xFF) as usize;
+                let palette = (entry >> 10 & 0x03) as usize;
+                let column = if entry & 0x4000 != 0 {
+                    x % 8
+                } else {
+                    7 - x % 8
+                };
+                let row = if entry & 0x8000 != 0 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+                let planes = [0, 1, 16, 17].map(|offset| self.tiles[tile * 32 + row * 2 + offset]);
+                let color = (0..4).fold(0, |color, plane| {
+                    color | (planes[plane] >> column & 1) << plane
+                }) as usize;
+                let in_game = (GAME_X..GAME_X + GAME_WIDTH).contains(&x)
+                    && (GAME_Y..GAME_Y + GAME_HEIGHT).contains(&y);
+                if color != 0 {
+                    self.screen[y * SCREEN_WIDTH + x] =
+                        Color::Rgb555(self.border_palettes[palette * 16 + color]);
+                } else if !in_game {
+                    self.screen[y * SCREEN_WIDTH + x] = Color::Rgb555(color0);
+                }
+            }
+        }
+    }
+}
+
+/// 4KB of VRAM as shown by the BG map: tiles row by row, 20 of the 32 entries of each row.
+fn transfer_data(ppu: &PPU) -> Vec<u8> {
+    let lcdc = ppu.read_byte(0xFF40);
+    let map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+    let mut data = Vec::with_capacity(TRANSFER_SIZE);
+    for i in 0..TRANSFER_SIZE as u16 / 16 {
+        let tile = ppu.read_byte(map + (i / 20) * 32 + i % 20);
+        let address = if lcdc & 0x10 != 0 {
+            0x8000 + tile as u16 * 16
+        } else {
+            0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+        };
+        data.extend((0..16).map(|offset| ppu.read_byte(address + offset)));
+    }
+    data
+}
+
+/// The screen is drawn again at the next frame.
+impl Snapshot for Sgb {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u8(self.p1);
+        writer.bool(self.receiving);
+        writer.u8(self.bit as u8);
+        writer.bytes(&self.packet);
+        writer.bytes(&self.command);
+        writer.u8(self.players);
+        writer.u8(self.player);
+        for color in self.palettes.iter().flatten().chain(&self.system_palettes) {
+            writer.u16(*color);
+        }
+        writer.bytes(&self.attributes);
+        writer.u8(self.mask as u8);
+        writer.u8(Transfer::code(self.transfer));
+        writer.bytes(&self.tiles);
+        writer.bytes(&self.border_map);
+        for color in &self.border_palettes {
+            writer.u16(*color);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.p1 = reader.u8()? & 0x30;
+        self.receiving = reader.bool()?;
+        self.bit = (reader.u8()? as usize).min(128);
+        reader.bytes_into(&mut self.packet, "SGB packet size")?;
+        self.command = reader.bytes()?.to_vec();
+        self.players = reader.u8()?;
+        if ![1, 2, 4].contains(&self.players) {
+            return Err(StateError::Invalid("SGB players"));
+        }
+        self.player = reader.u8()? % self.players;
+        for color in self.palettes.iter_mut().flatten() {
+            *color = reader.u16()? & 0x7FFF;
+        }
+        for color in &mut self.system_palettes {
+            *color = reader.u16()? & 0x7FFF;
+        }
+        reader.bytes_into(&mut self.attributes, "SGB attributes size")?;
+        for attribute in &mut self.attributes {
+            *attribute &= 0x03;
+        }
+        self.mask = match reader.u8()? {
+            0 => Mask::None,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            _ => Mask::Color0,
+        };
+        self.transfer = Transfer::from_code(reader.u8()?);
+        reader.bytes_into(&mut self.tiles, "SGB tiles size")?;
+        reader.bytes_into(&mut self.border_map, "SGB border map size")?;
+        for color in &mut self.border_palettes {
+            *color = reader.u16()? & 0x7FFF;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send packets through P1 as a game does.
+    fn send(sgb: &mut Sgb, data: &[u8]) {
+        for packet in data.chunks(16) {
+            sgb.write_p1(0x00);
+            sgb.write_p1(0x30);
+            for i in 0..128 {
+                let bit = packet
+                    .get(i / 8)
+                    .is_some_and(|byte| byte >> (i % 8) & 1 != 0);
+                sgb.write_p1(if bit { 0x10 } else { 0x20 });
+                sgb.write_p1(0x30);
+            }
+            sgb.write_p1(0x20); // Stop bit
+            sgb.write_p1(0x30);
+        }
+    }
+
+    #[test]
+    fn test_palettes() {
+        let mut sgb = Sgb::new();
+        // PAL01: color 0, palette 0 colors 1-3, palette 1 colors 1-3
+        let colors: [u16; 7] = [0x7FFF, 0x0001, 0x0002, 0x0003, 0x0011, 0x0012, 0x0013];
+        let mut packet = vec![0x01]; // PAL01, 1 packet
+        packet.extend(colors.iter().flat_map(|color| color.to_le_bytes()));
+        send(&mut sgb, &packet);
+        assert_eq!(sgb.palettes[0], [0x7FFF, 0x0001, 0x0002, 0x0003]);
+        assert_eq!(sgb.palettes[1], [0x7FFF, 0x0011, 0x0012, 0x0013]);
+        assert_eq!(sgb.palettes[3][0], 0x7FFF);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut sgb = Sgb::new();
+        // ATTR_BLK: inside and line of (1,1)-(4,4) get palette 1 and 2, outside 3.
+        send(&mut sgb, &[0x04 << 3 | 1, 1, 0x07, 0x39, 1, 1, 4, 4]);
+        assert_eq!(sgb.attribute(2, 2), 1);
+        assert_eq!(sgb.attribute(1, 3), 2);
+        assert_eq!(sgb.attribute(10, 10), 3);
+        // ATTR_LIN: row 5 gets palette 2.
+        send(&mut sgb, &[0x05 << 3 | 1, 1, 0x80 | 2 << 5 | 5]);
+        assert_eq!(sgb.attribute(10, 5), 2);
+        // ATTR_DIV: columns before 10 get 1, column 10 gets 2, after it 3.
+        send(&mut sgb, &[0x06 << 3 | 1, 2 << 4 | 1 << 2 | 3, 10]);
+        assert_eq!([9, 10, 11].map(|x| sgb.attribute(x, 0)), [1, 2, 3]);
+        // ATTR_CHR: 5 cells from (18, 0) left to right, wrapping to the next row.
+        send(
+            &mut sgb,
+            &[0x07 << 3 | 1, 18, 0, 5, 0, 0, 0b01_10_11_00, 0b01_000000],
+        );
+        assert_eq!(
+            [(18, 0), (19, 0), (0, 1), (1, 1), (2, 1)].map(|(x, y)| sgb.attribute(x, y)),
+            [1, 2, 3, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_multiplayer() {
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[0x11 << 3 | 1, 0x01]);
+        assert_eq!(sgb.read_p1(0xFF), 0xFF);
+        sgb.write_p1(0x20);
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.read_p1(0xFF), 0xFE);
+        assert_eq!(sgb.read_p1(0xEE), 0xEF); // no buttons of player 2
+    }
+
+    #[test]
+    fn test_vram_transfers() {
+        let mut ppu = PPU::new();
+        ppu.write_byte(0xFF40, 0x10); // LCD off, tiles at 0x8000, every map entry shows tile 0
+        for offset in 0..16 {
+            ppu.write_byte(0x8000 + offset, 0xFF);
+        }
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &[0x13 << 3 | 1, 0x01]); // CHR_TRN, tiles 0x80-0xFF
+        sgb.frame_done(&ppu);
+        assert_eq!(sgb.tiles[0x0FFF], 0x00);
+        assert_eq!(sgb.tiles[0x1000], 0xFF);
+        send(&mut sgb, &[0x14 << 3 | 1]); // PCT_TRN
+        sgb.frame_done(&ppu);
+        assert_eq!(sgb.border_palettes[63], 0x7FFF);
+        // Every entry shows tile 0xFF with palette 7 and color 15.
+        assert_eq!(sgb.screen()[0], Color::Rgb555(0x7FFF));
+    }
+
+    #[test]
+    fn test_mask_and_draw() {
+        let mut sgb = Sgb::new();
+        let mut frame = vec![Color::Shade(3); GAME_WIDTH * GAME_HEIGHT];
+        frame[0] = Color::Shade(0);
+        sgb.draw(&frame);
+        assert_eq!(
+            sgb.screen()[GAME_Y * SCREEN_WIDTH + GAME_X],
+            Color::Rgb555(0x67BF)
+        );
+        assert_eq!(
+            sgb.screen()[GAME_Y * SCREEN_WIDTH + GAME_X + 1],
+            Color::Rgb555(0x2866)
+        );
+        assert_eq!(sgb.screen()[0], Color::Rgb555(0x67BF)); // Border color 0
+
+        send(&mut sgb, &[0x17 << 3 | 1, 2]);
+        assert_eq!(sgb.mask(), Mask::Black);
+        sgb.draw(&frame);
+        assert_eq!(
+            sgb.screen()[GAME_Y * SCREEN_WIDTH + GAME_X],
+            Color::Rgb555(0)
+        );
+    }
+}
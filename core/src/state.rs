@@ -0,0 +1,355 @@
+// Save state binary format, all numbers little endian:
+// 0   "RBST"
+// 4   u16 format version
+// 6   u32 CRC-32 of the ROM the state was saved with
+// 10  u8 length + UTF-8 emulator version that wrote the state
+// ..  Chunks: 4 byte tag, u32 length, payload
+//
+// Chunks can nest (the MMU chunk holds the PPU, timer... chunks) and unknown ones are
+// skipped, so new components only add chunks. When the payload of an existing chunk
+// changes, VERSION is bumped and `Snapshot::load` reads older layouts by checking
+// `StateReader::version`.
+
+use std::fmt;
+
+pub const MAGIC: [u8; 4] = *b"RBST";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic.
+    InvalidMagic,
+    /// Saved by a newer emulator with a format version this one cannot read.
+    UnsupportedVersion(u16),
+    /// Saved with another ROM, holds the CRC-32 of the ROM in the state.
+    RomMismatch(u32),
+    MissingChunk([u8; 4]),
+    /// The data ends in the middle of a value.
+    Truncated,
+    /// A value is out of range for what it describes.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is newer than the supported version {}",
+                version, VERSION
+            ),
+            StateError::RomMismatch(checksum) => write!(
+                f,
+                "save state belongs to another ROM (CRC-32 {:08X})",
+                checksum
+            ),
+            StateError::MissingChunk(tag) => {
+                write!(
+                    f,
+                    "save state has no {} chunk",
+                    String::from_utf8_lossy(tag)
+                )
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has an invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Component that can be saved into and restored from a save state.
+pub trait Snapshot {
+    fn save(&self, writer: &mut StateWriter);
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Identifies the machine a state was saved from.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StateHeader {
+    pub version: u16,
+    pub rom_checksum: u32,
+    pub emulator_version: String,
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    version: u16,
+}
+
+impl StateWriter {
+    /// Start a state with the header for a ROM.
+    pub fn new(rom_checksum: u32) -> Self {
+        let mut writer = StateWriter::default();
+        writer.data.extend_from_slice(&MAGIC);
+        writer.u16(VERSION);
+        writer.u32(rom_checksum);
+        let version = env!("CARGO_PKG_VERSION");
+        writer.u8(version.len() as u8);
+        writer.data.extend_from_slice(version.as_bytes());
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Length prefixed bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Write a chunk whose payload is written by `write`.
+    pub fn chunk<F: FnOnce(&mut StateWriter)>(&mut self, tag: &[u8; 4], write: F) {
+        self.data.extend_from_slice(tag);
+        let length_at = self.data.len();
+        self.u32(0);
+        write(self);
+        let length = (self.data.len() - length_at - 4) as u32;
+        self.data[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl<'a> StateReader<'a> {
+    /// Parse the header of a state, returning it with a reader over the chunks.
+    pub fn new(data: &'a [u8]) -> Result<(StateHeader, Self), StateError> {
+        if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+        let mut reader = StateReader {
+            data,
+            position: MAGIC.len(),
+            version: 0,
+        };
+        let version = reader.u16()?;
+        if version > VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        reader.version = version;
+        let rom_checksum = reader.u32()?;
+        let length = reader.u8()? as usize;
+        let emulator_version = String::from_utf8_lossy(reader.take(length)?).into_owned();
+
+        let header = StateHeader {
+            version,
+            rom_checksum,
+            emulator_version,
+        };
+        Ok((header, reader))
+    }
+
+    /// Reader over data without a save state header, e.g. a format embedding the same encoding.
+    pub fn raw(data: &'a [u8]) -> Self {
+        StateReader {
+            data,
+            position: 0,
+            version: VERSION,
+        }
+    }
+
+    /// Whether every byte has been read.
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    /// Bytes not read yet, e.g. the chunks after the header.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.position.min(self.data.len())..]
+    }
+
+    /// Format version the state was written with.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], StateError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .ok_or(StateError::Truncated)?;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or(StateError::Truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Length prefixed bytes.
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    /// Length prefixed bytes into `buffer`, which must have the saved length.
+    pub fn bytes_into(&mut self, buffer: &mut [u8], what: &'static str) -> Result<(), StateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(StateError::Invalid(what));
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Reader over the payload of the chunk `tag`, searched among the chunks from the
+    /// current position to the end of this reader.
+    pub fn chunk(&self, tag: &[u8; 4]) -> Result<StateReader<'a>, StateError> {
+        let mut position = self.position;
+        while position < self.data.len() {
+            let header = self
+                .data
+                .get(position..position + 8)
+                .ok_or(StateError::Truncated)?;
+            let length = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+            let start = position + 8;
+            let end = start.checked_add(length).ok_or(StateError::Truncated)?;
+            if end > self.data.len() {
+                return Err(StateError::Truncated);
+            }
+            if header[..4] == tag[..] {
+                return Ok(StateReader {
+                    data: &self.data[..end],
+                    position: start,
+                    version: self.version,
+                });
+            }
+            position = end;
+        }
+        Err(StateError::MissingChunk(*tag))
+    }
+
+    /// Load `component` from the chunk `tag`.
+    pub fn load_chunk<S: Snapshot + ?Sized>(
+        &self,
+        tag: &[u8; 4],
+        component: &mut S,
+    ) -> Result<(), StateError> {
+        component.load(&mut self.chunk(tag)?)
+    }
+}
+
+/// CRC-32 (IEEE), used to tell which ROM a state belongs to.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::new(0x1234_5678);
+        writer.chunk(b"ONE ", |w| {
+            w.u8(0x12);
+            w.u16(0x3456);
+            w.chunk(b"IN  ", |w| w.bytes(&[1, 2, 3]));
+        });
+        writer.chunk(b"TWO ", |w| w.u64(u64::MAX));
+        let data = writer.finish();
+
+        let (header, reader) = StateReader::new(&data).unwrap();
+        assert_eq!(header.version, VERSION);
+        assert_eq!(header.rom_checksum, 0x1234_5678);
+        assert_eq!(header.emulator_version, env!("CARGO_PKG_VERSION"));
+
+        assert_eq!(reader.chunk(b"TWO ").unwrap().u64(), Ok(u64::MAX));
+        let mut one = reader.chunk(b"ONE ").unwrap();
+        assert_eq!(one.u8(), Ok(0x12));
+        assert_eq!(one.u16(), Ok(0x3456));
+        assert_eq!(one.chunk(b"IN  ").unwrap().bytes(), Ok(&[1u8, 2, 3][..]));
+
+        let mut two = reader.chunk(b"TWO ").unwrap();
+        two.u64().unwrap();
+        assert_eq!(two.u8(), Err(StateError::Truncated));
+        assert_eq!(
+            reader.chunk(b"NONE").err(),
+            Some(StateError::MissingChunk(*b"NONE"))
+        );
+    }
+
+    #[test]
+    fn test_header_errors() {
+        assert_eq!(
+            StateReader::new(b"nope").err(),
+            Some(StateError::InvalidMagic)
+        );
+
+        let mut data = StateWriter::new(0).finish();
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            StateReader::new(&data).err(),
+            Some(StateError::UnsupportedVersion(VERSION + 1))
+        );
+
+        let data = StateWriter::new(0).finish();
+        assert_eq!(
+            StateReader::new(&data[..8]).err(),
+            Some(StateError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,242 @@
+// Labels from the RGBDS linker, to name addresses in disassembly, traces and the debugger.
+// .sym files hold one `bank:address name` line per label (`;` starts a comment), .map files
+// have an `AREA bank #n:` header per bank followed by `$address = name` lines.
+//
+// The same address names different labels depending on the mapped bank, so lookups take the
+// bank from `Memory::bank`.
+
+use std::fmt;
+
+use crate::{disasm, memory::Memory};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SymbolError {
+    /// A .sym line is not `bank:address name`, holds the line number.
+    Syntax(usize),
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolError::Syntax(line) => {
+                write!(f, "line {}: expected `bank:address name`", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub bank: u16,
+    pub address: u16,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// Sorted by address, then bank.
+    symbols: Vec<Symbol>,
+}
+
+/// Areas of the memory map, a label only names addresses of its own area.
+fn area(address: u16) -> u8 {
+    match address {
+        0x0000..=0x3FFF => 0,
+        0x4000..=0x7FFF => 1,
+        0x8000..=0x9FFF => 2,
+        0xA000..=0xBFFF => 3,
+        0xC000..=0xCFFF => 4,
+        0xD000..=0xDFFF => 5,
+        0xE000..=0xFDFF => 6,
+        0xFE00..=0xFEFF => 7,
+        0xFF00..=0xFF7F => 8,
+        0xFF80..=0xFFFE => 9,
+        0xFFFF => 10,
+    }
+}
+
+impl Symbols {
+    /// Parse a .sym or a .map file, told apart by their content.
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        if text.lines().any(|line| line.contains(" bank #")) {
+            Ok(Symbols::parse_map(text))
+        } else {
+            Symbols::parse_sym(text)
+        }
+    }
+
+    pub fn parse_sym(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let symbol = line
+                .split_once(' ')
+                .and_then(|(location, name)| {
+                    let (bank, address) = location.split_once(':')?;
+                    Some(Symbol {
+                        bank: u16::from_str_radix(bank, 16).ok()?,
+                        address: u16::from_str_radix(address, 16).ok()?,
+                        name: name.trim().to_string(),
+                    })
+                })
+                .ok_or(SymbolError::Syntax(number + 1))?;
+            symbols.push(symbol);
+        }
+        Ok(Symbols::from_symbols(symbols))
+    }
+
+    pub fn parse_map(text: &str) -> Self {
+        let mut symbols = Vec::new();
+        let mut bank = None;
+        for line in text.lines() {
+            if let Some((_, number)) = line.split_once(" bank #") {
+                bank = number.trim_end_matches(':').trim().parse().ok();
+                continue;
+            }
+            let (Some(bank), Some((address, name))) = (bank, line.trim().split_once(" = ")) else {
+                continue;
+            };
+            let address = address
+                .strip_prefix('$')
+                .map(|a| u16::from_str_radix(a, 16));
+            if let Some(Ok(address)) = address {
+                symbols.push(Symbol {
+                    bank,
+                    address,
+                    name: name.trim().to_string(),
+                });
+            }
+        }
+        Symbols::from_symbols(symbols)
+    }
+
+    fn from_symbols(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|symbol| (symbol.address, symbol.bank));
+        Symbols { symbols }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Closest label at or before `address` in `bank`, with the offset from it.
+    pub fn label(&self, bank: u16, address: u16) -> Option<(&Symbol, u16)> {
+        let end = self.symbols.partition_point(|s| s.address <= address);
+        self.symbols[..end]
+            .iter()
+            .rev()
+            .take_while(|symbol| area(symbol.address) == area(address))
+            .find(|symbol| symbol.bank == bank)
+            .map(|symbol| (symbol, address - symbol.address))
+    }
+
+    /// `Label` or `Label+offset` for an address in the bank currently mapped there.
+    pub fn name<M: Memory + ?Sized>(&self, memory: &M, address: u16) -> Option<String> {
+        self.name_in(memory.bank(address), address)
+    }
+
+    /// `Label` or `Label+offset` for an address in `bank`.
+    pub fn name_in(&self, bank: u16, address: u16) -> Option<String> {
+        match self.label(bank, address)? {
+            (symbol, 0) => Some(symbol.name.clone()),
+            (symbol, offset) => Some(format!("{}+{}", symbol.name, offset)),
+        }
+    }
+
+    /// Name of the address an instruction jumps to or accesses, if it has a label.
+    pub fn operand_name<M: Memory + ?Sized>(
+        &self,
+        memory: &M,
+        instruction: &disasm::Instruction,
+    ) -> Option<String> {
+        let target = instruction
+            .operands
+            .iter()
+            .find_map(|operand| match *operand {
+                disasm::Operand::Immediate16(address) if instruction.is_branch() => Some(address),
+                disasm::Operand::Address(address) => Some(address),
+                disasm::Operand::HighAddress(offset) => Some(0xFF00 | offset as u16),
+                disasm::Operand::Relative { target, .. } => Some(target),
+                disasm::Operand::Vector(vector) => Some(vector as u16),
+                _ => None,
+            })?;
+        self.name(memory, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::FlatMemory;
+
+    const SYM: &str = "; File generated by rgblink
+00:0150 Main
+00:0153 Main.loop
+01:4000 Banked
+02:4000 OtherBank
+00:C000 wCounter
+";
+
+    #[test]
+    fn test_parse_sym() {
+        let symbols = Symbols::parse(SYM).unwrap();
+        assert_eq!(symbols.find("Main.loop").unwrap().address, 0x0153);
+        assert_eq!(symbols.name_in(0, 0x0155).as_deref(), Some("Main.loop+2"));
+        assert_eq!(symbols.name_in(0, 0x0100), None);
+        assert_eq!(
+            Symbols::parse("00:0150 Main\n00:zz Bad").err(),
+            Some(SymbolError::Syntax(2))
+        );
+    }
+
+    #[test]
+    fn test_banks_and_areas() {
+        let symbols = Symbols::parse(SYM).unwrap();
+        assert_eq!(symbols.name_in(1, 0x4010).as_deref(), Some("Banked+16"));
+        assert_eq!(symbols.name_in(2, 0x4010).as_deref(), Some("OtherBank+16"));
+        assert_eq!(symbols.name_in(3, 0x4010), None);
+        // Main is in ROM0, it does not name the start of the switchable bank.
+        assert_eq!(symbols.name_in(0, 0x4000), None);
+        assert_eq!(symbols.name_in(0, 0xC001).as_deref(), Some("wCounter+1"));
+    }
+
+    #[test]
+    fn test_parse_map() {
+        let symbols = Symbols::parse(
+            "ROM0 bank #0:\n\tSECTION: $0150-$0160 ($0011 bytes) [\"Main\"]\n\t         $0150 = Main\n\
+             WRAM0 bank #0:\n\t         $C000 = wCounter\n\
+             ROMX bank #2:\n\t         $4000 = Far\n",
+        )
+        .unwrap();
+        assert_eq!(symbols.find("Main").unwrap().address, 0x0150);
+        assert_eq!(symbols.find("Far").unwrap().bank, 2);
+        assert_eq!(symbols.find("wCounter").unwrap().address, 0xC000);
+    }
+
+    #[test]
+    fn test_operand_name() {
+        let symbols = Symbols::parse(SYM).unwrap();
+        let memory = FlatMemory::new();
+        let call = disasm::decode_bytes(&[0xCD, 0x53, 0x01], 0x0200);
+        assert_eq!(
+            symbols.operand_name(&memory, &call).as_deref(),
+            Some("Main.loop")
+        );
+        let store = disasm::decode_bytes(&[0xEA, 0x00, 0xC0], 0x0200);
+        assert_eq!(
+            symbols.operand_name(&memory, &store).as_deref(),
+            Some("wCounter")
+        );
+        let constant = disasm::decode_bytes(&[0x21, 0x50, 0x01], 0x0200);
+        assert_eq!(symbols.operand_name(&memory, &constant), None);
+    }
+}
@@ -0,0 +1,153 @@
+// Timer and divider registers.
+// FF04   DIV  - Upper 8 bits of the 16 bit internal counter, writing any value resets it
+// FF05   TIMA - Timer counter, incremented at the frequency selected by TAC
+// FF06   TMA  - Timer modulo, loaded into TIMA when it overflows
+// FF07   TAC  - Bit 2: timer enable, bits 1-0: clock select
+//
+// TIMA increments on the falling edge of the internal counter bit selected by TAC,
+// so resetting DIV or changing TAC can also increment it.
+
+use crate::{
+    interrupt,
+    state::{Snapshot, StateError, StateReader, StateWriter},
+};
+
+pub const DIV: u16 = 0xFF04;
+pub const TIMA: u16 = 0xFF05;
+pub const TMA: u16 = 0xFF06;
+pub const TAC: u16 = 0xFF07;
+
+/// Internal counter bit watched for each TAC clock select (4096, 262144, 65536, 16384 Hz).
+const TAC_BITS: [u16; 4] = [9, 3, 5, 7];
+
+#[derive(Default)]
+pub struct Timer {
+    counter: u16, // counts clocks (4 per M-cycle)
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    interrupt: u8,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer::default()
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            DIV => (self.counter >> 8) as u8,
+            TIMA => self.tima,
+            TMA => self.tma,
+            _ => 0xF8 | self.tac,
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            DIV => self.set_counter(0),
+            TIMA => self.tima = value,
+            TMA => self.tma = value,
+            _ => {
+                let before = self.input();
+                self.tac = value & 0x07;
+                if before && !self.input() {
+                    self.increment();
+                }
+            }
+        }
+    }
+
+    /// Advance by M-cycles and return the interrupts requested meanwhile.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        for _ in 0..cycles {
+            self.set_counter(self.counter.wrapping_add(4));
+        }
+        std::mem::take(&mut self.interrupt)
+    }
+
+    /// The internal counter, whose upper byte is DIV.
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub(crate) fn set_counter(&mut self, counter: u16) {
+        let before = self.input();
+        self.counter = counter;
+        if before && !self.input() {
+            self.increment();
+        }
+    }
+
+    /// Timer enable ANDed with the selected counter bit.
+    fn input(&self) -> bool {
+        let bit = TAC_BITS[(self.tac & 0x03) as usize];
+        self.tac & 0x04 != 0 && self.counter & (1 << bit) != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        if overflow {
+            self.tima = self.tma;
+            self.interrupt |= interrupt::TIMER;
+        } else {
+            self.tima = tima;
+        }
+    }
+}
+
+impl Snapshot for Timer {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.u16(self.counter);
+        writer.u8(self.tima);
+        writer.u8(self.tma);
+        writer.u8(self.tac);
+        writer.u8(self.interrupt);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.counter = reader.u16()?;
+        self.tima = reader.u8()?;
+        self.tma = reader.u8()?;
+        self.tac = reader.u8()? & 0x07;
+        self.interrupt = reader.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_div() {
+        let mut timer = Timer::new();
+        timer.tick(64);
+        assert_eq!(timer.read_byte(DIV), 1);
+        timer.write_byte(DIV, 0x55);
+        assert_eq!(timer.read_byte(DIV), 0);
+    }
+
+    #[test]
+    fn test_tima_overflow() {
+        let mut timer = Timer::new();
+        timer.write_byte(TMA, 0xF0);
+        timer.write_byte(TIMA, 0xFF);
+        timer.write_byte(TAC, 0x05); // enabled, every 4 M-cycles
+
+        assert_eq!(timer.tick(3), 0);
+        assert_eq!(timer.tick(1), interrupt::TIMER);
+        assert_eq!(timer.read_byte(TIMA), 0xF0);
+        timer.tick(4);
+        assert_eq!(timer.read_byte(TIMA), 0xF1);
+    }
+
+    #[test]
+    fn test_div_reset_falling_edge() {
+        let mut timer = Timer::new();
+        timer.write_byte(TAC, 0x05);
+        timer.tick(2); // bit 3 of the counter is set
+        timer.write_byte(DIV, 0);
+        assert_eq!(timer.read_byte(TIMA), 1);
+    }
+}
@@ -0,0 +1,315 @@
+// Screenshot tests: run a ROM for a number of frames and compare the framebuffer with a
+// reference PNG. On a mismatch the actual, expected and diff images are written to
+// `target/tmp/screenshots/` (diff: matching pixels faded, differing pixels in red).
+//
+// Set UPDATE_SCREENSHOTS=1 to write the current output as the reference of the ROMs built by
+// the tests instead. The references of the test ROMs come with them and are never written.
+//
+// The visual test ROMs and their references are not vendored, they are looked up in the
+// `test-roms/` directory (or TEST_ROMS_DIR) at the paths listed in `SUITE`. Missing ROMs
+// are skipped. `tests/screenshots/` holds references of ROMs built by the tests themselves.
+// ROMs with CGB functions run on a CGB, the others on a DMG.
+
+use std::{
+    env, fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use rusty_boy_core::{
+    gameboy::GameBoy,
+    model::Model,
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+/// Name, ROM, reference image (relative to the test ROM directory) and frames to run.
+const SUITE: [(&str, &str, &str, u32); 7] = [
+    (
+        "dmg-acid2",
+        "dmg-acid2/dmg-acid2.gb",
+        "dmg-acid2/reference-dmg.png",
+        60,
+    ),
+    (
+        "cgb-acid2",
+        "cgb-acid2/cgb-acid2.gbc",
+        "cgb-acid2/reference.png",
+        60,
+    ),
+    (
+        "m2_win_en_toggle",
+        "mealybug/m2_win_en_toggle.gb",
+        "mealybug/expected/DMG-blob/m2_win_en_toggle.png",
+        60,
+    ),
+    (
+        "m3_bgp_change",
+        "mealybug/m3_bgp_change.gb",
+        "mealybug/expected/DMG-blob/m3_bgp_change.png",
+        60,
+    ),
+    (
+        "m3_lcdc_bg_en_change",
+        "mealybug/m3_lcdc_bg_en_change.gb",
+        "mealybug/expected/DMG-blob/m3_lcdc_bg_en_change.png",
+        60,
+    ),
+    (
+        "m3_scx_low_3_bits",
+        "mealybug/m3_scx_low_3_bits.gb",
+        "mealybug/expected/DMG-blob/m3_scx_low_3_bits.png",
+        60,
+    ),
+    (
+        "m3_window_timing",
+        "mealybug/m3_window_timing.gb",
+        "mealybug/expected/DMG-blob/m3_window_timing.png",
+        60,
+    ),
+];
+
+struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Image {
+    fn read(path: &Path) -> Result<Image, String> {
+        let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buffer)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let channels = info.color_type.samples();
+        let rgb = buffer[..info.buffer_size()]
+            .chunks(channels)
+            .flat_map(|pixel| match channels {
+                1 | 2 => [pixel[0]; 3],
+                _ => [pixel[0], pixel[1], pixel[2]],
+            })
+            .collect();
+        Ok(Image {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgb,
+        })
+    }
+
+    fn write(&self, path: &Path) {
+        let file = BufWriter::new(fs::File::create(path).unwrap());
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Best);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.rgb).unwrap();
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = &[u8]> {
+        self.rgb.chunks(3)
+    }
+}
+
+/// Closest DMG shade (0 white - 3 black) of a color, so references using other greys match.
+fn shade(rgb: &[u8]) -> u8 {
+    let luma = (rgb[0] as u32 * 299 + rgb[1] as u32 * 587 + rgb[2] as u32 * 114) / 1000;
+    3 - ((luma + 42) / 85) as u8
+}
+
+/// Whether two pixels show the same color: the same shade for greys, otherwise components
+/// within the rounding of different RGB555 to 8-bit conversions.
+fn same_color(a: &[u8], e: &[u8]) -> bool {
+    let grey = |rgb: &[u8]| rgb[0] == rgb[1] && rgb[1] == rgb[2];
+    if grey(a) && grey(e) {
+        shade(a) == shade(e)
+    } else {
+        a.iter().zip(e).all(|(a, e)| a.abs_diff(*e) <= 8)
+    }
+}
+
+fn screenshot(rom: Vec<u8>, frames: u32) -> Result<Image, String> {
+    let model = Model::for_rom(&rom);
+    let mut gameboy = GameBoy::with_model(rom, model).map_err(|e| e.to_string())?;
+    for _ in 0..frames {
+        gameboy.run_frame();
+    }
+    Ok(Image {
+        width: SCREEN_WIDTH,
+        height: SCREEN_HEIGHT,
+        rgb: gameboy.frame().iter().flat_map(|p| p.to_rgb()).collect(),
+    })
+}
+
+fn diff(actual: &Image, expected: &Image) -> Image {
+    let rgb = actual
+        .pixels()
+        .zip(expected.pixels())
+        .flat_map(|(a, e)| {
+            if same_color(a, e) {
+                let faded = 0xC0 + a[0] / 4;
+                [faded, faded, faded]
+            } else {
+                [0xFF, 0x00, 0x00]
+            }
+        })
+        .collect();
+    Image {
+        width: actual.width,
+        height: actual.height,
+        rgb,
+    }
+}
+
+/// Directory of the references of ROMs built by the tests.
+fn own_references() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/screenshots")
+}
+
+/// Run `rom` and compare its output with the image at `reference`.
+fn check(name: &str, rom: Vec<u8>, frames: u32, reference: &Path) -> Result<(), String> {
+    let actual = screenshot(rom, frames)?;
+    if env::var_os("UPDATE_SCREENSHOTS").is_some() && reference.starts_with(own_references()) {
+        actual.write(reference);
+        return Ok(());
+    }
+
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("screenshots");
+    fs::create_dir_all(&output).unwrap();
+    let actual_path = output.join(format!("{}.actual.png", name));
+
+    let expected = match Image::read(reference) {
+        Ok(expected) => expected,
+        Err(error) => {
+            actual.write(&actual_path);
+            return Err(format!("{} (actual: {})", error, actual_path.display()));
+        }
+    };
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        actual.write(&actual_path);
+        return Err(format!(
+            "reference is {}x{}, expected {}x{} (actual: {})",
+            expected.width,
+            expected.height,
+            actual.width,
+            actual.height,
+            actual_path.display()
+        ));
+    }
+
+    let differences = actual
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, e)| !same_color(a, e))
+        .count();
+    if differences == 0 {
+        return Ok(());
+    }
+
+    actual.write(&actual_path);
+    expected.write(&output.join(format!("{}.expected.png", name)));
+    diff(&actual, &expected).write(&output.join(format!("{}.diff.png", name)));
+    Err(format!(
+        "{} pixels differ, see {}/{}.{{actual,expected,diff}}.png",
+        differences,
+        output.display(),
+        name
+    ))
+}
+
+#[test]
+fn test_suite() {
+    let directory = env::var_os("TEST_ROMS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-roms"));
+
+    let mut failures = Vec::new();
+    for (name, rom, reference, frames) in SUITE {
+        let Ok(rom) = fs::read(directory.join(rom)) else {
+            println!("{:<24} skipped, no ROM", name);
+            continue;
+        };
+        match check(name, rom, frames, &directory.join(reference)) {
+            Ok(()) => println!("{:<24} pass", name),
+            Err(error) => {
+                println!("{:<24} FAIL {}", name, error);
+                failures.push(name);
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "screenshots differ: {}",
+        failures.join(", ")
+    );
+}
+
+/// ROM drawing a checkerboard of a striped tile with a flipped sprite in the middle.
+fn pattern_rom() -> Vec<u8> {
+    let program: [u8; 64] = [
+        0x21, 0x10, 0x80, //       ld hl, $8010
+        0x06, 0x08, //             ld b, 8
+        0x3E, 0xF0, //       .tile ld a, $F0
+        0x22, //                   ld [hl+], a
+        0x3E, 0xCC, //             ld a, $CC
+        0x22, //                   ld [hl+], a
+        0x05, //                   dec b
+        0x20, 0xF7, //             jr nz, .tile
+        0x21, 0x00, 0x98, //       ld hl, $9800
+        0x01, 0x00, 0x04, //       ld bc, $0400
+        0x7D, //              .map ld a, l
+        0x0F, 0x0F, 0x0F, 0x0F, 0x0F, // rrca (x5), bit 0 of the row
+        0xAD, //                   xor l
+        0xE6, 0x01, //             and 1
+        0x22, //                   ld [hl+], a
+        0x0B, //                   dec bc
+        0x78, //                   ld a, b
+        0xB1, //                   or c
+        0x20, 0xF1, //             jr nz, .map
+        0x21, 0x00, 0xFE, //       ld hl, $FE00
+        0x36, 0x4C, 0x23, //       ld [hl], 76; inc hl
+        0x36, 0x54, 0x23, //       ld [hl], 84; inc hl
+        0x36, 0x01, 0x23, //       ld [hl], 1; inc hl
+        0x36, 0x20, //             ld [hl], $20 (X flip)
+        0x3E, 0xE4, 0xE0, 0x47, // ld a, $E4; ldh [BGP], a
+        0x3E, 0x1B, 0xE0, 0x48, // ld a, $1B; ldh [OBP0], a
+        0x3E, 0x93, 0xE0, 0x40, // ld a, $93; ldh [LCDC], a
+        0x76, //                   halt
+        0x18, 0xFE, //             jr @
+    ];
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+    rom
+}
+
+#[test]
+fn test_pattern() {
+    let reference = own_references().join("pattern.png");
+    if let Err(error) = check("pattern", pattern_rom(), 3, &reference) {
+        panic!("{}", error);
+    }
+}
+
+#[test]
+fn test_mismatch_writes_images() {
+    let reference = own_references().join("pattern.png");
+    let mut rom = pattern_rom();
+    rom[0x0150 + 50] = 0x1B; // BGP inverted
+
+    if env::var_os("UPDATE_SCREENSHOTS").is_some() {
+        return;
+    }
+    assert!(check("pattern-inverted", rom, 3, &reference).is_err());
+    let output = Path::new(env!("CARGO_TARGET_TMPDIR")).join("screenshots");
+    for kind in ["actual", "expected", "diff"] {
+        let image = Image::read(&output.join(format!("pattern-inverted.{}.png", kind))).unwrap();
+        assert_eq!((image.width, image.height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+    }
+}
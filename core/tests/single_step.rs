@@ -0,0 +1,297 @@
+// Runs SM83 single step tests against the CPU: each test sets the registers and RAM,
+// runs one instruction and compares the registers, RAM and the bus activity of each M-cycle.
+//
+// The community suite (https://github.com/SingleStepTests/sm83) is not vendored, extract its
+// `v1` directory to `tests/sm83/v1` or point the SM83_TESTS environment variable at it.
+// `tests/sm83/fixtures.json` uses the same format and always runs.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use rusty_boy_core::{
+    cpu::{BusCycle, CPU},
+    disasm,
+    memory::{FlatMemory, Memory},
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Test {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<Option<Cycle>>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ime: u8,
+    ie: Option<u8>,
+    /// EI executed, interrupts are enabled after the next instruction.
+    ei: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+/// Bus activity of one M-cycle: address, data and pins ("r-m" read, "-wm" write), or null
+/// for an internal M-cycle.
+#[derive(Deserialize)]
+struct Cycle(u16, Option<u8>, String);
+
+impl Cycle {
+    fn bus_cycle(cycle: Option<&Cycle>) -> BusCycle {
+        match cycle {
+            Some(Cycle(address, Some(value), pins)) if pins.contains('r') => {
+                BusCycle::Read(*address, *value)
+            }
+            Some(Cycle(address, Some(value), pins)) if pins.contains('w') => {
+                BusCycle::Write(*address, *value)
+            }
+            _ => BusCycle::Idle,
+        }
+    }
+}
+
+fn setup(state: &State) -> CPU<FlatMemory> {
+    let mut memory = FlatMemory::new();
+    if let Some(ie) = state.ie {
+        memory.write_byte(0xFFFF, ie);
+    }
+    for &(address, value) in &state.ram {
+        memory.write_byte(address, value);
+    }
+
+    let mut cpu = CPU::new(memory);
+    cpu.set_bus_log(true);
+    let registers = cpu.registers_mut();
+    registers.pc = state.pc;
+    registers.sp = state.sp;
+    registers.a = state.a;
+    registers.b = state.b;
+    registers.c = state.c;
+    registers.d = state.d;
+    registers.e = state.e;
+    registers.f.0 = state.f;
+    registers.h = state.h;
+    registers.l = state.l;
+    cpu.set_ime(state.ime != 0);
+    cpu
+}
+
+/// Run a test and return the list of differences from the expected state.
+fn run(test: &Test) -> Vec<String> {
+    let mut cpu = setup(&test.initial);
+    let cycles = cpu.step();
+
+    let mut errors = Vec::new();
+    let mut check = |what: &str, actual: u32, expected: u32| {
+        if actual != expected {
+            errors.push(format!(
+                "{}: expected {:#X}, got {:#X}",
+                what, expected, actual
+            ));
+        }
+    };
+
+    let expected = &test.expected;
+    let registers = cpu.registers();
+    check("a", registers.a.into(), expected.a.into());
+    check("b", registers.b.into(), expected.b.into());
+    check("c", registers.c.into(), expected.c.into());
+    check("d", registers.d.into(), expected.d.into());
+    check("e", registers.e.into(), expected.e.into());
+    check("f", registers.f.0.into(), expected.f.into());
+    check("h", registers.h.into(), expected.h.into());
+    check("l", registers.l.into(), expected.l.into());
+    check("pc", registers.pc.into(), expected.pc.into());
+    check("sp", registers.sp.into(), expected.sp.into());
+    check("ime", cpu.ime().into(), expected.ime.into());
+    if let Some(ei) = expected.ei {
+        check("ei", cpu.ime_scheduled().into(), ei.into());
+    }
+    if let Some(ie) = expected.ie {
+        check("ie", cpu.mmu().read_byte(0xFFFF).into(), ie.into());
+    }
+    for &(address, value) in &expected.ram {
+        let actual = cpu.mmu().read_byte(address);
+        check(&format!("[{:04X}]", address), actual.into(), value.into());
+    }
+    check("M-cycles", cycles, test.cycles.len() as u32);
+
+    let expected_bus: Vec<BusCycle> = test
+        .cycles
+        .iter()
+        .map(|c| Cycle::bus_cycle(c.as_ref()))
+        .collect();
+    if cpu.bus_log() != expected_bus {
+        errors.push(format!(
+            "bus: expected {:X?}, got {:X?}",
+            expected_bus,
+            cpu.bus_log()
+        ));
+    }
+
+    errors
+}
+
+/// Run every test of a file, returning how many passed and printing the first failures.
+fn run_file(path: &Path) -> (usize, usize) {
+    let json = fs::read_to_string(path).unwrap();
+    let tests: Vec<Test> =
+        serde_json::from_str(&json).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+
+    let mut passed = 0;
+    let mut reported = 0;
+    for test in &tests {
+        let errors = run(test);
+        if errors.is_empty() {
+            passed += 1;
+        } else if reported < 3 {
+            let instruction = disasm::decode_bytes(
+                &[
+                    lookup(&test.initial.ram, test.initial.pc),
+                    lookup(&test.initial.ram, test.initial.pc.wrapping_add(1)),
+                    lookup(&test.initial.ram, test.initial.pc.wrapping_add(2)),
+                ],
+                test.initial.pc,
+            );
+            println!(
+                "FAIL {} `{}`: {}",
+                test.name,
+                instruction,
+                errors.join(", ")
+            );
+            reported += 1;
+        }
+    }
+    (passed, tests.len())
+}
+
+fn lookup(ram: &[(u16, u8)], address: u16) -> u8 {
+    ram.iter()
+        .find(|(a, _)| *a == address)
+        .map(|(_, value)| *value)
+        .unwrap_or(0)
+}
+
+fn run_directory(directory: &Path) {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .collect();
+    files.sort();
+
+    let mut failed_files = Vec::new();
+    for file in &files {
+        let (passed, total) = run_file(file);
+        if passed != total {
+            let name = file.file_stem().unwrap().to_string_lossy().to_string();
+            failed_files.push(format!("{} ({}/{})", name, passed, total));
+        }
+    }
+
+    assert!(
+        failed_files.is_empty(),
+        "{} of {} opcodes failed: {}",
+        failed_files.len(),
+        files.len(),
+        failed_files.join(", ")
+    );
+}
+
+#[test]
+fn test_fixtures() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sm83/fixtures.json");
+    let (passed, total) = run_file(&path);
+    assert_eq!(passed, total);
+}
+
+#[test]
+fn test_single_step_suite() {
+    let directory = env::var_os("SM83_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sm83/v1"));
+    if !directory.is_dir() {
+        println!(
+            "skipping: no SM83 single step tests in {}",
+            directory.display()
+        );
+        return;
+    }
+    run_directory(&directory);
+}
+
+#[test]
+fn test_cycles_match_disassembler() {
+    // Branches are not taken with every flag cleared for NZ/NC and set for Z/C.
+    for opcode in 0..=0xFFu8 {
+        let instruction = disasm::decode_bytes(&[opcode], 0xC000);
+        if instruction.mnemonic == disasm::Mnemonic::Illegal
+            || instruction.mnemonic == disasm::Mnemonic::Halt
+            || opcode == 0xCB
+        {
+            continue;
+        }
+
+        for taken in [false, true] {
+            if taken && instruction.branch_cycles.is_none() {
+                continue;
+            }
+            let condition = instruction
+                .operands
+                .iter()
+                .find_map(|operand| match operand {
+                    disasm::Operand::Condition(condition) => Some(*condition),
+                    _ => None,
+                });
+            let flags = match condition {
+                Some(disasm::Condition::NZ) | Some(disasm::Condition::NC) if taken => 0x00,
+                Some(disasm::Condition::Z) | Some(disasm::Condition::C) if !taken => 0x00,
+                _ => 0xF0,
+            };
+
+            let mut memory = FlatMemory::new();
+            memory.load(0xC000, &[opcode]);
+            let mut cpu = CPU::new(memory);
+            cpu.registers_mut().pc = 0xC000;
+            cpu.registers_mut().sp = 0xD000;
+            cpu.registers_mut().f.0 = flags;
+
+            let expected = if taken {
+                instruction.branch_cycles.unwrap()
+            } else {
+                instruction.cycles
+            };
+            assert_eq!(
+                cpu.step(),
+                expected as u32,
+                "{:02X} `{}` (taken: {})",
+                opcode,
+                instruction,
+                taken
+            );
+        }
+    }
+
+    for opcode in 0..=0xFFu8 {
+        let instruction = disasm::decode_bytes(&[0xCB, opcode], 0xC000);
+        let mut memory = FlatMemory::new();
+        memory.load(0xC000, &[0xCB, opcode]);
+        let mut cpu = CPU::new(memory);
+        cpu.registers_mut().pc = 0xC000;
+        assert_eq!(cpu.step(), instruction.cycles as u32, "CB {:02X}", opcode);
+    }
+}
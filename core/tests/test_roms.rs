@@ -0,0 +1,348 @@
+// Runs Blargg and Mooneye test ROMs headlessly and prints a summary table.
+//
+// The ROMs are not vendored. Put them in `test-roms/` at the root of the repository (or point
+// the TEST_ROMS_DIR environment variable at another directory) with this layout:
+//   test-roms/blargg/{cpu_instrs,instr_timing,mem_timing,halt_bug}...  (any depth)
+//   test-roms/mooneye/acceptance/...
+// The test is skipped when the directory does not exist.
+//
+// Blargg ROMs report through the serial port ("Passed"/"Failed") and, when the cartridge has
+// RAM, through the signature DE B0 61 at A001-A003 with the status at A000.
+// Mooneye ROMs execute LD B,B with the Fibonacci numbers in B,C,D,E,H,L on success,
+// or 0x42 in every register on failure.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
+};
+
+use rusty_boy_core::{gameboy::GameBoy, memory::Memory};
+
+/// M-cycles in one emulated second.
+const CYCLES_PER_SECOND: u64 = 1 << 20;
+
+const BLARGG_SUITES: [&str; 4] = ["cpu_instrs", "instr_timing", "mem_timing", "halt_bug"];
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: u8 = 0x42;
+const LD_B_B: u8 = 0x40;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed(String),
+    Timeout,
+    /// The ROM could not be loaded.
+    Error(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "pass"),
+            Outcome::Failed(reason) => write!(f, "FAIL {}", reason),
+            Outcome::Timeout => write!(f, "TIMEOUT"),
+            Outcome::Error(error) => write!(f, "ERROR {}", error),
+        }
+    }
+}
+
+/// Run a ROM until it reports a result or `timeout` emulated seconds have passed.
+fn run_rom(rom: Vec<u8>, timeout: u64) -> Outcome {
+    let mut gameboy = match GameBoy::new(rom) {
+        Ok(gameboy) => gameboy,
+        Err(error) => return Outcome::Error(error.to_string()),
+    };
+
+    let mut cycles: u64 = 0;
+    let mut next_check: u64 = 0;
+    while cycles < timeout * CYCLES_PER_SECOND {
+        let cpu = gameboy.cpu();
+        if cpu.mmu().read_byte(cpu.registers().pc) == LD_B_B && !cpu.is_halted() {
+            if let Some(outcome) = mooneye_result(&gameboy) {
+                return outcome;
+            }
+        }
+        cycles += gameboy.step() as u64;
+
+        // The serial output and cartridge RAM only need to be looked at once in a while.
+        if cycles >= next_check {
+            next_check = cycles + CYCLES_PER_SECOND / 60;
+            if let Some(outcome) = blargg_result(&gameboy) {
+                return outcome;
+            }
+        }
+    }
+    Outcome::Timeout
+}
+
+fn mooneye_result(gameboy: &GameBoy) -> Option<Outcome> {
+    let r = gameboy.cpu().registers();
+    let registers = [r.b, r.c, r.d, r.e, r.h, r.l];
+    if registers == MOONEYE_PASS {
+        Some(Outcome::Passed)
+    } else if registers == [MOONEYE_FAIL; 6] {
+        Some(Outcome::Failed("registers hold 0x42".to_string()))
+    } else {
+        None
+    }
+}
+
+fn blargg_result(gameboy: &GameBoy) -> Option<Outcome> {
+    let output = String::from_utf8_lossy(gameboy.serial_output());
+    if output.contains("Passed") {
+        return Some(Outcome::Passed);
+    }
+    if output.contains("Failed") {
+        return Some(Outcome::Failed(summarize(&output)));
+    }
+
+    let mmu = gameboy.mmu();
+    let signature = [0xA001, 0xA002, 0xA003].map(|address| mmu.read_byte(address));
+    let status = mmu.read_byte(0xA000);
+    if signature != [0xDE, 0xB0, 0x61] || status == 0x80 {
+        return None;
+    }
+    if status == 0x00 {
+        return Some(Outcome::Passed);
+    }
+    let text: Vec<u8> = (0xA004..0xC000)
+        .map(|address| mmu.read_byte(address))
+        .take_while(|&c| c != 0)
+        .collect();
+    Some(Outcome::Failed(format!(
+        "status {:#04X}: {}",
+        status,
+        summarize(&String::from_utf8_lossy(&text))
+    )))
+}
+
+/// Single line version of a ROM's output for the summary table.
+fn summarize(output: &str) -> String {
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether a Mooneye ROM runs on DMG, from the model suffix of its name
+/// (e.g. `-GS` for DMG/MGB/SGB, `-dmgABCmgb`, `-C` for CGB only).
+fn runs_on_dmg(path: &Path) -> bool {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    match stem.rsplit_once('-') {
+        None => true,
+        Some((_, models)) => {
+            models.contains("dmgABC")
+                || (models.chars().all(|c| c.is_ascii_uppercase()) && models.contains('G'))
+        }
+    }
+}
+
+fn find_roms(directory: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|e| e == "gb") {
+            roms.push(path);
+        }
+    }
+}
+
+/// Every ROM to run with its timeout in emulated seconds.
+fn collect_roms(directory: &Path) -> Vec<(PathBuf, u64)> {
+    let mut blargg = Vec::new();
+    find_roms(&directory.join("blargg"), &mut blargg);
+    let mut mooneye = Vec::new();
+    find_roms(&directory.join("mooneye/acceptance"), &mut mooneye);
+
+    let mut roms: Vec<(PathBuf, u64)> = blargg
+        .into_iter()
+        .filter(|path| {
+            let path = path.to_string_lossy();
+            BLARGG_SUITES.iter().any(|suite| path.contains(suite))
+        })
+        .map(|path| {
+            // The combined cpu_instrs ROM runs all 11 tests in about a minute.
+            let timeout = if path.ends_with("cpu_instrs.gb") {
+                120
+            } else {
+                30
+            };
+            (path, timeout)
+        })
+        .chain(
+            mooneye
+                .into_iter()
+                .filter(|path| runs_on_dmg(path))
+                .map(|path| (path, 10)),
+        )
+        .collect();
+    roms.sort();
+    roms
+}
+
+/// Run the ROMs on every core, keeping their order in the results.
+fn run_all(roms: &[(PathBuf, u64)]) -> Vec<(Outcome, f64)> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = roms.len().div_ceil(workers).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = roms
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(path, timeout)| {
+                            let start = Instant::now();
+                            let outcome = match fs::read(path) {
+                                Ok(rom) => run_rom(rom, *timeout),
+                                Err(error) => Outcome::Error(error.to_string()),
+                            };
+                            (outcome, start.elapsed().as_secs_f64())
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_roms() {
+    let directory = env::var_os("TEST_ROMS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-roms"));
+    if !directory.is_dir() {
+        println!("skipping: no test ROMs in {}", directory.display());
+        return;
+    }
+
+    let roms = collect_roms(&directory);
+    let results = run_all(&roms);
+
+    let width = roms
+        .iter()
+        .map(|(path, _)| path.strip_prefix(&directory).unwrap().as_os_str().len())
+        .max()
+        .unwrap_or(0);
+    let mut failures = Vec::new();
+    println!("{:<width$}  {:>7}  result", "rom", "time");
+    for ((path, _), (outcome, seconds)) in roms.iter().zip(&results) {
+        let name = path.strip_prefix(&directory).unwrap().display().to_string();
+        println!("{:<width$}  {:>6.1}s  {}", name, seconds, outcome);
+        if *outcome != Outcome::Passed {
+            failures.push(name);
+        }
+    }
+    println!("{} of {} passed", roms.len() - failures.len(), roms.len());
+
+    assert!(
+        failures.is_empty(),
+        "{} test ROMs failed: {}",
+        failures.len(),
+        failures.join(", ")
+    );
+}
+
+/// 32KB ROM-only cartridge running `program` from 0x0150.
+fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP; JP $0150
+    rom[0x0150..0x0150 + program.len()].copy_from_slice(program);
+    rom
+}
+
+/// ROM printing the null terminated `message` at 0x0170 through the serial port.
+fn serial_rom(message: &[u8]) -> Vec<u8> {
+    let mut rom = rom_with_program(&[
+        0x21, 0x70, 0x01, //       ld hl, $0170
+        0x2A, //             .loop ld a, [hl+]
+        0xB7, //                   or a
+        0x28, 0x0E, //             jr z, .done
+        0xE0, 0x01, //             ldh [$FF01], a
+        0x3E, 0x81, //             ld a, $81
+        0xE0, 0x02, //             ldh [$FF02], a
+        0xF0, 0x02, //       .wait ldh a, [$FF02]
+        0xCB, 0x7F, //             bit 7, a
+        0x20, 0xFA, //             jr nz, .wait
+        0x18, 0xEE, //             jr .loop
+        0x18, 0xFE, //       .done jr .done
+    ]);
+    rom[0x0170..0x0170 + message.len()].copy_from_slice(message);
+    rom
+}
+
+#[test]
+fn test_serial_pass() {
+    assert_eq!(
+        run_rom(serial_rom(b"cpu_instrs\n\nPassed\n\0"), 1),
+        Outcome::Passed
+    );
+}
+
+#[test]
+fn test_serial_fail() {
+    assert_eq!(
+        run_rom(serial_rom(b"01-special\n\nFailed #2\n\0"), 1),
+        Outcome::Failed("01-special Failed #2".to_string())
+    );
+}
+
+#[test]
+fn test_blargg_memory_signature() {
+    let mut rom = rom_with_program(&[
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld [$0000], a
+        0x21, 0x01, 0xA0, //             ld hl, $A001
+        0x36, 0xDE, 0x23, //             ld [hl], $DE; inc hl
+        0x36, 0xB0, 0x23, //             ld [hl], $B0; inc hl
+        0x36, 0x61, 0x23, //             ld [hl], $61; inc hl
+        0x36, 0x00, //                   ld [hl], $00
+        0xAF, 0xEA, 0x00, 0xA0, //       xor a; ld [$A000], a
+        0x18, 0xFE, //                   jr @
+    ]);
+    rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x0149] = 0x02; // 8KB
+    assert_eq!(run_rom(rom, 1), Outcome::Passed);
+}
+
+#[test]
+fn test_mooneye_pass() {
+    let rom = rom_with_program(&[
+        0x06, 0x03, 0x0E, 0x05, 0x16, 0x08, 0x1E, 0x0D, 0x26, 0x15, 0x2E, 0x22, // ld b-l
+        0x40, 0x18, 0xFE, // ld b, b; jr @
+    ]);
+    assert_eq!(run_rom(rom, 1), Outcome::Passed);
+}
+
+#[test]
+fn test_mooneye_fail() {
+    let rom = rom_with_program(&[
+        0x06, 0x42, 0x0E, 0x42, 0x16, 0x42, 0x1E, 0x42, 0x26, 0x42, 0x2E, 0x42, // ld b-l
+        0x40, 0x18, 0xFE, // ld b, b; jr @
+    ]);
+    assert!(matches!(run_rom(rom, 1), Outcome::Failed(_)));
+}
+
+#[test]
+fn test_timeout() {
+    assert_eq!(
+        run_rom(rom_with_program(&[0x18, 0xFE]), 1),
+        Outcome::Timeout
+    );
+}
+
+#[test]
+fn test_runs_on_dmg() {
+    assert!(runs_on_dmg(Path::new("acceptance/di_timing-GS.gb")));
+    assert!(runs_on_dmg(Path::new("acceptance/ei_sequence.gb")));
+    assert!(runs_on_dmg(Path::new("acceptance/boot_hwio-dmgABCmgb.gb")));
+    assert!(!runs_on_dmg(Path::new("acceptance/boot_regs-sgb2.gb")));
+    assert!(!runs_on_dmg(Path::new("acceptance/boot_div-dmg0.gb")));
+    assert!(!runs_on_dmg(Path::new("acceptance/boot_div2-S.gb")));
+}
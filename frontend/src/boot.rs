@@ -0,0 +1,113 @@
+// Powering on a Game Boy from the files given on the command line or in a launch request,
+// and the save file kept by the battery of its cartridge.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rusty_boy_core::{boot::BootRom, gameboy::GameBoy, model::Model};
+
+/// The Game Boy to run and the files it starts from, shared by every mode.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Machine {
+    pub rom: PathBuf,
+    /// Save state to start from instead of powering on.
+    pub load_state: Option<PathBuf>,
+    /// Hardware to emulate, the CGB for ROMs with CGB functions and the DMG otherwise.
+    pub model: Option<Model>,
+    /// Boot ROM to run first, instead of starting in the state it leaves.
+    pub boot_rom: Option<PathBuf>,
+    /// Battery save file, read at the start and written at the end. The terminal defaults
+    /// to `<rom>.sav`, the other modes keep no save file when not set.
+    pub save_file: Option<PathBuf>,
+}
+
+impl Machine {
+    /// A Game Boy with the ROM inserted, before the save file and save state are loaded.
+    pub fn power_on(&self) -> Result<GameBoy, String> {
+        power_on(&self.rom, self.model, self.boot_rom.as_deref())
+    }
+}
+
+/// A Game Boy with the ROM at `rom` inserted, running `boot_rom` when given. The model
+/// defaults to the CGB for ROMs with CGB functions and to the DMG otherwise.
+pub fn power_on(
+    rom: &Path,
+    model: Option<Model>,
+    boot_rom: Option<&Path>,
+) -> Result<GameBoy, String> {
+    let read = |path: &Path| fs::read(path).map_err(|e| format!("{}: {}", path.display(), e));
+    let data = read(rom)?;
+    let model = model.unwrap_or_else(|| Model::for_rom(&data));
+    let gameboy = match boot_rom {
+        Some(path) => GameBoy::with_boot_rom(data, model, read_boot_rom(path, model)?),
+        None => GameBoy::with_model(data, model),
+    };
+    gameboy.map_err(|e| format!("{}: {}", rom.display(), e))
+}
+
+/// The boot ROM of `model` at `path`.
+pub fn read_boot_rom(path: &Path, model: Model) -> Result<BootRom, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    BootRom::new(data, model).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The save file next to the ROM at `rom`.
+pub fn save_file_path(rom: &Path) -> PathBuf {
+    rom.with_extension("sav")
+}
+
+/// Load the RAM and clock of a cartridge with a battery from the save file at `path`, if
+/// there is one.
+pub fn load_save_file(gameboy: &mut GameBoy, path: &Path) -> Result<(), String> {
+    if !gameboy.mmu().cartridge().header().has_battery() {
+        return Ok(());
+    }
+    match fs::read(path) {
+        Ok(data) => gameboy
+            .load_save_file(&data)
+            .map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// Write the RAM and clock of a cartridge with a battery to the save file at `path`.
+pub fn write_save_file(gameboy: &GameBoy, path: &Path) -> Result<(), String> {
+    if !gameboy.mmu().cartridge().header().has_battery() {
+        return Ok(());
+    }
+    fs::write(path, gameboy.save_file()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_file() {
+        let dir = std::env::temp_dir().join("rusty-boy-save-file-test");
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x03; // MBC1 with RAM and battery
+        rom[0x0149] = 0x02;
+        fs::write(&rom_path, &rom).unwrap();
+        let save_path = save_file_path(&rom_path);
+        assert_eq!(save_path, dir.join("game.sav"));
+        let _ = fs::remove_file(&save_path);
+
+        // No save file yet.
+        let mut gameboy = power_on(&rom_path, None, None).unwrap();
+        load_save_file(&mut gameboy, &save_path).unwrap();
+        fs::write(&save_path, [0x42; 0x2000]).unwrap();
+        load_save_file(&mut gameboy, &save_path).unwrap();
+        write_save_file(&gameboy, &save_path).unwrap();
+        assert_eq!(fs::read(&save_path).unwrap(), [0x42; 0x2000]);
+
+        fs::write(&save_path, [0x42; 0x10]).unwrap();
+        assert!(load_save_file(&mut gameboy, &save_path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+// Images for the sensor of a Pocket Camera, read from PNG files. The files are shown one
+// per frame in turn, like the frames of a video; a single file is a still image.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use rusty_boy_core::{
+    cartridge::{SENSOR_HEIGHT, SENSOR_WIDTH},
+    gameboy::GameBoy,
+};
+
+#[derive(Debug, Default)]
+pub struct Camera {
+    /// Brightness of the sensor pixels of each image.
+    images: Vec<Vec<u8>>,
+}
+
+impl Camera {
+    /// The images of the PNG files at `paths`, in order.
+    pub fn load(paths: &[PathBuf]) -> Result<Self, String> {
+        let images = paths
+            .iter()
+            .map(|path| read_png(path).map_err(|e| format!("{}: {}", path.display(), e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Camera { images })
+    }
+
+    /// Point the camera at the image of `frame`. Does nothing without images.
+    pub fn show(&self, gameboy: &mut GameBoy, frame: u32) {
+        if !self.images.is_empty() {
+            gameboy.set_camera_image(&self.images[frame as usize % self.images.len()]);
+        }
+    }
+}
+
+fn read_png(path: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut decoder = png::Decoder::new(file);
+    // Palettes expanded and 16-bit samples stripped to 8 bits.
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+    let luma: Vec<u8> = match info.color_type {
+        png::ColorType::Grayscale => data[..info.buffer_size()].to_vec(),
+        png::ColorType::GrayscaleAlpha => data[..info.buffer_size()]
+            .iter()
+            .step_by(2)
+            .copied()
+            .collect(),
+        color => {
+            let step = if color == png::ColorType::Rgba { 4 } else { 3 };
+            data[..info.buffer_size()]
+                .chunks_exact(step)
+                .map(|p| {
+                    ((u32::from(p[0]) * 299 + u32::from(p[1]) * 587 + u32::from(p[2]) * 114) / 1000)
+                        as u8
+                })
+                .collect()
+        }
+    };
+    Ok(scale(&luma, info.width as usize, info.height as usize))
+}
+
+/// Scale an image to the sensor size, cropping its middle to the sensor's aspect ratio.
+fn scale(luma: &[u8], width: usize, height: usize) -> Vec<u8> {
+    // Crop to the widest part with the aspect ratio of the sensor.
+    let (crop_width, crop_height) = if width * SENSOR_HEIGHT > height * SENSOR_WIDTH {
+        (height * SENSOR_WIDTH / SENSOR_HEIGHT, height)
+    } else {
+        (width, width * SENSOR_HEIGHT / SENSOR_WIDTH)
+    };
+    let (left, top) = ((width - crop_width) / 2, (height - crop_height) / 2);
+    let mut image = Vec::with_capacity(SENSOR_WIDTH * SENSOR_HEIGHT);
+    for y in 0..SENSOR_HEIGHT {
+        let row = top + y * crop_height / SENSOR_HEIGHT;
+        for x in 0..SENSOR_WIDTH {
+            image.push(luma[row * width + left + x * crop_width / SENSOR_WIDTH]);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale() {
+        // A 512x224 image: the left and right quarters are cropped, the middle halved.
+        let (width, height) = (512, 224);
+        let luma: Vec<u8> = (0..width * height).map(|i| (i % width / 2) as u8).collect();
+        let image = scale(&luma, width, height);
+        assert_eq!(image.len(), SENSOR_WIDTH * SENSOR_HEIGHT);
+        assert_eq!(image[0], 64);
+        assert_eq!(image[1], 65);
+        assert_eq!(image[SENSOR_WIDTH * SENSOR_HEIGHT - 1], 191);
+    }
+
+    #[test]
+    fn test_load_png() {
+        let path = std::env::temp_dir().join("rusty-boy-camera-test.png");
+        let file = File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(file, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 255, 255, 0, 0, 0]).unwrap();
+        writer.finish().unwrap();
+
+        let camera = Camera::load(std::slice::from_ref(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Cropped to the sensor's aspect ratio, only the white left pixel is left.
+        assert_eq!(camera.images.len(), 1);
+        assert!(camera.images[0].iter().all(|&pixel| pixel == 255));
+        assert!(Camera::load(&["missing.png".into()]).is_err());
+    }
+}
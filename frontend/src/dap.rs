@@ -0,0 +1,785 @@
+// Debug Adapter Protocol server on stdin/stdout, for VS Code and other DAP clients.
+// Messages are JSON with a `Content-Length` header. The ROM is given by the launch request:
+//   { "program": "game.gb", "symbols": "game.sym", "model": "cgb", "bootRom": "cgb.bin",
+//     "stopOnEntry": true }
+// `symbols` is an RGBDS .sym or .map file, `game.sym` is tried when it is not set. `model` is
+// picked from the ROM header when it is not set, and `bootRom` runs first when set. Source line
+// breakpoints resolve to the label defined on that line.
+//
+// https://microsoft.github.io/debug-adapter-protocol/specification
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rusty_boy_core::{
+    debug::{self, Breakpoint, Debugger, StopReason},
+    disasm,
+    gameboy::{GameBoy, CYCLES_PER_FRAME},
+    memory::Memory,
+    model::Model,
+    symbols::Symbols,
+};
+use serde_json::{json, Value};
+
+use crate::{boot, symbols};
+
+const FRAME_RATE: f64 = 59.7275;
+
+/// The only thread, the SM83.
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+
+/// M-cycles a step over or out runs before stopping anyway, 10 emulated seconds.
+const STEP_CYCLES: u64 = 10 << 20;
+
+pub struct Adapter {
+    gameboy: Option<GameBoy>,
+    debugger: Debugger,
+    symbols: Symbols,
+    stop_on_entry: bool,
+    running: bool,
+    /// Breakpoint indexes set by `setBreakpoints`, per source path.
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    /// Stop of a step or pause request, reported after its response.
+    step_stop: Option<StopReason>,
+    seq: u64,
+    /// Messages waiting to be sent.
+    output: Vec<Value>,
+}
+
+/// Read one message, or None at the end of the input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length: usize = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// `0x0150` for memory and instruction references.
+fn reference(address: u16) -> String {
+    format!("0x{:04X}", address)
+}
+
+fn parse_reference(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Adapter {
+            gameboy: None,
+            debugger: Debugger::new(),
+            symbols: Symbols::default(),
+            stop_on_entry: false,
+            running: false,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            step_stop: None,
+            seq: 0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Messages to send since the last call.
+    pub fn take_output(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn send(&mut self, mut message: Value) {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        self.output.push(message);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn stopped(&mut self, reason: &str) {
+        self.running = false;
+        self.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+    }
+
+    fn report(&mut self, reason: StopReason) {
+        match reason {
+            StopReason::Step => self.stopped("step"),
+            StopReason::Breakpoint(_) => self.stopped("breakpoint"),
+            StopReason::Watchpoint(..) => self.stopped("data breakpoint"),
+            StopReason::Timeout => self.stopped("pause"),
+        }
+    }
+
+    /// Handle a request, returning false once the client disconnected.
+    pub fn handle(&mut self, request: &Value) -> bool {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+        let result = self.dispatch(command, arguments);
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+
+        if let Some(reason) = self.step_stop.take() {
+            self.report(reason);
+        }
+        match command {
+            "launch" if self.gameboy.is_some() => self.event("initialized", json!({})),
+            "configurationDone" if self.stop_on_entry => self.stopped("entry"),
+            "configurationDone" => self.running = true,
+            "disconnect" | "terminate" => {
+                self.event("terminated", json!({}));
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn dispatch(&mut self, command: &str, arguments: &Value) -> Result<Value, String> {
+        if !matches!(
+            command,
+            "initialize" | "launch" | "disconnect" | "terminate"
+        ) && self.gameboy.is_none()
+        {
+            return Err("no ROM launched".to_string());
+        }
+        let body = match command {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsReadMemoryRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsSteppingGranularity": false,
+                "supportsTerminateRequest": true,
+            }),
+            "launch" => {
+                self.launch(arguments)?;
+                json!({})
+            }
+            "configurationDone" | "disconnect" | "terminate" => json!({}),
+            "setBreakpoints" => self.set_breakpoints(arguments)?,
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments)?,
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "SM83" }] }),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "presentationHint": "registers",
+                    "variablesReference": REGISTERS_REFERENCE,
+                    "expensive": false,
+                }]
+            }),
+            "variables" => self.variables(arguments),
+            "continue" => {
+                self.running = true;
+                json!({ "allThreadsContinued": true })
+            }
+            "pause" => {
+                self.step_stop = Some(StopReason::Timeout);
+                json!({})
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let gameboy = self.gameboy.as_mut().unwrap();
+                let reason = match command {
+                    "next" => self.debugger.step_over(gameboy, STEP_CYCLES),
+                    "stepIn" => self.debugger.step(gameboy),
+                    _ => self.debugger.step_out(gameboy, STEP_CYCLES),
+                };
+                self.step_stop = Some(reason);
+                json!({})
+            }
+            "readMemory" => self.read_memory(arguments)?,
+            "disassemble" => self.disassemble(arguments)?,
+            _ => return Err(format!("unsupported request `{}`", command)),
+        };
+        Ok(body)
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<(), String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("launch needs a `program`")?;
+        let model = match arguments["model"].as_str() {
+            Some(name) => {
+                Some(Model::parse(name).ok_or_else(|| format!("unknown model `{}`", name))?)
+            }
+            None => None,
+        };
+        let boot_rom = arguments["bootRom"].as_str().map(Path::new);
+        let gameboy = boot::power_on(Path::new(program), model, boot_rom)?;
+
+        self.symbols = symbols::load(
+            Path::new(program),
+            arguments["symbols"].as_str().map(Path::new),
+        )?;
+
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.gameboy = Some(gameboy);
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, address: u16, bank: Option<u16>) -> usize {
+        self.debugger.add_breakpoint(Breakpoint {
+            address,
+            bank,
+            condition: None,
+        })
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["source"]["path"]
+            .as_str()
+            .ok_or("breakpoints need a source path")?
+            .to_string();
+        for index in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            self.debugger.remove_breakpoint(index);
+        }
+        let source = fs::read_to_string(&path).unwrap_or_default();
+
+        let mut indexes = Vec::new();
+        let mut breakpoints = Vec::new();
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            let symbol = symbols::label_on_line(&source, line)
+                .and_then(|label| self.symbols.find(&label))
+                .map(|symbol| (symbol.address, symbol.bank));
+            match symbol {
+                Some((address, bank)) => {
+                    indexes.push(self.add_breakpoint(address, Some(bank)));
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": reference(address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no label from the symbol file on this line",
+                })),
+            }
+        }
+        self.source_breakpoints.insert(path, indexes);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        for index in std::mem::take(&mut self.instruction_breakpoints) {
+            self.debugger.remove_breakpoint(index);
+        }
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(parse_reference)
+                .map(|address| {
+                    let offset = breakpoint["offset"].as_i64().unwrap_or(0);
+                    address.wrapping_add(offset as u16)
+                });
+            match address {
+                Some(address) => {
+                    let index = self.add_breakpoint(address, None);
+                    self.instruction_breakpoints.push(index);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": reference(address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": "invalid instruction reference",
+                })),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Name of an address in `bank`, `Label+offset` when there are symbols.
+    fn describe(&self, bank: u16, address: u16) -> String {
+        self.symbols
+            .name_in(bank, address)
+            .unwrap_or_else(|| reference(address))
+    }
+
+    /// The current instruction, then the call site of each frame of the call stack.
+    fn stack_trace(&self) -> Value {
+        let gameboy = self.gameboy.as_ref().unwrap();
+        let pc = gameboy.cpu().registers().pc;
+        let mut locations = vec![(gameboy.mmu().bank(pc), pc)];
+        let frames = self.debugger.call_stack().iter().rev();
+        locations.extend(frames.map(|frame| (frame.bank, frame.call_site)));
+        let frames: Vec<Value> = locations
+            .iter()
+            .enumerate()
+            .map(|(id, &(bank, address))| {
+                json!({
+                    "id": id,
+                    "name": self.describe(bank, address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": reference(address),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn variables(&self, arguments: &Value) -> Value {
+        if arguments["variablesReference"].as_u64() != Some(REGISTERS_REFERENCE) {
+            return json!({ "variables": [] });
+        }
+        let cpu = self.gameboy.as_ref().unwrap().cpu();
+        let r = cpu.registers();
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        let flags: String = [
+            flag(r.f.z(), 'Z'),
+            flag(r.f.n(), 'N'),
+            flag(r.f.h(), 'H'),
+            flag(r.f.c(), 'C'),
+        ]
+        .iter()
+        .collect();
+        let variable = |name: &str, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let mut variables = vec![
+            variable("A", format!("${:02X}", r.a)),
+            variable("F", format!("${:02X}", r.f.0)),
+            variable("B", format!("${:02X}", r.b)),
+            variable("C", format!("${:02X}", r.c)),
+            variable("D", format!("${:02X}", r.d)),
+            variable("E", format!("${:02X}", r.e)),
+            variable("H", format!("${:02X}", r.h)),
+            variable("L", format!("${:02X}", r.l)),
+        ];
+        for (name, value) in [
+            ("AF", r.af()),
+            ("BC", r.bc()),
+            ("DE", r.de()),
+            ("HL", r.hl()),
+            ("SP", r.sp),
+            ("PC", r.pc),
+        ] {
+            let mut variable = variable(name, format!("${:04X}", value));
+            variable["memoryReference"] = json!(reference(value));
+            variables.push(variable);
+        }
+        variables.push(variable("flags", flags));
+        variables.push(variable("IME", u8::from(cpu.ime()).to_string()));
+        json!({ "variables": variables })
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let address = arguments["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .ok_or("invalid memory reference")?;
+        let address = address.wrapping_add(arguments["offset"].as_i64().unwrap_or(0) as u16);
+        let count = arguments["count"].as_u64().unwrap_or(0).min(0x10000) as usize;
+        let memory = self.gameboy.as_ref().unwrap().mmu();
+        let data: Vec<u8> = (0..count)
+            .map(|i| memory.read_byte(address.wrapping_add(i as u16)))
+            .collect();
+        Ok(json!({ "address": reference(address), "data": base64(&data) }))
+    }
+
+    fn disassemble(&self, arguments: &Value) -> Result<Value, String> {
+        let address = arguments["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .ok_or("invalid memory reference")?;
+        let address = address.wrapping_add(arguments["offset"].as_i64().unwrap_or(0) as u16);
+        // 64K instructions of at least a byte cover the whole memory.
+        let instruction_offset = arguments["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .clamp(-0x10000, 0x10000);
+        let count = arguments["instructionCount"]
+            .as_u64()
+            .unwrap_or(0)
+            .min(0x10000) as usize;
+        let memory = self.gameboy.as_ref().unwrap().mmu();
+
+        let instructions = if instruction_offset < 0 {
+            let before = instruction_offset.unsigned_abs() as usize;
+            debug::disassemble_around(memory, address, before, count)
+        } else {
+            let skipped = disasm::disassemble(memory, address, instruction_offset as usize);
+            let start = skipped.last().map_or(address, |i| i.next_address());
+            disasm::disassemble(memory, start, count)
+        };
+        let instructions: Vec<Value> = instructions
+            .iter()
+            .map(|instruction| {
+                let bytes: Vec<String> = (0..instruction.length as u16)
+                    .map(|i| {
+                        format!(
+                            "{:02X}",
+                            memory.read_byte(instruction.address.wrapping_add(i))
+                        )
+                    })
+                    .collect();
+                let mut value = json!({
+                    "address": reference(instruction.address),
+                    "instructionBytes": bytes.join(" "),
+                    "instruction": instruction.to_string(),
+                });
+                let bank = memory.bank(instruction.address);
+                if let Some((symbol, 0)) = self.symbols.label(bank, instruction.address) {
+                    value["symbol"] = json!(symbol.name);
+                }
+                value
+            })
+            .collect();
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    /// Run a frame while running, reporting the stop when something is hit.
+    pub fn run_frame(&mut self) {
+        let Some(gameboy) = self.gameboy.as_mut() else {
+            return;
+        };
+        match self.debugger.resume(gameboy, CYCLES_PER_FRAME.into()) {
+            StopReason::Timeout => {}
+            reason => self.report(reason),
+        }
+    }
+}
+
+/// Serve a client on stdin/stdout until it disconnects.
+pub fn run() -> Result<(), String> {
+    // Requests are read on their own thread so `pause` arrives while the game runs.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin());
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter::new();
+    let mut stdout = io::stdout();
+    let frame_time = Duration::from_secs_f64(1.0 / FRAME_RATE);
+    let mut next_frame = Instant::now();
+    loop {
+        let request = if adapter.is_running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        let connected = match request {
+            Some(request) => adapter.handle(&request),
+            None => {
+                adapter.run_frame();
+                next_frame += frame_time;
+                let now = Instant::now();
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
+                } else {
+                    next_frame = now;
+                }
+                true
+            }
+        };
+        for message in adapter.take_output() {
+            write_message(&mut stdout, &message).map_err(|e| e.to_string())?;
+        }
+        if !connected {
+            return Ok(());
+        }
+        if !adapter.is_running() {
+            next_frame = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::PathBuf};
+
+    use super::*;
+
+    const SOURCE: &str = "\
+SECTION \"Main\", ROM0[$150]
+Main::
+    ld b, 0
+.loop
+    inc b
+    call Store
+    jr .loop
+Store:
+    ld [$C000], a
+    inc a
+    ret
+";
+
+    /// Adapter launched on the program of SOURCE, with its source and symbol files in a
+    /// temporary directory named after the test.
+    fn launch(name: &str, stop_on_entry: bool) -> (Adapter, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("rusty-boy-dap-{}", name));
+        fs::create_dir_all(&directory).unwrap();
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x0150..0x015D].copy_from_slice(&[
+            0x06, 0x00, 0x04, 0xCD, 0x58, 0x01, 0x18, 0xFA, 0xEA, 0x00, 0xC0, 0x3C, 0xC9,
+        ]);
+        fs::write(directory.join("main.gb"), rom).unwrap();
+        fs::write(
+            directory.join("main.sym"),
+            "; rgblink\n00:0150 Main\n00:0152 Main.loop\n00:0158 Store\n",
+        )
+        .unwrap();
+        fs::write(directory.join("main.asm"), SOURCE).unwrap();
+
+        let mut adapter = Adapter::new();
+        request(&mut adapter, "initialize", json!({}));
+        let program = directory.join("main.gb");
+        let output = request(
+            &mut adapter,
+            "launch",
+            json!({ "program": program, "stopOnEntry": stop_on_entry }),
+        );
+        assert_eq!(output[1]["event"], "initialized");
+        (adapter, directory)
+    }
+
+    /// Send a request and return the messages it produced, checking that it succeeded.
+    fn request(adapter: &mut Adapter, command: &str, arguments: Value) -> Vec<Value> {
+        adapter.handle(&json!({
+            "seq": 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        }));
+        let output = adapter.take_output();
+        assert_eq!(output[0]["success"], true, "{}", output[0]);
+        output
+    }
+
+    fn run_until_stopped(adapter: &mut Adapter) -> Value {
+        for _ in 0..60 {
+            adapter.run_frame();
+            if let Some(event) = adapter.take_output().pop() {
+                return event;
+            }
+        }
+        panic!("still running");
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(buffer, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+        let mut reader = Cursor::new(buffer);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "seq": 1 }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+        assert_eq!(base64(b"Game Boy"), "R2FtZSBCb3k=");
+    }
+
+    #[test]
+    fn test_source_breakpoints_and_stepping() {
+        let (mut adapter, directory) = launch("source", false);
+        let output = request(
+            &mut adapter,
+            "setBreakpoints",
+            json!({
+                "source": { "path": directory.join("main.asm") },
+                "breakpoints": [{ "line": 4 }, { "line": 9 }],
+            }),
+        );
+        let breakpoints = &output[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["instructionReference"], "0x0152");
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        request(&mut adapter, "configurationDone", json!({}));
+        assert!(adapter.is_running());
+        let event = run_until_stopped(&mut adapter);
+        assert_eq!(event["body"]["reason"], "breakpoint");
+
+        let output = request(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        let frame = &output[0]["body"]["stackFrames"][0];
+        assert_eq!(frame["name"], "Main.loop");
+        assert_eq!(frame["instructionPointerReference"], "0x0152");
+
+        request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+        request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+        let output = request(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        let frames = &output[0]["body"]["stackFrames"];
+        assert_eq!(output[0]["body"]["totalFrames"], 2);
+        assert_eq!(frames[0]["name"], "Store");
+        assert_eq!(frames[1]["name"], "Main.loop+1");
+
+        let output = request(&mut adapter, "stepOut", json!({ "threadId": 1 }));
+        assert_eq!(output[1]["body"]["reason"], "step");
+        let output = request(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(output[0]["body"]["stackFrames"][0]["name"], "Main.loop+4");
+
+        let output = request(&mut adapter, "disconnect", json!({}));
+        assert_eq!(output[1]["event"], "terminated");
+    }
+
+    #[test]
+    fn test_registers_memory_and_disassembly() {
+        let (mut adapter, _) = launch("inspect", true);
+        let output = request(&mut adapter, "configurationDone", json!({}));
+        assert_eq!(output[1]["body"]["reason"], "entry");
+
+        let output = request(&mut adapter, "scopes", json!({ "frameId": 0 }));
+        let reference = output[0]["body"]["scopes"][0]["variablesReference"].clone();
+        let output = request(
+            &mut adapter,
+            "variables",
+            json!({ "variablesReference": reference }),
+        );
+        let variables = output[0]["body"]["variables"].as_array().unwrap();
+        let value =
+            |name: &str| variables.iter().find(|v| v["name"] == name).unwrap()["value"].clone();
+        assert_eq!(value("A"), "$01");
+        assert_eq!(value("PC"), "$0100");
+        assert_eq!(value("flags"), "Z-HC");
+
+        let output = request(
+            &mut adapter,
+            "readMemory",
+            json!({ "memoryReference": "0x0150", "count": 3 }),
+        );
+        assert_eq!(output[0]["body"]["data"], base64(&[0x06, 0x00, 0x04]));
+
+        let output = request(
+            &mut adapter,
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x0158" }] }),
+        );
+        assert_eq!(output[0]["body"]["breakpoints"][0]["verified"], true);
+        request(&mut adapter, "continue", json!({ "threadId": 1 }));
+        assert_eq!(
+            run_until_stopped(&mut adapter)["body"]["reason"],
+            "breakpoint"
+        );
+
+        let output = request(
+            &mut adapter,
+            "disassemble",
+            json!({ "memoryReference": "0x0158", "instructionOffset": -1, "instructionCount": 3 }),
+        );
+        let instructions = output[0]["body"]["instructions"].as_array().unwrap();
+        assert_eq!(instructions[0]["address"], "0x0156");
+        assert_eq!(instructions[1]["instruction"], "ld [$C000], a");
+        assert_eq!(instructions[1]["symbol"], "Store");
+        assert_eq!(instructions[2]["instructionBytes"], "3C");
+
+        for offset in [i64::MIN, i64::MAX] {
+            let output = request(
+                &mut adapter,
+                "disassemble",
+                json!({
+                    "memoryReference": "0x0158",
+                    "instructionOffset": offset,
+                    "instructionCount": u64::MAX,
+                }),
+            );
+            let instructions = output[0]["body"]["instructions"].as_array().unwrap();
+            assert_eq!(instructions.len(), 0x10000);
+        }
+    }
+}
@@ -0,0 +1,575 @@
+// Debugger mode: a command line on stdin driving the core debug interface, or a GDB stub.
+// Addresses and values are hexadecimal (`$`/`0x` prefixes are accepted), counts are decimal.
+// Addresses can also be labels of the symbol file, which name addresses in the output.
+// An empty line repeats the last step or continue command.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    net::TcpListener,
+    path::PathBuf,
+};
+
+use rusty_boy_core::{
+    debug::{
+        self, gdb::GdbStub, Access, Breakpoint, Comparison, Condition, Debugger, RegisterName,
+        StopReason, Watchpoint,
+    },
+    disasm,
+    gameboy::GameBoy,
+    memory::Memory,
+    symbols::Symbols,
+};
+
+use crate::{boot, symbols};
+
+const HELP: &str = "\
+b <addr> [if <reg> <op> <value>]   break at an address, e.g. `b Main.loop if a == 3`
+w <addr>[-<end>] [r|w|rw]          watch accesses (default: writes)
+d <id>                             delete a breakpoint or watchpoint
+l                                  list breakpoints and watchpoints
+s [n]                              step n instructions
+n                                  step over calls
+f                                  step out of the current function
+c                                  continue
+r                                  show registers and flags
+bt                                 show the call stack
+x <addr> [len]                     dump memory
+dis [addr] [n]                     disassemble (default: around PC)
+q                                  quit";
+
+/// M-cycles a continue or step out runs before giving the prompt back, 10 emulated seconds.
+const RUN_CYCLES: u64 = 10 << 20;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub machine: boot::Machine,
+    /// RGBDS .sym or .map file, `<rom>.sym` is used when it exists.
+    pub symbols: Option<PathBuf>,
+    /// Port to wait for a GDB client on, instead of reading commands from stdin.
+    pub gdb: Option<u16>,
+}
+
+pub struct Repl {
+    gameboy: GameBoy,
+    debugger: Debugger,
+    symbols: Symbols,
+    /// Breakpoint and watchpoint ids, in the order they were added.
+    ids: Vec<Point>,
+    last: String,
+}
+
+#[derive(Clone, Copy)]
+enum Point {
+    Breakpoint(usize),
+    Watchpoint(usize),
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid number `{}`", text))
+}
+
+fn parse_count(text: Option<&str>, default: usize) -> Result<usize, String> {
+    text.map_or(Ok(default), |text| {
+        text.parse()
+            .map_err(|_| format!("invalid count `{}`", text))
+    })
+}
+
+fn parse_condition(words: &[&str]) -> Result<Condition, String> {
+    let [register, operator, value] = words else {
+        return Err("conditions look like `if <reg> <op> <value>`".to_string());
+    };
+    Ok(Condition {
+        register: RegisterName::parse(register)
+            .ok_or_else(|| format!("unknown register `{}`", register))?,
+        comparison: Comparison::parse(operator)
+            .ok_or_else(|| format!("unknown comparison `{}`", operator))?,
+        value: parse_number(value)?,
+    })
+}
+
+fn format_access(access: Access) -> &'static str {
+    match access {
+        Access::Read => "r",
+        Access::Write => "w",
+        Access::ReadWrite => "rw",
+    }
+}
+
+impl Repl {
+    pub fn new(gameboy: GameBoy, symbols: Symbols) -> Self {
+        Repl {
+            gameboy,
+            debugger: Debugger::new(),
+            symbols,
+            ids: Vec::new(),
+            last: String::new(),
+        }
+    }
+
+    /// Run one command line and return its output, or None to quit.
+    pub fn execute(&mut self, line: &str) -> Result<Option<String>, String> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(Some(String::new()));
+        };
+        if matches!(command, "s" | "n" | "f" | "c") {
+            self.last = line.clone();
+        }
+
+        let output = match command {
+            "b" => self.add_breakpoint(args)?,
+            "w" => self.add_watchpoint(args)?,
+            "d" => self.delete(args)?,
+            "l" => self.list(),
+            "s" => {
+                let count = parse_count(args.first().copied(), 1)?;
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.debugger.step(&mut self.gameboy);
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                self.stopped(reason)
+            }
+            "n" => {
+                let reason = self.debugger.step_over(&mut self.gameboy, RUN_CYCLES);
+                self.stopped(reason)
+            }
+            "f" => {
+                let reason = self.debugger.step_out(&mut self.gameboy, RUN_CYCLES);
+                self.stopped(reason)
+            }
+            "c" => {
+                let reason = self.debugger.resume(&mut self.gameboy, RUN_CYCLES);
+                self.stopped(reason)
+            }
+            "r" => self.registers(),
+            "bt" => self.backtrace(),
+            "x" => {
+                let (address, _) = self.parse_address(args.first().ok_or("x needs an address")?)?;
+                let length = parse_count(args.get(1).copied(), 64)?;
+                self.hexdump(address, length)
+            }
+            "dis" => match args.first() {
+                Some(address) => {
+                    let count = parse_count(args.get(1).copied(), 10)?;
+                    let (address, _) = self.parse_address(address)?;
+                    let instructions = disasm::disassemble(self.gameboy.mmu(), address, count);
+                    self.listing(&instructions)
+                }
+                None => self.around_pc(),
+            },
+            "h" | "help" => HELP.to_string(),
+            "q" => return Ok(None),
+            _ => return Err(format!("unknown command `{}`, try `help`", command)),
+        };
+        Ok(Some(output))
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let (address, rest) = args.split_first().ok_or("b needs an address")?;
+        let (address, bank) = self.parse_address(address)?;
+        let condition = match rest {
+            [] => None,
+            ["if", condition @ ..] => Some(parse_condition(condition)?),
+            _ => return Err("expected `if` after the address".to_string()),
+        };
+        let index = self.debugger.add_breakpoint(Breakpoint {
+            address,
+            bank,
+            condition,
+        });
+        self.ids.push(Point::Breakpoint(index));
+        Ok(format!("breakpoint {}", self.ids.len()))
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let range = args.first().ok_or("w needs an address")?;
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (self.parse_address(start)?.0, self.parse_address(end)?.0),
+            None => (self.parse_address(range)?.0, self.parse_address(range)?.0),
+        };
+        if end < start {
+            return Err(format!("empty range `{}`", range));
+        }
+        let access = match args.get(1).copied() {
+            None | Some("w") => Access::Write,
+            Some("r") => Access::Read,
+            Some("rw") => Access::ReadWrite,
+            Some(other) => return Err(format!("unknown access `{}`", other)),
+        };
+        let index = self
+            .debugger
+            .add_watchpoint(Watchpoint { start, end, access });
+        self.debugger.update_watchpoints(&mut self.gameboy);
+        self.ids.push(Point::Watchpoint(index));
+        Ok(format!("watchpoint {}", self.ids.len()))
+    }
+
+    fn delete(&mut self, args: &[&str]) -> Result<String, String> {
+        let id = parse_count(args.first().copied(), 0)?;
+        let removed = match id.checked_sub(1).and_then(|i| self.ids.get(i)) {
+            Some(Point::Breakpoint(index)) => self.debugger.remove_breakpoint(*index),
+            Some(Point::Watchpoint(index)) => {
+                let removed = self.debugger.remove_watchpoint(*index);
+                self.debugger.update_watchpoints(&mut self.gameboy);
+                removed
+            }
+            None => false,
+        };
+        if !removed {
+            return Err(format!("no breakpoint or watchpoint {}", id));
+        }
+        Ok(format!("deleted {}", id))
+    }
+
+    fn list(&self) -> String {
+        let mut lines = Vec::new();
+        for (id, point) in self.ids.iter().enumerate() {
+            let id = id + 1;
+            match *point {
+                Point::Breakpoint(index) => {
+                    let Some((_, b)) = self.debugger.breakpoints().find(|(i, _)| *i == index)
+                    else {
+                        continue;
+                    };
+                    let mut line = format!("{}: break ${:04X}", id, b.address);
+                    if let Some(bank) = b.bank {
+                        if let Some(name) = self.symbols.name_in(bank, b.address) {
+                            line = format!("{} ({})", line, name);
+                        }
+                    }
+                    if let Some(condition) = b.condition {
+                        line = format!("{} if {}", line, condition);
+                    }
+                    lines.push(line);
+                }
+                Point::Watchpoint(index) => {
+                    let Some((_, w)) = self.debugger.watchpoints().find(|(i, _)| *i == index)
+                    else {
+                        continue;
+                    };
+                    lines.push(format!(
+                        "{}: watch ${:04X}-${:04X} {}",
+                        id,
+                        w.start,
+                        w.end,
+                        format_access(w.access)
+                    ));
+                }
+            }
+        }
+        if lines.is_empty() {
+            return "no breakpoints or watchpoints".to_string();
+        }
+        lines.join("\n")
+    }
+
+    /// Address of a number or a label, with the bank of labels.
+    fn parse_address(&self, text: &str) -> Result<(u16, Option<u16>), String> {
+        if let Ok(address) = parse_number(text) {
+            return Ok((address, None));
+        }
+        match self.symbols.find(text) {
+            Some(symbol) => Ok((symbol.address, Some(symbol.bank))),
+            None => Err(format!("invalid address or unknown label `{}`", text)),
+        }
+    }
+
+    /// `$0150` followed by the label of the address when there is one.
+    fn describe(&self, address: u16) -> String {
+        match self.symbols.name(self.gameboy.mmu(), address) {
+            Some(name) => format!("${:04X} {}", address, name),
+            None => format!("${:04X}", address),
+        }
+    }
+
+    fn backtrace(&self) -> String {
+        let pc = self.gameboy.cpu().registers().pc;
+        let mut lines = vec![format!("#0 {}", self.describe(pc))];
+        for (depth, frame) in self.debugger.call_stack().iter().rev().enumerate() {
+            let mut line = format!("#{} ${:04X}", depth + 1, frame.call_site);
+            if let Some(name) = self.symbols.name_in(frame.bank, frame.call_site) {
+                line = format!("{} {}", line, name);
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
+    /// Id shown to the user for a debugger index.
+    fn id_of(&self, wanted: Point) -> usize {
+        let position = self.ids.iter().position(|point| match (point, wanted) {
+            (Point::Breakpoint(a), Point::Breakpoint(b)) => *a == b,
+            (Point::Watchpoint(a), Point::Watchpoint(b)) => *a == b,
+            _ => false,
+        });
+        position.map_or(0, |i| i + 1)
+    }
+
+    fn stopped(&self, reason: StopReason) -> String {
+        let header = match reason {
+            StopReason::Step => None,
+            StopReason::Breakpoint(index) => Some(format!(
+                "breakpoint {}",
+                self.id_of(Point::Breakpoint(index))
+            )),
+            StopReason::Watchpoint(index, hit) => Some(format!(
+                "watchpoint {}: {} ${:04X} = ${:02X}",
+                self.id_of(Point::Watchpoint(index)),
+                if hit.access == Access::Read {
+                    "read"
+                } else {
+                    "write"
+                },
+                hit.address,
+                hit.value
+            )),
+            StopReason::Timeout => Some("still running, `c` to go on".to_string()),
+        };
+        let pc = self.gameboy.cpu().registers().pc;
+        let current = self.listing(&[disasm::decode(self.gameboy.mmu(), pc)]);
+        match header {
+            Some(header) => format!("{}\n{}", header, current),
+            None => current,
+        }
+    }
+
+    fn registers(&self) -> String {
+        let cpu = self.gameboy.cpu();
+        let r = cpu.registers();
+        let flag = |set: bool, name: char| if set { name } else { '-' };
+        format!(
+            "AF ${:04X}  BC ${:04X}  DE ${:04X}  HL ${:04X}  SP ${:04X}  PC ${:04X}\n\
+             flags {}{}{}{}  IME {}  cycles {}",
+            r.af(),
+            r.bc(),
+            r.de(),
+            r.hl(),
+            r.sp,
+            r.pc,
+            flag(r.f.z(), 'Z'),
+            flag(r.f.n(), 'N'),
+            flag(r.f.h(), 'H'),
+            flag(r.f.c(), 'C'),
+            u8::from(cpu.ime()),
+            cpu.cycles()
+        )
+    }
+
+    fn hexdump(&self, address: u16, length: usize) -> String {
+        let memory = self.gameboy.mmu();
+        let mut lines = Vec::new();
+        for row in (0..length).step_by(16) {
+            let start = address.wrapping_add(row as u16);
+            let bytes: Vec<u8> = (0..(length - row).min(16))
+                .map(|i| memory.read_byte(start.wrapping_add(i as u16)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                .collect();
+            lines.push(format!("{:04X}: {:<47}  {}", start, hex.join(" "), text));
+        }
+        lines.join("\n")
+    }
+
+    fn around_pc(&self) -> String {
+        let pc = self.gameboy.cpu().registers().pc;
+        self.listing(&debug::disassemble_around(self.gameboy.mmu(), pc, 4, 10))
+    }
+
+    fn listing(&self, instructions: &[disasm::Instruction]) -> String {
+        let pc = self.gameboy.cpu().registers().pc;
+        let memory = self.gameboy.mmu();
+        let mut lines = Vec::new();
+        for instruction in instructions {
+            if let Some((symbol, 0)) = self
+                .symbols
+                .label(memory.bank(instruction.address), instruction.address)
+            {
+                lines.push(format!("{}:", symbol.name));
+            }
+            let bytes: Vec<String> = (0..instruction.length as u16)
+                .map(|i| {
+                    format!(
+                        "{:02X}",
+                        memory.read_byte(instruction.address.wrapping_add(i))
+                    )
+                })
+                .collect();
+            let marker = if instruction.address == pc { '>' } else { ' ' };
+            let mut line = format!(
+                "{} {:04X}: {:<9} {}",
+                marker,
+                instruction.address,
+                bytes.join(" "),
+                instruction
+            );
+            if let Some(name) = self.symbols.operand_name(memory, instruction) {
+                line = format!("{} ; {}", line, name);
+            }
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let read = |path: &PathBuf| fs::read(path).map_err(|e| format!("{}: {}", path.display(), e));
+    let machine = &options.machine;
+    let mut gameboy = machine.power_on()?;
+    if let Some(path) = &machine.save_file {
+        boot::load_save_file(&mut gameboy, path)?;
+    }
+    if let Some(path) = &machine.load_state {
+        gameboy
+            .load_state(&read(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    if let Some(port) = options.gdb {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+        eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+        let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+        GdbStub::new(&mut gameboy, stream)
+            .run()
+            .map_err(|e| format!("gdb: {}", e))?;
+    } else {
+        let symbols = symbols::load(&machine.rom, options.symbols.as_deref())?;
+        let mut repl = Repl::new(gameboy, symbols);
+        prompt(&mut repl)?;
+        gameboy = repl.gameboy;
+    }
+
+    match &machine.save_file {
+        Some(path) => boot::write_save_file(&gameboy, path),
+        None => Ok(()),
+    }
+}
+
+/// Execute the commands read from stdin until `q` or the end of the input.
+fn prompt(repl: &mut Repl) -> Result<(), String> {
+    println!("{}", repl.around_pc());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(rusty-boy) ");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        match repl.execute(&line.map_err(|e| e.to_string())?) {
+            Ok(Some(output)) if output.is_empty() => {}
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => return Ok(()),
+            Err(error) => println!("error: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl() -> Repl {
+        repl_with_symbols(Symbols::default())
+    }
+
+    fn repl_with_symbols(symbols: Symbols) -> Repl {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x0150..0x015D].copy_from_slice(&[
+            0x06, 0x00, //       0150 ld b, 0
+            0x04, //             0152 inc b
+            0xCD, 0x58, 0x01, // 0153 call $0158
+            0x18, 0xFA, //       0156 jr $0152
+            0xEA, 0x00, 0xC0, // 0158 ld [$C000], a
+            0x3C, //             015B inc a
+            0xC9, //             015C ret
+        ]);
+        Repl::new(GameBoy::new(rom).unwrap(), symbols)
+    }
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        repl.execute(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let mut repl = repl();
+        assert_eq!(run(&mut repl, "b $153 if b == 2"), "breakpoint 1");
+        assert_eq!(
+            run(&mut repl, "c"),
+            "breakpoint 1\n> 0153: CD 58 01  call $0158"
+        );
+        assert_eq!(run(&mut repl, "n"), "> 0156: 18 FA     jr $0152");
+        assert_eq!(run(&mut repl, ""), "> 0152: 04        inc b");
+        assert_eq!(run(&mut repl, "s 2"), "> 0158: EA 00 C0  ld [$C000], a");
+        assert_eq!(run(&mut repl, "f"), "> 0156: 18 FA     jr $0152");
+        assert!(run(&mut repl, "r").contains("BC $03"));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut repl = repl();
+        assert_eq!(run(&mut repl, "w c000-c001"), "watchpoint 1");
+        assert_eq!(
+            run(&mut repl, "c"),
+            "watchpoint 1: write $C000 = $01\n> 015B: 3C        inc a"
+        );
+        assert_eq!(run(&mut repl, "l"), "1: watch $C000-$C001 w");
+        assert_eq!(run(&mut repl, "d 1"), "deleted 1");
+        assert_eq!(run(&mut repl, "l"), "no breakpoints or watchpoints");
+        assert!(repl.execute("d 1").is_err());
+    }
+
+    #[test]
+    fn test_memory_and_disassembly() {
+        let mut repl = repl();
+        assert_eq!(
+            run(&mut repl, "x 150 4"),
+            format!("0150: {:<47}  ....", "06 00 04 CD")
+        );
+        assert_eq!(
+            run(&mut repl, "dis 156 2"),
+            "  0156: 18 FA     jr $0152\n  0158: EA 00 C0  ld [$C000], a"
+        );
+        assert!(run(&mut repl, "dis").contains("> 0100: 00        nop"));
+        assert!(repl.execute("b").is_err());
+        assert!(repl.execute("b 150 if a ~ 1").is_err());
+        assert!(repl.execute("frobnicate").is_err());
+        assert_eq!(repl.execute("q"), Ok(None));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols =
+            Symbols::parse("00:0150 Main\n00:0152 Main.loop\n00:0158 Store\n00:C000 wValue\n");
+        let mut repl = repl_with_symbols(symbols.unwrap());
+        assert_eq!(run(&mut repl, "b Store"), "breakpoint 1");
+        assert_eq!(run(&mut repl, "l"), "1: break $0158 (Store)");
+        assert_eq!(
+            run(&mut repl, "c"),
+            "breakpoint 1\nStore:\n> 0158: EA 00 C0  ld [$C000], a ; wValue"
+        );
+        assert_eq!(run(&mut repl, "bt"), "#0 $0158 Store\n#1 $0153 Main.loop+1");
+        assert_eq!(
+            run(&mut repl, "dis Main.loop 2"),
+            "Main.loop:\n  0152: 04        inc b\n  0153: CD 58 01  call $0158 ; Store"
+        );
+        assert!(repl.execute("b Nowhere").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+// Headless mode: run a ROM for a number of frames as fast as possible, without a display,
+// then write what was asked for (screenshot, serial output, save state, movie).
+
+use std::{fs, path::PathBuf};
+
+use rusty_boy_core::{
+    cpu::trace::Tracer,
+    gameboy::GameBoy,
+    movie::{self, FrameStatus, Mode, Movie, Session},
+    palette::ColorStage,
+    ppu::SCREEN_WIDTH,
+    sgb, state,
+};
+
+use crate::{boot, camera::Camera, input::InputScript, screenshot, symbols};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub machine: boot::Machine,
+    pub frames: u32,
+    pub screenshot: Option<PathBuf>,
+    /// DMG palette and color correction of the screenshot.
+    pub colors: ColorStage,
+    pub input: Option<PathBuf>,
+    /// Where to write the serial output, printed to stdout when not set.
+    pub serial: Option<PathBuf>,
+    /// PNG files seen by a Pocket Camera, one per frame in turn.
+    pub camera: Vec<PathBuf>,
+    pub save_state: Option<PathBuf>,
+    /// Where to log every instruction, labelled with the symbols.
+    pub trace: Option<PathBuf>,
+    /// RGBDS .sym or .map file, `<rom>.sym` is used when it exists.
+    pub symbols: Option<PathBuf>,
+    /// Movie to play back, on the model and boot ROM it was recorded with.
+    pub play_movie: Option<PathBuf>,
+    /// Where to write the input of every frame as a movie. With `play_movie`, the movie is
+    /// played until the input script presses a button and recorded from there.
+    pub record_movie: Option<PathBuf>,
+}
+
+/// Frames between the checksums of recorded movies.
+const MOVIE_CHECKSUM_INTERVAL: u32 = 60;
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write(path: &PathBuf, data: &[u8]) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Power on as the options ask, with the movie session to run the frames through.
+fn start(options: &Options) -> Result<(GameBoy, Option<Session>), String> {
+    let machine = &options.machine;
+    if let Some(path) = &options.play_movie {
+        let movie =
+            Movie::from_bytes(&read(path)?).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let boot_rom = match &machine.boot_rom {
+            Some(boot_rom) => Some(boot::read_boot_rom(boot_rom, movie.model)?),
+            None => None,
+        };
+        let gameboy = movie
+            .start(read(&machine.rom)?, boot_rom)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mode = match options.record_movie {
+            Some(_) => Mode::ReadWrite,
+            None => Mode::ReadOnly,
+        };
+        return Ok((gameboy, Some(Session::play(movie, mode))));
+    }
+
+    let mut gameboy = machine.power_on()?;
+    if let Some(path) = &machine.save_file {
+        boot::load_save_file(&mut gameboy, path)?;
+    }
+    if let Some(path) = &machine.load_state {
+        gameboy
+            .load_state(&read(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    let session = match (&options.record_movie, &machine.load_state) {
+        (None, _) => None,
+        (Some(_), Some(_)) => Some(Session::record(Movie::from_state(
+            &gameboy,
+            MOVIE_CHECKSUM_INTERVAL,
+        ))),
+        (Some(_), None) => {
+            let checksum = gameboy.mmu().cartridge().rom_checksum();
+            let mut movie = Movie::new(checksum, gameboy.model(), MOVIE_CHECKSUM_INTERVAL);
+            if let Some(path) = &machine.boot_rom {
+                movie.boot_rom_checksum = Some(state::crc32(&read(path)?));
+            }
+            Some(Session::record(movie))
+        }
+    };
+    Ok((gameboy, session))
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let (mut gameboy, mut session) = start(options)?;
+    let script = match &options.input {
+        Some(path) => {
+            let text = String::from_utf8_lossy(&read(path)?).into_owned();
+            InputScript::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => InputScript::parse("").unwrap(),
+    };
+    let camera = Camera::load(&options.camera)?;
+    if let Some(path) = &options.trace {
+        let symbols = symbols::load(&options.machine.rom, options.symbols.as_deref())?;
+        let tracer = Tracer::to_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        gameboy
+            .cpu_mut()
+            .set_tracer(Some(tracer.with_symbols(symbols)));
+    }
+
+    for frame in 0..options.frames {
+        camera.show(&mut gameboy, frame);
+        let Some(session) = &mut session else {
+            movie::apply_input(&mut gameboy, script.input(frame));
+            gameboy.run_frame();
+            continue;
+        };
+        if let FrameStatus::Desync { frame, .. } =
+            session.run_frame(&mut gameboy, script.input(frame))
+        {
+            let path = options.play_movie.as_ref().unwrap();
+            return Err(format!("{}: desync at frame {}", path.display(), frame));
+        }
+    }
+
+    if let Some(path) = &options.trace {
+        if let Some(error) = gameboy.cpu().tracer().and_then(|tracer| tracer.error()) {
+            return Err(format!("{}: {}", path.display(), error));
+        }
+        // Dropping the tracer flushes it.
+        gameboy.cpu_mut().set_tracer(None);
+    }
+    if let Some(path) = &options.screenshot {
+        // With the border on an SGB.
+        match gameboy.sgb_frame() {
+            Some(frame) => screenshot::write_png(path, frame, sgb::SCREEN_WIDTH, &options.colors)?,
+            None => screenshot::write_png(path, gameboy.frame(), SCREEN_WIDTH, &options.colors)?,
+        }
+    }
+    match &options.serial {
+        Some(path) => write(path, gameboy.serial_output())?,
+        None => print!("{}", String::from_utf8_lossy(gameboy.serial_output())),
+    }
+    if let Some(path) = &options.machine.save_file {
+        boot::write_save_file(&gameboy, path)?;
+    }
+    if let Some(path) = &options.save_state {
+        write(path, &gameboy.save_state())?;
+    }
+    if let (Some(path), Some(session)) = (&options.record_movie, session) {
+        write(path, &session.into_movie().to_bytes())?;
+    }
+    Ok(())
+}
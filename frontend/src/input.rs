@@ -0,0 +1,89 @@
+// Input scripts for headless runs. Each line sets the buttons held from a frame on:
+//
+//   # comment
+//   120 start        hold Start from frame 120
+//   125              release everything at frame 125
+//   300 a right      hold A and Right from frame 300
+//
+// Frames must be in increasing order. Button names are case insensitive.
+
+use rusty_boy_core::{joypad::Button, movie};
+
+pub struct InputScript {
+    /// (first frame, joypad byte) sorted by frame.
+    changes: Vec<(u32, u8)>,
+}
+
+fn parse_button(name: &str) -> Option<Button> {
+    let button = match name.to_ascii_lowercase().as_str() {
+        "right" => Button::Right,
+        "left" => Button::Left,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "a" => Button::A,
+        "b" => Button::B,
+        "select" => Button::Select,
+        "start" => Button::Start,
+        _ => return None,
+    };
+    Some(button)
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut changes: Vec<(u32, u8)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let Some(frame) = words.next() else {
+                continue;
+            };
+            let frame: u32 = frame
+                .parse()
+                .map_err(|_| format!("line {}: invalid frame `{}`", number + 1, frame))?;
+            if changes.last().is_some_and(|&(last, _)| frame <= last) {
+                return Err(format!("line {}: frames must increase", number + 1));
+            }
+
+            let mut buttons = Vec::new();
+            for word in words {
+                let button = parse_button(word)
+                    .ok_or_else(|| format!("line {}: unknown button `{}`", number + 1, word))?;
+                buttons.push(button);
+            }
+            changes.push((frame, movie::buttons_to_input(&buttons)));
+        }
+        Ok(InputScript { changes })
+    }
+
+    /// Joypad byte to hold during `frame`.
+    pub fn input(&self, frame: u32) -> u8 {
+        let index = self.changes.partition_point(|&(start, _)| start <= frame);
+        match index {
+            0 => 0,
+            _ => self.changes[index - 1].1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script = InputScript::parse("# boot\n10 start\n12\n\n20 A right # jump\n").unwrap();
+        assert_eq!(script.input(0), 0);
+        assert_eq!(script.input(10), 0x80);
+        assert_eq!(script.input(11), 0x80);
+        assert_eq!(script.input(12), 0);
+        assert_eq!(script.input(500), 0x11);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(InputScript::parse("x start").is_err());
+        assert!(InputScript::parse("10 jump").is_err());
+        assert!(InputScript::parse("10 a\n5 b").is_err());
+    }
+}
@@ -1,3 +1,365 @@
-fn main() {
-    println!("Hello, world!");
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+use rusty_boy_core::{model::Model, palette::Palette};
+
+mod boot;
+mod camera;
+mod dap;
+mod debugger;
+mod headless;
+mod input;
+mod screenshot;
+mod symbols;
+mod terminal;
+
+const USAGE: &str = "\
+usage: rusty-boy <rom> [options]               play in the terminal
+       rusty-boy --headless <rom> [options]    run without a display
+       rusty-boy --debug <rom> [options]       run in the command line debugger
+       rusty-boy --dap                         serve a debug adapter client on stdin/stdout
+
+options:
+    --load-state <file>    start from a save state
+    --save-file <file>     RAM and clock of a cartridge with a battery (terminal default: <rom>.sav)
+    --model <model>        dmg, mgb, sgb, cgb or agb (default: cgb for CGB games, dmg otherwise)
+    --boot-rom <file>      run a DMG (256 bytes) or CGB (2304 bytes) boot ROM first
+
+terminal and headless options:
+    --palette <palette>    DMG colors: grey, green, pocket or a file of 4 `RRGGBB` lines
+    --color-correction     show CGB colors like the CGB LCD does
+    --camera <png>         image seen by a Pocket Camera, repeat to show one per frame in turn
+
+debug and headless options:
+    --symbols <file>       RGBDS .sym or .map file naming addresses (default: <rom>.sym)
+
+debug options:
+    --gdb <port>           serve a GDB remote protocol client on localhost instead of the prompt
+
+terminal options:
+    --ansi16               use the 16 ANSI colors instead of 24-bit colors
+
+headless options:
+    --frames <n>           frames to run (default 600)
+    --screenshot <png>     write the last frame
+    --input <script>       buttons to hold, one `<frame> [button...]` line per change
+    --serial <file>        write the serial output (default: stdout)
+    --save-state <file>    write a save state at the end
+    --trace <file>         log every instruction
+    --record-movie <file>  record the input of every frame, from power on or --load-state
+    --play-movie <file>    play a movie back on its model, give --boot-rom if it was recorded
+                           with one (with --record-movie: record from the first --input press)";
+
+/// Options each mode has no use for, rejected rather than ignored.
+const DEBUG_IGNORES: &[&str] = &[
+    "--headless",
+    "--palette",
+    "--color-correction",
+    "--camera",
+    "--ansi16",
+    "--frames",
+    "--screenshot",
+    "--input",
+    "--serial",
+    "--save-state",
+    "--trace",
+    "--record-movie",
+    "--play-movie",
+];
+const TERMINAL_IGNORES: &[&str] = &[
+    "--symbols",
+    "--frames",
+    "--screenshot",
+    "--input",
+    "--serial",
+    "--save-state",
+    "--trace",
+    "--record-movie",
+    "--play-movie",
+];
+const HEADLESS_IGNORES: &[&str] = &["--ansi16"];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Dap,
+    Debug(debugger::Options),
+    Headless(headless::Options),
+    Terminal(terminal::Options),
+    Help,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut headless = false;
+    let mut debug = false;
+    let mut gdb = None;
+    let mut options = headless::Options {
+        frames: 600,
+        ..Default::default()
+    };
+    let mut ansi16 = false;
+    let mut rom = None;
+    let mut given = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            given.push(arg.clone());
+        }
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--headless" => headless = true,
+            "--dap" => return Ok(Command::Dap),
+            "--debug" => debug = true,
+            "--gdb" => {
+                let port = value(&arg)?;
+                gdb = Some(
+                    port.parse()
+                        .map_err(|_| format!("invalid port `{}`", port))?,
+                );
+                debug = true;
+            }
+            "--ansi16" => ansi16 = true,
+            "--palette" => options.colors.palette = palette(&value(&arg)?)?,
+            "--color-correction" => options.colors.correction = true,
+            "--model" => {
+                let name = value(&arg)?;
+                options.machine.model =
+                    Some(Model::parse(&name).ok_or_else(|| format!("unknown model `{}`", name))?);
+            }
+            "--frames" => {
+                let frames = value(&arg)?;
+                options.frames = frames
+                    .parse()
+                    .map_err(|_| format!("invalid frame count `{}`", frames))?;
+            }
+            "--screenshot" => options.screenshot = Some(value(&arg)?.into()),
+            "--input" => options.input = Some(value(&arg)?.into()),
+            "--serial" => options.serial = Some(value(&arg)?.into()),
+            "--load-state" => options.machine.load_state = Some(value(&arg)?.into()),
+            "--save-state" => options.save_state = Some(value(&arg)?.into()),
+            "--trace" => options.trace = Some(value(&arg)?.into()),
+            "--symbols" => options.symbols = Some(value(&arg)?.into()),
+            "--boot-rom" => options.machine.boot_rom = Some(value(&arg)?.into()),
+            "--save-file" => options.machine.save_file = Some(value(&arg)?.into()),
+            "--camera" => options.camera.push(value(&arg)?.into()),
+            "--record-movie" => options.record_movie = Some(value(&arg)?.into()),
+            "--play-movie" => options.play_movie = Some(value(&arg)?.into()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    options.machine.rom = rom.ok_or("no ROM given")?;
+    let (mode, ignores) = if debug {
+        ("debug", DEBUG_IGNORES)
+    } else if headless {
+        ("headless", HEADLESS_IGNORES)
+    } else {
+        ("terminal", TERMINAL_IGNORES)
+    };
+    if let Some(option) = given
+        .iter()
+        .find(|&option| ignores.contains(&option.as_str()))
+    {
+        return Err(format!("{} is not used in {} mode", option, mode));
+    }
+
+    if debug {
+        return Ok(Command::Debug(debugger::Options {
+            machine: options.machine,
+            symbols: options.symbols,
+            gdb,
+        }));
+    }
+    if headless {
+        check_movie_options(&options)?;
+        return Ok(Command::Headless(options));
+    }
+    Ok(Command::Terminal(terminal::Options {
+        machine: options.machine,
+        ansi16,
+        colors: options.colors,
+        camera: options.camera,
+    }))
+}
+
+/// Reject the options a movie cannot replay, or that a played back movie replaces.
+fn check_movie_options(options: &headless::Options) -> Result<(), String> {
+    if options.play_movie.is_none() && options.record_movie.is_none() {
+        return Ok(());
+    }
+    let machine = &options.machine;
+    if machine.save_file.is_some() || !options.camera.is_empty() {
+        return Err("movies do not record --save-file or --camera".to_string());
+    }
+    if options.play_movie.is_some() {
+        if machine.model.is_some() || machine.load_state.is_some() {
+            return Err("--play-movie starts on the model and state of the movie".to_string());
+        }
+        if options.input.is_some() && options.record_movie.is_none() {
+            return Err("--input needs --record-movie to branch a played back movie".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// A built-in palette by name, or read from a file.
+fn palette(name: &str) -> Result<Palette, String> {
+    if let Some(palette) = Palette::named(name) {
+        return Ok(palette);
+    }
+    let text = fs::read_to_string(name).map_err(|e| format!("palette `{}`: {}", name, e))?;
+    Palette::parse(&text).map_err(|e| format!("{}: {}", name, e))
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(env::args().skip(1)).and_then(|command| match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Dap => dap::run(),
+        Command::Debug(options) => debugger::run(&options),
+        Command::Headless(options) => headless::run(&options),
+        Command::Terminal(options) => terminal::run(&options),
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("rusty-boy: {}\n\n{}", error, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_boy_core::palette::ColorStage;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_headless() {
+        let command = parse(
+            "--headless rom.gb --frames 30 --screenshot out.png --input script.txt --trace t.log \
+             --save-file rom.sav",
+        )
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Headless(headless::Options {
+                machine: boot::Machine {
+                    rom: "rom.gb".into(),
+                    save_file: Some("rom.sav".into()),
+                    ..Default::default()
+                },
+                frames: 30,
+                screenshot: Some("out.png".into()),
+                input: Some("script.txt".into()),
+                trace: Some("t.log".into()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse("--headless rom.gb --play-movie a.rbm --record-movie b.rbm --input script.txt"),
+            Ok(Command::Headless(headless::Options {
+                machine: boot::Machine {
+                    rom: "rom.gb".into(),
+                    ..Default::default()
+                },
+                frames: 600,
+                input: Some("script.txt".into()),
+                play_movie: Some("a.rbm".into()),
+                record_movie: Some("b.rbm".into()),
+                ..Default::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_terminal() {
+        assert_eq!(
+            parse(
+                "rom.gb --ansi16 --model agb --palette green --color-correction --boot-rom agb.bin \
+                 --camera a.png --camera b.png"
+            ),
+            Ok(Command::Terminal(terminal::Options {
+                machine: boot::Machine {
+                    rom: "rom.gb".into(),
+                    load_state: None,
+                    model: Some(Model::Agb),
+                    boot_rom: Some("agb.bin".into()),
+                    save_file: None,
+                },
+                ansi16: true,
+                colors: ColorStage {
+                    palette: Palette::GREEN,
+                    correction: true,
+                },
+                camera: vec!["a.png".into(), "b.png".into()],
+            }))
+        );
+        assert!(parse("rom.gb --palette missing.pal")
+            .unwrap_err()
+            .starts_with("palette `missing.pal`"));
+    }
+
+    #[test]
+    fn test_parse_debug() {
+        assert_eq!(
+            parse("--debug rom.gb --load-state rom.state --symbols game.sym --save-file rom.sav"),
+            Ok(Command::Debug(debugger::Options {
+                machine: boot::Machine {
+                    rom: "rom.gb".into(),
+                    load_state: Some("rom.state".into()),
+                    model: None,
+                    boot_rom: None,
+                    save_file: Some("rom.sav".into()),
+                },
+                symbols: Some("game.sym".into()),
+                gdb: None,
+            }))
+        );
+        assert_eq!(
+            parse("rom.gb --gdb 2345"),
+            Ok(Command::Debug(debugger::Options {
+                machine: boot::Machine {
+                    rom: "rom.gb".into(),
+                    ..Default::default()
+                },
+                symbols: None,
+                gdb: Some(2345),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("--headless").is_err());
+        assert!(parse("--ansi16").is_err());
+        assert!(parse("--headless rom.gb --frames").is_err());
+        assert!(parse("--headless rom.gb --frames many").is_err());
+        assert!(parse("--headless rom.gb --speed 2").is_err());
+        assert!(parse("rom.gb --gdb port").is_err());
+        assert!(parse("rom.gb --model gba").is_err());
+        assert_eq!(
+            parse("--debug rom.gb --screenshot out.png"),
+            Err("--screenshot is not used in debug mode".to_string())
+        );
+        assert!(parse("rom.gb --gdb 2345 --palette green").is_err());
+        assert!(parse("--debug --headless rom.gb").is_err());
+        assert!(parse("rom.gb --trace t.log").is_err());
+        assert!(parse("rom.gb --symbols game.sym").is_err());
+        assert!(parse("rom.gb --frames 10").is_err());
+        assert!(parse("--headless rom.gb --ansi16").is_err());
+        assert!(parse("--headless rom.gb --record-movie a.rbm --save-file rom.sav").is_err());
+        assert!(parse("--headless rom.gb --play-movie a.rbm --model cgb").is_err());
+        assert!(parse("--headless rom.gb --play-movie a.rbm --input script.txt").is_err());
+        assert_eq!(parse("--help"), Ok(Command::Help));
+        assert_eq!(parse("--dap"), Ok(Command::Dap));
+    }
 }
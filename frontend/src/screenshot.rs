@@ -0,0 +1,23 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use rusty_boy_core::{palette::ColorStage, ppu::Color};
+
+/// Write a frame `width` pixels wide as an RGB PNG, with its colors through `colors`.
+pub fn write_png(
+    path: &Path,
+    frame: &[Color],
+    width: usize,
+    colors: &ColorStage,
+) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let height = frame.len() / width;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let rgb = colors.render(frame);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&rgb))
+        .map_err(|e| error(&e))
+}
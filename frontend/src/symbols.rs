@@ -0,0 +1,97 @@
+// Loading RGBDS symbol files next to the ROM, and finding the labels defined in source files.
+
+use std::{fs, path::Path};
+
+use rusty_boy_core::symbols::Symbols;
+
+/// Symbols of `explicit`, or of `<rom>.sym` when it exists, empty otherwise.
+pub fn load(rom: &Path, explicit: Option<&Path>) -> Result<Symbols, String> {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let path = rom.with_extension("sym");
+            if !path.exists() {
+                return Ok(Symbols::default());
+            }
+            path
+        }
+    };
+    let text =
+        fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Symbols::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Label defined on line `line` (1-based) of an RGBDS source file, with local labels
+/// prefixed by the global label they follow as in `.sym` files.
+pub fn label_on_line(source: &str, line: usize) -> Option<String> {
+    let mut global = String::new();
+    for (number, text) in source.lines().enumerate() {
+        let label = label_definition(text);
+        let name = match label {
+            Some(label) if label.starts_with('.') => format!("{}{}", global, label),
+            Some(label) => {
+                if !label.contains('.') {
+                    global = label.to_string();
+                }
+                label.to_string()
+            }
+            None => String::new(),
+        };
+        if number + 1 == line {
+            return label.map(|_| name);
+        }
+    }
+    None
+}
+
+/// Label defined at the start of a source line: `Name:`, `Name::`, `.local:` or `.local`.
+fn label_definition(line: &str) -> Option<&str> {
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "_.#@$".contains(c)))
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(end);
+    let local = name.starts_with('.') && name.len() > 1;
+    let global = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if (global && rest.starts_with(':'))
+        || (local && (rest.is_empty() || rest.starts_with([':', ' ', '\t', ';'])))
+    {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join("rusty-boy-symbols-load");
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.gb");
+        let _ = fs::remove_file(dir.join("game.sym"));
+        assert!(load(&rom, None).unwrap().is_empty());
+        fs::write(dir.join("game.sym"), "00:0150 Main\n").unwrap();
+        assert_eq!(
+            load(&rom, None).unwrap().find("Main").unwrap().address,
+            0x0150
+        );
+        fs::write(dir.join("bad.sym"), "Main\n").unwrap();
+        assert!(load(&rom, Some(&dir.join("bad.sym"))).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_label_on_line() {
+        let source = "SECTION \"Main\", ROM0\nMain::\n    ld b, 0\n.loop\n    inc b\n.done: ret\n";
+        assert_eq!(label_on_line(source, 2).as_deref(), Some("Main"));
+        assert_eq!(label_on_line(source, 3), None);
+        assert_eq!(label_on_line(source, 4).as_deref(), Some("Main.loop"));
+        assert_eq!(label_on_line(source, 6).as_deref(), Some("Main.done"));
+        assert_eq!(label_on_line(source, 1), None);
+    }
+}
@@ -0,0 +1,518 @@
+// Terminal mode: draws the screen with "▀" half blocks, the foreground color is the upper
+// pixel and the background the lower one, so 160x144 pixels take 160x72 characters.
+//
+// Keys: arrows = D-pad, X = A, Z = B, Enter = Start, Backspace = Select,
+//       R (held) = rewind, P = pause, Esc / Q = quit.
+// The accelerometer of MBC7 cartridges tilts 1 g with I / J / K / L (up, left, down, right),
+// or towards the mouse pointer from the middle of the screen while the left button is held.
+//
+// Most terminals only report key presses (and repeats). There a button stays held for a
+// few frames after the last press; terminals with the kitty keyboard protocol report
+// releases too.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyboardEnhancementFlags, MouseButton, MouseEvent, MouseEventKind,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue, terminal,
+};
+use rusty_boy_core::{
+    gameboy::GameBoy,
+    joypad::Button,
+    movie,
+    palette::ColorStage,
+    ppu::{Color, SCREEN_HEIGHT, SCREEN_WIDTH},
+    rewind::Rewind,
+    sgb,
+};
+
+use crate::{boot, camera::Camera};
+
+/// Frames per second of the DMG (4194304 Hz / 70224 clocks per frame).
+const FRAME_RATE: f64 = 59.7275;
+
+/// Frames a button stays held after a key press when releases are not reported.
+const HOLD_FRAMES: u32 = 15;
+
+/// Rewind snapshots, one per frame.
+const REWIND_FRAMES: usize = 600;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub machine: boot::Machine,
+    /// PNG files seen by a Pocket Camera, one per frame in turn.
+    pub camera: Vec<PathBuf>,
+    /// Use the 16 ANSI colors instead of 24-bit colors.
+    pub ansi16: bool,
+    /// DMG palette and color correction of the 24-bit colors.
+    pub colors: ColorStage,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor(ColorStage),
+    /// Shades mapped to the ANSI black, bright black, white and bright white.
+    Ansi16,
+}
+
+enum Key {
+    Button(Button),
+    /// Tilt towards one of `TILTS`.
+    Tilt(usize),
+    Rewind,
+    Pause,
+    Quit,
+}
+
+/// Accelerometer directions of the tilt keys: left, right, up and down.
+const TILTS: [(f32, f32); 4] = [(-1.0, 0.0), (1.0, 0.0), (0.0, -1.0), (0.0, 1.0)];
+
+/// Buttons held down, from key presses with or without release events.
+#[derive(Default)]
+struct KeyState {
+    /// Frames left to hold each button of `Button::ALL`, u32::MAX until released.
+    held: [u32; 8],
+    /// The same for each direction of `TILTS`.
+    tilts: [u32; 4],
+    rewind: u32,
+}
+
+/// Restores the terminal when dropped, including on panics.
+struct TerminalGuard {
+    enhanced: bool,
+}
+
+fn key(code: KeyCode) -> Option<Key> {
+    let key = match code {
+        KeyCode::Right => Key::Button(Button::Right),
+        KeyCode::Left => Key::Button(Button::Left),
+        KeyCode::Up => Key::Button(Button::Up),
+        KeyCode::Down => Key::Button(Button::Down),
+        KeyCode::Char('x') | KeyCode::Char('X') => Key::Button(Button::A),
+        KeyCode::Char('z') | KeyCode::Char('Z') => Key::Button(Button::B),
+        KeyCode::Enter => Key::Button(Button::Start),
+        KeyCode::Backspace => Key::Button(Button::Select),
+        KeyCode::Char('j') | KeyCode::Char('J') => Key::Tilt(0),
+        KeyCode::Char('l') | KeyCode::Char('L') => Key::Tilt(1),
+        KeyCode::Char('i') | KeyCode::Char('I') => Key::Tilt(2),
+        KeyCode::Char('k') | KeyCode::Char('K') => Key::Tilt(3),
+        KeyCode::Char('r') | KeyCode::Char('R') => Key::Rewind,
+        KeyCode::Char('p') | KeyCode::Char('P') => Key::Pause,
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('Q') => Key::Quit,
+        _ => return None,
+    };
+    Some(key)
+}
+
+impl KeyState {
+    fn slot(&mut self, key: &Key) -> Option<&mut u32> {
+        match key {
+            Key::Button(button) => {
+                let index = Button::ALL.iter().position(|b| b == button).unwrap();
+                Some(&mut self.held[index])
+            }
+            Key::Tilt(index) => Some(&mut self.tilts[*index]),
+            Key::Rewind => Some(&mut self.rewind),
+            _ => None,
+        }
+    }
+
+    /// Key down, `releases` tells whether a release event will follow.
+    fn press(&mut self, key: &Key, releases: bool) {
+        if let Some(frames) = self.slot(key) {
+            *frames = if releases { u32::MAX } else { HOLD_FRAMES };
+        }
+    }
+
+    fn release(&mut self, key: &Key) {
+        if let Some(frames) = self.slot(key) {
+            *frames = 0;
+        }
+    }
+
+    /// Joypad byte for this frame, and whether rewind is held. Counts down timed holds.
+    fn frame(&mut self) -> (u8, bool) {
+        let mut input = 0;
+        for (bit, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                input |= 1 << bit;
+                if *frames != u32::MAX {
+                    *frames -= 1;
+                }
+            }
+        }
+        for frames in &mut self.tilts {
+            if *frames > 0 && *frames != u32::MAX {
+                *frames -= 1;
+            }
+        }
+        let rewind = self.rewind > 0;
+        if self.rewind > 0 && self.rewind != u32::MAX {
+            self.rewind -= 1;
+        }
+        (input, rewind)
+    }
+
+    /// Accelerometer tilt of the tilt keys held.
+    fn tilt(&self) -> (f32, f32) {
+        TILTS
+            .iter()
+            .zip(self.tilts)
+            .filter(|&(_, frames)| frames > 0)
+            .fold((0.0, 0.0), |(x, y), (tilt, _)| (x + tilt.0, y + tilt.1))
+    }
+}
+
+/// Accelerometer tilt towards the character at `column`, `row` from the middle of the screen,
+/// 1 g at its edges.
+fn mouse_tilt(column: u16, row: u16) -> (f32, f32) {
+    let half_width = SCREEN_WIDTH as f32 / 2.0;
+    let half_height = SCREEN_HEIGHT as f32 / 4.0;
+    let x = (f32::from(column) + 0.5 - half_width) / half_width;
+    let y = (f32::from(row) + 0.5 - half_height) / half_height;
+    (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0))
+}
+
+impl TerminalGuard {
+    fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            EnableMouseCapture
+        )?;
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(TerminalGuard { enhanced })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(
+            stdout,
+            DisableMouseCapture,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Foreground codes of the 16 ANSI colors with their usual RGB values.
+const ANSI16: [(u8, [u8; 3]); 16] = [
+    (30, [0, 0, 0]),
+    (31, [205, 0, 0]),
+    (32, [0, 205, 0]),
+    (33, [205, 205, 0]),
+    (34, [0, 0, 238]),
+    (35, [205, 0, 205]),
+    (36, [0, 205, 205]),
+    (37, [229, 229, 229]),
+    (90, [127, 127, 127]),
+    (91, [255, 0, 0]),
+    (92, [0, 255, 0]),
+    (93, [255, 255, 0]),
+    (94, [92, 92, 255]),
+    (95, [255, 0, 255]),
+    (96, [0, 255, 255]),
+    (97, [255, 255, 255]),
+];
+
+/// ANSI color of a DMG shade, or the closest one to a CGB color.
+fn ansi16(pixel: Color) -> u8 {
+    match pixel {
+        Color::Shade(0) => 97,
+        Color::Shade(1) => 37,
+        Color::Shade(2) => 90,
+        Color::Shade(_) => 30,
+        Color::Rgb555(_) => {
+            let rgb = pixel.to_rgb();
+            let distance = |other: &[u8; 3]| -> u32 {
+                rgb.iter()
+                    .zip(other)
+                    .map(|(&a, &b)| (a.abs_diff(b) as u32).pow(2))
+                    .sum()
+            };
+            ANSI16
+                .iter()
+                .min_by_key(|(_, other)| distance(other))
+                .map(|&(code, _)| code)
+                .unwrap()
+        }
+    }
+}
+
+/// Escape sequence selecting `pixel` as the foreground (or background) color.
+fn color(output: &mut String, pixel: Color, mode: ColorMode, background: bool) {
+    match mode {
+        ColorMode::TrueColor(colors) => {
+            let [r, g, b] = colors.rgb(pixel);
+            let layer = if background { 48 } else { 38 };
+            let _ = write!(output, "\x1b[{};2;{};{};{}m", layer, r, g, b);
+        }
+        ColorMode::Ansi16 => {
+            let code = ansi16(pixel) + if background { 10 } else { 0 };
+            let _ = write!(output, "\x1b[{}m", code);
+        }
+    }
+}
+
+/// The game screen, with the SGB colors on an SGB.
+fn game_screen(gameboy: &GameBoy) -> Vec<Color> {
+    let Some(frame) = gameboy.sgb_frame() else {
+        return gameboy.frame().to_vec();
+    };
+    let (left, top) = (
+        (sgb::SCREEN_WIDTH - SCREEN_WIDTH) / 2,
+        (sgb::SCREEN_HEIGHT - SCREEN_HEIGHT) / 2,
+    );
+    (top..top + SCREEN_HEIGHT)
+        .flat_map(|y| {
+            &frame[y * sgb::SCREEN_WIDTH + left..y * sgb::SCREEN_WIDTH + left + SCREEN_WIDTH]
+        })
+        .copied()
+        .collect()
+}
+
+/// Draw a frame from the top left of the terminal, only changing colors when needed.
+pub fn render(frame: &[Color], mode: ColorMode) -> String {
+    let mut output = String::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+    output.push_str("\x1b[H");
+    for row in 0..SCREEN_HEIGHT / 2 {
+        let mut current = None;
+        for x in 0..SCREEN_WIDTH {
+            let top = frame[row * 2 * SCREEN_WIDTH + x];
+            let bottom = frame[(row * 2 + 1) * SCREEN_WIDTH + x];
+            if current != Some((top, bottom)) {
+                if current.map(|(t, _)| t) != Some(top) {
+                    color(&mut output, top, mode, false);
+                }
+                if current.map(|(_, b)| b) != Some(bottom) {
+                    color(&mut output, bottom, mode, true);
+                }
+                current = Some((top, bottom));
+            }
+            output.push('▀');
+        }
+        output.push_str("\x1b[0m\r\n");
+    }
+    output
+}
+
+pub fn run(options: &Options) -> Result<(), String> {
+    let machine = &options.machine;
+    let mut gameboy = machine.power_on()?;
+    let save_file = machine
+        .save_file
+        .clone()
+        .unwrap_or_else(|| boot::save_file_path(&machine.rom));
+    boot::load_save_file(&mut gameboy, &save_file)?;
+    if let Some(path) = &machine.load_state {
+        let state = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        gameboy
+            .load_state(&state)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    let mode = if options.ansi16 {
+        ColorMode::Ansi16
+    } else {
+        ColorMode::TrueColor(options.colors)
+    };
+
+    let camera = Camera::load(&options.camera)?;
+
+    let guard = TerminalGuard::new().map_err(|e| e.to_string())?;
+    let result = run_loop(&mut gameboy, mode, &camera, guard.enhanced);
+    drop(guard);
+    result.map_err(|e| e.to_string())?;
+    boot::write_save_file(&gameboy, &save_file)
+}
+
+fn run_loop(
+    gameboy: &mut GameBoy,
+    mode: ColorMode,
+    camera: &Camera,
+    releases: bool,
+) -> io::Result<()> {
+    let frame_time = Duration::from_secs_f64(1.0 / FRAME_RATE);
+    let mut keys = KeyState::default();
+    let mut rewind = Rewind::new(1, REWIND_FRAMES);
+    let mut paused = false;
+    let mut frames = 0;
+    let mut mouse = None;
+    let mut next_frame = Instant::now();
+    let mut stdout = io::stdout();
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            let (code, kind) = match event::read()? {
+                Event::Key(KeyEvent { code, kind, .. }) => (code, kind),
+                Event::Mouse(MouseEvent {
+                    kind, column, row, ..
+                }) => {
+                    match kind {
+                        MouseEventKind::Down(MouseButton::Left)
+                        | MouseEventKind::Drag(MouseButton::Left) => {
+                            mouse = Some(mouse_tilt(column, row))
+                        }
+                        MouseEventKind::Up(MouseButton::Left) => mouse = None,
+                        _ => {}
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let Some(key) = key(code) else {
+                continue;
+            };
+            match (kind, &key) {
+                (KeyEventKind::Release, _) => keys.release(&key),
+                (_, Key::Quit) => return Ok(()),
+                (KeyEventKind::Press, Key::Pause) => paused = !paused,
+                _ => keys.press(&key, releases),
+            }
+        }
+
+        let (input, rewinding) = keys.frame();
+        let status = if rewinding {
+            rewind
+                .step_back(gameboy)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            "rewind"
+        } else if paused {
+            "paused"
+        } else {
+            movie::apply_input(gameboy, input);
+            let (x, y) = mouse.unwrap_or_else(|| keys.tilt());
+            gameboy.set_accelerometer(x, y);
+            camera.show(gameboy, frames);
+            frames += 1;
+            gameboy.run_frame();
+            rewind.frame_done(gameboy);
+            ""
+        };
+
+        let mut output = render(&game_screen(gameboy), mode);
+        let _ = write!(
+            output,
+            "\x1b[2K{:<8} rewind {:>4.1}s  x:A z:B enter:Start backspace:Select r:rewind p:pause q:quit",
+            status,
+            rewind.len() as f64 / FRAME_RATE
+        );
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()?;
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else if now - next_frame > frame_time * 4 {
+            // Too far behind (e.g. a slow terminal), drop the lag instead of catching up.
+            next_frame = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_boy_core::palette::Palette;
+
+    #[test]
+    fn test_render_half_blocks() {
+        let mut frame = vec![Color::WHITE; SCREEN_WIDTH * SCREEN_HEIGHT];
+        frame[SCREEN_WIDTH] = Color::Shade(3); // (0, 1)
+        let output = render(&frame, ColorMode::Ansi16);
+        let lines: Vec<&str> = output.split("\r\n").collect();
+        assert_eq!(lines.len(), SCREEN_HEIGHT / 2 + 1);
+        assert_eq!(lines[0].matches('▀').count(), SCREEN_WIDTH);
+        assert!(lines[0].starts_with("\x1b[H\x1b[97m\x1b[40m▀\x1b[107m▀▀"));
+        assert!(lines[1].starts_with("\x1b[97m\x1b[107m▀▀"));
+    }
+
+    #[test]
+    fn test_render_true_color() {
+        let frame = vec![Color::Shade(1); SCREEN_WIDTH * SCREEN_HEIGHT];
+        let output = render(&frame, ColorMode::TrueColor(ColorStage::default()));
+        assert!(output.starts_with("\x1b[H\x1b[38;2;170;170;170m\x1b[48;2;170;170;170m▀▀"));
+        let colors = ColorStage {
+            palette: Palette::GREEN,
+            correction: false,
+        };
+        let output = render(&frame, ColorMode::TrueColor(colors));
+        assert!(output.starts_with("\x1b[H\x1b[38;2;139;172;15m"));
+    }
+
+    #[test]
+    fn test_ansi16_colors() {
+        assert_eq!(ansi16(Color::Rgb555(0x001F)), 91);
+        assert_eq!(ansi16(Color::Rgb555(0x3C00)), 34);
+        assert_eq!(ansi16(Color::Rgb555(0x7FFF)), 97);
+    }
+
+    #[test]
+    fn test_timed_hold() {
+        let mut keys = KeyState::default();
+        keys.press(&Key::Button(Button::A), false);
+        for _ in 0..HOLD_FRAMES {
+            assert_eq!(keys.frame(), (0x10, false));
+        }
+        assert_eq!(keys.frame(), (0x00, false));
+    }
+
+    #[test]
+    fn test_tilt() {
+        let mut keys = KeyState::default();
+        keys.press(&Key::Tilt(1), false);
+        keys.press(&Key::Tilt(2), true);
+        assert_eq!(keys.tilt(), (1.0, -1.0));
+        for _ in 0..HOLD_FRAMES {
+            keys.frame();
+        }
+        assert_eq!(keys.tilt(), (0.0, -1.0));
+        keys.release(&Key::Tilt(2));
+        assert_eq!(keys.tilt(), (0.0, 0.0));
+
+        assert_eq!(mouse_tilt(0, 0), (-0.99375, -0.9861111));
+        assert_eq!(mouse_tilt(80, 36), (0.00625, 0.013888889));
+        assert_eq!(mouse_tilt(500, 500), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_press_and_release() {
+        let mut keys = KeyState::default();
+        keys.press(&Key::Button(Button::Start), true);
+        keys.press(&Key::Rewind, true);
+        for _ in 0..HOLD_FRAMES * 2 {
+            assert_eq!(keys.frame(), (0x80, true));
+        }
+        keys.release(&Key::Button(Button::Start));
+        keys.release(&Key::Rewind);
+        assert_eq!(keys.frame(), (0x00, false));
+    }
+}